@@ -0,0 +1,66 @@
+// Browser training demo: an MLP learning the two moons dataset, one optimizer step per train_step() call.
+// Build with `wasm-pack build --target web examples/wasm` and drive it from JS:
+//   const demo = new MoonsDemo(42n);
+//   for (let i = 0; i < 100; i++) console.log(demo.train_step());
+//   demo.predict(0.5, -0.2);
+use rust_micrograd::{make_moons, Adam, Arena, ArenaLifeTime, Loss, Module, Optimizer, Rng, Value, ValueData, ValueFactory, MLP};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct MoonsDemo {
+    // owns every Value below; the graph of each step stays in the arena until the demo is dropped
+    _life_time: ArenaLifeTime<ValueData>,
+    vf: ValueFactory,
+    model: MLP,
+    optimizer: Adam,
+    xs: Vec<Vec<f64>>,
+    ys: Vec<f64>
+}
+
+#[wasm_bindgen]
+impl MoonsDemo {
+    #[wasm_bindgen(constructor)]
+    pub fn new(seed: u64) -> MoonsDemo {
+        let (life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let mut rng = Rng::new(seed);
+        let model = MLP::new_with_rng(&vf, 2, &[8, 8, 1], &mut rng);
+        let optimizer = Adam::new(model.parameters().cloned().collect(), 0.05);
+        let (xs, labels) = make_moons(60, 0.1, rng.next_seed());
+        let ys = labels.iter().map(|&l| if l == 0 { -1.0 } else { 1.0 }).collect();
+        MoonsDemo { _life_time: life_time, vf, model, optimizer, xs, ys }
+    }
+
+    // One full-batch step, returns the loss before the update
+    pub fn train_step(&mut self) -> f64 {
+        let losses: Vec<Value> = self.xs.iter().zip(&self.ys).map(|(x, y)| {
+            let x: Vec<Value> = x.iter().map(|xi| self.vf.value(*xi)).collect();
+            Loss::Mse.value(&self.model.call(&x), &[self.vf.value(*y)])
+        }).collect();
+        let loss = &losses.into_iter().reduce(|acc, l| &acc + &l).unwrap() / self.xs.len() as f64;
+        self.optimizer.zero_grad();
+        loss.backward();
+        self.optimizer.step();
+        loss.get_data()
+    }
+
+    // Model output in [-1, 1]; positive means the second moon
+    pub fn predict(&self, x: f64, y: f64) -> f64 {
+        self.model.predict(&[x, y])[0]
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loss_decreases() {
+        let mut demo = MoonsDemo::new(42);
+        let first = demo.train_step();
+        let last = (0..50).map(|_| demo.train_step()).last().unwrap();
+        assert!(last < first / 2.0, "{} -> {}", first, last);
+    }
+}
@@ -0,0 +1,34 @@
+/* C interface of rust_micrograd, built with `cargo build --release --features ffi`.
+ * Link against target/release/librust_micrograd.so (or .dylib / .dll).
+ *
+ *   CMlp *m = mlp_load_json("model.json");
+ *   double out[1];
+ *   if (m && mlp_predict(m, in, mlp_input_len(m), out, 1) == MLP_OK) { ... }
+ *   mlp_free(m);
+ *   m = NULL;
+ */
+#ifndef RUST_MICROGRAD_H
+#define RUST_MICROGRAD_H
+
+#include <stddef.h>
+
+#define MLP_OK 0
+#define MLP_ERR_NULL (-1)
+#define MLP_ERR_LENGTH (-2)
+#define MLP_ERR_PANIC (-3)
+
+typedef struct CMlp CMlp;
+
+/* Model written by MLP::save_json, or NULL when it cannot be read or parsed. */
+CMlp *mlp_load_json(const char *path);
+
+size_t mlp_input_len(const CMlp *handle);
+size_t mlp_output_len(const CMlp *handle);
+
+/* input_len and output_len must match the model exactly; output is untouched on error. */
+int mlp_predict(const CMlp *handle, const double *input, size_t input_len, double *output, size_t output_len);
+
+/* Frees a handle. NULL is a no-op; freeing the same handle twice is undefined behavior. */
+void mlp_free(CMlp *handle);
+
+#endif
@@ -0,0 +1,58 @@
+# Run with: maturin develop && pytest python/tests
+import pytest
+
+from rust_micrograd import MLP, Value, ValueFactory
+
+
+def test_micrograd_example():
+    vf = ValueFactory()
+    a = vf.value(-4.0)
+    b = vf.value(2.0)
+    c = a + b
+    d = a * b + b**3
+    c += c + 1
+    c += 1 + c + (-a)
+    d += d * 2 + (b + a).relu()
+    d += 3 * d + (b - a).relu()
+    e = c - d
+    f = e**2
+    g = f / 2.0
+    g += 10.0 / f
+    g.backward()
+    assert isinstance(g, Value)
+    assert g.data == pytest.approx(24.7041, abs=1e-4)
+    assert a.grad == pytest.approx(138.8338, abs=1e-4)
+    assert b.grad == pytest.approx(645.5773, abs=1e-4)
+
+
+def test_mlp():
+    vf = ValueFactory()
+    model = MLP(vf, 3, [4, 4, 1], seed=1)
+    assert len(model.parameters()) == 41
+    loss = (model([2.0, 3.0, -1.0]) - 1.0) ** 2
+    loss.backward()
+    assert any(p.grad != 0.0 for p in model.parameters())
+    model.zero_grad()
+    assert all(p.grad == 0.0 for p in model.parameters())
+    assert len(vf.mlp(2, [3])([1.0, 2.0])) == 3
+
+
+def test_closed_factory_raises():
+    vf = ValueFactory()
+    a = vf.value(1.0)
+    model = vf.mlp(2, [1])
+    vf.close()
+    assert vf.closed
+    with pytest.raises(RuntimeError):
+        a.data
+    with pytest.raises(RuntimeError):
+        a * 2
+    with pytest.raises(RuntimeError):
+        model([1.0, 2.0])
+    assert repr(a) == "Value(<closed>)"
+
+
+def test_collected_factory_raises():
+    a = ValueFactory().value(1.0)
+    with pytest.raises(RuntimeError):
+        a.backward()
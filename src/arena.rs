@@ -1,40 +1,702 @@
-use std::{cell::RefCell, rc::{Rc, Weak}};
+use std::{cell::{Cell, RefCell, RefMut}, collections::HashMap, fmt, mem, rc::{Rc, Weak}};
+use std::sync::atomic::{AtomicU64, Ordering};
 
+// Nodes live by value in one Vec and are addressed by index. Every allocation is stamped with a new
+// generation, so a handle to a freed slot never matches whatever is allocated at that index later.
 pub struct Arena<V> {
-    arena: Vec<Rc<RefCell<V>>>
+    slots: RefCell<Vec<Slot<V>>>,
+    // number of handles per slot, kept apart from the slots so handles can be cloned and dropped while a
+    // slot is borrowed
+    handles: RefCell<Vec<HandleCount>>,
+    occupied: Cell<usize>,
+    next_generation: Cell<u64>,
+    // see ArenaRef::intern
+    interned: RefCell<HashMap<u64, ArenaHandle<V>>>,
+    // released slots, reused by the next allocations before the Vecs grow
+    free: RefCell<Vec<u32>>,
+    // see ArenaRef::set_auto_shrink_threshold
+    auto_shrink: Cell<Option<f64>>,
+    // see ArenaRef::set_max_len
+    max_len: Cell<Option<usize>>
+}
+
+struct Slot<V> {
+    generation: u64,
+    // None once freed
+    value: Option<V>
+}
+
+struct HandleCount {
+    generation: u64,
+    count: usize
 }
 
 impl<V> Arena<V> {
     pub fn build() -> (ArenaLifeTime<V>, ArenaRef<V>) {
-        let arena = Rc::new(RefCell::new(Arena { arena: Vec::new() }));
-        (ArenaLifeTime(arena.clone()), ArenaRef(Rc::downgrade(&arena)))
+        Arena::build_with_capacity(0)
+    }
+
+    // Preallocates room for capacity entries, e.g. the node count of one training step
+    pub fn build_with_capacity(capacity: usize) -> (ArenaLifeTime<V>, ArenaRef<V>) {
+        let arena = Rc::new(Arena {
+            slots: RefCell::new(Vec::with_capacity(capacity)),
+            handles: RefCell::new(Vec::with_capacity(capacity)),
+            occupied: Cell::new(0),
+            next_generation: Cell::new(0),
+            interned: RefCell::new(HashMap::new()),
+            free: RefCell::new(Vec::new()),
+            auto_shrink: Cell::new(None),
+            max_len: Cell::new(None)
+        });
+        (ArenaLifeTime(arena.clone()), ArenaRef(Rc::downgrade(&arena), NEXT_ARENA_ID.fetch_add(1, Ordering::Relaxed)))
     }
 
-    fn alloc(&mut self, value: V) -> Weak<RefCell<V>> {
-        let shared = Rc::new(RefCell::new(value));
-        self.arena.push(shared.clone());
-        Rc::downgrade(&shared)
+    fn alloc(&self, value: V) -> (u32, u64) {
+        let generation = self.next_generation.get();
+        self.next_generation.set(generation + 1);
+        let index = Self::place(&mut self.slots.borrow_mut(), &mut self.handles.borrow_mut(), &mut self.free.borrow_mut(), value, generation, 0);
+        self.occupied.set(self.occupied.get() + 1);
+        (index, generation)
+    }
+
+    // Puts value into a released slot if there is one, otherwise at the end
+    fn place(slots: &mut Vec<Slot<V>>, handles: &mut Vec<HandleCount>, free: &mut Vec<u32>, value: V, generation: u64, count: usize) -> u32 {
+        let (slot, handle_count) = (Slot { generation, value: Some(value) }, HandleCount { generation, count });
+        match free.pop() {
+            Some(index) => {
+                slots[index as usize] = slot;
+                handles[index as usize] = handle_count;
+                index
+            }
+            None => {
+                let index = u32::try_from(slots.len()).expect("Arena is full");
+                slots.push(slot);
+                handles.push(handle_count);
+                index
+            }
+        }
+    }
+
+    // Takes the value out of every slot free says so for, given its index and generation, and queues the
+    // slots for reuse. The caller drops the values once no borrow is held.
+    fn free_where(&self, mut free: impl FnMut(usize, u64) -> bool) -> Vec<V> {
+        let (mut slots, mut free_list) = (self.slots.borrow_mut(), self.free.borrow_mut());
+        let freed: Vec<V> = slots.iter_mut().enumerate()
+            .filter(|(index, slot)| slot.value.is_some() && free(*index, slot.generation))
+            .filter_map(|(index, slot)| {
+                free_list.push(index as u32);
+                slot.value.take()
+            })
+            .collect();
+        self.occupied.set(self.occupied.get() - freed.len());
+        freed
+    }
+
+    // Freed slots at the end are dropped from the Vec; ones in the middle stay as holes until an allocation
+    // reuses them. Interned entries that were freed are forgotten.
+    fn pop_free_tail(&self) {
+        let mut slots = self.slots.borrow_mut();
+        while slots.last().is_some_and(|slot| slot.value.is_none()) {
+            slots.pop();
+        }
+        self.handles.borrow_mut().truncate(slots.len());
+        self.free.borrow_mut().retain(|index| (*index as usize) < slots.len());
+        let shrink = self.auto_shrink.get().is_some_and(|factor| slots.capacity() as f64 > factor * slots.len() as f64);
+        drop(slots);
+        self.interned.borrow_mut().retain(|_, handle| handle.is_alive());
+        if shrink {
+            self.shrink_to_fit();
+        }
+    }
+
+    fn shrink_to_fit(&self) {
+        self.slots.borrow_mut().shrink_to_fit();
+        self.handles.borrow_mut().shrink_to_fit();
+        self.free.borrow_mut().shrink_to_fit();
+        self.interned.borrow_mut().shrink_to_fit();
     }
 }
 
 // Arena deallocated when ArenaLifeTime struct goes out of scope. ArenaLifeTime is sole owner of Rc to Arena.
 #[must_use]
-pub struct ArenaLifeTime<V>(#[allow(dead_code)]Rc<RefCell<Arena<V>>>);
+pub struct ArenaLifeTime<V>(#[allow(dead_code)]Rc<Arena<V>>);
+
+// User interacts with the Arena using a wrapped weak pointer, plus the id of the arena so that it can still
+// be named in messages after the arena is gone.
+pub struct ArenaRef<V>(Weak<Arena<V>>, u64);
+
+// Ids are unique across arenas of every type built by this process
+static NEXT_ARENA_ID: AtomicU64 = AtomicU64::new(0);
+
+// An allocation that would take the arena beyond its ArenaRef::set_max_len
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArenaLimitError {
+    pub arena: u64,
+    pub limit: usize,
+    // entries held at the time
+    pub len: usize,
+    pub requested: usize
+}
+
+impl fmt::Display for ArenaLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "arena {} holds {} of at most {} nodes, no room for {} more (see set_max_nodes)", self.arena, self.len, self.limit, self.requested)
+    }
+}
 
-// User interacts with the Arena using a wrapped weak pointer.
-pub struct ArenaRef<V>(Weak<RefCell<Arena<V>>>);
+impl std::error::Error for ArenaLimitError {}
+
+// Generation of the next allocation at the time of ArenaRef::checkpoint. Generations only grow, so it tells
+// later allocations apart wherever they are placed, released slots below the mark included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ArenaMark(u64);
 
 impl<V> ArenaRef<V> {
     // Always panic if Arena deallocated
-    pub fn alloc_with_mut_borrow(&self, value: V) -> Weak<RefCell<V>> {
-        let value_ptr = self.0.upgrade().expect("Arena lifetime has ended");
-        let mut borrow = value_ptr.borrow_mut();
-        borrow.alloc(value)
+    fn arena(&self) -> Rc<Arena<V>> {
+        self.upgrade().unwrap_or_else(|| panic!("Arena lifetime has ended (arena {})", self.1))
+    }
+
+    // Every access to the arena goes through here
+    fn upgrade(&self) -> Option<Rc<Arena<V>>> {
+        #[cfg(test)]
+        UPGRADES.with(|n| n.set(n.get() + 1));
+        self.0.upgrade()
+    }
+
+    // Runs f with every entry borrowed at once, upgrading the arena a single time however many entries f
+    // touches. f must not go through the arena otherwise (allocating, handle accessors): that would panic.
+    pub(crate) fn with_view<R>(&self, f: impl FnOnce(&mut ArenaView<'_, V>) -> R) -> R {
+        let arena = self.arena();
+        let mut view = ArenaView { slots: arena.slots.borrow_mut() };
+        f(&mut view)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.1
+    }
+
+    // false once the ArenaLifeTime was dropped
+    pub fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    // Panics if the arena is at its max_len
+    pub fn alloc_with_mut_borrow(&self, value: V) -> ArenaHandle<V> {
+        self.try_alloc_with_mut_borrow(value).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_alloc_with_mut_borrow(&self, value: V) -> Result<ArenaHandle<V>, ArenaLimitError> {
+        self.check_room(1)?;
+        let (index, generation) = self.arena().alloc(value);
+        Ok(ArenaHandle::new(self.clone(), index, generation))
+    }
+
+    // Allocates every value in order, borrowing the arena once for the whole batch. values must not use
+    // this arena.
+    pub fn alloc_many_with_mut_borrow(&self, values: impl ExactSizeIterator<Item = V>) -> Vec<ArenaHandle<V>> {
+        self.try_alloc_many_with_mut_borrow(values).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Allocates nothing if the whole batch does not fit under max_len
+    pub fn try_alloc_many_with_mut_borrow(&self, values: impl ExactSizeIterator<Item = V>) -> Result<Vec<ArenaHandle<V>>, ArenaLimitError> {
+        self.check_room(values.len())?;
+        let arena = self.arena();
+        let (mut slots, mut handles, mut free) = (arena.slots.borrow_mut(), arena.handles.borrow_mut(), arena.free.borrow_mut());
+        let n = values.len();
+        slots.reserve(n.saturating_sub(free.len()));
+        handles.reserve(n.saturating_sub(free.len()));
+        let first_generation = arena.next_generation.get();
+        arena.next_generation.set(first_generation + n as u64);
+        arena.occupied.set(arena.occupied.get() + n);
+        Ok(values.zip(first_generation..).map(|(value, generation)| {
+            // counting the handle returned below
+            let index = Arena::place(&mut slots, &mut handles, &mut free, value, generation, 1);
+            ArenaHandle { arena: self.clone(), index, generation, counted: true }
+        }).collect())
+    }
+
+    fn check_room(&self, n: usize) -> Result<(), ArenaLimitError> {
+        let arena = self.arena();
+        match arena.max_len.get() {
+            Some(limit) if arena.occupied.get() + n > limit => Err(ArenaLimitError { arena: self.1, limit, len: arena.occupied.get(), requested: n }),
+            _ => Ok(())
+        }
+    }
+
+    // With Some(limit), allocating beyond limit live entries fails: the plain allocations panic, the try_
+    // ones return an error. Freed entries (truncate_to, reset, compact) make room again. Lowering the limit
+    // below the current length frees nothing, it only stops further allocations.
+    pub fn set_max_len(&self, limit: Option<usize>) {
+        self.arena().max_len.set(limit);
+    }
+
+    pub fn max_len(&self) -> Option<usize> {
+        self.arena().max_len.get()
+    }
+
+    // Number of allocations currently held
+    pub fn len(&self) -> usize {
+        self.arena().occupied.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.arena().slots.borrow().capacity()
+    }
+
+    // Makes room for at least additional more entries. Handles are indices, so growing the backing Vec
+    // never invalidates them.
+    pub fn reserve(&self, additional: usize) {
+        let arena = self.arena();
+        arena.slots.borrow_mut().reserve(additional);
+        arena.handles.borrow_mut().reserve(additional);
+    }
+
+    // Gives back the memory beyond the current length, e.g. after truncating away a large evaluation graph.
+    // Freed slots in the middle still count towards the length.
+    pub fn shrink_to_fit(&self) {
+        self.arena().shrink_to_fit();
+    }
+
+    // With Some(factor), every truncate_to, reset, retain or compact also shrinks the arena once its capacity
+    // exceeds factor times its length. None (the default) leaves the capacity at its peak.
+    pub fn set_auto_shrink_threshold(&self, factor: Option<f64>) {
+        assert!(factor.is_none_or(|f| f >= 1.0), "Auto shrink factor must be at least 1, got {:?}", factor);
+        self.arena().auto_shrink.set(factor);
+    }
+
+    pub fn auto_shrink_threshold(&self) -> Option<f64> {
+        self.arena().auto_shrink.get()
+    }
+
+    // Size of the slots in use, freed holes included, plus their handle counts. Heap memory owned by the
+    // entries themselves is not included.
+    pub fn approx_bytes(&self) -> usize {
+        self.arena().slots.borrow().len() * (mem::size_of::<Slot<V>>() + mem::size_of::<HandleCount>())
+    }
+
+    // Oldest slot first
+    pub(crate) fn for_each(&self, mut f: impl FnMut(usize, &V)) {
+        let arena = self.arena();
+        let slots = arena.slots.borrow();
+        slots.iter().enumerate().for_each(|(index, slot)| if let Some(v) = &slot.value {f(index, v)});
+    }
+
+    // Number of entries with at least one counted handle
+    pub(crate) fn counted_len(&self) -> usize {
+        let arena = self.arena();
+        let (slots, handles) = (arena.slots.borrow(), arena.handles.borrow());
+        slots.iter().zip(handles.iter()).filter(|(slot, h)| slot.value.is_some() && h.count > 0).count()
+    }
+
+    // Which slots are reachable from an entry with at least one counted handle, following the indices
+    // edges pushes for each entry. Indexed by slot; freed slots are false.
+    pub(crate) fn reachable_from_handles(&self, edges: impl Fn(&V, &mut Vec<usize>)) -> Vec<bool> {
+        let arena = self.arena();
+        let (slots, handles) = (arena.slots.borrow(), arena.handles.borrow());
+        let mut reached = vec![false; slots.len()];
+        let mut stack: Vec<usize> = (0..slots.len()).filter(|&i| slots[i].value.is_some() && handles[i].count > 0).collect();
+        while let Some(index) = stack.pop() {
+            if let (false, Some(v)) = (reached[index], &slots[index].value) {
+                reached[index] = true;
+                edges(v, &mut stack);
+            }
+        }
+        reached
+    }
+
+    // Allocation cached under key, made with make the first time or after the cached one was freed. The
+    // cache does not count as a handle, so compact can still free it.
+    pub(crate) fn intern(&self, key: u64, make: impl FnOnce() -> V) -> ArenaHandle<V> {
+        let arena = self.arena();
+        let cached = arena.interned.borrow().get(&key).filter(|handle| handle.is_alive()).map(ArenaHandle::counted);
+        cached.unwrap_or_else(|| {
+            let handle = self.alloc_with_mut_borrow(make());
+            arena.interned.borrow_mut().insert(key, handle.uncounted());
+            handle
+        })
+    }
+
+    pub fn clear_interned(&self) {
+        self.arena().interned.borrow_mut().clear();
+    }
+
+    pub fn checkpoint(&self) -> ArenaMark {
+        ArenaMark(self.arena().next_generation.get())
+    }
+
+    // Frees every allocation made after mark, including ones that reused a released slot; their handles
+    // stop resolving. Allocations made before it are untouched, so a training loop can take a mark once the
+    // parameters exist and truncate back to it after every step. Truncating to a mark taken after every
+    // current allocation does nothing.
+    pub fn truncate_to(&self, mark: ArenaMark) {
+        let arena = self.arena();
+        let removed = arena.free_where(|_, generation| generation >= mark.0);
+        drop(removed);
+        arena.pop_free_tail();
+    }
+
+    // Frees the allocation handle points at and lets the next allocation reuse its slot, under a new
+    // generation so that stale handles keep failing. Panics if any other counted handle refers to it.
+    pub(crate) fn free_slot(&self, handle: &ArenaHandle<V>) {
+        assert_eq!(handle.arena.1, self.1, "Released an allocation of another arena");
+        let arena = self.arena();
+        let index = handle.index as usize;
+        let count = arena.handles.borrow().get(index).filter(|h| h.generation == handle.generation).map(|h| h.count);
+        assert!(handle.is_alive(), "Released an allocation that was already freed");
+        assert!(count.unwrap_or(0) <= 1, "Released an allocation that {} other handles still refer to", count.unwrap_or(0) - 1);
+        let removed = arena.slots.borrow_mut()[index].value.take();
+        arena.occupied.set(arena.occupied.get() - 1);
+        arena.free.borrow_mut().push(handle.index);
+        drop(removed);
+    }
+
+    // Frees every allocation keep returns false for, identified by index. Their handles stop resolving.
+    pub(crate) fn retain(&self, mut keep: impl FnMut(usize) -> bool) {
+        let arena = self.arena();
+        let removed = arena.free_where(|index, _| !keep(index));
+        drop(removed);
+        arena.pop_free_tail();
     }
 }
 
 impl<V> Clone for ArenaRef<V> {
     fn clone(&self) -> Self {
-        Self(self.0.clone())
+        Self(self.0.clone(), self.1)
+    }
+}
+
+// Arena upgrades made by this thread, see ArenaRef::upgrade
+#[cfg(test)]
+thread_local! {
+    pub(crate) static UPGRADES: Cell<usize> = const { Cell::new(0) };
+}
+
+// Entries of an arena borrowed for the duration of ArenaRef::with_view
+pub(crate) struct ArenaView<'a, V> {
+    slots: RefMut<'a, Vec<Slot<V>>>
+}
+
+impl<V> ArenaView<'_, V> {
+    // None once the allocation was freed
+    pub(crate) fn get(&self, handle: &ArenaHandle<V>) -> Option<&V> {
+        self.slots.get(handle.index as usize).filter(|slot| slot.generation == handle.generation)?.value.as_ref()
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: &ArenaHandle<V>) -> Option<&mut V> {
+        self.slots.get_mut(handle.index as usize).filter(|slot| slot.generation == handle.generation)?.value.as_mut()
+    }
+}
+
+// Index of one allocation plus the generation it was made with. The arena counts the handles pointing at
+// each slot, except uncounted ones, which are meant for references from one entry to another.
+pub struct ArenaHandle<V> {
+    arena: ArenaRef<V>,
+    index: u32,
+    generation: u64,
+    counted: bool
+}
+
+impl<V> ArenaHandle<V> {
+    fn new(arena: ArenaRef<V>, index: u32, generation: u64) -> ArenaHandle<V> {
+        let handle = ArenaHandle { arena, index, generation, counted: true };
+        handle.count(|count| *count += 1);
+        handle
+    }
+
+    pub(crate) fn counted(&self) -> ArenaHandle<V> {
+        ArenaHandle::new(self.arena.clone(), self.index, self.generation)
+    }
+
+    pub(crate) fn uncounted(&self) -> ArenaHandle<V> {
+        ArenaHandle { arena: self.arena.clone(), index: self.index, generation: self.generation, counted: false }
+    }
+
+    // Handles can be dropped after their arena, so a missing arena or a reused slot is skipped
+    fn count(&self, f: impl FnOnce(&mut usize)) {
+        if !self.counted {
+            return;
+        }
+        if let Some(arena) = self.arena.upgrade() {
+            if let Some(h) = arena.handles.borrow_mut().get_mut(self.index as usize) {
+                if h.generation == self.generation {
+                    f(&mut h.count);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn arena(&self) -> &ArenaRef<V> {
+        &self.arena
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    // None once the allocation was freed or the arena dropped
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&V) -> R) -> Option<R> {
+        let arena = self.arena.upgrade()?;
+        let slots = arena.slots.try_borrow().unwrap_or_else(|_| self.busy());
+        let slot = slots.get(self.index as usize).filter(|slot| slot.generation == self.generation)?;
+        slot.value.as_ref().map(f)
+    }
+
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(&mut V) -> R) -> Option<R> {
+        let arena = self.arena.upgrade()?;
+        let mut slots = arena.slots.try_borrow_mut().unwrap_or_else(|_| self.busy());
+        let slot = slots.get_mut(self.index as usize).filter(|slot| slot.generation == self.generation)?;
+        slot.value.as_mut().map(f)
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.with(|_| ()).is_some()
+    }
+
+    // The entries are borrowed elsewhere, e.g. by a with_view that went back through a handle
+    fn busy(&self) -> ! {
+        panic!("node {} of arena {} was accessed while the arena is borrowed (from inside a sweep over the graph)", self.index, self.arena.1)
+    }
+}
+
+impl<V> Clone for ArenaHandle<V> {
+    fn clone(&self) -> Self {
+        if self.counted { self.counted() } else { self.uncounted() }
+    }
+}
+
+impl<V> Drop for ArenaHandle<V> {
+    fn drop(&mut self) {
+        self.count(|count| *count = count.saturating_sub(1));
+    }
+}
+
+impl<V> PartialEq for ArenaHandle<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.arena.1 == other.arena.1 && self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<V> Eq for ArenaHandle<V> {}
+
+impl<V> std::hash::Hash for ArenaHandle<V> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Module, Optimizer, Sgd, Value, ValueFactory, MLP};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Counts heap allocations per thread, so tests running in parallel don't disturb each other
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    fn allocations<R>(f: impl FnOnce() -> R) -> (R, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let r = f();
+        (r, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    #[test]
+    fn truncate_to_mark() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref.clone());
+
+        let w = vf.value(3.0);
+        let mark = arena_ref.checkpoint();
+        let y = &w * &w;
+        y.backward();
+        arena_ref.truncate_to(mark);
+        assert_eq!(arena_ref.len(), 1);
+        assert!(w.is_alive() && !y.is_alive());
+        assert_eq!((w.get_data(), w.get_grad()), (3.0, 6.0));
+
+        // an inner mark is released without touching what the outer one protects
+        let a = vf.value(1.0);
+        let inner = arena_ref.checkpoint();
+        let b = &a + 1.0;
+        assert!(inner > mark);
+        arena_ref.truncate_to(inner);
+        assert!(a.is_alive() && !b.is_alive());
+        arena_ref.truncate_to(inner);
+        assert_eq!(arena_ref.len(), 2);
+        arena_ref.truncate_to(mark);
+        assert!(w.is_alive() && !a.is_alive());
+    }
+
+    #[test]
+    fn marked_training_loop() {
+        // the same seeded training run with and without truncating back to the mark after every step
+        fn train(truncate: bool) -> (Vec<f64>, Vec<usize>) {
+            let (_arena_life_time, arena_ref) = Arena::build();
+            let vf = ValueFactory::new(arena_ref.clone());
+            let model = MLP::new_with_seed(&vf, 3, &[4, 4, 1], 11);
+            let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.1);
+            let xs = [[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0], [1.0, 1.0, -1.0]];
+            let ys = [1.0, -1.0, -1.0, 1.0];
+
+            let mark = arena_ref.checkpoint();
+            let (mut losses, mut lens) = (Vec::new(), Vec::new());
+            for _ in 0..20 {
+                let loss = xs.iter().zip(ys).fold(vf.value(0.0), |acc, (x, y)| {
+                    let x: Vec<Value> = x.iter().map(|xi| vf.value(*xi)).collect();
+                    &acc + &(&model.call(&x)[0] - y).powi(2)
+                });
+                opt.zero_grad();
+                loss.backward();
+                opt.step();
+                losses.push(loss.get_data());
+                if truncate {
+                    arena_ref.truncate_to(mark);
+                }
+                lens.push(arena_ref.len());
+            }
+            (losses, lens)
+        }
+        let (losses, lens) = train(true);
+        assert_eq!(losses, train(false).0);
+        assert!(losses.last().unwrap() < &losses[0]);
+        assert!(lens.iter().all(|&len| len == lens[0]));
+    }
+
+    #[test]
+    fn capacity() {
+        let (_arena_life_time, arena_ref) = Arena::build_with_capacity(64);
+        let vf = ValueFactory::new(arena_ref.clone());
+        let capacity = arena_ref.capacity();
+        assert!(capacity >= 64);
+
+        let a = vf.value(0.5);
+        let b = (&(&a * &a) + 1.0).tanh();
+        assert_eq!(arena_ref.capacity(), capacity);
+
+        arena_ref.reserve(1000);
+        assert!(arena_ref.capacity() >= arena_ref.len() + 1000);
+        b.backward();
+        assert_eq!((a.get_data(), b.get_data()), (0.5, 1.25_f64.tanh()));
+        assert!((a.get_grad() - (1.0 - 1.25_f64.tanh().powi(2))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shrink() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref.clone());
+        let w = vf.value(0.5);
+        let mark = arena_ref.checkpoint();
+
+        let y = (0..1000).fold(vf.value(0.0), |acc, i| &acc + &(&w * i as f64));
+        y.backward();
+        arena_ref.truncate_to(mark);
+        assert!(arena_ref.capacity() >= 1000);
+        arena_ref.shrink_to_fit();
+        assert!(arena_ref.capacity() < 16);
+        assert_eq!((w.get_data(), w.get_grad()), (0.5, 499500.0));
+
+        assert_eq!(arena_ref.capacity(), arena_ref.len());
+
+        // shrinks once capacity > 4 * len, not at exactly 4 * len
+        arena_ref.set_auto_shrink_threshold(Some(4.0));
+        let mut vs = vf.values(&[1.0; 3]);
+        let mark = arena_ref.checkpoint();
+        arena_ref.reserve(12);
+        assert_eq!(arena_ref.capacity(), 16);
+        drop(vf.values(&[2.0; 12]));
+        arena_ref.truncate_to(mark);
+        assert_eq!((arena_ref.len(), arena_ref.capacity()), (4, 16));
+        vs.pop();
+        arena_ref.compact();
+        assert_eq!((arena_ref.len(), arena_ref.capacity()), (3, 3));
+        assert_eq!(vs[1].get_data(), 1.0);
+
+        for round in 0..20 {
+            let big = vf.values(&vec![round as f64; 500 * (round % 3 + 1)]);
+            let z = &big[0] * &w;
+            z.backward();
+            assert_eq!(z.get_data(), round as f64 * 0.5);
+            drop((big, z));
+            arena_ref.compact();
+            assert!(arena_ref.capacity() <= 4 * arena_ref.len().max(1));
+        }
+        assert_eq!((w.get_data(), arena_ref.auto_shrink_threshold()), (0.5, Some(4.0)));
+    }
+
+    #[test]
+    fn allocations_per_node() {
+        let (_arena_life_time, arena_ref) = Arena::build_with_capacity(16);
+        let vf = ValueFactory::new(arena_ref);
+
+        // nodes live in the arena's Vec, so a leaf costs no allocation of its own
+        let (a, n) = allocations(|| vf.value(2.0));
+        assert_eq!(n, 0);
+        let b = vf.value(3.0);
+        // an op node: only its backward closure, the op is stored inline
+        let (c, n) = allocations(|| &a * &b);
+        assert_eq!(n, 1);
+        assert_eq!(allocations(|| c.powi(2)).1, 1);
+        c.backward();
+        assert_eq!((a.get_grad(), b.get_grad()), (3.0, 2.0));
+    }
+
+    #[test]
+    fn mlp_call_allocations() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 16, &[8, 8, 1], 4);
+        let x = vf.values(&(0..16).map(|i| i as f64 * 0.1).collect::<Vec<f64>>());
+        n.reserve_for_calls(&vf, 1);
+        // a backward closure per op plus one output Vec per layer; the input is not copied
+        let (_y, count) = allocations(|| n.call(&x));
+        assert_eq!(count, n.nodes_per_call() + 3);
+    }
+
+    #[test]
+    fn stale_handles() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref.clone());
+
+        let w = vf.value(1.0);
+        let mark = arena_ref.checkpoint();
+        let old = &w + 1.0;
+        arena_ref.truncate_to(mark);
+        // the new nodes take over the freed slots under a new generation
+        let new = &w * 5.0;
+        assert!(!old.is_alive() && new.is_alive());
+        assert!(old != new);
+        assert!(catch_unwind(AssertUnwindSafe(|| old.get_data())).is_err());
+        assert_eq!(new.get_data(), 5.0);
+
+        // dropping the arena invalidates every handle
+        drop(_arena_life_time);
+        assert!(!new.is_alive() && !w.is_alive());
     }
 }
@@ -0,0 +1,551 @@
+// The file-backed callbacks are not available on wasm32, which has no filesystem
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::VecDeque;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::{self, File, OpenOptions};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+use std::iter::once;
+use std::fmt;
+use crate::{History, Value};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Module;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallbackAction {
+    Continue,
+    Stop
+}
+
+// Hooks into Trainer::fit. Every method has a no-op default so implementors only override what they need.
+// Returning CallbackAction::Stop from on_epoch_end ends training after that epoch.
+pub trait Callback {
+    fn on_epoch_start(&mut self, _epoch: usize) {}
+
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) {}
+
+    // history already contains the record of this epoch
+    fn on_epoch_end(&mut self, _epoch: usize, _history: &History) -> CallbackAction {
+        CallbackAction::Continue
+    }
+
+    fn on_train_end(&mut self, _history: &History) {}
+}
+
+// Prints the training (and validation, if any) loss every log_every epochs
+pub struct ConsoleLogger {
+    log_every: usize
+}
+
+impl ConsoleLogger {
+    pub fn new(log_every: usize) -> ConsoleLogger {
+        assert!(log_every > 0, "ConsoleLogger log_every must be positive");
+        ConsoleLogger { log_every }
+    }
+}
+
+impl Callback for ConsoleLogger {
+    fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+        if epoch.is_multiple_of(self.log_every) {
+            let record = history.records().last().unwrap();
+            match record.val_loss {
+                Some(val_loss) => println!("Loss at step {}: {} (validation {})", epoch, record.train_loss, val_loss),
+                None => println!("Loss at step {}: {}", epoch, record.train_loss)
+            }
+        }
+        CallbackAction::Continue
+    }
+}
+
+// Adapts a closure into an on_epoch_end callback
+pub struct CallbackFn<F: FnMut(usize, &History) -> CallbackAction> {
+    f: F
+}
+
+impl<F: FnMut(usize, &History) -> CallbackAction> CallbackFn<F> {
+    pub fn new(f: F) -> CallbackFn<F> {
+        CallbackFn { f }
+    }
+}
+
+impl<F: FnMut(usize, &History) -> CallbackAction> Callback for CallbackFn<F> {
+    fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+        (self.f)(epoch, history)
+    }
+}
+
+// Lets a callback that is still needed after training, e.g. a GradRecorder, be passed as Box::new(&mut c)
+impl<C: Callback + ?Sized> Callback for &mut C {
+    fn on_epoch_start(&mut self, epoch: usize) {
+        (**self).on_epoch_start(epoch)
+    }
+
+    fn on_batch_end(&mut self, epoch: usize, batch: usize, loss: f64) {
+        (**self).on_batch_end(epoch, batch, loss)
+    }
+
+    fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+        (**self).on_epoch_end(epoch, history)
+    }
+
+    fn on_train_end(&mut self, history: &History) {
+        (**self).on_train_end(history)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateWatchError {
+    pub name: String
+}
+
+impl fmt::Display for DuplicateWatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a value named {:?} is already watched", self.name)
+    }
+}
+
+impl std::error::Error for DuplicateWatchError {}
+
+// Snapshots (step, data, grad) of watched values on every record(step). A value that is gone (freed, or
+// its arena dropped) is recorded as NaN. As a Callback it records after every batch, numbering the steps
+// 0, 1, 2, ... across epochs; pass it as Box::new(&mut recorder) to read the series after fit.
+#[derive(Default)]
+pub struct GradRecorder {
+    watched: Vec<Watched>,
+    steps: usize
+}
+
+struct Watched {
+    name: String,
+    value: Value,
+    series: Vec<(usize, f64, f64)>
+}
+
+impl GradRecorder {
+    pub fn new() -> GradRecorder {
+        GradRecorder::default()
+    }
+
+    pub fn watch(&mut self, name: &str, value: &Value) -> Result<(), DuplicateWatchError> {
+        if self.watched.iter().any(|w| w.name == name) {
+            return Err(DuplicateWatchError { name: name.to_string() });
+        }
+        self.watched.push(Watched { name: name.to_string(), value: value.clone(), series: Vec::new() });
+        Ok(())
+    }
+
+    pub fn record(&mut self, step: usize) {
+        self.watched.iter_mut().for_each(|w| {
+            let (data, grad) = if w.value.is_alive() {(w.value.get_data(), w.value.get_grad())} else {(f64::NAN, f64::NAN)};
+            w.series.push((step, data, grad));
+        });
+    }
+
+    // Names in the order they were watched
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.watched.iter().map(|w| w.name.as_str())
+    }
+
+    // Panics if nothing is watched under name
+    pub fn series(&self, name: &str) -> &[(usize, f64, f64)] {
+        match self.watched.iter().find(|w| w.name == name) {
+            Some(w) => &w.series,
+            None => panic!("no value named {:?} is watched", name)
+        }
+    }
+
+    // Columns: name,step,data,grad with one row per recorded snapshot, grouped by name
+    pub fn to_csv_string(&self) -> String {
+        let mut csv = String::from("name,step,data,grad\n");
+        self.watched.iter().for_each(|w| {
+            w.series.iter().for_each(|(step, data, grad)| csv.push_str(&format!("{},{},{},{}\n", w.name, step, data, grad)));
+        });
+        csv
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv_string())
+    }
+}
+
+impl Callback for GradRecorder {
+    fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) {
+        self.record(self.steps);
+        self.steps += 1;
+    }
+}
+
+// Appends one CSV row per epoch: epoch, the logged fields, then seconds elapsed since the logger was created.
+// Each row is flushed right away so an interrupted run still leaves a usable file. When the file already
+// has content its header must match the fields of the first logged row, and rows are appended after it.
+// As a Callback it logs loss, val_loss, lr and the record's metrics, with NaN for missing values.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct CsvLogger {
+    file: File,
+    existing_header: Option<String>,
+    header_checked: bool,
+    start: Instant
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CsvLogger {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<CsvLogger> {
+        let existing_header = match File::open(&path) {
+            Ok(f) => BufReader::new(f).lines().next().transpose()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e)
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CsvLogger { file, existing_header, header_checked: false, start: Instant::now() })
+    }
+
+    pub fn log_row(&mut self, epoch: usize, fields: &[(&str, f64)]) -> io::Result<()> {
+        if !self.header_checked {
+            let header = once("epoch")
+                .chain(fields.iter().map(|(name, _)| *name))
+                .chain(once("elapsed_secs"))
+                .collect::<Vec<&str>>()
+                .join(",");
+            match &self.existing_header {
+                Some(existing) if *existing != header => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("existing header {:?} does not match {:?}", existing, header)
+                    ));
+                }
+                Some(_) => (),
+                None => writeln!(self.file, "{}", header)?
+            }
+            self.header_checked = true;
+        }
+        let mut row = epoch.to_string();
+        fields.iter().for_each(|(_, v)| row.push_str(&format!(",{}", v)));
+        writeln!(self.file, "{},{}", row, self.start.elapsed().as_secs_f64())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Callback for CsvLogger {
+    fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+        let record = history.records().last().unwrap();
+        let mut fields = vec![
+            ("loss", record.train_loss),
+            ("val_loss", record.val_loss.unwrap_or(f64::NAN)),
+            ("lr", record.lr.unwrap_or(f64::NAN))
+        ];
+        fields.extend(record.metrics.iter().map(|(name, v)| (name.as_str(), *v)));
+        self.log_row(epoch, &fields).expect("Failed to write CSV log");
+        CallbackAction::Continue
+    }
+}
+
+// Writes the parameters (see Module::state_dict) whenever the monitored loss improves. The monitored loss is
+// the validation loss, or the training loss for epochs without validation. The path template may contain
+// {epoch} and {metric}, e.g. "ckpt_epoch{epoch}.json". With keep_best = Some(n) only the n most recent
+// (i.e. best) checkpoints are kept on disk.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ModelCheckpoint<'a, M: Module> {
+    model: &'a M,
+    template: String,
+    keep_best: Option<usize>,
+    best: f64,
+    saved: VecDeque<PathBuf>
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<'a, M: Module> ModelCheckpoint<'a, M> {
+    pub fn new(model: &'a M, template: &str, keep_best: Option<usize>) -> ModelCheckpoint<'a, M> {
+        ModelCheckpoint { model, template: template.to_string(), keep_best, best: f64::INFINITY, saved: VecDeque::new() }
+    }
+
+    pub fn path_for(&self, epoch: usize, metric: f64) -> PathBuf {
+        PathBuf::from(self.template.replace("{epoch}", &epoch.to_string()).replace("{metric}", &format!("{:.6}", metric)))
+    }
+
+    // Checkpoints currently on disk, oldest first
+    pub fn saved(&self) -> impl Iterator<Item = &PathBuf> {
+        self.saved.iter()
+    }
+
+    fn save(&mut self, epoch: usize, metric: f64) -> io::Result<()> {
+        let path = self.path_for(epoch, metric);
+        write_state_json(&path, &self.model.state_dict())?;
+        // a template without {epoch} or {metric} overwrites the same file, which must not be counted twice
+        // and then removed as the older copy
+        self.saved.retain(|p| *p != path);
+        self.saved.push_back(path);
+        if let Some(keep) = self.keep_best {
+            while self.saved.len() > keep {
+                fs::remove_file(self.saved.pop_front().unwrap())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<M: Module> Callback for ModelCheckpoint<'_, M> {
+    fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+        let record = history.records().last().unwrap();
+        let metric = record.val_loss.unwrap_or(record.train_loss);
+        if metric < self.best {
+            self.best = metric;
+            self.save(epoch, metric).expect("Failed to write checkpoint");
+        }
+        CallbackAction::Continue
+    }
+}
+
+// State dicts are stored as a flat JSON array of numbers. JSON has no NaN or infinity, so non-finite
+// values are written as null and read back as NaN.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_state_json(path: impl AsRef<Path>, state: &[f64]) -> io::Result<()> {
+    let values: Vec<String> = state.iter()
+        .map(|v| if v.is_finite() { format!("{:?}", v) } else { String::from("null") })
+        .collect();
+    fs::write(path, format!("[{}]\n", values.join(", ")))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_state_json(path: impl AsRef<Path>) -> io::Result<Vec<f64>> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let inner = contents.trim()
+        .strip_prefix('[')
+        .and_then(|c| c.strip_suffix(']'))
+        .ok_or_else(|| invalid("state dict must be a JSON array"))?;
+    if inner.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    inner.split(',')
+        .map(|v| match v.trim() {
+            "null" => Ok(f64::NAN),
+            v => v.parse::<f64>().map_err(|e| invalid(&format!("invalid number {:?}: {}", v, e)))
+        })
+        .collect()
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, EpochRecord, Loss, Sgd, Trainer, TrainerOptions, ValueFactory, MLP};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_micrograd_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(checkpoint: &mut ModelCheckpoint<MLP>, metrics: &[f64]) {
+        let mut history = History::new();
+        metrics.iter().enumerate().for_each(|(epoch, m)| {
+            history.record(EpochRecord { epoch, train_loss: 1.0, val_loss: Some(*m), ..Default::default() });
+            checkpoint.on_epoch_end(epoch, &history);
+        });
+    }
+
+    #[test]
+    fn saves_on_improvement() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let dir = temp_dir("ckpt_improve");
+
+        let n = MLP::new(&vf, 2, &[3, 1]);
+        let template = dir.join("ckpt_epoch{epoch}.json");
+        let mut checkpoint = ModelCheckpoint::new(&n, template.to_str().unwrap(), None);
+        run(&mut checkpoint, &[0.9, 0.8, 0.85, 0.7, 0.7]);
+        let saved: Vec<String> = checkpoint.saved()
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(saved, vec!["ckpt_epoch0.json", "ckpt_epoch1.json", "ckpt_epoch3.json"]);
+        assert!(!dir.join("ckpt_epoch2.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_best_n() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let dir = temp_dir("ckpt_keep");
+
+        let n = MLP::new(&vf, 2, &[3, 1]);
+        let template = dir.join("e{epoch}_m{metric}.json");
+        let mut checkpoint = ModelCheckpoint::new(&n, template.to_str().unwrap(), Some(2));
+        run(&mut checkpoint, &[0.9, 0.8, 0.7, 0.6]);
+        let mut on_disk: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        on_disk.sort();
+        assert_eq!(on_disk, vec!["e2_m0.700000.json", "e3_m0.600000.json"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_fixed_path() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let dir = temp_dir("ckpt_fixed");
+
+        let n = MLP::new(&vf, 2, &[3, 1]);
+        let template = dir.join("best.json");
+        let mut checkpoint = ModelCheckpoint::new(&n, template.to_str().unwrap(), Some(1));
+        run(&mut checkpoint, &[0.9, 0.8, 0.85, 0.7]);
+        assert!(template.exists());
+        assert_eq!(checkpoint.saved().collect::<Vec<_>>(), vec![&template]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_restores_model() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let dir = temp_dir("ckpt_restore");
+
+        let n = MLP::new(&vf, 2, &[3, 1]);
+        let template = dir.join("best.json");
+        let mut checkpoint = ModelCheckpoint::new(&n, template.to_str().unwrap(), None);
+        run(&mut checkpoint, &[0.5]);
+        let expected = n.predict(&[0.3, -0.7]);
+
+        let m = MLP::new(&vf, 2, &[3, 1]);
+        m.load_state_dict(&read_state_json(dir.join("best.json")).unwrap()).unwrap();
+        assert_eq!(m.predict(&[0.3, -0.7]), expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn state_json_non_finite() {
+        let dir = temp_dir("state_non_finite");
+        let path = dir.join("state.json");
+
+        write_state_json(&path, &[1.5, f64::NAN, f64::INFINITY, -0.25]).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[1.5, null, null, -0.25]\n");
+        let state = read_state_json(&path).unwrap();
+        assert_eq!((state[0], state[3]), (1.5, -0.25));
+        assert!(state[1].is_nan() && state[2].is_nan());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_logger() {
+        let dir = temp_dir("csv_logger");
+        let path = dir.join("log.csv");
+
+        let mut logger = CsvLogger::new(&path).unwrap();
+        let mut history = History::new();
+        let losses = [0.5, 0.25, 1.0 / 3.0];
+        losses.iter().enumerate().for_each(|(epoch, loss)| {
+            history.record(EpochRecord {
+                epoch,
+                train_loss: *loss,
+                lr: Some(0.1),
+                metrics: vec![(String::from("accuracy"), 0.8)],
+                ..Default::default()
+            });
+            logger.on_epoch_end(epoch, &history);
+        });
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let mut lines = contents.lines();
+        assert_eq!(lines.next().unwrap(), "epoch,loss,val_loss,lr,accuracy,elapsed_secs");
+        let rows: Vec<Vec<f64>> = lines.map(|l| l.split(',').map(|f| f.parse().unwrap()).collect()).collect();
+        assert_eq!(rows.len(), 3);
+        rows.iter().zip(losses).enumerate().for_each(|(epoch, (row, loss))| {
+            assert_eq!(row[0], epoch as f64);
+            assert_eq!(row[1], loss);
+            assert!(row[2].is_nan());
+            assert_eq!((row[3], row[4]), (0.1, 0.8));
+            assert!(row[5] >= 0.0);
+        });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn csv_logger_appends() {
+        let dir = temp_dir("csv_logger_append");
+        let path = dir.join("log.csv");
+
+        CsvLogger::new(&path).unwrap().log_row(0, &[("loss", 1.5)]).unwrap();
+        CsvLogger::new(&path).unwrap().log_row(1, &[("loss", 0.75)]).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "epoch,loss,elapsed_secs");
+        assert!(lines[1].starts_with("0,1.5,") && lines[2].starts_with("1,0.75,"));
+
+        let err = CsvLogger::new(&path).unwrap().log_row(2, &[("loss", 0.5), ("lr", 0.1)]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn grad_recorder() {
+        let (arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.param(0.5);
+        let mut recorder = GradRecorder::new();
+        recorder.watch("w", &w).unwrap();
+        assert_eq!(recorder.watch("w", &vf.value(1.0)), Err(DuplicateWatchError { name: String::from("w") }));
+        assert_eq!(recorder.watch("w", &w).unwrap_err().to_string(), "a value named \"w\" is already watched");
+
+        // minimize (w - 2)^2 by hand
+        let mut expected = Vec::new();
+        for step in 0..4 {
+            w.set_grad(0.0);
+            (&w - 2.0).powi(2).backward();
+            expected.push((step, w.get_data(), w.get_grad()));
+            recorder.record(step);
+            w.set_data(w.get_data() - 0.1 * w.get_grad());
+        }
+        assert_eq!(recorder.series("w"), &expected[..]);
+        assert_eq!(recorder.names().collect::<Vec<_>>(), vec!["w"]);
+
+        let path = temp_dir("grad_recorder").join("grads.csv");
+        recorder.to_csv(&path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("name,step,data,grad"));
+        let parsed: Vec<(usize, f64, f64)> = lines.map(|l| {
+            let fields: Vec<&str> = l.split(',').collect();
+            assert_eq!(fields[0], "w");
+            (fields[1].parse().unwrap(), fields[2].parse().unwrap(), fields[3].parse().unwrap())
+        }).collect();
+        assert_eq!(parsed, expected);
+
+        // a dead arena is recorded as NaN
+        drop(arena_life_time);
+        recorder.record(4);
+        let (step, data, grad) = recorder.series("w")[4];
+        assert!(step == 4 && data.is_nan() && grad.is_nan());
+    }
+
+    #[test]
+    fn grad_recorder_callback() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 2, &[3, 1], 1);
+        let mut recorder = GradRecorder::new();
+        recorder.watch("w0", n.parameters().next().unwrap()).unwrap();
+        let xs = vec![vec![0.5, -1.0], vec![1.0, 2.0], vec![-0.5, 0.5]];
+        let ys = vec![vec![1.0], vec![-1.0], vec![0.5]];
+        let options = TrainerOptions { epochs: 3, batch_size: 2, ..Default::default() };
+        let mut trainer = Trainer::new(&n, |x| n.call(x), Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        trainer.fit_with_callbacks(&xs, &ys, &[], &[], vec![Box::new(&mut recorder)]);
+        // two batches per epoch
+        let steps: Vec<usize> = recorder.series("w0").iter().map(|s| s.0).collect();
+        assert_eq!(steps, (0..6).collect::<Vec<_>>());
+        assert!(recorder.series("w0").iter().all(|s| s.2 != 0.0));
+    }
+}
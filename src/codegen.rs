@@ -0,0 +1,102 @@
+use crate::{Activation, MLP};
+
+fn float_literal(v: f64) -> String {
+    if v.is_finite() {
+        format!("{:?}", v)
+    } else if v.is_nan() {
+        String::from("f64::NAN")
+    } else if v > 0.0 {
+        String::from("f64::INFINITY")
+    } else {
+        String::from("f64::NEG_INFINITY")
+    }
+}
+
+fn float_array(values: &[f64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| float_literal(*v)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+impl MLP {
+    // Self-contained Rust source for graph-free inference with this model's current weights:
+    //   pub fn fn_name(x: &[f64; NIN]) -> [f64; NOUT]
+    // Weights are baked in as consts named after the function. Neurons sum the same way as MLP::call
+    // (bias first, then inputs in order) so the outputs agree up to the tanh formula.
+    pub fn to_rust_source(&self, fn_name: &str) -> String {
+        let spec = self.to_spec();
+        let prefix = fn_name.to_uppercase();
+        let nout = spec.layers.last().unwrap().nout;
+        let mut src = format!("// Generated by rust_micrograd from an MLP {} -> {:?}\n", spec.nin, spec.layer_sizes());
+
+        let mut nin = spec.nin;
+        for (i, l) in spec.layers.iter().enumerate() {
+            let rows: Vec<String> = l.weights.iter().map(|w| float_array(w)).collect();
+            src.push_str(&format!("const {}_W{}: [[f64; {}]; {}] = [\n    {}\n];\n", prefix, i, nin, l.nout, rows.join(",\n    ")));
+            src.push_str(&format!("const {}_B{}: [f64; {}] = {};\n", prefix, i, l.nout, float_array(&l.biases)));
+            nin = l.nout;
+        }
+
+        src.push_str(&format!("\npub fn {}(x: &[f64; {}]) -> [f64; {}] {{\n", fn_name, spec.nin, nout));
+        src.push_str("    let h0 = *x;\n");
+        for (i, l) in spec.layers.iter().enumerate() {
+            let activation = match l.activation {
+                Activation::Tanh => "acc.tanh()",
+                Activation::Relu => "acc.max(0.0)",
+                Activation::Linear => "acc"
+            };
+            src.push_str(&format!("    let mut h{} = [0.0; {}];\n", i + 1, l.nout));
+            src.push_str(&format!("    for (j, out) in h{}.iter_mut().enumerate() {{\n", i + 1));
+            src.push_str(&format!("        let mut acc = {}_B{}[j];\n", prefix, i));
+            src.push_str(&format!("        for (w, xi) in {}_W{}[j].iter().zip(&h{}) {{\n", prefix, i, i));
+            src.push_str("            acc += w * xi;\n");
+            src.push_str("        }\n");
+            src.push_str(&format!("        *out = {};\n", activation));
+            src.push_str("    }\n");
+        }
+        src.push_str(&format!("    h{}\n}}\n", spec.layers.len()));
+        src
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, Rng, Value, ValueFactory};
+
+    // Golden files compiled into this module. Regenerate them with
+    //   UPDATE_FIXTURES=1 cargo test codegen
+    mod generated {
+        include!("../tests/fixtures/generated_tanh_mlp.rs");
+        include!("../tests/fixtures/generated_relu_mlp.rs");
+    }
+
+    fn check_generated(n: &MLP, fn_name: &str, generated: fn(&[f64; 3]) -> [f64; 2], vf: &ValueFactory) {
+        let path = format!("{}/tests/fixtures/generated_{}.rs", env!("CARGO_MANIFEST_DIR"), fn_name);
+        let src = n.to_rust_source(fn_name);
+        if std::env::var_os("UPDATE_FIXTURES").is_some() {
+            std::fs::write(&path, &src).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), src, "{} is stale, rerun with UPDATE_FIXTURES=1", path);
+
+        let mut rng = Rng::new(8);
+        for _ in 0..50 {
+            let x = [rng.uniform(-3.0, 3.0), rng.uniform(-3.0, 3.0), rng.uniform(-3.0, 3.0)];
+            let expected: Vec<f64> = n.call(&x.iter().map(|v| vf.value(*v)).collect::<Vec<Value>>()).iter().map(|v| v.get_data()).collect();
+            generated(&x).iter().zip(&expected).for_each(|(g, e)| assert!((g - e).abs() < 1e-12, "{} vs {}", g, e));
+        }
+    }
+
+    #[test]
+    fn generated_source_matches_call() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let tanh = MLP::new_with_seed(&vf, 3, &[5, 4, 2], 21);
+        check_generated(&tanh, "tanh_mlp", generated::tanh_mlp, &vf);
+        let activations = [Activation::Relu, Activation::Relu, Activation::Linear];
+        let relu = MLP::new_with_activations(&vf, 3, &[5, 4, 2], &activations, &mut Rng::new(22));
+        check_generated(&relu, "relu_mlp", generated::relu_mlp, &vf);
+    }
+}
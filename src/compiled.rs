@@ -0,0 +1,156 @@
+use crate::{BindError, TapeError, TapeOp, Value};
+
+// A graph whose structure stays fixed while its data changes. The topological order is computed once;
+// refresh recomputes every node from the current leaf data and backward reruns the backward pass, neither
+// allocating nodes.
+pub struct CompiledGraph {
+    root: Value,
+    topo: Vec<Value>,
+    // op nodes in topological order with their op and operands (the same one twice for x * x)
+    ops: Vec<(Value, TapeOp, Value, Value)>
+}
+
+impl Value {
+    pub fn compile(&self) -> Result<CompiledGraph, TapeError> {
+        let topo = self.topo_order();
+        let mut ops = Vec::new();
+        for v in &topo {
+            if let (Some(op), children) = v.op_and_children() {
+                let op = TapeOp::from_op(op).ok_or_else(|| TapeError(format!("unsupported op {:?}", op.to_string())))?;
+                let b = children.get(1).unwrap_or(&children[0]).clone();
+                ops.push((v.clone(), op, children[0].clone(), b));
+            }
+        }
+        Ok(CompiledGraph { root: self.clone(), topo, ops })
+    }
+}
+
+impl CompiledGraph {
+    pub fn root(&self) -> &Value {
+        &self.root
+    }
+
+    // Value::bind_inputs and bind_named without walking the graph again
+    pub fn bind_inputs(&self, pairs: &[(&Value, f64)]) -> Result<(), BindError> {
+        Value::bind_along(&self.topo, pairs)
+    }
+
+    pub fn bind_named(&self, pairs: &[(&str, f64)]) -> Result<(), BindError> {
+        Value::bind_named_along(&self.topo, pairs)
+    }
+
+    // Forward pass with the current leaf data
+    pub fn refresh(&self) {
+        self.ops.iter().for_each(|(v, op, a, b)| v.set_data(op.apply(a.get_data(), b.get_data())));
+    }
+
+    // Like root().backward(), except that the grads of the op nodes are reset first: they would otherwise
+    // add up across calls. Leaf grads accumulate as usual, so zero the parameters beforehand.
+    pub fn backward(&self) {
+        self.ops.iter().for_each(|(v, _, _, _)| v.set_grad(0.0));
+        self.root.backward_along(&self.topo);
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::{Arena, ValueFactory};
+
+    #[test]
+    fn refresh_and_backward() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let x = vf.value(0.5);
+        let w = vf.value(-1.5);
+        let y = (&(&(&x * &w).tanh() * &x) + &(&w.relu() + &x.exp())).powi(2);
+        let graph = y.compile().unwrap();
+
+        for (xi, wi) in [(0.5, -1.5), (2.0, 0.25), (-1.0, 3.0)] {
+            x.set_data(xi);
+            w.set_data(wi);
+            x.set_grad(0.0);
+            w.set_grad(0.0);
+            let len = vf.len();
+            graph.refresh();
+            graph.backward();
+            assert_eq!(vf.len(), len);
+
+            // the same expression built from scratch
+            let (fx, fw) = (vf.value(xi), vf.value(wi));
+            let fy = (&(&(&fx * &fw).tanh() * &fx) + &(&fw.relu() + &fx.exp())).powi(2);
+            fy.backward();
+            assert_eq!(y.get_data(), fy.get_data());
+            assert_eq!((x.get_grad(), w.get_grad()), (fx.get_grad(), fw.get_grad()));
+        }
+        assert_eq!(graph.root().get_data(), y.get_data());
+    }
+
+    #[test]
+    fn bind_inputs() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let x1 = vf.value_labeled(0.0, "x1");
+        let x2 = vf.value_labeled(0.0, "x2");
+        let h = (&x1 * &x2).tanh();
+        h.set_label("h");
+        let two = vf.constant(2.0);
+        let y = &(&h + &x1) * &two;
+        let graph = y.compile().unwrap();
+
+        y.bind_inputs(&[(&x1, 0.5), (&x2, -1.5)]).unwrap();
+        graph.refresh();
+        let fresh = |a: f64, b: f64| (&(&(&vf.value(a) * &vf.value(b)).tanh() + &vf.value(a)) * 2.0).get_data();
+        assert_eq!(y.get_data(), fresh(0.5, -1.5));
+        graph.bind_named(&[("x1", 2.0), ("x2", 0.25)]).unwrap();
+        graph.refresh();
+        assert_eq!(y.get_data(), fresh(2.0, 0.25));
+
+        // nothing is set unless every binding is valid
+        let other = vf.value_labeled(1.0, "other");
+        let err = graph.bind_inputs(&[(&x1, 7.0), (&h, 1.0), (&other, 1.0), (&two, 1.0)]).unwrap_err();
+        assert_eq!(err.unknown, vec![String::from("\"other\"")]);
+        assert_eq!((err.not_leaves.len(), err.not_leaves[0].as_str()), (2, "\"h\""));
+        let err = y.bind_named(&[("x1", 7.0), ("x9", 1.0), ("h", 1.0)]).unwrap_err();
+        assert_eq!(err.to_string(), "cannot bind inputs; not in the graph: \"x9\"; not input leaves: \"h\"");
+        assert_eq!(x1.get_data(), 2.0);
+    }
+
+    #[test]
+    fn bind_and_refresh_training() {
+        use crate::{Module, Optimizer, Sgd, MLP};
+        let samples = [([0.5, -1.0], 1.0), ([-0.25, 2.0], -1.0), ([1.5, 0.5], 0.5)];
+        let train = |compiled: bool| {
+            let (_arena_life_time, arena_ref) = Arena::build();
+            let vf = ValueFactory::new(arena_ref);
+            let model = MLP::new_with_seed(&vf, 2, &[3, 1], 1);
+            let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.1);
+            let inputs = vf.values_labeled(&[(0.0, "a"), (0.0, "b")]);
+            let target = vf.value_labeled(0.0, "target");
+            let graph = (&model.call(&inputs)[0] - &target).powi(2).compile().unwrap();
+            let mut losses = Vec::new();
+            for _ in 0..3 {
+                for (x, t) in samples {
+                    opt.zero_grad();
+                    let loss = if compiled {
+                        graph.bind_named(&[("a", x[0]), ("b", x[1]), ("target", t)]).unwrap();
+                        graph.refresh();
+                        graph.backward();
+                        graph.root().get_data()
+                    } else {
+                        let loss = (&model.call(&vf.values(&x))[0] - t).powi(2);
+                        loss.backward();
+                        loss.get_data()
+                    };
+                    opt.step();
+                    losses.push(loss);
+                }
+            }
+            losses
+        };
+        assert_eq!(train(true), train(false));
+    }
+}
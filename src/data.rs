@@ -0,0 +1,885 @@
+use std::fmt;
+use std::f64::consts::PI;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, io, path::Path};
+use crate::Rng;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataError {
+    LengthMismatch { xs: usize, ys: usize },
+    InvalidArgument(String),
+    // IDX files
+    BadMagic { expected: u32, found: u32 },
+    Truncated { expected: usize, found: usize },
+    Io(String),
+    // text formats, line is 1-based
+    Parse { line: usize, message: String }
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::LengthMismatch { xs, ys } => write!(f, "xs has {} samples but ys has {}", xs, ys),
+            DataError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            DataError::BadMagic { expected, found } => write!(f, "bad magic number {:#010x}, expected {:#010x}", found, expected),
+            DataError::Truncated { expected, found } => write!(f, "file is truncated: expected {} bytes, found {}", expected, found),
+            DataError::Io(msg) => write!(f, "io error: {}", msg),
+            DataError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+type Batch<'a> = (Vec<&'a [f64]>, Vec<&'a [f64]>);
+
+// Iterates over one epoch of (batch_x, batch_y) pairs. Call next_epoch() to rewind, which also reshuffles
+// when shuffling is on. The shuffle order only depends on the seed and the number of epochs so far.
+pub struct BatchIter<'a> {
+    xs: &'a [Vec<f64>],
+    ys: &'a [Vec<f64>],
+    batch_size: usize,
+    shuffle: bool,
+    drop_last: bool,
+    rng: Rng,
+    order: Vec<usize>,
+    pos: usize
+}
+
+impl<'a> BatchIter<'a> {
+    pub fn new(xs: &'a [Vec<f64>], ys: &'a [Vec<f64>], batch_size: usize, shuffle: bool, seed: u64) -> Result<BatchIter<'a>, DataError> {
+        BatchIter::new_with_rng(xs, ys, batch_size, shuffle, Rng::new(seed))
+    }
+
+    pub fn new_with_rng(xs: &'a [Vec<f64>], ys: &'a [Vec<f64>], batch_size: usize, shuffle: bool, rng: Rng) -> Result<BatchIter<'a>, DataError> {
+        if xs.len() != ys.len() {
+            return Err(DataError::LengthMismatch { xs: xs.len(), ys: ys.len() });
+        }
+        if batch_size == 0 {
+            return Err(DataError::InvalidArgument(String::from("batch_size must be positive")));
+        }
+        let mut iter = BatchIter {
+            xs,
+            ys,
+            batch_size,
+            shuffle,
+            drop_last: false,
+            rng,
+            order: (0..xs.len()).collect(),
+            pos: 0
+        };
+        iter.next_epoch();
+        Ok(iter)
+    }
+
+    // Skip the final batch of an epoch when it is smaller than batch_size
+    pub fn set_drop_last(&mut self, drop_last: bool) {
+        self.drop_last = drop_last;
+    }
+
+    pub fn next_epoch(&mut self) {
+        if self.shuffle {
+            self.rng.shuffle(&mut self.order);
+        }
+        self.pos = 0;
+    }
+
+    // Dataset indices of the next batch, advancing the iterator like next() does
+    pub fn next_indices(&mut self) -> Option<&[usize]> {
+        let remaining = self.order.len() - self.pos;
+        if remaining == 0 || (self.drop_last && remaining < self.batch_size) {
+            return None;
+        }
+        let start = self.pos;
+        self.pos += remaining.min(self.batch_size);
+        Some(&self.order[start..self.pos])
+    }
+
+    pub fn num_batches(&self) -> usize {
+        if self.drop_last {
+            self.order.len() / self.batch_size
+        } else {
+            self.order.len().div_ceil(self.batch_size)
+        }
+    }
+}
+
+impl<'a> Iterator for BatchIter<'a> {
+    type Item = Batch<'a>;
+
+    fn next(&mut self) -> Option<Batch<'a>> {
+        let (xs, ys) = (self.xs, self.ys);
+        self.next_indices().map(|idx| {
+            (idx.iter().map(|&i| xs[i].as_slice()).collect(), idx.iter().map(|&i| ys[i].as_slice()).collect())
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dataset {
+    pub xs: Vec<Vec<f64>>,
+    pub ys: Vec<Vec<f64>>
+}
+
+impl Dataset {
+    pub fn len(&self) -> usize {
+        self.xs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.xs.is_empty()
+    }
+
+    pub fn from_indices(xs: &[Vec<f64>], ys: &[Vec<f64>], indices: &[usize]) -> Dataset {
+        Dataset {
+            xs: indices.iter().map(|&i| xs[i].clone()).collect(),
+            ys: indices.iter().map(|&i| ys[i].clone()).collect()
+        }
+    }
+}
+
+fn check_split_args(xs: &[Vec<f64>], ys: &[Vec<f64>], val_fraction: f64) -> Result<(), DataError> {
+    if xs.len() != ys.len() {
+        return Err(DataError::LengthMismatch { xs: xs.len(), ys: ys.len() });
+    }
+    if !(val_fraction > 0.0 && val_fraction < 1.0) {
+        return Err(DataError::InvalidArgument(format!("val_fraction must be in (0, 1), got {}", val_fraction)));
+    }
+    Ok(())
+}
+
+// Seeded shuffled split into (train, validation). The validation set gets round(val_fraction * n) samples,
+// and it is an error for either side to end up empty.
+pub fn train_val_split(xs: &[Vec<f64>], ys: &[Vec<f64>], val_fraction: f64, seed: u64) -> Result<(Dataset, Dataset), DataError> {
+    check_split_args(xs, ys, val_fraction)?;
+    let n_val = (val_fraction * xs.len() as f64).round() as usize;
+    if n_val == 0 || n_val == xs.len() {
+        return Err(DataError::InvalidArgument(format!("val_fraction {} leaves an empty split of {} samples", val_fraction, xs.len())));
+    }
+    let mut order: Vec<usize> = (0..xs.len()).collect();
+    Rng::new(seed).shuffle(&mut order);
+    let (val, train) = order.split_at(n_val);
+    Ok((Dataset::from_indices(xs, ys, train), Dataset::from_indices(xs, ys, val)))
+}
+
+// Like train_val_split but splits every class separately so both sides keep the class proportions.
+// Each class contributes round(val_fraction * class_size) samples to the validation set.
+pub fn train_val_split_stratified(
+    xs: &[Vec<f64>],
+    ys: &[Vec<f64>],
+    labels: &[usize],
+    val_fraction: f64,
+    seed: u64
+) -> Result<(Dataset, Dataset), DataError> {
+    check_split_args(xs, ys, val_fraction)?;
+    if labels.len() != xs.len() {
+        return Err(DataError::LengthMismatch { xs: xs.len(), ys: labels.len() });
+    }
+    let mut rng = Rng::new(seed);
+    let n_classes = labels.iter().max().map_or(0, |m| m + 1);
+    let (mut train, mut val) = (Vec::new(), Vec::new());
+    for class in 0..n_classes {
+        let mut members: Vec<usize> = (0..labels.len()).filter(|&i| labels[i] == class).collect();
+        rng.shuffle(&mut members);
+        let n_val = (val_fraction * members.len() as f64).round() as usize;
+        val.extend_from_slice(&members[..n_val]);
+        train.extend_from_slice(&members[n_val..]);
+    }
+    if train.is_empty() || val.is_empty() {
+        return Err(DataError::InvalidArgument(format!("val_fraction {} leaves an empty split of {} samples", val_fraction, xs.len())));
+    }
+    Ok((Dataset::from_indices(xs, ys, &train), Dataset::from_indices(xs, ys, &val)))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fold {
+    pub train: Vec<usize>,
+    pub val: Vec<usize>
+}
+
+// Splits 0..n_samples into k shuffled folds. Every index is in exactly one validation set; the first
+// n_samples % k folds get one extra validation sample.
+pub fn kfold(n_samples: usize, k: usize, seed: u64) -> Result<Vec<Fold>, DataError> {
+    if k < 2 || k > n_samples {
+        return Err(DataError::InvalidArgument(format!("k must be in [2, {}], got {}", n_samples, k)));
+    }
+    let mut order: Vec<usize> = (0..n_samples).collect();
+    Rng::new(seed).shuffle(&mut order);
+    let (base, extra) = (n_samples / k, n_samples % k);
+    let mut start = 0;
+    Ok((0..k).map(|fold| {
+        let end = start + base + if fold < extra {1} else {0};
+        let val = order[start..end].to_vec();
+        let train = order[..start].iter().chain(&order[end..]).copied().collect();
+        start = end;
+        Fold { train, val }
+    }).collect())
+}
+
+fn linspace(start: f64, end: f64, n: usize, endpoint: bool) -> impl Iterator<Item = f64> {
+    let steps = if endpoint { n.saturating_sub(1).max(1) } else { n.max(1) };
+    (0..n).map(move |i| start + (end - start) * i as f64 / steps as f64)
+}
+
+fn add_noise(points: &mut [Vec<f64>], noise: f64, rng: &mut Rng) {
+    points.iter_mut().flatten().for_each(|v| *v += noise * rng.normal());
+}
+
+// Two interleaving half circles, as in the micrograd demo. The first n / 2 samples are the upper moon
+// (label 0), the rest the lower moon (label 1). noise is the std of the gaussian noise added to each
+// coordinate.
+pub fn make_moons(n: usize, noise: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let mut rng = Rng::new(seed);
+    let n_upper = n / 2;
+    let mut xs: Vec<Vec<f64>> = linspace(0.0, PI, n_upper, true)
+        .map(|t| vec![t.cos(), t.sin()])
+        .chain(linspace(0.0, PI, n - n_upper, true).map(|t| vec![1.0 - t.cos(), 0.5 - t.sin()]))
+        .collect();
+    add_noise(&mut xs, noise, &mut rng);
+    let labels = (0..n).map(|i| if i < n_upper {0} else {1}).collect();
+    (xs, labels)
+}
+
+// Two concentric circles: the outer one of radius 1 (label 0) and the inner one of radius factor (label 1)
+pub fn make_circles(n: usize, noise: f64, factor: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let mut rng = Rng::new(seed);
+    let n_outer = n / 2;
+    let mut xs: Vec<Vec<f64>> = linspace(0.0, 2.0 * PI, n_outer, false)
+        .map(|t| vec![t.cos(), t.sin()])
+        .chain(linspace(0.0, 2.0 * PI, n - n_outer, false).map(|t| vec![factor * t.cos(), factor * t.sin()]))
+        .collect();
+    add_noise(&mut xs, noise, &mut rng);
+    let labels = (0..n).map(|i| if i < n_outer {0} else {1}).collect();
+    (xs, labels)
+}
+
+// Interleaved spiral arms (the cs231n dataset). Arm j has n_per_class points at radius r in [0, 1] and angle
+// 4 * (j + r), with gaussian noise of std noise added to the angle.
+pub fn make_spiral(classes: usize, n_per_class: usize, noise: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let mut rng = Rng::new(seed);
+    let mut xs = Vec::with_capacity(classes * n_per_class);
+    let mut labels = Vec::with_capacity(classes * n_per_class);
+    for class in 0..classes {
+        for r in linspace(0.0, 1.0, n_per_class, true) {
+            let t = 4.0 * (class as f64 + r) + noise * rng.normal();
+            xs.push(vec![r * t.sin(), r * t.cos()]);
+            labels.push(class);
+        }
+    }
+    (xs, labels)
+}
+
+// Isotropic gaussian clusters around the given centers. Sample i belongs to center i % centers.len().
+pub fn make_blobs(n: usize, centers: &[Vec<f64>], cluster_std: f64, seed: u64) -> (Vec<Vec<f64>>, Vec<usize>) {
+    let mut rng = Rng::new(seed);
+    let labels: Vec<usize> = (0..n).map(|i| i % centers.len()).collect();
+    let mut xs: Vec<Vec<f64>> = labels.iter().map(|&c| centers[c].clone()).collect();
+    add_noise(&mut xs, cluster_std, &mut rng);
+    (xs, labels)
+}
+
+// Per-feature scaling to zero mean and unit variance. Fit it on the training inputs and keep it with the
+// model (it serializes like MlpSpec) so inference applies the same transform. Features with zero variance
+// keep a std of 1, so they are only centered.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Standardizer {
+    pub mean: Vec<f64>,
+    pub std: Vec<f64>
+}
+
+impl Standardizer {
+    pub fn fit(xs: &[Vec<f64>]) -> Standardizer {
+        let width = fit_width(xs);
+        let n = xs.len() as f64;
+        let mean: Vec<f64> = (0..width).map(|j| xs.iter().map(|x| x[j]).sum::<f64>() / n).collect();
+        let std = (0..width).map(|j| {
+            let var = xs.iter().map(|x| (x[j] - mean[j]).powi(2)).sum::<f64>() / n;
+            if var > 0.0 {var.sqrt()} else {1.0}
+        }).collect();
+        Standardizer { mean, std }
+    }
+
+    pub fn transform_one(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.mean.len(), "sample has {} features, the standardizer was fitted on {}", x.len(), self.mean.len());
+        x.iter().zip(self.mean.iter().zip(&self.std)).map(|(v, (m, s))| (v - m) / s).collect()
+    }
+
+    pub fn transform(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        xs.iter().map(|x| self.transform_one(x)).collect()
+    }
+
+    pub fn inverse_transform_one(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.mean.len(), "sample has {} features, the standardizer was fitted on {}", x.len(), self.mean.len());
+        x.iter().zip(self.mean.iter().zip(&self.std)).map(|(v, (m, s))| v * s + m).collect()
+    }
+
+    pub fn inverse_transform(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        xs.iter().map(|x| self.inverse_transform_one(x)).collect()
+    }
+}
+
+// Per-feature scaling of the training range to [0, 1]. Constant features keep a range of 1 and map to 0.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MinMaxScaler {
+    pub min: Vec<f64>,
+    pub range: Vec<f64>
+}
+
+impl MinMaxScaler {
+    pub fn fit(xs: &[Vec<f64>]) -> MinMaxScaler {
+        let width = fit_width(xs);
+        let min: Vec<f64> = (0..width).map(|j| xs.iter().map(|x| x[j]).fold(f64::INFINITY, f64::min)).collect();
+        let range = (0..width).map(|j| {
+            let range = xs.iter().map(|x| x[j]).fold(f64::NEG_INFINITY, f64::max) - min[j];
+            if range > 0.0 {range} else {1.0}
+        }).collect();
+        MinMaxScaler { min, range }
+    }
+
+    pub fn transform_one(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.min.len(), "sample has {} features, the scaler was fitted on {}", x.len(), self.min.len());
+        x.iter().zip(self.min.iter().zip(&self.range)).map(|(v, (m, r))| (v - m) / r).collect()
+    }
+
+    pub fn transform(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        xs.iter().map(|x| self.transform_one(x)).collect()
+    }
+
+    pub fn inverse_transform_one(&self, x: &[f64]) -> Vec<f64> {
+        assert_eq!(x.len(), self.min.len(), "sample has {} features, the scaler was fitted on {}", x.len(), self.min.len());
+        x.iter().zip(self.min.iter().zip(&self.range)).map(|(v, (m, r))| v * r + m).collect()
+    }
+
+    pub fn inverse_transform(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        xs.iter().map(|x| self.inverse_transform_one(x)).collect()
+    }
+}
+
+fn fit_width(xs: &[Vec<f64>]) -> usize {
+    assert!(!xs.is_empty(), "cannot fit a scaler on an empty dataset");
+    check_width(xs, "input").unwrap_or_else(|e| panic!("{}", e))
+}
+
+// Row i is all zeros except a 1 at labels[i], e.g. as targets for a classifier with n_classes outputs
+pub fn one_hot_encode(labels: &[usize], n_classes: usize) -> Vec<Vec<f64>> {
+    labels.iter().map(|&l| {
+        assert!(l < n_classes, "label {} is out of range for {} classes", l, n_classes);
+        let mut row = vec![0.0; n_classes];
+        row[l] = 1.0;
+        row
+    }).collect()
+}
+
+// Rows must all have the width of the first row
+fn check_width(rows: &[Vec<f64>], what: &str) -> Result<usize, DataError> {
+    let width = rows.first().map_or(0, |r| r.len());
+    match rows.iter().position(|r| r.len() != width) {
+        Some(i) => Err(DataError::InvalidArgument(format!("{} row {} has {} values, expected {}", what, i, rows[i].len(), width))),
+        None => Ok(width)
+    }
+}
+
+// One row per sample: x0..xn, pred0..pm, then target0..tm when targets are given. precision is the
+// number of decimals, None writes each value in full.
+pub fn predictions_to_csv_string(
+    inputs: &[Vec<f64>],
+    predictions: &[Vec<f64>],
+    targets: Option<&[Vec<f64>]>,
+    precision: Option<usize>
+) -> Result<String, DataError> {
+    if inputs.len() != predictions.len() {
+        return Err(DataError::LengthMismatch { xs: inputs.len(), ys: predictions.len() });
+    }
+    if let Some(targets) = targets {
+        if targets.len() != predictions.len() {
+            return Err(DataError::InvalidArgument(format!("{} predictions but {} targets", predictions.len(), targets.len())));
+        }
+    }
+    let x_width = check_width(inputs, "input")?;
+    let pred_width = check_width(predictions, "prediction")?;
+    let target_width = targets.map(|t| check_width(t, "target")).transpose()?;
+
+    let mut header: Vec<String> = (0..x_width).map(|i| format!("x{}", i))
+        .chain((0..pred_width).map(|i| format!("pred{}", i)))
+        .collect();
+    header.extend((0..target_width.unwrap_or(0)).map(|i| format!("target{}", i)));
+    let fmt_value = |v: &f64| match precision {
+        Some(p) => format!("{:.*}", p, v),
+        None => v.to_string()
+    };
+
+    let mut csv = header.join(",");
+    csv.push('\n');
+    for (i, (x, pred)) in inputs.iter().zip(predictions).enumerate() {
+        let target = targets.map_or(&[][..], |t| &t[i][..]);
+        let row: Vec<String> = x.iter().chain(pred).chain(target).map(fmt_value).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    Ok(csv)
+}
+
+// Writes predictions_to_csv_string to path. Mismatched lengths are reported as InvalidInput before the
+// file is touched.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export_predictions(
+    path: impl AsRef<Path>,
+    inputs: &[Vec<f64>],
+    predictions: &[Vec<f64>],
+    targets: Option<&[Vec<f64>]>
+) -> io::Result<()> {
+    export_predictions_with_precision(path, inputs, predictions, targets, None)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn export_predictions_with_precision(
+    path: impl AsRef<Path>,
+    inputs: &[Vec<f64>],
+    predictions: &[Vec<f64>],
+    targets: Option<&[Vec<f64>]>,
+    precision: Option<usize>
+) -> io::Result<()> {
+    let csv = predictions_to_csv_string(inputs, predictions, targets, precision)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    fs::write(path, csv)
+}
+
+// IDX magic numbers: two zero bytes, the element type (0x08 = unsigned byte) and the number of dimensions
+const IDX_IMAGES_MAGIC: u32 = 0x0000_0803;
+const IDX_LABELS_MAGIC: u32 = 0x0000_0801;
+
+// Checks the magic number and returns the big-endian dimensions plus exactly the data they describe
+fn parse_idx(bytes: &[u8], magic: u32) -> Result<(Vec<usize>, &[u8]), DataError> {
+    let ndims = (magic & 0xff) as usize;
+    let header_len = 4 * (1 + ndims);
+    let word = |i: usize| u32::from_be_bytes([bytes[4 * i], bytes[4 * i + 1], bytes[4 * i + 2], bytes[4 * i + 3]]);
+    if bytes.len() < 4 {
+        return Err(DataError::Truncated { expected: header_len, found: bytes.len() });
+    }
+    if word(0) != magic {
+        return Err(DataError::BadMagic { expected: magic, found: word(0) });
+    }
+    if bytes.len() < header_len {
+        return Err(DataError::Truncated { expected: header_len, found: bytes.len() });
+    }
+    let dims: Vec<usize> = (1..=ndims).map(|i| word(i) as usize).collect();
+    let data_len = dims.iter()
+        .try_fold(1usize, |acc, d| acc.checked_mul(*d))
+        .ok_or_else(|| DataError::InvalidArgument(format!("IDX dimensions {:?} are too large", dims)))?;
+    if bytes.len() - header_len < data_len {
+        return Err(DataError::Truncated { expected: header_len + data_len, found: bytes.len() });
+    }
+    Ok((dims, &bytes[header_len..header_len + data_len]))
+}
+
+// Images of an idx3-ubyte file (e.g. MNIST train-images-idx3-ubyte), flattened row by row with pixels
+// scaled to [0, 1]
+pub fn parse_idx_images(bytes: &[u8]) -> Result<Vec<Vec<f64>>, DataError> {
+    let (dims, data) = parse_idx(bytes, IDX_IMAGES_MAGIC)?;
+    let size = dims[1] * dims[2];
+    if size == 0 {
+        return Ok(vec![Vec::new(); dims[0]]);
+    }
+    Ok(data.chunks(size).map(|img| img.iter().map(|&p| p as f64 / 255.0).collect()).collect())
+}
+
+pub fn parse_idx_labels(bytes: &[u8]) -> Result<Vec<usize>, DataError> {
+    let (_, data) = parse_idx(bytes, IDX_LABELS_MAGIC)?;
+    Ok(data.iter().map(|&l| l as usize).collect())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_idx_images(path: impl AsRef<Path>) -> Result<Vec<Vec<f64>>, DataError> {
+    parse_idx_images(&fs::read(path).map_err(|e| DataError::Io(e.to_string()))?)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_idx_labels(path: impl AsRef<Path>) -> Result<Vec<usize>, DataError> {
+    parse_idx_labels(&fs::read(path).map_err(|e| DataError::Io(e.to_string()))?)
+}
+
+// Average pooling of square images over factor x factor blocks, e.g. factor 4 turns 28x28 MNIST digits
+// into 7x7 ones that the scalar engine can train on
+pub fn downscale(images: &[Vec<f64>], factor: usize) -> Result<Vec<Vec<f64>>, DataError> {
+    if factor == 0 {
+        return Err(DataError::InvalidArgument(String::from("factor must be positive")));
+    }
+    images.iter().map(|img| {
+        let side = (img.len() as f64).sqrt().round() as usize;
+        if side * side != img.len() {
+            return Err(DataError::InvalidArgument(format!("image of {} pixels is not square", img.len())));
+        }
+        if !side.is_multiple_of(factor) {
+            return Err(DataError::InvalidArgument(format!("side {} is not a multiple of factor {}", side, factor)));
+        }
+        let out_side = side / factor;
+        Ok((0..out_side * out_side).map(|i| {
+            let (row, col) = (i / out_side * factor, i % out_side * factor);
+            let sum: f64 = (row..row + factor).flat_map(|r| (col..col + factor).map(move |c| img[r * side + c])).sum();
+            sum / (factor * factor) as f64
+        }).collect())
+    }).collect()
+}
+
+// svmlight / libsvm text: one "label idx:val idx:val ..." sample per line with 1-based feature indices.
+// Missing features are 0, '#' starts a comment and qid:n tokens are ignored. Without n_features the width
+// is the largest index seen.
+pub fn parse_svmlight(src: &str, n_features: Option<usize>) -> Result<(Vec<Vec<f64>>, Vec<f64>), DataError> {
+    let mut rows: Vec<Vec<(usize, f64)>> = Vec::new();
+    let mut labels = Vec::new();
+    let mut width = 0;
+    for (i, line) in src.lines().enumerate() {
+        let err = |message: String| DataError::Parse { line: i + 1, message };
+        let mut tokens = line.split('#').next().unwrap().split_whitespace();
+        let Some(label) = tokens.next() else { continue };
+        labels.push(label.parse::<f64>().map_err(|_| err(format!("invalid label {:?}", label)))?);
+        let row = tokens.filter(|t| !t.starts_with("qid:")).map(|t| {
+            let (idx, val) = t.split_once(':').ok_or_else(|| err(format!("expected idx:val, got {:?}", t)))?;
+            let idx: usize = idx.parse().map_err(|_| err(format!("invalid feature index in {:?}", t)))?;
+            let val: f64 = val.parse().map_err(|_| err(format!("invalid feature value in {:?}", t)))?;
+            if idx == 0 {
+                return Err(err(String::from("feature indices are 1-based")));
+            }
+            if let Some(n) = n_features.filter(|&n| idx > n) {
+                return Err(err(format!("feature index {} exceeds n_features {}", idx, n)));
+            }
+            width = width.max(idx);
+            Ok((idx - 1, val))
+        }).collect::<Result<Vec<_>, DataError>>()?;
+        rows.push(row);
+    }
+    let width = n_features.unwrap_or(width);
+    let xs = rows.into_iter().map(|row| {
+        let mut x = vec![0.0; width];
+        row.into_iter().for_each(|(idx, val)| x[idx] = val);
+        x
+    }).collect();
+    Ok((xs, labels))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_svmlight(path: impl AsRef<Path>, n_features: Option<usize>) -> Result<(Vec<Vec<f64>>, Vec<f64>), DataError> {
+    parse_svmlight(&fs::read_to_string(path).map_err(|e| DataError::Io(e.to_string()))?, n_features)
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(n: usize) -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let xs = (0..n).map(|i| vec![i as f64, -(i as f64)]).collect();
+        let ys = (0..n).map(|i| vec![i as f64 * 10.0]).collect();
+        (xs, ys)
+    }
+
+    fn epoch_order(iter: &mut BatchIter) -> Vec<f64> {
+        iter.flat_map(|(bx, _)| bx.into_iter().map(|x| x[0]).collect::<Vec<_>>()).collect()
+    }
+
+    #[test]
+    fn every_sample_once_per_epoch() {
+        let (xs, ys) = dataset(10);
+        let mut iter = BatchIter::new(&xs, &ys, 3, true, 7).unwrap();
+        for _ in 0..3 {
+            let mut seen = epoch_order(&mut iter);
+            seen.sort_by(f64::total_cmp);
+            assert_eq!(seen, (0..10).map(|i| i as f64).collect::<Vec<_>>());
+            iter.next_epoch();
+        }
+
+        // x and y stay paired
+        let mut iter = BatchIter::new(&xs, &ys, 4, true, 7).unwrap();
+        iter.by_ref().for_each(|(bx, by)| bx.iter().zip(by).for_each(|(x, y)| assert_eq!(x[0] * 10.0, y[0])));
+    }
+
+    #[test]
+    fn shuffle_is_reproducible() {
+        let (xs, ys) = dataset(20);
+        let unshuffled = epoch_order(&mut BatchIter::new(&xs, &ys, 5, false, 7).unwrap());
+        assert_eq!(unshuffled, (0..20).map(|i| i as f64).collect::<Vec<_>>());
+
+        let mut a = BatchIter::new(&xs, &ys, 5, true, 7).unwrap();
+        let mut b = BatchIter::new(&xs, &ys, 5, true, 7).unwrap();
+        let first = epoch_order(&mut a);
+        assert_eq!(first, epoch_order(&mut b));
+        assert_ne!(first, unshuffled);
+
+        // reshuffled between epochs
+        a.next_epoch();
+        b.next_epoch();
+        let second = epoch_order(&mut a);
+        assert_eq!(second, epoch_order(&mut b));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn partial_batches() {
+        let (xs, ys) = dataset(10);
+        let mut iter = BatchIter::new(&xs, &ys, 4, false, 0).unwrap();
+        assert_eq!(iter.num_batches(), 3);
+        let sizes: Vec<usize> = iter.by_ref().map(|(bx, _)| bx.len()).collect();
+        assert_eq!(sizes, vec![4, 4, 2]);
+
+        iter.set_drop_last(true);
+        iter.next_epoch();
+        assert_eq!(iter.num_batches(), 2);
+        let sizes: Vec<usize> = iter.map(|(bx, _)| bx.len()).collect();
+        assert_eq!(sizes, vec![4, 4]);
+    }
+
+    #[test]
+    fn invalid_input() {
+        let (xs, ys) = dataset(10);
+        assert_eq!(BatchIter::new(&xs, &ys[..9], 4, false, 0).err(), Some(DataError::LengthMismatch { xs: 10, ys: 9 }));
+        assert!(matches!(BatchIter::new(&xs, &ys, 0, false, 0), Err(DataError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn split_sizes_and_disjointness() {
+        let (xs, ys) = dataset(10);
+        let (train, val) = train_val_split(&xs, &ys, 0.25, 3).unwrap();
+        // round(2.5) = 3
+        assert_eq!((train.len(), val.len()), (7, 3));
+        let mut all: Vec<f64> = train.xs.iter().chain(&val.xs).map(|x| x[0]).collect();
+        all.sort_by(f64::total_cmp);
+        assert_eq!(all, (0..10).map(|i| i as f64).collect::<Vec<_>>());
+        train.xs.iter().zip(&train.ys).for_each(|(x, y)| assert_eq!(x[0] * 10.0, y[0]));
+
+        // same seed, same split
+        assert_eq!(train_val_split(&xs, &ys, 0.25, 3).unwrap(), (train.clone(), val.clone()));
+        assert_ne!(train_val_split(&xs, &ys, 0.25, 4).unwrap().1, val);
+    }
+
+    #[test]
+    fn split_degenerate_fractions() {
+        let (xs, ys) = dataset(10);
+        assert!(matches!(train_val_split(&xs, &ys, 0.0, 0), Err(DataError::InvalidArgument(_))));
+        assert!(matches!(train_val_split(&xs, &ys, 1.0, 0), Err(DataError::InvalidArgument(_))));
+        assert!(matches!(train_val_split(&xs, &ys, 0.01, 0), Err(DataError::InvalidArgument(_))));
+        assert!(matches!(train_val_split(&xs, &ys[1..], 0.5, 0), Err(DataError::LengthMismatch { .. })));
+    }
+
+    #[test]
+    fn stratified_split_keeps_ratios() {
+        let (xs, ys) = dataset(40);
+        // 30 of class 0, 10 of class 1
+        let labels: Vec<usize> = (0..40).map(|i| if i % 4 == 0 {1} else {0}).collect();
+        let (train, val) = train_val_split_stratified(&xs, &ys, &labels, 0.3, 5).unwrap();
+        assert_eq!(train.len() + val.len(), 40);
+        let class_of = |x: &Vec<f64>| labels[x[0] as usize];
+        let val_ones = val.xs.iter().filter(|x| class_of(x) == 1).count();
+        let val_zeros = val.xs.len() - val_ones;
+        assert!((val_ones as i64 - 3).abs() <= 1);
+        assert!((val_zeros as i64 - 9).abs() <= 1);
+        assert!(val.xs.iter().all(|x| !train.xs.contains(x)));
+    }
+
+    #[test]
+    fn kfold_partitions() {
+        let folds = kfold(11, 3, 2).unwrap();
+        assert_eq!(folds.len(), 3);
+        assert_eq!(folds.iter().map(|f| f.val.len()).collect::<Vec<_>>(), vec![4, 4, 3]);
+        let mut all_val: Vec<usize> = folds.iter().flat_map(|f| f.val.clone()).collect();
+        all_val.sort();
+        assert_eq!(all_val, (0..11).collect::<Vec<_>>());
+        folds.iter().for_each(|f| {
+            assert_eq!(f.train.len() + f.val.len(), 11);
+            assert!(f.val.iter().all(|i| !f.train.contains(i)));
+        });
+        assert_eq!(kfold(11, 3, 2).unwrap(), folds);
+
+        assert!(matches!(kfold(3, 4, 0), Err(DataError::InvalidArgument(_))));
+        assert!(matches!(kfold(3, 1, 0), Err(DataError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn generator_shapes() {
+        let (xs, labels) = make_moons(101, 0.1, 3);
+        assert_eq!((xs.len(), labels.len()), (101, 101));
+        assert_eq!(labels.iter().filter(|&&l| l == 0).count(), 50);
+        assert!(xs.iter().all(|x| x.len() == 2));
+
+        let (xs, labels) = make_circles(40, 0.05, 0.5, 3);
+        assert_eq!(xs.len(), 40);
+        assert!(labels.iter().all(|&l| l < 2));
+
+        let (xs, labels) = make_spiral(3, 25, 0.2, 3);
+        assert_eq!(xs.len(), 75);
+        assert_eq!(*labels.iter().max().unwrap(), 2);
+
+        let centers = vec![vec![0.0, 0.0, 0.0], vec![5.0, 5.0, 5.0]];
+        let (xs, labels) = make_blobs(30, &centers, 1.0, 3);
+        assert!(xs.iter().all(|x| x.len() == 3));
+        assert_eq!(labels.iter().filter(|&&l| l == 1).count(), 15);
+
+        // same seed, same data
+        assert_eq!(make_moons(50, 0.2, 9), make_moons(50, 0.2, 9));
+        assert_eq!(make_spiral(2, 20, 0.3, 9), make_spiral(2, 20, 0.3, 9));
+        assert_ne!(make_moons(50, 0.2, 9), make_moons(50, 0.2, 10));
+    }
+
+    #[test]
+    fn generators_without_noise() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+
+        let (xs, _) = make_moons(20, 0.0, 1);
+        assert!(xs[..10].iter().all(|x| close(x[0].powi(2) + x[1].powi(2), 1.0) && x[1] >= 0.0));
+        assert!(xs[10..].iter().all(|x| close((x[0] - 1.0).powi(2) + (x[1] - 0.5).powi(2), 1.0) && x[1] <= 0.5));
+        assert!(close(xs[0][0], 1.0) && close(xs[9][0], -1.0));
+
+        let (xs, _) = make_circles(20, 0.0, 0.3, 1);
+        assert!(xs[..10].iter().all(|x| close(x[0].hypot(x[1]), 1.0)));
+        assert!(xs[10..].iter().all(|x| close(x[0].hypot(x[1]), 0.3)));
+
+        let (xs, labels) = make_spiral(2, 5, 0.0, 1);
+        // last point of arm 1: r = 1, t = 8
+        assert_eq!(labels[9], 1);
+        assert!(close(xs[9][0], 8.0_f64.sin()) && close(xs[9][1], 8.0_f64.cos()));
+        assert!(close(xs[0][0], 0.0) && close(xs[0][1], 0.0));
+
+        let centers = vec![vec![1.0, 2.0], vec![-3.0, 4.0]];
+        let (xs, _) = make_blobs(4, &centers, 0.0, 1);
+        assert_eq!(xs, vec![centers[0].clone(), centers[1].clone(), centers[0].clone(), centers[1].clone()]);
+    }
+
+    #[test]
+    fn prediction_export() {
+        let inputs = vec![vec![0.5, -1.0], vec![2.0, 0.25]];
+        let predictions = vec![vec![0.3], vec![-0.75]];
+        let targets = vec![vec![0.0], vec![-1.0]];
+        let path = std::env::temp_dir().join(format!("rust_micrograd_predictions_{}.csv", std::process::id()));
+
+        export_predictions(&path, &inputs, &predictions, Some(&targets)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x0,x1,pred0,target0\n0.5,-1,0.3,0\n2,0.25,-0.75,-1\n");
+        export_predictions_with_precision(&path, &inputs, &predictions, None, Some(2)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x0,x1,pred0\n0.50,-1.00,0.30\n2.00,0.25,-0.75\n");
+        fs::remove_file(&path).unwrap();
+
+        // nothing is written when the lengths disagree
+        let err = export_predictions(&path, &inputs, &predictions[..1], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(export_predictions(&path, &inputs, &predictions, Some(&targets[..1])).is_err());
+        assert!(export_predictions(&path, &inputs, &[vec![1.0], vec![1.0, 2.0]], None).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn idx_fixture() {
+        let fixture = |name: &str| format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let images = load_idx_images(fixture("tiny-images-idx3-ubyte")).unwrap();
+        let labels = load_idx_labels(fixture("tiny-labels-idx1-ubyte")).unwrap();
+        assert_eq!(images.len(), 3);
+        assert!(images.iter().all(|img| img.len() == 16 && img.iter().all(|p| (0.0..=1.0).contains(p))));
+        // pixel (r, c) of image i is (16 i + 4 r + c) * 5
+        assert_eq!(images[1][2 * 4 + 3], (16 + 8 + 3) as f64 * 5.0 / 255.0);
+        assert_eq!(labels, vec![7, 2, 1]);
+        assert!(labels.iter().all(|&l| l < 10));
+    }
+
+    #[test]
+    fn idx_errors() {
+        let images = include_bytes!("../tests/fixtures/tiny-images-idx3-ubyte");
+        let labels = include_bytes!("../tests/fixtures/tiny-labels-idx1-ubyte");
+        assert_eq!(parse_idx_images(labels), Err(DataError::BadMagic { expected: 0x803, found: 0x801 }));
+        assert_eq!(parse_idx_labels(images), Err(DataError::BadMagic { expected: 0x801, found: 0x803 }));
+        assert_eq!(parse_idx_images(&images[..60]), Err(DataError::Truncated { expected: 64, found: 60 }));
+        assert_eq!(parse_idx_images(&images[..10]), Err(DataError::Truncated { expected: 16, found: 10 }));
+        assert_eq!(parse_idx_labels(&labels[..2]), Err(DataError::Truncated { expected: 8, found: 2 }));
+        assert!(matches!(load_idx_labels("does/not/exist"), Err(DataError::Io(_))));
+    }
+
+    #[test]
+    fn downscaling() {
+        let images = vec![(0..16).map(|p| p as f64).collect::<Vec<f64>>()];
+        assert_eq!(downscale(&images, 2).unwrap(), vec![vec![2.5, 4.5, 10.5, 12.5]]);
+        assert_eq!(downscale(&images, 4).unwrap(), vec![vec![7.5]]);
+        assert_eq!(downscale(&images, 1).unwrap(), images);
+        assert!(downscale(&images, 3).is_err());
+        assert!(downscale(&[images[0][..15].to_vec()], 1).is_err());
+    }
+
+    #[test]
+    fn svmlight() {
+        let path = format!("{}/tests/fixtures/tiny.svmlight", env!("CARGO_MANIFEST_DIR"));
+        let (xs, ys) = load_svmlight(&path, None).unwrap();
+        assert_eq!(ys, vec![1.0, -1.0, 1.0]);
+        assert_eq!(xs, vec![vec![0.5, 0.0, -1.25, 2.0], vec![0.0, 0.0, 0.0, 0.75], vec![1.0, 0.0, 0.001, 0.0]]);
+        // a wider explicit n_features pads with zeros, a narrower one is an error on the first offending line
+        assert!(load_svmlight(&path, Some(6)).unwrap().0.iter().all(|x| x.len() == 6));
+        assert!(matches!(load_svmlight(&path, Some(3)), Err(DataError::Parse { line: 2, .. })));
+
+        assert!(matches!(parse_svmlight("1 1:2\n0 1:2 3-4\n", None), Err(DataError::Parse { line: 2, .. })));
+        assert!(matches!(parse_svmlight("# header\n1 0:2\n", None), Err(DataError::Parse { line: 2, .. })));
+        assert!(matches!(parse_svmlight("abc 1:2\n", None), Err(DataError::Parse { line: 1, .. })));
+        assert!(matches!(parse_svmlight("1 2:x\n", None), Err(DataError::Parse { line: 1, .. })));
+    }
+
+    #[test]
+    fn scalers_round_trip() {
+        let xs = vec![vec![1.0, 5.0, 1000.0], vec![3.0, 5.0, -2000.0], vec![2.0, 5.0, 4000.0]];
+        let close = |a: &[Vec<f64>], b: &[Vec<f64>]| a.iter().flatten().zip(b.iter().flatten()).all(|(x, y)| (x - y).abs() < 1e-9);
+
+        let st = Standardizer::fit(&xs);
+        let t = st.transform(&xs);
+        assert!(close(&st.inverse_transform(&t), &xs));
+        for j in [0, 2] {
+            let mean = t.iter().map(|x| x[j]).sum::<f64>() / 3.0;
+            let var = t.iter().map(|x| x[j] * x[j]).sum::<f64>() / 3.0;
+            assert!(mean.abs() < 1e-12 && (var - 1.0).abs() < 1e-12);
+        }
+        // the constant feature is centered, not divided by zero
+        assert_eq!(st.std[1], 1.0);
+        assert!(t.iter().all(|x| x[1] == 0.0));
+
+        let mm = MinMaxScaler::fit(&xs);
+        let t = mm.transform(&xs);
+        assert_eq!(t[0], vec![0.0, 0.0, 0.5]);
+        assert_eq!(t[1], vec![1.0, 0.0, 0.0]);
+        assert!(close(&mm.inverse_transform(&t), &xs));
+        assert!(t.iter().flatten().all(|v| v.is_finite()));
+    }
+
+    #[test]
+    fn one_hot() {
+        assert_eq!(one_hot_encode(&[2, 0, 1], 3), vec![vec![0.0, 0.0, 1.0], vec![1.0, 0.0, 0.0], vec![0.0, 1.0, 0.0]]);
+        assert!(one_hot_encode(&[], 4).is_empty());
+        assert!(std::panic::catch_unwind(|| one_hot_encode(&[3], 3)).is_err());
+    }
+
+    #[test]
+    fn standardized_inputs_train() {
+        use crate::{Arena, Module, Optimizer, Sgd, Value, ValueFactory, MLP};
+
+        // the label only depends on the tiny second feature, the first one is large noise
+        let mut rng = Rng::new(3);
+        let xs: Vec<Vec<f64>> = (0..16).map(|i| vec![50.0 * rng.normal(), if i % 2 == 0 {0.002} else {-0.002} + 0.0005 * rng.normal()]).collect();
+        let ys: Vec<f64> = (0..16).map(|i| if i % 2 == 0 {1.0} else {-1.0}).collect();
+
+        let train = |xs: &[Vec<f64>]| {
+            let (_arena_life_time, arena_ref) = Arena::build();
+            let vf = ValueFactory::new(arena_ref);
+            let model = MLP::new_with_seed(&vf, 2, &[8, 1], 5);
+            let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.02);
+            let mut loss = 0.0;
+            for _ in 0..60 {
+                let total = xs.iter().zip(&ys).fold(vf.zero(), |acc, (x, y)| {
+                    let x: Vec<Value> = vf.values(x);
+                    &acc + &(&model.call(&x)[0] - *y).powi(2)
+                });
+                opt.zero_grad();
+                total.backward();
+                opt.step();
+                loss = total.get_data() / xs.len() as f64;
+            }
+            loss
+        };
+        let raw = train(&xs);
+        let standardized = train(&Standardizer::fit(&xs).transform(&xs));
+        assert!(standardized < 0.1, "standardized loss {}", standardized);
+        assert!(raw > 0.5, "raw loss {}", raw);
+    }
+}
@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use crate::{Arena, ArenaLifeTime, Value, ValueData, ValueFactory};
+
+// Opt-in shortcut for scripts and doctests. Each thread gets its own arena, built the first time it is
+// needed. Nothing allocated through it is freed before the thread exits or reset_default_arena is called;
+// use reset / truncate_to / compact on default_factory().arena() to bound it in a loop.
+thread_local! {
+    static DEFAULT_ARENA: RefCell<Option<(ArenaLifeTime<ValueData>, ValueFactory)>> = const { RefCell::new(None) };
+}
+
+pub fn default_factory() -> ValueFactory {
+    DEFAULT_ARENA.with(|default| {
+        default.borrow_mut()
+            .get_or_insert_with(|| {
+                let (life_time, arena_ref) = Arena::build();
+                (life_time, ValueFactory::new(arena_ref))
+            })
+            .1
+            .clone()
+    })
+}
+
+pub fn val(x: f64) -> Value {
+    default_factory().value(x)
+}
+
+pub fn vals(data: &[f64]) -> Vec<Value> {
+    default_factory().values(data)
+}
+
+// Drops this thread's default arena. Its Values stop being alive and panic when used, and so do factories
+// returned by default_factory before the reset. The next call builds a fresh arena.
+pub fn reset_default_arena() {
+    let old = DEFAULT_ARENA.with(|default| default.borrow_mut().take());
+    drop(old);
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_values() {
+        let a = val(2.0);
+        let bs = vals(&[3.0, -1.0]);
+        let y = &(&a * &bs[0]) + &(&bs[1] * 4.0);
+        y.backward();
+        assert_eq!(y.get_data(), 2.0);
+        assert_eq!((a.get_grad(), bs[0].get_grad()), (3.0, 2.0));
+        assert_eq!(default_factory().len(), 6);
+
+        // an explicit arena works alongside
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let c = &vf.value(1.5) * 2.0;
+        assert_eq!(c.get_data(), 3.0);
+        assert_eq!(default_factory().len(), 6);
+
+        reset_default_arena();
+        assert!(!a.is_alive() && !y.is_alive() && c.is_alive());
+        assert!(default_factory().is_empty());
+        assert_eq!((&val(1.0) + 1.0).get_data(), 2.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "DAG properties of autograd graph violated")]
+    fn used_after_reset() {
+        let a = val(2.0);
+        reset_default_arena();
+        a.get_data();
+    }
+
+    #[test]
+    fn per_thread_arenas() {
+        let _a = vals(&[1.0, 2.0, 3.0]);
+        let other = std::thread::spawn(|| {
+            let b = val(5.0);
+            (default_factory().len(), (&b * &b).get_data())
+        }).join().unwrap();
+        assert_eq!(other, (1, 25.0));
+        assert_eq!(default_factory().len(), 3);
+    }
+}
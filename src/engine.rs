@@ -1,124 +1,653 @@
 use core::fmt;
-use std::{collections::HashSet, ops};
-use std::hash::{Hash, Hasher};
-use std::rc::Weak;
-use std::cell::{RefCell};
-use crate::ArenaRef;
+use std::{cmp::Ordering, collections::HashSet, mem, ops};
+use std::rc::Rc;
+use crate::{ArenaHandle, ArenaLimitError, ArenaRef, ArenaView, Float, Rng};
+
+pub type Value = ValueG<f64>;
+pub type ValueData = ValueDataG<f64>;
+pub type ValueFactory = ValueFactoryG<f64>;
 
 // For the convenience of creating Values without having to manually clone the arena
-pub struct ValueFactory {
-    arena: ArenaRef<ValueData>
+#[derive(Clone)]
+pub struct ValueFactoryG<T: Float> {
+    arena: ArenaRef<ValueDataG<T>>
+}
+
+impl<T: Float> ValueFactoryG<T> {
+    pub fn new(arena: ArenaRef<ValueDataG<T>>) -> ValueFactoryG<T> {
+        ValueFactoryG { arena }
+    }
+
+    pub fn value(&self, data: T) -> ValueG<T> {
+        ValueG::build(self.arena.clone(), data)
+    }
+
+    // value and values, returning an error instead of panicking when the arena is at its set_max_nodes
+    pub fn try_value(&self, data: T) -> Result<ValueG<T>, ArenaLimitError> {
+        self.arena.try_alloc_with_mut_borrow(ValueDataG::new(data, T::zero(), &[], None)).map(|node| ValueG { node })
+    }
+
+    pub fn try_values(&self, data: &[T]) -> Result<Vec<ValueG<T>>, ArenaLimitError> {
+        let nodes = data.iter().map(|x| ValueDataG::new(*x, T::zero(), &[], None));
+        Ok(self.arena.try_alloc_many_with_mut_borrow(nodes)?.into_iter().map(|node| ValueG { node }).collect())
+    }
+
+    // Plain leaves, e.g. the start of a sum. Unlike constant these take a gradient and are not shared.
+    pub fn zero(&self) -> ValueG<T> {
+        self.value(T::zero())
+    }
+
+    pub fn one(&self) -> ValueG<T> {
+        self.value(T::one())
+    }
+
+    pub fn value_labeled(&self, data: T, label: &str) -> ValueG<T> {
+        let v = self.value(data);
+        v.set_label(label);
+        v
+    }
+
+    // Leaf marked as a trainable parameter (see NodeKind)
+    pub fn param(&self, data: T) -> ValueG<T> {
+        ValueG { node: self.arena.alloc_with_mut_borrow(ValueDataG { kind: NodeKind::Param, ..ValueDataG::new(data, T::zero(), &[], None) }) }
+    }
+
+    // Parameters drawn from rng, uniform in [lo, hi) or normal. The draws are f64, rounded for f32.
+    pub fn rand_uniform(&self, lo: f64, hi: f64, rng: &mut Rng) -> ValueG<T> {
+        self.param(T::from_f64(rng.uniform(lo, hi)))
+    }
+
+    pub fn rand_normal(&self, mean: f64, std: f64, rng: &mut Rng) -> ValueG<T> {
+        self.param(T::from_f64(mean + std * rng.normal()))
+    }
+
+    pub fn rand_uniform_vec(&self, n: usize, lo: f64, hi: f64, rng: &mut Rng) -> Vec<ValueG<T>> {
+        self.params((0..n).map(|_| T::from_f64(rng.uniform(lo, hi))))
+    }
+
+    pub fn rand_normal_vec(&self, n: usize, mean: f64, std: f64, rng: &mut Rng) -> Vec<ValueG<T>> {
+        self.params((0..n).map(|_| T::from_f64(mean + std * rng.normal())))
+    }
+
+    fn params(&self, data: impl ExactSizeIterator<Item = T>) -> Vec<ValueG<T>> {
+        let nodes = data.map(|x| ValueDataG { kind: NodeKind::Param, ..ValueDataG::new(x, T::zero(), &[], None) });
+        self.arena.alloc_many_with_mut_borrow(nodes).into_iter().map(|node| ValueG { node }).collect()
+    }
+
+    // vf.value for each element, allocated in one go
+    pub fn values(&self, data: &[T]) -> Vec<ValueG<T>> {
+        let nodes = data.iter().map(|x| ValueDataG::new(*x, T::zero(), &[], None));
+        self.arena.alloc_many_with_mut_borrow(nodes).into_iter().map(|node| ValueG { node }).collect()
+    }
+
+    // values with a label on each
+    pub fn values_labeled(&self, data: &[(T, &str)]) -> Vec<ValueG<T>> {
+        let nodes = data.iter().map(|(x, label)| ValueDataG { label: Some(label.to_string()), ..ValueDataG::new(*x, T::zero(), &[], None) });
+        self.arena.alloc_many_with_mut_borrow(nodes).into_iter().map(|node| ValueG { node }).collect()
+    }
+
+    // Leaf that takes no gradient, shared by every use of the same constant in this arena (NaN excepted).
+    // The scalar operands of the arithmetic operators don't need it: they are stored in the op.
+    pub fn constant(&self, x: T) -> ValueG<T> {
+        ValueG::constant(&self.arena, x)
+    }
+
+    // Forgets the shared constants; later uses allocate fresh ones. Freed constants are forgotten anyway.
+    pub fn clear_constants(&self) {
+        self.arena.clear_interned();
+    }
+
+    pub fn arena(&self) -> &ArenaRef<ValueDataG<T>> {
+        &self.arena
+    }
+
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.arena.live_count()
+    }
+
+    // Guards against a runaway graph, e.g. a loop that keeps building on last step's output. With
+    // Some(limit), any allocation that would take the arena beyond limit nodes panics, op nodes included;
+    // try_value and try_values return an error instead. Nodes freed by truncate_to, reset or compact no
+    // longer count. The limit belongs to the arena, so every factory on it shares it.
+    pub fn set_max_nodes(&self, limit: Option<usize>) {
+        self.arena.set_max_len(limit);
+    }
+
+    pub fn max_nodes(&self) -> Option<usize> {
+        self.arena.max_len()
+    }
+
+    // Nodes counted against set_max_nodes, the same as len
+    pub fn node_count(&self) -> usize {
+        self.arena.len()
+    }
+
+    // ArenaRef::approx_bytes plus what the nodes own on the heap: backward closures, operand lists and
+    // labels
+    pub fn approx_bytes(&self) -> usize {
+        let mut heap = 0;
+        self.arena.for_each(|_, node| {
+            heap += node.closure_bytes() + node.heap_bytes();
+        });
+        self.arena.approx_bytes() + heap
+    }
+
+    // Breakdown of every node allocated in the arena, reachable or not
+    pub fn memory_report(&self) -> GraphMemoryReport {
+        let mut report = GraphMemoryReport::default();
+        self.arena.for_each(|_, node| report.add(node));
+        report
+    }
 }
 
-impl ValueFactory {
-    pub fn new(arena: ArenaRef<ValueData>) -> ValueFactory {
-        ValueFactory { arena }
+// Memory held by a set of nodes: their inline size plus what they own on the heap. Allocator overhead
+// and the arena's free slots are not included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GraphMemoryReport {
+    pub nodes: usize,
+    // nodes times the size of ValueData
+    pub node_bytes: usize,
+    pub closure_bytes: usize,
+    // operand lists longer than two and labels
+    pub other_heap_bytes: usize
+}
+
+impl GraphMemoryReport {
+    fn add<T: Float>(&mut self, node: &ValueDataG<T>) {
+        self.nodes += 1;
+        self.node_bytes += mem::size_of::<ValueDataG<T>>();
+        self.closure_bytes += node.closure_bytes();
+        self.other_heap_bytes += node.heap_bytes();
     }
 
-    pub fn value(&self, data: f64) -> Value {
-        Value::build(self.arena.clone(), data)
+    pub fn total_bytes(&self) -> usize {
+        self.node_bytes + self.closure_bytes + self.other_heap_bytes
     }
 }
 
-#[derive(Clone)]
-pub struct Value {
-    value: Weak<RefCell<ValueData>>,
-    arena: ArenaRef<ValueData>
+impl fmt::Display for GraphMemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "graph memory: {} nodes, {}", self.nodes, format_bytes(self.total_bytes()))?;
+        writeln!(f, "  node data: {}", format_bytes(self.node_bytes))?;
+        writeln!(f, "  backward closures: {}", format_bytes(self.closure_bytes))?;
+        write!(f, "  operand lists and labels: {}", format_bytes(self.other_heap_bytes))
+    }
 }
 
-impl Value {
-    pub fn build(arena: ArenaRef<ValueData>, data: f64) -> Value {
-        Value {
-            value: arena.alloc_with_mut_borrow(ValueData::new(data, 0.0, Box::new(|| {}), &[], None)),
-            arena
+// e.g. "512 B", "1.5 KiB", "3.2 MiB"
+pub(crate) fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        b if b < 1 << 10 => format!("{} B", b),
+        b if b < 1 << 20 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{:.1} MiB", b as f64 / (1 << 20) as f64)
+    }
+}
+
+impl<T: Float> ArenaRef<ValueDataG<T>> {
+    // Frees every node except the survivors and the nodes they were computed from, e.g. reset(&params)
+    // after the optimizer step so the next forward pass starts from an arena holding only the model.
+    // Every other Value of this arena is dangling afterwards. Returns the number of nodes freed.
+    pub fn reset(&self, survivors: &[ValueG<T>]) -> usize {
+        fn mark<T: Float>(v: &ValueG<T>, keep: &mut HashSet<usize>) {
+            if keep.insert(v.node.index()) {
+                v.with_borrow(|node| node.prev.iter().for_each(|child| mark(child, keep)));
+            }
         }
+        let mut keep = HashSet::new();
+        survivors.iter().for_each(|v| mark(v, &mut keep));
+        let before = self.len();
+        self.retain(|index| keep.contains(&index));
+        before - self.len()
     }
 
-    fn new(arena: ArenaRef<ValueData>, data: f64, children: &[Value], op: String) -> Value {
-        Value { 
-            value: arena.alloc_with_mut_borrow(ValueData::new(data, 0.0, Box::new(|| {}), children, Some(op))),
-            arena
+    // Frees v's node right away and lets the next allocation reuse its slot, for long-running loops that
+    // interleave short-lived nodes with ones that must stay. v must be the only Value holding the node;
+    // nodes that have it as an operand must not be used afterwards. Its operands are left alone.
+    pub fn release(&self, v: &ValueG<T>) {
+        self.free_slot(&v.node);
+    }
+
+    // Nodes a Value handle refers to, directly or through the graph of a held node. Results whose handles
+    // were all dropped stay allocated until a reset, truncate or compact; they are the difference to len.
+    pub fn live_count(&self) -> usize {
+        self.held().into_iter().filter(|held| *held).count()
+    }
+
+    // Frees the nodes live_count leaves out and returns how many. Dropping the handle to the root of an
+    // expression frees the whole expression, leaves included unless something else still holds them.
+    // Interior nodes of a graph whose root is still held survive.
+    pub fn compact(&self) -> usize {
+        let held = self.held();
+        let before = self.len();
+        self.retain(|index| held[index]);
+        before - self.len()
+    }
+
+    // Snapshot of what the arena holds, for checking that a loop doesn't leak nodes (see assert_no_growth)
+    pub fn live_report(&self) -> LiveReport {
+        let held = self.held();
+        let mut labels = Vec::new();
+        self.for_each(|index, node| if let (true, Some(label)) = (held[index], &node.label) {labels.push(label.clone())});
+        labels.truncate(LiveReport::MAX_LABELS);
+        LiveReport { nodes: self.len(), live: held.iter().filter(|h| **h).count(), handled: self.counted_len(), labels }
+    }
+
+    // By slot index. Operands are followed explicitly rather than relying on allocation order, since a
+    // released slot can be reused by a node whose operands were allocated after it.
+    fn held(&self) -> Vec<bool> {
+        self.reachable_from_handles(|node, stack| stack.extend(node.prev.iter().map(|c| c.node.index())))
+    }
+}
+
+// Working memory of backward: the topological order, the visited set and the traversal stack. Cleared after
+// every use but never shrunk, so one instance can serve any number of backward_with calls, on any graph.
+pub struct BackwardBuffers<T: Float = f64> {
+    topo: Vec<ValueG<T>>,
+    visited: HashSet<ValueG<T>>,
+    stack: Vec<(ValueG<T>, bool)>
+}
+
+impl<T: Float> BackwardBuffers<T> {
+    pub fn new() -> BackwardBuffers<T> {
+        BackwardBuffers { topo: Vec::new(), visited: HashSet::new(), stack: Vec::new() }
+    }
+
+    // Room for the largest graph seen so far, in nodes
+    pub fn capacity(&self) -> usize {
+        self.topo.capacity().min(self.visited.capacity())
+    }
+
+    fn clear(&mut self) {
+        self.topo.clear();
+        self.visited.clear();
+        self.stack.clear();
+    }
+}
+
+impl<T: Float> Default for BackwardBuffers<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiveReport {
+    // allocated nodes, as ArenaRef::len
+    pub nodes: usize,
+    // nodes a Value still refers to, directly or through a held graph, as ArenaRef::live_count
+    pub live: usize,
+    // nodes with a Value handle of their own
+    pub handled: usize,
+    // labels of live nodes, oldest first, at most MAX_LABELS
+    pub labels: Vec<String>
+}
+
+impl LiveReport {
+    pub const MAX_LABELS: usize = 16;
+}
+
+// Panics if the arena holds more nodes, or more live ones, in after than in before. Meant for tests: take
+// a report before a training step, another one after it.
+pub fn assert_no_growth(before: &LiveReport, after: &LiveReport) {
+    assert!(
+        after.nodes <= before.nodes && after.live <= before.live,
+        "arena grew from {} nodes ({} live) to {} nodes ({} live); live labels now {:?}",
+        before.nodes, before.live, after.nodes, after.live, after.labels
+    );
+}
+
+// Index and value of the largest data (in data_cmp order), the first one on ties. Panics on an empty slice.
+pub fn max_by_data<T: Float>(values: &[ValueG<T>]) -> (usize, &ValueG<T>) {
+    assert!(!values.is_empty(), "max_by_data of an empty slice");
+    values.iter().enumerate().fold((0, &values[0]), |max, (i, v)| if v.data_cmp(max.1) == Ordering::Greater {(i, v)} else {max})
+}
+
+// Indices that sort values by ascending data (in data_cmp order), keeping the order of equal data
+pub fn sort_indices_by_data<T: Float>(values: &[ValueG<T>]) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..values.len()).collect();
+    indices.sort_by(|&i, &j| values[i].data_cmp(&values[j]));
+    indices
+}
+
+#[derive(Clone)]
+pub struct ValueG<T: Float> {
+    node: ArenaHandle<ValueDataG<T>>
+}
+
+// by node, so no Hash bound on T (f32 has none)
+impl<T: Float> PartialEq for ValueG<T> {
+    fn eq(&self, other: &ValueG<T>) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: Float> Eq for ValueG<T> {}
+
+impl<T: Float> std::hash::Hash for ValueG<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.node.hash(state);
+    }
+}
+
+impl<T: Float> ValueG<T> {
+    pub fn build(arena: ArenaRef<ValueDataG<T>>, data: T) -> ValueG<T> {
+        ValueG { node: arena.alloc_with_mut_borrow(ValueDataG::new(data, T::zero(), &[], None)) }
+    }
+
+    fn constant(arena: &ArenaRef<ValueDataG<T>>, x: T) -> ValueG<T> {
+        let make = || ValueDataG { kind: NodeKind::Constant, ..ValueDataG::new(x, T::zero(), &[], None) };
+        if x.is_nan() {
+            return ValueG { node: arena.alloc_with_mut_borrow(make()) };
         }
+        ValueG { node: arena.intern(x.to_bits_u64(), make) }
+    }
+
+    pub(crate) fn new(arena: ArenaRef<ValueDataG<T>>, data: T, children: &[ValueG<T>], op: OpKind) -> ValueG<T> {
+        let out = ValueG { node: arena.alloc_with_mut_borrow(ValueDataG::new(data, T::zero(), children, Some(op))) };
+        #[cfg(feature = "trace")]
+        crate::trace::node_created(out.node.index(), op, children.iter().map(|c| c.node.index()), data);
+        out
+    }
+
+    fn arena(&self) -> &ArenaRef<ValueDataG<T>> {
+        self.node.arena()
+    }
+
+    // Handle for references from one node to another (operands, backward closures). Unlike clone, it does
+    // not count as holding the node for compact and live_count.
+    pub(crate) fn internal(&self) -> ValueG<T> {
+        ValueG { node: self.node.uncounted() }
+    }
+
+    // Always panic if the node was freed or its arena dropped (autograd graph not DAG). f runs while the
+    // whole arena is borrowed, so it must not create or modify Values.
+    fn with_borrow<R>(&self, f: impl FnOnce(&ValueDataG<T>) -> R) -> R {
+        self.node.with(f).unwrap_or_else(|| self.dangling())
+    }
+
+    // Always panic if the node was freed or its arena dropped (autograd graph not DAG)
+    fn with_mut_borrow<R>(&self, f: impl FnOnce(&mut ValueDataG<T>) -> R) -> R {
+        self.node.with_mut(f).unwrap_or_else(|| self.dangling())
+    }
+
+    // get_data for the operands of a new node: a gone operand panics naming the op it was used in
+    fn operand_data(&self, op: OpKind) -> T {
+        self.try_item().unwrap_or_else(|e| panic!("DAG properties of autograd graph violated: {}, used as an operand of {}", e, op))
+    }
+
+    fn dangling(&self) -> ! {
+        panic!("DAG properties of autograd graph violated: {}", self.dangling_error())
+    }
+
+    fn dangling_error(&self) -> DanglingValueError {
+        DanglingValueError { arena: self.arena_id(), node: self.node.index(), arena_dropped: !self.arena_alive() }
+    }
+
+    // Id of the arena this value lives in, unique within the process
+    pub fn arena_id(&self) -> u64 {
+        self.arena().id()
     }
 
-    // Always panic if upgrade references a dropped value (autograd graph not DAG)
-    fn with_borrow<R>(&self, f: impl FnOnce(&ValueData) -> R) -> R {
-        let value_ptr = self.value.upgrade().expect("DAG properties of autograd graph violated");
-        let borrow = value_ptr.borrow();
-        f(&borrow)
+    // false once that arena's ArenaLifeTime was dropped; is_alive is also false when only the node was freed
+    pub fn arena_alive(&self) -> bool {
+        self.arena().is_alive()
     }
 
-    // Always panic if upgrade references a dropped value (autograd graph not DAG)
-    fn with_mut_borrow<R>(&self, f: impl FnOnce(&mut ValueData) -> R) -> R {
-        let value_ptr = self.value.upgrade().expect("DAG properties of autograd graph violated");
-        let mut borrow = value_ptr.borrow_mut();
-        f(&mut borrow)
+    // false for constants, whose grad stays 0 through backward
+    pub fn requires_grad(&self) -> bool {
+        self.kind() != NodeKind::Constant
     }
 
-    pub fn get_data(&self) -> f64 {
+    pub fn kind(&self) -> NodeKind {
+        self.with_borrow(|v| v.kind)
+    }
+
+    // false once the arena owning this value has been dropped or the node freed
+    pub fn is_alive(&self) -> bool {
+        self.node.is_alive()
+    }
+
+    pub fn get_data(&self) -> T {
         self.with_borrow(|v| v.data)
     }
 
-    pub fn get_grad(&self) -> f64 {
+    // The scalar this value holds, as get_data. TryFrom returns an error instead of panicking when the node
+    // is gone.
+    pub fn item(&self) -> T {
+        self.get_data()
+    }
+
+    fn try_item(&self) -> Result<T, DanglingValueError> {
+        self.node.with(|v| v.data).ok_or_else(|| self.dangling_error())
+    }
+
+    pub fn get_grad(&self) -> T {
         self.with_borrow(|v| v.grad)
     }
 
-    pub fn set_data(&self, data: f64) {
+    pub fn set_data(&self, data: T) {
         self.with_mut_borrow(|v| v.data = data);
     }
 
-    pub fn set_grad(&self, grad: f64) {
+    // Compares data rather than nodes (== compares nodes). NaN data equals nothing, not even NaN.
+    pub fn data_eq(&self, other: &ValueG<T>, tol: f64) -> bool {
+        (self.get_data().to_f64() - other.get_data().to_f64()).abs() <= tol
+    }
+
+    // Total order on data: -0 and 0 are equal and NaN is greater than every number, so a NaN output wins
+    // max_by_data instead of hiding
+    pub fn data_cmp(&self, other: &ValueG<T>) -> Ordering {
+        let (a, b) = (self.get_data(), other.get_data());
+        a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+    }
+
+    pub fn label(&self) -> Option<String> {
+        self.with_borrow(|v| v.label.clone())
+    }
+
+    pub fn set_label(&self, label: &str) {
+        self.with_mut_borrow(|v| v.label = Some(label.to_string()));
+        #[cfg(feature = "trace")]
+        crate::trace::labeled(self.node.index(), self.op(), label, self.get_data());
+    }
+
+    pub fn set_grad(&self, grad: T) {
         self.with_mut_borrow(|v| v.grad = grad);
     }
 
-    fn add_grad(&self, delta: f64) {
-        self.with_mut_borrow(|v| v.grad += delta);
+    // Sets the data of several inputs of this value's graph at once, e.g. the next sample before
+    // CompiledGraph::refresh. Every bound value must be a leaf of the graph other than a constant;
+    // otherwise nothing is set.
+    pub fn bind_inputs(&self, pairs: &[(&ValueG<T>, T)]) -> Result<(), BindError> {
+        ValueG::bind_along(&self.topo_order(), pairs)
+    }
+
+    // bind_inputs by label. Every leaf of the graph carrying the label is set.
+    pub fn bind_named(&self, pairs: &[(&str, T)]) -> Result<(), BindError> {
+        ValueG::bind_named_along(&self.topo_order(), pairs)
+    }
+
+    pub(crate) fn bind_along(topo: &[ValueG<T>], pairs: &[(&ValueG<T>, T)]) -> Result<(), BindError> {
+        ValueG::bind_checked(topo, pairs, Vec::new())
+    }
+
+    pub(crate) fn bind_named_along(topo: &[ValueG<T>], pairs: &[(&str, T)]) -> Result<(), BindError> {
+        let (mut resolved, mut unknown) = (Vec::new(), Vec::new());
+        for (name, x) in pairs {
+            let n = resolved.len();
+            resolved.extend(topo.iter().filter(|v| v.with_borrow(|node| node.label.as_deref() == Some(*name))).map(|v| (v, *x)));
+            if resolved.len() == n {
+                unknown.push(format!("{:?}", name));
+            }
+        }
+        ValueG::bind_checked(topo, &resolved, unknown)
+    }
+
+    // unknown holds the bindings already known not to resolve
+    fn bind_checked(topo: &[ValueG<T>], pairs: &[(&ValueG<T>, T)], unknown: Vec<String>) -> Result<(), BindError> {
+        let graph: HashSet<&ValueG<T>> = topo.iter().collect();
+        let mut error = BindError { unknown, not_leaves: Vec::new() };
+        for (v, _) in pairs {
+            if !graph.contains(v) {
+                error.unknown.push(v.describe());
+            } else if v.with_borrow(|node| node.op.is_some() || node.kind == NodeKind::Constant) {
+                error.not_leaves.push(v.describe());
+            }
+        }
+        if !error.unknown.is_empty() || !error.not_leaves.is_empty() {
+            return Err(error);
+        }
+        pairs.iter().for_each(|(v, x)| v.set_data(*x));
+        Ok(())
+    }
+
+    // Accessors for backward closures, which run inside ArenaRef::with_view
+    pub(crate) fn data_in(&self, view: &ArenaView<'_, ValueDataG<T>>) -> T {
+        view.get(&self.node).unwrap_or_else(|| self.dangling()).data
+    }
+
+    pub(crate) fn grad_in(&self, view: &ArenaView<'_, ValueDataG<T>>) -> T {
+        view.get(&self.node).unwrap_or_else(|| self.dangling()).grad
+    }
+
+    pub(crate) fn add_grad_in(&self, view: &mut ArenaView<'_, ValueDataG<T>>, delta: T) {
+        let v = view.get_mut(&self.node).unwrap_or_else(|| self.dangling());
+        if v.kind != NodeKind::Constant {v.grad += delta}
     }
 
-    fn set_backward(&self, backward_fn: impl Fn() + 'static) {
-        self.with_mut_borrow(|v| v.backward = Box::new(backward_fn));
+    pub(crate) fn set_backward(&self, backward_fn: impl Fn(&mut ArenaView<'_, ValueDataG<T>>) + 'static) {
+        self.with_mut_borrow(|v| v.backward = Some(Rc::new(backward_fn)));
     }
 
     pub fn backward(&self) {
-        let mut topo: Vec<Value> = Vec::new();
-        let mut visited: HashSet<Value> = HashSet::new();
-        fn build_topo(v: &Value, visited: &mut HashSet<Value>, topo: &mut Vec<Value>) {
-            if !visited.contains(v) {
-                visited.insert(v.clone());
-                v.with_borrow(|node| {
-                    node.prev.iter().for_each(|child| {build_topo(child, visited, topo);});
-                });
-                topo.push(v.clone());
+        self.backward_with(&mut BackwardBuffers::default());
+    }
+
+    // Same as backward, with the working memory taken from buffers. Calling it every training step with the
+    // same buffers avoids reallocating them each time.
+    pub fn backward_with(&self, buffers: &mut BackwardBuffers<T>) {
+        self.fill_topo(buffers);
+        #[cfg(feature = "trace")]
+        crate::trace::backward_started(self.node.index(), buffers.topo.len());
+        self.backward_along(&buffers.topo);
+        #[cfg(feature = "trace")]
+        self.trace_grads(&buffers.topo);
+        buffers.clear();
+    }
+
+    // Every node of this value's graph, operands before the nodes using them, ending with this value.
+    // The handles don't count as holding the nodes.
+    pub(crate) fn topo_order(&self) -> Vec<ValueG<T>> {
+        let mut buffers = BackwardBuffers::default();
+        self.fill_topo(&mut buffers);
+        buffers.topo
+    }
+
+    fn fill_topo(&self, buffers: &mut BackwardBuffers<T>) {
+        buffers.clear();
+        let BackwardBuffers { topo, visited, stack } = buffers;
+        // depth-first with an explicit stack, so long chains don't overflow the call stack. A node is pushed
+        // again as done once its operands are on the stack, first operand on top.
+        stack.push((self.internal(), false));
+        self.arena().with_view(|view| {
+            while let Some((v, done)) = stack.pop() {
+                if done {
+                    topo.push(v);
+                } else if !visited.contains(&v) {
+                    visited.insert(v.internal());
+                    let node = view.get(&v.node).unwrap_or_else(|| {
+                        // only a visited node can have pushed v; find it to name it in the message
+                        match visited.iter().find_map(|u| view.get(&u.node).filter(|d| d.prev.contains(&v)).map(|d| d.describe(u.node.index()))) {
+                            Some(user) => panic!("DAG properties of autograd graph violated: {}, operand of {}", v.dangling_error(), user),
+                            None => v.dangling()
+                        }
+                    });
+                    let operands = node.prev.iter().rev().filter(|child| !visited.contains(*child)).map(|child| (child.internal(), false));
+                    stack.push((v, true));
+                    stack.extend(operands);
+                }
             }
+        });
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace_grads(&self, topo: &[ValueG<T>]) {
+        if crate::trace::warn_enabled() {
+            self.arena().with_view(|view| topo.iter().for_each(|v| {
+                if let Some(d) = view.get(&v.node).filter(|d| !d.grad.to_f64().is_finite()) {
+                    crate::trace::non_finite_grad(&d.describe(v.node.index()), d.grad);
+                }
+            }));
         }
-        build_topo(&self, &mut visited, &mut topo);
-        
-        // go one variable at a time and apply the chain rule to get its gradient
-        self.with_mut_borrow(|v| v.grad = 1.0);
-        topo.iter().rev().for_each(|node| {
-            node.with_borrow(|v| (v.backward)());
+        crate::trace::backward_finished(self.node.index());
+    }
+
+    // Backward pass given topo_order()
+    pub(crate) fn backward_along(&self, topo: &[ValueG<T>]) {
+        // go one variable at a time and apply the chain rule to get its gradient. The arena is borrowed once
+        // for the whole sweep rather than once per read or write of a node.
+        self.arena().with_view(|view| {
+            view.get_mut(&self.node).unwrap_or_else(|| self.dangling()).grad = T::one();
+            topo.iter().rev().for_each(|node| {
+                let backward = view.get(&node.node).unwrap_or_else(|| node.dangling()).backward.clone();
+                if let Some(backward) = backward {
+                    backward(view);
+                }
+            });
         });
     }
 
-    fn trace(&self) -> (HashSet<Value>, HashSet<(Value, Value)>) {
-        let mut nodes: HashSet<Value> = HashSet::new();
-        let mut edges: HashSet<(Value, Value)> = HashSet::new();
-        fn build(v: &Value, nodes: &mut HashSet<Value>, edges: &mut HashSet<(Value, Value)>) {
+    // Operation that produced this value, None for leaves
+    pub fn op(&self) -> Option<OpKind> {
+        self.with_borrow(|v| v.op)
+    }
+
+    // Operation that produced this value (None for leaves) and its distinct operands in order
+    pub(crate) fn op_and_children(&self) -> (Option<OpKind>, Vec<ValueG<T>>) {
+        self.with_borrow(|v| (v.op, v.prev.iter().map(|c| ValueG { node: c.node.counted() }).collect()))
+    }
+
+    fn trace(&self) -> (HashSet<Self>, HashSet<(Self, Self)>) {
+        let mut nodes: HashSet<ValueG<T>> = HashSet::new();
+        let mut edges: HashSet<(ValueG<T>, ValueG<T>)> = HashSet::new();
+        fn build<T: Float>(v: &ValueG<T>, nodes: &mut HashSet<ValueG<T>>, edges: &mut HashSet<(ValueG<T>, ValueG<T>)>) {
             if !nodes.contains(v) {
-                nodes.insert(v.clone());
+                nodes.insert(v.internal());
                 v.with_borrow(|node| {
                     node.prev.iter().for_each(|child| {
-                        edges.insert((child.clone(), v.clone()));
+                        edges.insert((child.internal(), v.internal()));
                         build(child, nodes, edges);
                     });
                 });
             }
         }
-        build(&self, &mut nodes, &mut edges);
+        build(self, &mut nodes, &mut edges);
         (nodes, edges)
     }
 
+    // Breakdown of the nodes this value was computed from, itself included
+    pub fn graph_memory_report(&self) -> GraphMemoryReport {
+        let mut report = GraphMemoryReport::default();
+        let topo = self.topo_order();
+        self.arena().with_view(|view| {
+            topo.iter().for_each(|v| report.add(view.get(&v.node).unwrap_or_else(|| v.dangling())));
+        });
+        report
+    }
+
     pub fn draw_dot(&self) -> String {
+        self.draw_dot_with(&DotOptions::default())
+    }
+
+    pub fn draw_dot_with(&self, options: &DotOptions) -> String {
         let mut dot = String::new();
 
         // Configure the digraph
@@ -130,27 +659,27 @@ impl Value {
 
         let (nodes, edges) = self.trace();
 
-        let get_node_id = |n: &Value| -> String {
-            (n.value.as_ptr() as usize).to_string()
+        let get_node_id = |n: &ValueG<T>| -> String {
+            n.node.index().to_string()
         };
         
         nodes.iter().for_each(|n| {
-            let (data, grad, opt_op) = n.with_borrow(
-                |value| (value.data, value.grad, value.op.clone())
+            let (data, grad, opt_op, label, kind) = n.with_borrow(
+                |value| (value.data, value.grad, value.op, value.label.clone(), value.kind)
             );
-            let n_id = get_node_id(&n);
-            dot.push_str(&Self::add_data_node(&n_id, data, grad));
+            let n_id = get_node_id(n);
+            dot.push_str(&Self::add_data_node(&n_id, &options.format.format(data), &options.format.format(grad), label.as_deref(), kind));
 
             if let Some(op) = &opt_op {
                 let op_id = format!("\"{}{}\"", n_id, op);
-                dot.push_str(&Self::add_op_node(&op_id, op));
+                dot.push_str(&Self::add_op_node(&op_id, &op.to_string()));
                 dot.push_str(&Self::add_edge(&op_id, &n_id));
             }
         });
 
         edges.iter().for_each(|(n1, n2)| {
             let n_id = get_node_id(n1);
-            let op = n2.with_borrow(|v| {v.op.clone().unwrap()});
+            let op = n2.with_borrow(|v| {v.op.unwrap()});
             let op_id = format!("\"{}{}\"", get_node_id(n2), op);
             dot.push_str(&Self::add_edge(&n_id, &op_id));
         });
@@ -160,9 +689,17 @@ impl Value {
         dot
     }
 
-    fn add_data_node(id: &str, data: f64, grad: f64) -> String {
+    fn add_data_node(id: &str, data: &str, grad: &str, label: Option<&str>, kind: NodeKind) -> String {
         // a [ shape=record, label = "data 1" ]
-        format!("    {} [ shape=record, label = \"{{data {:.4} | grad {:.4}}}\" ]\n", id, data, grad)
+        let style = match kind {
+            NodeKind::Plain => "",
+            NodeKind::Param => ", style=filled, fillcolor=lightblue",
+            NodeKind::Constant => ", style=dashed"
+        };
+        match label {
+            Some(label) => format!("    {} [ shape=record, label = \"{{{} | data {} | grad {}}}\"{} ]\n", id, label, data, grad, style),
+            None => format!("    {} [ shape=record, label = \"{{data {} | grad {}}}\"{} ]\n", id, data, grad, style)
+        }
     }
 
     fn add_op_node(id: &str, op: &str) -> String {
@@ -175,612 +712,2904 @@ impl Value {
         format!("    {} -> {}\n", id_1, id_2)
     }
 
-    pub fn relu(&self) -> Value {
-        let self_data = self.get_data();
-        let out = Value::new(
-            self.arena.clone(),
-            if self_data < 0.0 {0.0} else {self_data},
-            &[self.clone()],
-            String::from("ReLU")
+    pub fn relu(&self) -> ValueG<T> {
+        let self_data = self.operand_data(OpKind::Relu);
+        let out = ValueG::new(
+            self.arena().clone(),
+            if self_data < T::zero() {T::zero()} else {self_data},
+            std::slice::from_ref(self),
+            OpKind::Relu
         );
 
-        let (out_ref, self_ref) = (out.clone(), self.clone());
-        out.set_backward(move || {
-            let (out_grad, out_data) = (out_ref.get_grad(), out_ref.get_data());
-            self_ref.add_grad(if out_data > 0.0 {out_grad} else {0.0});
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, if out_data > T::zero() {out_grad} else {T::zero()});
         });
 
         out
     }
 
-    pub fn tanh(&self) -> Value {
-        let x = self.get_data();
-        let t = ((2.0*x).exp() - 1.0) / ((2.0*x).exp() + 1.0);
-        let out = Value::new(
-            self.arena.clone(),
+    pub fn tanh(&self) -> ValueG<T> {
+        let (x, one, two) = (self.operand_data(OpKind::Tanh), T::one(), T::from_f64(2.0));
+        let t = ((two*x).exp() - one) / ((two*x).exp() + one);
+        let out = ValueG::new(
+            self.arena().clone(),
             t,
-            &[self.clone()],
-            String::from("tanh")
+            std::slice::from_ref(self),
+            OpKind::Tanh
         );
 
-        let (out_ref, self_ref) = (out.clone(), self.clone());
-        out.set_backward(move || {
-            let out_grad = out_ref.get_grad();
-            self_ref.add_grad((1.0 - t.powi(2)) * out_grad);
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, t) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, (one - t.powi(2)) * out_grad);
         });
 
         out
     }
 
-    pub fn exp(&self) -> Value {
-        let x = self.get_data();
-        let out = Value::new(
-            self.arena.clone(),
+    pub fn exp(&self) -> ValueG<T> {
+        let x = self.operand_data(OpKind::Exp);
+        let out = ValueG::new(
+            self.arena().clone(),
             x.exp(),
-            &[self.clone()],
-            String::from("exp")
+            std::slice::from_ref(self),
+            OpKind::Exp
         );
 
-        let (out_ref, self_ref) = (out.clone(), self.clone());
-        out.set_backward(move || {
-            let (out_grad, out_data) = (out_ref.get_grad(), out_ref.get_data());
-            self_ref.add_grad(out_data * out_grad);
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, out_data * out_grad);
         });
 
         out
     }
 
-    pub fn powi(&self, other: i32) -> Value {
-        let out = Value::new(
-            self.arena.clone(),
-            self.get_data().powi(other),
-            &[self.clone()],
-            String::from(format!("powi{}", other))
+    // ln(0) is -inf and negative inputs give NaN, as for floats; try_ln and safe_ln check instead
+    pub fn ln(&self) -> ValueG<T> {
+        let x = self.operand_data(OpKind::Ln);
+        let out = ValueG::new(
+            self.arena().clone(),
+            x.ln(),
+            std::slice::from_ref(self),
+            OpKind::Ln
         );
 
-        let (out_ref, self_ref) = (out.clone(), self.clone());
-        out.set_backward(move || {
-            let out_grad = out_ref.get_grad();
-            self_ref.add_grad(other as f64 * self_ref.get_data().powi(other - 1) * out_grad);
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, out_grad / x);
         });
 
         out
     }
 
-    pub fn powf(&self, other: f64) -> Value {
-        let out = Value::new(
-            self.arena.clone(),
-            self.get_data().powf(other),
-            &[self.clone()],
-            String::from(format!("powf{}", other))
+    pub fn exp2(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Exp2).exp2(),
+            std::slice::from_ref(self),
+            OpKind::Exp2
         );
 
-        let (out_ref, self_ref) = (out.clone(), self.clone());
-        out.set_backward(move || {
-            let out_grad = out_ref.get_grad();
-            self_ref.add_grad(other * self_ref.get_data().powf(other - 1.0) * out_grad);
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, T::from_f64(std::f64::consts::LN_2) * out_data * out_grad);
         });
 
         out
     }
-}
 
-impl fmt::Display for Value {
-    // f"Value(data={self.data}, grad={self.grad})"
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.with_borrow(|v| v.fmt(f))
-    }
-}
+    pub fn log2(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Log2).log2(),
+            std::slice::from_ref(self),
+            OpKind::Log2
+        );
 
-impl<'a, 'b> ops::Add<&'b Value> for &'a Value {
-    type Output = Value;
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, out_grad / (x * T::from_f64(std::f64::consts::LN_2)));
+        });
+
+        out
+    }
 
-    fn add(self, rhs: &'b Value) -> Value {
-        let out = Value::new(
-            self.arena.clone(),
-            self.get_data() + rhs.get_data(),
-            &[self.clone(), rhs.clone()],
-            String::from("+")
+    pub fn log10(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Log10).log10(),
+            std::slice::from_ref(self),
+            OpKind::Log10
         );
-        
-        let (out_ref, self_ref, rhs_ref) = (out.clone(), self.clone(), rhs.clone());
-        out.set_backward(move || {
-            let out_grad = out_ref.get_grad();
-            self_ref.add_grad(out_grad);
-            rhs_ref.add_grad(out_grad);
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, out_grad / (x * T::from_f64(std::f64::consts::LN_10)));
         });
 
         out
     }
-}
 
-impl<'a> ops::Add<f64> for &'a Value {
-    type Output = Value;
+    // A single node rather than 1 / (1 + exp(-x))
+    pub fn sigmoid(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Sigmoid).sigmoid(),
+            std::slice::from_ref(self),
+            OpKind::Sigmoid
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, s) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, s * (T::one() - s) * out_grad);
+        });
 
-    fn add(self, rhs: f64) -> Value {
-        self + &Value::build(self.arena.clone(), rhs)
+        out
     }
-}
 
-impl<'a> ops::Add<&'a Value> for f64 {
-    type Output = Value;
+    pub fn sin(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Sin).sin(),
+            std::slice::from_ref(self),
+            OpKind::Sin
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, x.cos() * out_grad);
+        });
 
-    fn add(self, rhs: &'a Value) -> Value {
-        &Value::build(rhs.arena.clone(), self) + rhs
+        out
     }
-}
 
-impl<'a> ops::Neg for &'a Value {
-    type Output = Value;
+    pub fn cos(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Cos).cos(),
+            std::slice::from_ref(self),
+            OpKind::Cos
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, -x.sin() * out_grad);
+        });
 
-    fn neg(self) -> Value {
-        self * &Value::build(self.arena.clone(), -1.0)
+        out
     }
-}
 
-impl<'a, 'b> ops::Sub<&'b Value> for &'a Value {
-    type Output = Value;
+    pub fn tan(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Tan).tan(),
+            std::slice::from_ref(self),
+            OpKind::Tan
+        );
 
-    fn sub(self, rhs: &'b Value) -> Value {
-        self + &(-rhs)
+        // 1 / cos^2 as 1 + tan^2, from the output rather than another trig call
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, t) = (out_ref.grad_in(view), out_ref.data_in(view));
+            self_ref.add_grad_in(view, (T::one() + t * t) * out_grad);
+        });
+
+        out
     }
-}
 
-impl<'a> ops::Sub<f64> for &'a Value {
-    type Output = Value;
+    // At +-1 the gradient is infinite, and NaN outside [-1, 1] like the data
+    pub fn asin(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Asin).asin(),
+            std::slice::from_ref(self),
+            OpKind::Asin
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, out_grad / (T::one() - x * x).sqrt());
+        });
 
-    fn sub(self, rhs: f64) -> Value {
-        self - &Value::build(self.arena.clone(), rhs)
+        out
     }
-}
 
-impl<'a> ops::Sub<&'a Value> for f64 {
-    type Output = Value;
+    pub fn acos(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Acos).acos(),
+            std::slice::from_ref(self),
+            OpKind::Acos
+        );
 
-    fn sub(self, rhs: &'a Value) -> Value {
-        &Value::build(rhs.arena.clone(), self) - rhs
-    }
-}
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, -out_grad / (T::one() - x * x).sqrt());
+        });
 
-impl<'a, 'b> ops::Mul<&'b Value> for &'a Value {
-    type Output = Value;
+        out
+    }
 
-    fn mul(self, rhs: &'b Value) -> Value {
-        let out = Value::new(
-            self.arena.clone(),
-            self.get_data() * rhs.get_data(),
-            &[self.clone(), rhs.clone()],
-            String::from("*")
+    pub fn atan(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Atan).atan(),
+            std::slice::from_ref(self),
+            OpKind::Atan
         );
 
-        let (out_ref, self_ref, rhs_ref) = (out.clone(), self.clone(), rhs.clone());
-        out.set_backward(move || {
-            let out_grad = out_ref.get_grad();
-            self_ref.add_grad(rhs_ref.get_data() * out_grad);
-            rhs_ref.add_grad(self_ref.get_data() * out_grad);
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, out_grad / (T::one() + x * x));
         });
 
         out
     }
-}
 
-impl<'a> ops::Mul<f64> for &'a Value {
-    type Output = Value;
+    // Angle of the point (other, self) in (-pi, pi], self being y and other x as for floats. At the origin
+    // the angle is 0 and so are both gradients.
+    pub fn atan2(&self, other: &ValueG<T>) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Atan2).atan2(other.operand_data(OpKind::Atan2)),
+            &[self.internal(), other.internal()],
+            OpKind::Atan2
+        );
+
+        let (out_ref, y_ref, x_ref) = (out.internal(), self.internal(), other.internal());
+        out.set_backward(move |view| {
+            let (out_grad, y, x) = (out_ref.grad_in(view), y_ref.data_in(view), x_ref.data_in(view));
+            let r2 = x * x + y * y;
+            if r2 == T::zero() {
+                return;
+            }
+            y_ref.add_grad_in(view, x / r2 * out_grad);
+            x_ref.add_grad_in(view, -y / r2 * out_grad);
+        });
 
-    fn mul(self, rhs: f64) -> Value {
-        self * &Value::build(self.arena.clone(), rhs)
+        out
     }
-}
 
-impl<'a> ops::Mul<&'a Value> for f64 {
-    type Output = Value;
+    pub fn sinh(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Sinh).sinh(),
+            std::slice::from_ref(self),
+            OpKind::Sinh
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, x.cosh() * out_grad);
+        });
 
-    fn mul(self, rhs: &'a Value) -> Value {
-        &Value::build(rhs.arena.clone(), self) * rhs
+        out
     }
-}
 
-impl<'a, 'b> ops::Div<&'b Value> for &'a Value {
-    type Output = Value;
+    pub fn cosh(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Cosh).cosh(),
+            std::slice::from_ref(self),
+            OpKind::Cosh
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, x.sinh() * out_grad);
+        });
 
-    fn div(self, rhs: &'b Value) -> Value {
-        self * &rhs.powi(-1)
+        out
     }
-}
 
-impl<'a> ops::Div<f64> for &'a Value {
-    type Output = Value;
+    // -1, 0 or 1. The derivative is zero wherever it exists, so the node has no backward and nothing flows
+    // through it to x.
+    pub fn sign(&self) -> ValueG<T> {
+        ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Sign).sign(),
+            std::slice::from_ref(self),
+            OpKind::Sign
+        )
+    }
 
-    fn div(self, rhs: f64) -> Value {
-        self / &Value::build(self.arena.clone(), rhs)
+    // The rounding ops are piecewise constant, so like sign they pass no gradient back; round_ste is the
+    // alternative for training through them.
+    pub fn floor(&self) -> ValueG<T> {
+        ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Floor).floor(),
+            std::slice::from_ref(self),
+            OpKind::Floor
+        )
     }
-}
 
-impl<'a> ops::Div<&'a Value> for f64 {
-    type Output = Value;
+    pub fn ceil(&self) -> ValueG<T> {
+        ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Ceil).ceil(),
+            std::slice::from_ref(self),
+            OpKind::Ceil
+        )
+    }
 
-    fn div(self, rhs: &'a Value) -> Value {
-        &Value::build(rhs.arena.clone(), self) / rhs
+    // Rounds half away from zero
+    pub fn round(&self) -> ValueG<T> {
+        ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Round).round(),
+            std::slice::from_ref(self),
+            OpKind::Round
+        )
     }
+
+    // round in the forward pass and the identity in the backward (the straight-through estimator), so that
+    // parameters behind it still learn
+    pub fn round_ste(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::RoundSte).round(),
+            std::slice::from_ref(self),
+            OpKind::RoundSte
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            self_ref.add_grad_in(view, out_ref.grad_in(view));
+        });
+
+        out
+    }
+
+    // |x| with gradient sign(x), taking the subgradient 0 at x == 0
+    pub fn abs(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Abs).abs(),
+            std::slice::from_ref(self),
+            OpKind::Abs
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, x) = (out_ref.grad_in(view), self_ref.data_in(view));
+            self_ref.add_grad_in(view, x.sign() * out_grad);
+        });
+
+        out
+    }
+
+    // The gradient 1 / (2 sqrt(x)) is infinite at x == 0, where this accumulates 0 instead so that the NaN it
+    // would turn into does not spread to everything upstream
+    pub fn sqrt(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Sqrt).sqrt(),
+            std::slice::from_ref(self),
+            OpKind::Sqrt
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            if out_data == T::zero() {
+                return;
+            }
+            self_ref.add_grad_in(view, out_grad / (T::from_f64(2.0) * out_data));
+        });
+
+        out
+    }
+
+    // Real cube root, defined for negative x unlike powf(1/3). Like sqrt it passes back 0 at x == 0.
+    pub fn cbrt(&self) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Cbrt).cbrt(),
+            std::slice::from_ref(self),
+            OpKind::Cbrt
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            if out_data == T::zero() {
+                return;
+            }
+            self_ref.add_grad_in(view, out_grad / (T::from_f64(3.0) * out_data * out_data));
+        });
+
+        out
+    }
+
+    // The larger of self and other (NaN loses, as for f64::max). The gradient goes to the operand equal to
+    // the result, and is split 50/50 on an exact tie, so max(x, x) still passes x its gradient once.
+    pub fn max(&self, other: &ValueG<T>) -> ValueG<T> {
+        self.extremum(other, OpKind::Max)
+    }
+
+    // The smaller of self and other, with gradients as for max
+    pub fn min(&self, other: &ValueG<T>) -> ValueG<T> {
+        self.extremum(other, OpKind::Min)
+    }
+
+    fn extremum(&self, other: &ValueG<T>, op: OpKind) -> ValueG<T> {
+        let (a, b) = (self.operand_data(op), other.operand_data(op));
+        let out = ValueG::new(
+            self.arena().clone(),
+            if op == OpKind::Max {a.max(b)} else {a.min(b)},
+            &[self.internal(), other.internal()],
+            op
+        );
+
+        let (out_ref, self_ref, other_ref) = (out.internal(), self.internal(), other.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            let (a_won, b_won) = (self_ref.data_in(view) == out_data, other_ref.data_in(view) == out_data);
+            let share = if a_won && b_won {out_grad / T::from_f64(2.0)} else {out_grad};
+            if a_won {
+                self_ref.add_grad_in(view, share);
+            }
+            if b_won {
+                other_ref.add_grad_in(view, share);
+            }
+        });
+
+        out
+    }
+
+    // max(self, c) with c kept in the op like the scalar arithmetic. c takes no gradient, so on a tie all of
+    // it goes to self.
+    pub fn max_scalar(&self, c: T) -> ValueG<T> {
+        self.scalar_extremum(c, OpKind::MaxScalar(c.to_f64()))
+    }
+
+    pub fn min_scalar(&self, c: T) -> ValueG<T> {
+        self.scalar_extremum(c, OpKind::MinScalar(c.to_f64()))
+    }
+
+    fn scalar_extremum(&self, c: T, op: OpKind) -> ValueG<T> {
+        let x = self.operand_data(op);
+        let data = if matches!(op, OpKind::MaxScalar(_)) {x.max(c)} else {x.min(c)};
+        let out = ValueG::new(self.arena().clone(), data, &[self.internal()], op);
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data, x) = (out_ref.grad_in(view), out_ref.data_in(view), self_ref.data_in(view));
+            if x == out_data {
+                self_ref.add_grad_in(view, out_grad);
+            }
+        });
+
+        out
+    }
+
+    // self clipped to [lo, hi]. The gradient passes only where lo < self < hi, so an input stuck at or
+    // beyond a bound gets none. Panics if lo > hi.
+    pub fn clamp(&self, lo: T, hi: T) -> ValueG<T> {
+        assert!(lo <= hi, "clamp bounds out of order: lo {:?} > hi {:?}", lo, hi);
+        let op = OpKind::Clamp(lo.to_f64(), hi.to_f64());
+        let out = ValueG::new(self.arena().clone(), self.operand_data(op).max(lo).min(hi), &[self.internal()], op);
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let x = self_ref.data_in(view);
+            if lo < x && x < hi {
+                self_ref.add_grad_in(view, out_ref.grad_in(view));
+            }
+        });
+
+        out
+    }
+
+    pub fn powi(&self, other: i32) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::PowI(other)).powi(other),
+            std::slice::from_ref(self),
+            OpKind::PowI(other)
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, T::from_f64(other as f64) * self_ref.data_in(view).powi(other - 1) * out_grad);
+        });
+
+        out
+    }
+
+    // self / rhs, or an error instead of an inf when rhs is exactly zero. The result and its gradients are
+    // the same as the / operator's.
+    pub fn try_div(&self, rhs: &ValueG<T>) -> Result<ValueG<T>, MathError> {
+        self.try_div_with_eps(rhs, 0.0)
+    }
+
+    // Also refuses divisors within eps of zero
+    pub fn try_div_with_eps(&self, rhs: &ValueG<T>, eps: f64) -> Result<ValueG<T>, MathError> {
+        rhs.check_divisor(Some(self), eps)?;
+        Ok(self / rhs)
+    }
+
+    pub fn try_reciprocal(&self) -> Result<ValueG<T>, MathError> {
+        self.try_reciprocal_with_eps(0.0)
+    }
+
+    pub fn try_reciprocal_with_eps(&self, eps: f64) -> Result<ValueG<T>, MathError> {
+        self.check_divisor(None, eps)?;
+        Ok(self.powi(-1))
+    }
+
+    fn check_divisor(&self, numerator: Option<&ValueG<T>>, eps: f64) -> Result<(), MathError> {
+        let data = self.get_data().to_f64();
+        if data.abs() <= eps {
+            return Err(MathError::DivisionByZero { numerator: numerator.map(ValueG::describe), divisor: self.describe(), data, eps });
+        }
+        Ok(())
+    }
+
+    pub fn try_sqrt(&self) -> Result<ValueG<T>, MathError> {
+        self.safe_sqrt(DomainPolicy::Error)
+    }
+
+    // Invalid inputs are negative ones
+    pub fn safe_sqrt(&self, policy: DomainPolicy) -> Result<ValueG<T>, MathError> {
+        let x = self.get_data().to_f64();
+        match policy {
+            DomainPolicy::Error if x < 0.0 => Err(MathError::Domain { op: OpKind::Sqrt.to_string(), operand: self.describe(), data: x }),
+            DomainPolicy::Clamp if x < 0.0 => Ok(self.clamp_from_zero(true).sqrt()),
+            _ => Ok(self.sqrt())
+        }
+    }
+
+    pub fn try_ln(&self) -> Result<ValueG<T>, MathError> {
+        self.safe_ln(DomainPolicy::Error)
+    }
+
+    // Invalid inputs are zero and negative ones
+    pub fn safe_ln(&self, policy: DomainPolicy) -> Result<ValueG<T>, MathError> {
+        let x = self.get_data().to_f64();
+        match policy {
+            DomainPolicy::Error if x <= 0.0 => Err(MathError::Domain { op: OpKind::Ln.to_string(), operand: self.describe(), data: x }),
+            DomainPolicy::Clamp if x <= 0.0 => Ok(self.clamp_from_zero(true).ln()),
+            _ => Ok(self.ln())
+        }
+    }
+
+    pub fn try_powf(&self, other: T) -> Result<ValueG<T>, MathError> {
+        self.safe_powf(other, DomainPolicy::Error)
+    }
+
+    // Invalid inputs are negative bases with a fractional exponent and a zero base with a negative one
+    pub fn safe_powf(&self, other: T, policy: DomainPolicy) -> Result<ValueG<T>, MathError> {
+        self.checked_powf(other, policy, &OpKind::PowF(other.to_f64()).to_string())
+    }
+
+    fn checked_powf(&self, other: T, policy: DomainPolicy, op: &str) -> Result<ValueG<T>, MathError> {
+        let (x, n) = (self.get_data().to_f64(), other.to_f64());
+        let fractional = n.fract() != 0.0;
+        match policy {
+            DomainPolicy::Error if (x < 0.0 && fractional) || (x == 0.0 && n < 0.0) => {
+                Err(MathError::Domain { op: String::from(op), operand: self.describe(), data: x })
+            }
+            DomainPolicy::Clamp if fractional && x < 0.0 => Ok(self.clamp_from_zero(true).powf(other)),
+            DomainPolicy::Clamp if n < 0.0 && x == 0.0 => Ok(self.clamp_from_zero(true).powf(other)),
+            _ => Ok(self.powf(other))
+        }
+    }
+
+    // max(self, DOMAIN_EPS), or min(self, -DOMAIN_EPS) when !positive, out of relu so that the clamped
+    // input takes no gradient
+    fn clamp_from_zero(&self, positive: bool) -> ValueG<T> {
+        let eps = T::from_f64(DOMAIN_EPS);
+        if positive {
+            &(self - eps).relu() + eps
+        } else {
+            -&(&(&(-self) - eps).relu() + eps)
+        }
+    }
+
+    // The label in quotes, or "node N" for unlabeled nodes
+    fn describe(&self) -> String {
+        self.label().map_or_else(|| format!("node {}", self.node.index()), |l| format!("{:?}", l))
+    }
+
+    pub fn powf(&self, other: T) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::PowF(other.to_f64())).powf(other),
+            std::slice::from_ref(self),
+            OpKind::PowF(other.to_f64())
+        );
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, other * self_ref.data_in(view).powf(other - T::one()) * out_grad);
+        });
+
+        out
+    }
+
+    // self ^ other with a learnable exponent. ln(self) is undefined for self <= 0, where the exponent gets no
+    // gradient instead of NaN; the base gets b * a^(b - 1) as for powf.
+    pub fn pow(&self, other: &ValueG<T>) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Pow).powf(other.operand_data(OpKind::Pow)),
+            &[self.internal(), other.internal()],
+            OpKind::Pow
+        );
+
+        let (out_ref, self_ref, other_ref) = (out.internal(), self.internal(), other.internal());
+        out.set_backward(move |view| {
+            let (out_grad, out_data) = (out_ref.grad_in(view), out_ref.data_in(view));
+            let (a, b) = (self_ref.data_in(view), other_ref.data_in(view));
+            self_ref.add_grad_in(view, b * a.powf(b - T::one()) * out_grad);
+            if a > T::zero() {
+                other_ref.add_grad_in(view, out_data * a.ln() * out_grad);
+            }
+        });
+
+        out
+    }
+}
+
+/// Shows the node itself without recursing: data, grad, op, label, number of operands, arena id and node
+/// index. The alternate form `{:#?}` adds one line per operand.
+///
+/// ```
+/// use rust_micrograd::{Arena, ValueFactory};
+///
+/// let (_arena_life_time, arena_ref) = Arena::build();
+/// let vf = ValueFactory::new(arena_ref);
+/// let a = vf.value(2.0);
+/// let b = dbg!(&a * 3.0);
+/// assert!(format!("{:?}", b).starts_with("Value { data: 6.0, grad: 0.0, op: Some(MulScalar(3.0)), children: 1"));
+/// ```
+impl<T: Float> fmt::Debug for ValueG<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_node(f)?;
+        if f.alternate() {
+            let children = self.node.with(|v| v.prev.iter().map(ValueG::internal).collect::<Vec<_>>()).unwrap_or_default();
+            for (i, child) in children.iter().enumerate() {
+                write!(f, "\n  child {}: ", i)?;
+                child.fmt_node(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Float> ValueG<T> {
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (arena, node) = (self.arena_id(), self.node.index());
+        let written = self.node.with(|v| {
+            write!(f, "Value {{ data: {:?}, grad: {:?}, op: {:?}, ", v.data, v.grad, v.op)?;
+            if let Some(label) = &v.label {
+                write!(f, "label: {:?}, ", label)?;
+            }
+            write!(f, "children: {}, arena: {}, node: {} }}", v.prev.iter().len(), arena, node)
+        });
+        written.unwrap_or_else(|| write!(f, "Value {{ gone, arena: {}, node: {} }}", arena, node))
+    }
+}
+
+// How data and grad are printed: precision is the number of decimals (of the mantissa when scientific),
+// None prints the shortest representation that round trips, as Display does
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FormatOptions {
+    pub precision: Option<usize>,
+    pub scientific: bool
 }
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
-        self.value.ptr_eq(&other.value)
+impl FormatOptions {
+    pub fn format<T: Float>(&self, x: T) -> String {
+        match (self.precision, self.scientific) {
+            (Some(p), true) => format!("{:.*e}", p, x),
+            (None, true) => format!("{:e}", x),
+            (Some(p), false) => format!("{:.*}", p, x),
+            (None, false) => format!("{}", x)
+        }
+    }
+}
+
+// draw_dot_with settings. The default prints 4 decimals, as draw_dot does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DotOptions {
+    pub format: FormatOptions
+}
+
+impl Default for DotOptions {
+    fn default() -> DotOptions {
+        DotOptions { format: FormatOptions { precision: Some(4), scientific: false } }
+    }
+}
+
+impl<T: Float> ValueG<T> {
+    // Like Display, e.g. "Value(data=3.333e-1, grad=1e0)" with precision 3 in scientific notation
+    pub fn format_with(&self, options: &FormatOptions) -> String {
+        let (data, grad) = self.with_borrow(|v| (v.data, v.grad));
+        format!("Value(data={}, grad={})", options.format(data), options.format(grad))
+    }
+}
+
+impl<T: Float> fmt::Display for ValueG<T> {
+    // f"Value(data={self.data}, grad={self.grad})"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.with_borrow(|v| v.fmt(f))
+    }
+}
+
+impl<'b, T: Float> ops::Add<&'b ValueG<T>> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn add(self, rhs: &'b ValueG<T>) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Add) + rhs.operand_data(OpKind::Add),
+            &[self.internal(), rhs.internal()],
+            OpKind::Add
+        );
+        
+        let (out_ref, self_ref, rhs_ref) = (out.internal(), self.internal(), rhs.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, out_grad);
+            rhs_ref.add_grad_in(view, out_grad);
+        });
+
+        out
+    }
+}
+
+// The scalar is stored in the op rather than in a node of its own
+impl<T: Float> ops::Add<T> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn add(self, rhs: T) -> ValueG<T> {
+        let op = OpKind::AddScalar(rhs.to_f64());
+        let out = ValueG::new(self.arena().clone(), self.operand_data(op) + rhs, &[self.internal()], op);
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, out_grad);
+        });
+
+        out
+    }
+}
+
+impl<T: Float> ops::Neg for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn neg(self) -> ValueG<T> {
+        self * -T::one()
+    }
+}
+
+impl<'b, T: Float> ops::Sub<&'b ValueG<T>> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn sub(self, rhs: &'b ValueG<T>) -> ValueG<T> {
+        self + &(-rhs)
+    }
+}
+
+impl<T: Float> ops::Sub<T> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn sub(self, rhs: T) -> ValueG<T> {
+        self + -rhs
+    }
+}
+
+impl<'b, T: Float> ops::Mul<&'b ValueG<T>> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn mul(self, rhs: &'b ValueG<T>) -> ValueG<T> {
+        let out = ValueG::new(
+            self.arena().clone(),
+            self.operand_data(OpKind::Mul) * rhs.operand_data(OpKind::Mul),
+            &[self.internal(), rhs.internal()],
+            OpKind::Mul
+        );
+
+        let (out_ref, self_ref, rhs_ref) = (out.internal(), self.internal(), rhs.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, rhs_ref.data_in(view) * out_grad);
+            rhs_ref.add_grad_in(view, self_ref.data_in(view) * out_grad);
+        });
+
+        out
+    }
+}
+
+impl<T: Float> ops::Mul<T> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn mul(self, rhs: T) -> ValueG<T> {
+        let op = OpKind::MulScalar(rhs.to_f64());
+        let out = ValueG::new(self.arena().clone(), self.operand_data(op) * rhs, &[self.internal()], op);
+
+        let (out_ref, self_ref) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            self_ref.add_grad_in(view, rhs * out_grad);
+        });
+
+        out
+    }
+}
+
+impl<'b, T: Float> ops::Div<&'b ValueG<T>> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn div(self, rhs: &'b ValueG<T>) -> ValueG<T> {
+        self * &rhs.powi(-1)
+    }
+}
+
+impl<T: Float> ops::Div<T> for &ValueG<T> {
+    type Output = ValueG<T>;
+
+    fn div(self, rhs: T) -> ValueG<T> {
+        self * rhs.powi(-1)
+    }
+}
+
+// scalar on the left: the orphan rule rules out a blanket impl over T, so one set per Float type
+macro_rules! scalar_lhs_ops {
+    ($($t:ty),*) => {$(
+        impl<'a> ops::Add<&'a ValueG<$t>> for $t {
+            type Output = ValueG<$t>;
+
+            fn add(self, rhs: &'a ValueG<$t>) -> ValueG<$t> {
+                rhs + self
+            }
+        }
+
+        impl<'a> ops::Sub<&'a ValueG<$t>> for $t {
+            type Output = ValueG<$t>;
+
+            fn sub(self, rhs: &'a ValueG<$t>) -> ValueG<$t> {
+                &(-rhs) + self
+            }
+        }
+
+        impl<'a> ops::Mul<&'a ValueG<$t>> for $t {
+            type Output = ValueG<$t>;
+
+            fn mul(self, rhs: &'a ValueG<$t>) -> ValueG<$t> {
+                rhs * self
+            }
+        }
+
+        impl<'a> ops::Div<&'a ValueG<$t>> for $t {
+            type Output = ValueG<$t>;
+
+            fn div(self, rhs: &'a ValueG<$t>) -> ValueG<$t> {
+                &rhs.powi(-1) * self
+            }
+        }
+    )*};
+}
+
+scalar_lhs_ops!(f32, f64);
+
+// Adds a node's contribution to its operands' grads, reading and writing nodes through the view of the whole
+// arena that backward_along holds
+type BackwardFn<T> = dyn Fn(&mut ArenaView<'_, ValueDataG<T>>);
+
+pub struct ValueDataG<T: Float> {
+    data: T,
+    grad: T,
+    // None for leaves. Rc so backward can take it out of the view before calling it with the view.
+    backward: Option<Rc<BackwardFn<T>>>,
+    // distinct children in operand order; a Vec rather than a set so backward visits nodes deterministically
+    prev: Children<T>,
+    op: Option<OpKind>,
+    // optional name shown by draw_dot and kept by graph dumps
+    label: Option<String>,
+    kind: NodeKind
+}
+
+impl<T: Float> ValueDataG<T> {
+    fn closure_bytes(&self) -> usize {
+        self.backward.as_ref().map_or(0, |b| mem::size_of_val(&**b))
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.prev.heap_bytes() + self.label.as_ref().map_or(0, String::capacity)
+    }
+
+    fn new(data: T, grad: T, children: &[ValueG<T>], op: Option<OpKind>) -> ValueDataG<T> {
+        let mut prev = Children::None;
+        children.iter().for_each(|c| if !prev.contains(c) {prev.push(c.internal())});
+        ValueDataG { data, grad, backward: None, prev, op, label: None, kind: NodeKind::Plain }
+    }
+}
+
+// A Value whose node was freed, or whose whole arena was dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DanglingValueError {
+    pub arena: u64,
+    pub node: usize,
+    pub arena_dropped: bool
+}
+
+impl fmt::Display for DanglingValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = if self.arena_dropped {"arena was dropped"} else {"node was freed"};
+        write!(f, "node {} of arena {} is gone ({})", self.node, self.arena, reason)
+    }
+}
+
+impl std::error::Error for DanglingValueError {}
+
+// Rejected bindings of bind_inputs or bind_named: values or labels not found in the graph, and bound nodes
+// that are not input leaves (op nodes and constants)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BindError {
+    pub unknown: Vec<String>,
+    pub not_leaves: Vec<String>
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if !self.unknown.is_empty() {
+            parts.push(format!("not in the graph: {}", self.unknown.join(", ")));
+        }
+        if !self.not_leaves.is_empty() {
+            parts.push(format!("not input leaves: {}", self.not_leaves.join(", ")));
+        }
+        write!(f, "cannot bind inputs; {}", parts.join("; "))
+    }
+}
+
+impl std::error::Error for BindError {}
+
+impl TryFrom<&ValueG<f64>> for f64 {
+    type Error = DanglingValueError;
+
+    fn try_from(v: &ValueG<f64>) -> Result<f64, DanglingValueError> {
+        v.try_item()
+    }
+}
+
+impl TryFrom<&ValueG<f32>> for f32 {
+    type Error = DanglingValueError;
+
+    fn try_from(v: &ValueG<f32>) -> Result<f32, DanglingValueError> {
+        v.try_item()
+    }
+}
+
+// A checked operation refused its operands, which are named by label or node index
+#[derive(Clone, Debug, PartialEq)]
+pub enum MathError {
+    // numerator is None for reciprocals
+    DivisionByZero { numerator: Option<String>, divisor: String, data: f64, eps: f64 },
+    // op is the name draw_dot shows, e.g. "powf0.5"
+    Domain { op: String, operand: String, data: f64 }
+}
+
+// How far inside the domain DomainPolicy::Clamp moves invalid inputs
+pub const DOMAIN_EPS: f64 = 1e-12;
+
+// What the safe_ ops do with inputs for which the op is NaN or infinite
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainPolicy {
+    // return MathError::Domain
+    Error,
+    // move the input DOMAIN_EPS inside the domain, or away from the pole at zero. The clamped input takes
+    // no gradient. Valid inputs are left alone however close to the edge they are.
+    Clamp
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::DivisionByZero { numerator, divisor, data, eps } => {
+                match numerator {
+                    Some(numerator) => write!(f, "cannot divide {} by {}", numerator, divisor)?,
+                    None => write!(f, "cannot take the reciprocal of {}", divisor)?
+                }
+                write!(f, ": its data {:e} is within {:e} of zero", data, eps)
+            }
+            MathError::Domain { op, operand, data } => write!(f, "{} of {} is undefined: its data is {:e}", op, operand, data)
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+// What a node stands for. Constants take no gradient; parameters are leaves an optimizer updates, which
+// draw_dot highlights. Everything else, op nodes included, is Plain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Plain,
+    Param,
+    Constant
+}
+
+// Operation that produced a node. Displays as the op label draw_dot shows, e.g. "+", "ReLU" or "powi2".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpKind {
+    Add,
+    Mul,
+    Relu,
+    Tanh,
+    Exp,
+    Ln,
+    Sigmoid,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    // children are (y, x), in the argument order of f64::atan2
+    Atan2,
+    Max,
+    Min,
+    // base ^ exponent with both as children, in that order
+    Pow,
+    Sinh,
+    Cosh,
+    Sqrt,
+    Cbrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Round,
+    RoundSte,
+    Exp2,
+    Log2,
+    Log10,
+    PowI(i32),
+    PowF(f64),
+    // x + c and x * c with the scalar c inline; subtraction, negation and division by a scalar go through them
+    AddScalar(f64),
+    MulScalar(f64),
+    // max(x, c) and min(x, c)
+    MaxScalar(f64),
+    MinScalar(f64),
+    // x clipped to [lo, hi]
+    Clamp(f64, f64),
+    // for ops defined outside the engine
+    Custom(&'static str)
+}
+
+impl fmt::Display for OpKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpKind::Add => write!(f, "+"),
+            OpKind::Mul => write!(f, "*"),
+            OpKind::Relu => write!(f, "ReLU"),
+            OpKind::Tanh => write!(f, "tanh"),
+            OpKind::Exp => write!(f, "exp"),
+            OpKind::Ln => write!(f, "ln"),
+            OpKind::Sigmoid => write!(f, "sigmoid"),
+            OpKind::Sin => write!(f, "sin"),
+            OpKind::Cos => write!(f, "cos"),
+            OpKind::Tan => write!(f, "tan"),
+            OpKind::Asin => write!(f, "asin"),
+            OpKind::Acos => write!(f, "acos"),
+            OpKind::Atan => write!(f, "atan"),
+            OpKind::Atan2 => write!(f, "atan2"),
+            OpKind::Max => write!(f, "max"),
+            OpKind::Min => write!(f, "min"),
+            OpKind::Pow => write!(f, "pow"),
+            OpKind::Sinh => write!(f, "sinh"),
+            OpKind::Cosh => write!(f, "cosh"),
+            OpKind::Sqrt => write!(f, "sqrt"),
+            OpKind::Cbrt => write!(f, "cbrt"),
+            OpKind::Abs => write!(f, "abs"),
+            OpKind::Sign => write!(f, "sign"),
+            OpKind::Floor => write!(f, "floor"),
+            OpKind::Ceil => write!(f, "ceil"),
+            OpKind::Round => write!(f, "round"),
+            OpKind::RoundSte => write!(f, "round_ste"),
+            OpKind::Exp2 => write!(f, "exp2"),
+            OpKind::Log2 => write!(f, "log2"),
+            OpKind::Log10 => write!(f, "log10"),
+            OpKind::PowI(n) => write!(f, "powi{}", n),
+            OpKind::PowF(x) => write!(f, "powf{}", x),
+            OpKind::AddScalar(x) => write!(f, "+{}", x),
+            OpKind::MulScalar(x) => write!(f, "*{}", x),
+            OpKind::MaxScalar(x) => write!(f, "max{}", x),
+            OpKind::MinScalar(x) => write!(f, "min{}", x),
+            OpKind::Clamp(lo, hi) => write!(f, "clamp[{},{}]", lo, hi),
+            OpKind::Custom(name) => write!(f, "{}", name)
+        }
+    }
+}
+
+// Operands of a node. Every op has one or two, which are stored inline; a longer list spills to a Vec.
+enum Children<T: Float> {
+    None,
+    One([ValueG<T>; 1]),
+    Two([ValueG<T>; 2]),
+    Many(Vec<ValueG<T>>)
+}
+
+impl<T: Float> Children<T> {
+    fn push(&mut self, v: ValueG<T>) {
+        *self = match mem::replace(self, Children::None) {
+            Children::None => Children::One([v]),
+            Children::One([a]) => Children::Two([a, v]),
+            Children::Two([a, b]) => Children::Many(vec![a, b, v]),
+            Children::Many(mut vs) => {
+                vs.push(v);
+                Children::Many(vs)
+            }
+        };
+    }
+
+    fn as_slice(&self) -> &[ValueG<T>] {
+        match self {
+            Children::None => &[],
+            Children::One(vs) => vs,
+            Children::Two(vs) => vs,
+            Children::Many(vs) => vs
+        }
+    }
+
+    fn iter(&self) -> std::slice::Iter<'_, ValueG<T>> {
+        self.as_slice().iter()
+    }
+
+    fn contains(&self, v: &ValueG<T>) -> bool {
+        self.as_slice().contains(v)
+    }
+
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Children::Many(vs) => vs.capacity() * mem::size_of::<ValueG<T>>(),
+            _ => 0
+        }
+    }
+}
+
+impl<T: Float> ValueDataG<T> {
+    // For panic messages, e.g. node #482 (op=tanh, label="h1")
+    fn describe(&self, index: usize) -> String {
+        let op = self.op.map_or(String::from("leaf"), |op| format!("op={}", op));
+        match &self.label {
+            Some(label) => format!("node #{} ({}, label={:?})", index, op, label),
+            None => format!("node #{} ({})", index, op)
+        }
+    }
+}
+
+impl<T: Float> fmt::Display for ValueDataG<T> {
+    // f"Value(data={self.data}, grad={self.grad})"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Value(data={}, grad={})", self.data, self.grad)
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_grad_close, assert_value_close};
+    use crate::Arena;
+
+    #[test]
+    fn display() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let v = vf.value(4.2);
+        assert_eq!(v.to_string(), "Value(data=4.2, grad=0)");
+    }
+
+    #[test]
+    fn add() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(2.5);
+        let b = vf.value(3.2);
+        let c = &a + &b;
+        let d = &(&a + &b) + &c;
+        assert_eq!(c.get_data(), 5.7);
+        assert_eq!(d.get_data(), 11.4);
+        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+        assert_eq!(d.to_string(), "Value(data=11.4, grad=0)");
+    }
+
+    #[test]
+    fn add_grad() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // Value + Value
+        let a = vf.value(2.5);
+        let b = vf.value(3.2);
+        let c = &a + &b;
+        assert_eq!(c.get_data(), 5.7);
+        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 1.0);
+        assert_eq!(b.get_grad(), 1.0);
+
+        // Value + f64
+        let a = vf.value(2.5);
+        let b = 3.2;
+        let c = &a + b;
+        assert_eq!(c.get_data(), 5.7);
+        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 1.0);
+
+        // f64 + Value
+        let a = 2.5;
+        let b = vf.value(3.2);
+        let c = a + &b;
+        assert_eq!(c.get_data(), 5.7);
+        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(b.get_grad(), 1.0);
+    }
+
+    #[test]
+    fn sub() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // Value - Value
+        let a = vf.value(2.5);
+        let b = vf.value(5.0);
+        let c = &a - &b;
+        assert_eq!(c.get_data(), -2.5);
+        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 1.0);
+        assert_eq!(b.get_grad(), -1.0);
+        
+        // Value - f64
+        let a = vf.value(2.5);
+        let b = 5.0;
+        let c = &a - b;
+        assert_eq!(c.get_data(), -2.5);
+        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 1.0);
+
+        // f64 - Value
+        let a = 2.5;
+        let b = vf.value(5.0);
+        let c = a - &b;
+        assert_eq!(c.get_data(), -2.5);
+        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(b.get_grad(), -1.0);
+    }
+
+    #[test]
+    fn mul() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        
+        // value mul with other values
+        let a = vf.value(2.5);
+        let b = vf.value(3.0);
+        let c = &a * &b;
+        let d = &(&a * &b) * &c;
+        assert_eq!(c.get_data(), 7.5);
+        assert_eq!(d.get_data(), 56.25);
+        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
+        assert_eq!(d.to_string(), "Value(data=56.25, grad=0)");
+
+        // float mul with value
+        let e = 2.0 * &d;
+        assert_eq!(e.get_data(), 112.5);
+        assert_eq!(e.to_string(), "Value(data=112.5, grad=0)");
+
+        // value mul with float
+        let f = &e * 2.0;
+        assert_eq!(f.get_data(), 225.0);
+        assert_eq!(f.to_string(), "Value(data=225, grad=0)");
+    }
+
+    #[test]
+    fn mul_grad() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // value * value
+        let a = vf.value(2.5);
+        let b = vf.value(3.0);
+        let c = &a * &b;
+        assert_eq!(c.get_data(), 7.5);
+        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 3.0);
+        assert_eq!(b.get_grad(), 2.5);
+
+        // value * float
+        let e = vf.value(2.5);
+        let f = &e * 3.0;
+        assert_eq!(f.get_data(), 7.5);
+        assert_eq!(f.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(e.get_grad(), 0.0);
+        f.backward();
+        assert_eq!(e.get_grad(), 3.0);
+
+        // float * value
+        let g = vf.value(3.0);
+        let h = 2.5 * &g;
+        assert_eq!(h.get_data(), 7.5);
+        assert_eq!(h.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(g.get_grad(), 0.0);
+        h.backward();
+        assert_eq!(h.to_string(), "Value(data=7.5, grad=1)");
+        assert_eq!(g.get_grad(), 2.5);
+        assert_eq!(g.to_string(), "Value(data=3, grad=2.5)");
+    }
+
+    #[test]
+    fn pow() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        
+        // pow with int
+        let a = vf.value(2.0);
+        let b = a.powi(2);
+        assert_eq!(b.get_data(), 4.0);
+        assert_eq!(b.to_string(), "Value(data=4, grad=0)");
+
+        // test grad as well
+        b.backward();
+        assert_eq!(b.get_grad(), 1.0);
+        assert_eq!(b.to_string(), "Value(data=4, grad=1)");
+        assert_eq!(a.get_grad(), 4.0);
+        assert_eq!(a.to_string(), "Value(data=2, grad=4)");
+
+        // pow with float works the same as pow with int
+        let a = vf.value(2.0);
+        let b = a.powf(2.0);
+        assert_eq!(b.get_data(), 4.0);
+        assert_eq!(b.to_string(), "Value(data=4, grad=0)");
+
+        // test grad as well
+        b.backward();
+        assert_eq!(b.get_grad(), 1.0);
+        assert_eq!(b.to_string(), "Value(data=4, grad=1)");
+        assert_eq!(a.get_grad(), 4.0);
+        assert_eq!(a.to_string(), "Value(data=2, grad=4)");
+    }
+
+    #[test]
+    fn neg() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        
+        let a = vf.value(2.0);
+        let b = -&a;
+        assert_eq!(b.get_data(), -2.0);
+        assert_eq!(b.to_string(), "Value(data=-2, grad=0)");
+
+        // test grad as well
+        b.backward();
+        assert_eq!(b.get_grad(), 1.0);
+        assert_eq!(b.to_string(), "Value(data=-2, grad=1)");
+        assert_eq!(a.get_grad(), -1.0);
+        assert_eq!(a.to_string(), "Value(data=2, grad=-1)");
+    }
+
+    #[test]
+    fn div() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // value / value
+        let a = vf.value(2.5);
+        let b = vf.value(1.0/3.0);
+        let c = &a / &b;
+        assert_eq!(c.get_data(), 7.5);
+        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(a.get_grad(), 0.0);
+        assert_eq!(b.get_grad(), 0.0);
+        c.backward();
+        assert_eq!(a.get_grad(), 3.0);
+        assert_eq!(b.get_grad(), -22.5);
+
+        // value / float
+        let e = vf.value(2.5);
+        let f = &e / (1.0/3.0);
+        assert_eq!(f.get_data(), 7.5);
+        assert_eq!(f.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(e.get_grad(), 0.0);
+        f.backward();
+        assert_eq!(e.get_grad(), 3.0);
+
+        // float / value
+        let g = vf.value(1.0/3.0);
+        let h = 2.5 / &g;
+        assert_eq!(h.get_data(), 7.5);
+        assert_eq!(h.to_string(), "Value(data=7.5, grad=0)");
+
+        assert_eq!(g.get_grad(), 0.0);
+        h.backward();
+        assert_eq!(h.to_string(), "Value(data=7.5, grad=1)");
+        assert_eq!(g.get_grad(), -22.5);
+        assert_eq!(g.to_string(), "Value(data=0.3333333333333333, grad=-22.5)");
+    }
+
+    #[test]
+    fn relu() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        
+        let a = vf.value(2.0);
+        let b = a.relu();
+        assert_eq!(b.get_data(), 2.0);
+        assert_eq!(b.to_string(), "Value(data=2, grad=0)");
+
+        // test grad as well
+        b.backward();
+        assert_eq!(b.get_grad(), 1.0);
+        assert_eq!(b.to_string(), "Value(data=2, grad=1)");
+        assert_eq!(a.get_grad(), 1.0);
+        assert_eq!(a.to_string(), "Value(data=2, grad=1)");
+
+        // less than 0 case
+        let c = vf.value(-2.0);
+        let d = c.relu();
+        assert_eq!(d.get_data(), 0.0);
+        assert_eq!(d.to_string(), "Value(data=0, grad=0)");
+
+        // test grad as well
+        d.backward();
+        assert_eq!(d.get_grad(), 1.0);
+        assert_eq!(d.to_string(), "Value(data=0, grad=1)");
+        assert_eq!(c.get_grad(), 0.0);
+        assert_eq!(c.to_string(), "Value(data=-2, grad=0)");
+    }
+
+    #[test]
+    fn tanh() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(2.0);
+        let b = a.tanh();
+
+        let expected_data = 2.0_f64.tanh();
+        assert_value_close!(b, expected_data, 1e-12);
+        assert_eq!(b.to_string(), format!("Value(data={}, grad=0)", b.get_data()));
+
+        // test grad as well
+        b.backward();
+
+        assert_grad_close!(a, 1.0 - expected_data * expected_data, 1e-12);
+        assert_grad_close!(b, 1.0, 0.0);
+    }
+
+    #[test]
+    fn exp() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(2.0);
+        let b = a.exp();
+
+        let expected_data = 2.0_f64.exp();
+        assert_value_close!(b, expected_data, 1e-12);
+        assert_eq!(b.to_string(), format!("Value(data={}, grad=0)", b.get_data()));
+
+        // test grad as well
+        b.backward();
+
+        assert_grad_close!(a, expected_data, 1e-12);
+        assert_grad_close!(b, 1.0, 0.0);
     }
-}
 
-impl Eq for Value {}
+    #[test]
+    fn ln() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(2.5);
+        let b = a.ln();
+        assert_eq!((b.get_data(), b.op()), (2.5f64.ln(), Some(OpKind::Ln)));
+        b.backward();
+        assert_grad_close!(a, 0.4, 1e-12);
+
+        // round trips through exp, with an end to end gradient of 1
+        let x = vf.value(0.7);
+        let y = x.ln().exp();
+        let z = x.exp().ln();
+        (&y + &z).backward();
+        assert_value_close!(y, 0.7, 1e-12);
+        assert_value_close!(z, 0.7, 1e-12);
+        assert_grad_close!(x, 2.0, 1e-12);
+        // ln(x^3) = 3 ln(x)
+        let x = vf.value(1.3);
+        let y = x.powf(3.0).ln();
+        y.backward();
+        assert_value_close!(y, 3.0 * 1.3f64.ln(), 1e-12);
+        assert_grad_close!(x, 3.0 / 1.3, 1e-12);
+
+        // the edges give what floats give, without panicking
+        let zero = vf.value(0.0);
+        let y = zero.ln();
+        y.backward();
+        assert_eq!((y.get_data(), zero.get_grad()), (f64::NEG_INFINITY, f64::INFINITY));
+        assert!(vf.value(-1.0).ln().get_data().is_nan());
+
+        assert_eq!(vf.value_labeled(0.0, "p").try_ln().unwrap_err().to_string(), "ln of \"p\" is undefined: its data is 0e0");
+        let clamped = zero.safe_ln(DomainPolicy::Clamp).unwrap();
+        assert_value_close!(clamped, DOMAIN_EPS.ln(), 1e-9);
+        assert_eq!(a.try_ln().unwrap().get_data(), b.get_data());
+    }
 
-impl Hash for Value {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.value.as_ptr().hash(state);
+    // central difference of f at x
+    fn numeric_grad(f: impl Fn(f64) -> f64, x: f64) -> f64 {
+        let h = 1e-6;
+        (f(x + h) - f(x - h)) / (2.0 * h)
     }
-}
 
-pub struct ValueData {
-    data: f64,
-    grad: f64,
-    backward: Box<dyn Fn()>,
-    prev: HashSet<Value>,
-    op: Option<String>,
-}
+    #[test]
+    fn sigmoid() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [-3.0, -0.5, 0.0, 0.25, 4.0] {
+            let a = vf.value(x);
+            let s = a.sigmoid();
+            s.backward();
+            assert_eq!(s.op(), Some(OpKind::Sigmoid));
+            assert_value_close!(s, 1.0 / (1.0 + (-x).exp()), 1e-15);
+            assert_grad_close!(a, numeric_grad(|x| 1.0 / (1.0 + (-x).exp()), x), 1e-9);
+        }
 
-impl ValueData {
-    fn new(data: f64, grad: f64, backward: Box<dyn Fn()>, children: &[Value], op: Option<String>) -> ValueData {
-        ValueData { data, grad, backward, prev: children.iter().cloned().collect(), op }
+        // far out, where 1 - s rounds to 0 and 1 + e^50 swallows the 1
+        let (pos, neg) = (vf.value(50.0), vf.value(-50.0));
+        let (sp, sn) = (pos.sigmoid(), neg.sigmoid());
+        (&sp + &sn).backward();
+        let tail = (-50.0f64).exp();
+        assert_eq!(sp.get_data(), 1.0);
+        assert!(((sn.get_data() - tail) / tail).abs() < 1e-15);
+        assert!(((neg.get_grad() - tail) / tail).abs() < 1e-15);
+        assert_eq!(pos.get_grad(), 0.0);
+        // no overflow to NaN however far
+        let far = vf.value(-1000.0);
+        let s = far.sigmoid();
+        s.backward();
+        assert_eq!((s.get_data(), far.get_grad()), (0.0, 0.0));
     }
-}
 
-impl fmt::Display for ValueData {
-    // f"Value(data={self.data}, grad={self.grad})"
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Value(data={}, grad={})", self.data, self.grad)
+    #[test]
+    fn sin_and_cos() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [-2.0, -0.3, 0.0, 1.0, 3.5] {
+            let a = vf.value(x);
+            let (s, c) = (a.sin(), a.cos());
+            assert_eq!((s.get_data(), c.get_data()), (f64::sin(x), f64::cos(x)));
+            // sin^2 + cos^2 is flat
+            let one = &s.powi(2) + &c.powi(2);
+            one.backward();
+            assert_value_close!(one, 1.0, 1e-15);
+            assert_grad_close!(a, 0.0, 1e-15);
+        }
+
+        // chain rule through a composite: d/dx sin(x^2) cos(x) by finite differences
+        let f = |x: f64| (x * x).sin() * x.cos();
+        let a = vf.value(0.8);
+        let y = &(&a * &a).sin() * &a.cos();
+        y.backward();
+        assert_grad_close!(a, numeric_grad(f, 0.8), 1e-8);
+        assert!(y.draw_dot().contains("[ label = \"sin\" ]") && y.draw_dot().contains("[ label = \"cos\" ]"));
     }
-}
 
-/******************************** unit tests ********************************/
+    #[test]
+    fn tan() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::Arena;
+        for x in [-1.2, -0.4, 0.0, 0.7, 1.4] {
+            let a = vf.value(x);
+            let t = a.tan();
+            t.backward();
+            assert_eq!(t.get_data(), x.tan());
+            assert_grad_close!(a, numeric_grad(f64::tan, x), 1e-6);
+            assert_grad_close!(a, 1.0 / x.cos().powi(2), 1e-12);
+        }
+
+        // next to the pole the data is large but finite, and so is the gradient
+        let a = vf.value(std::f64::consts::FRAC_PI_2 - 1e-8);
+        let t = a.tan();
+        t.backward();
+        assert!(t.get_data() > 9e7 && t.get_data().is_finite());
+        assert!(a.get_grad() > 9e15 && a.get_grad().is_finite());
+        assert_eq!(t.op().unwrap().to_string(), "tan");
+    }
 
     #[test]
-    fn display() {
+    fn inverse_trig() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        let v = vf.value(4.2);
-        assert_eq!(v.to_string(), "Value(data=4.2, grad=0)");
+        type Inverse = (UnaryOp<f64>, fn(f64) -> f64, &'static str);
+        let inverses: [Inverse; 3] = [(Value::asin, f64::asin, "asin"), (Value::acos, f64::acos, "acos"), (Value::atan, f64::atan, "atan")];
+        for (op, f, name) in inverses {
+            for x in [-0.9, -0.2, 0.0, 0.5, 0.95] {
+                let a = vf.value(x);
+                let y = op(&a);
+                y.backward();
+                assert_eq!((y.get_data(), y.op().unwrap().to_string()), (f(x), String::from(name)));
+                assert_grad_close!(a, numeric_grad(f, x), 1e-6);
+            }
+        }
+
+        // the edges of asin and acos: infinite gradients, no panic
+        for x in [-1.0, 1.0] {
+            let (a, b) = (vf.value(x), vf.value(x));
+            a.asin().backward();
+            b.acos().backward();
+            assert_eq!((a.get_grad(), b.get_grad()), (f64::INFINITY, f64::NEG_INFINITY));
+        }
+
+        // atan undoes tan near 0, gradient included
+        for x in [-0.3, 0.01, 0.4] {
+            let a = vf.value(x);
+            let y = a.tan().atan();
+            y.backward();
+            assert_value_close!(y, x, 1e-15);
+            assert_grad_close!(a, 1.0, 1e-12);
+        }
     }
 
     #[test]
-    fn add() {
+    fn atan2() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        let a = vf.value(2.5);
-        let b = vf.value(3.2);
-        let c = &a + &b;
-        let d = &(&a + &b) + &c;
-        assert_eq!(c.get_data(), 5.7);
-        assert_eq!(d.get_data(), 11.4);
-        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
-        assert_eq!(d.to_string(), "Value(data=11.4, grad=0)");
+        // one point per quadrant, plus the axes away from the cut along negative x
+        for (y, x) in [(1.0, 2.0), (0.5, -1.5), (-2.0, -0.25), (-1.0, 3.0), (0.0, 1.0), (2.0, 0.0)] {
+            let (vy, vx) = (vf.value(y), vf.value(x));
+            let angle = vy.atan2(&vx);
+            angle.backward();
+            assert_eq!((angle.get_data(), angle.op()), (f64::atan2(y, x), Some(OpKind::Atan2)));
+            assert_grad_close!(vy, numeric_grad(|y| y.atan2(x), y), 1e-6);
+            assert_grad_close!(vx, numeric_grad(|x| y.atan2(x), x), 1e-6);
+        }
+
+        // the origin takes no gradient instead of NaN
+        let (y, x) = (vf.value(0.0), vf.value(0.0));
+        let angle = y.atan2(&x);
+        angle.backward();
+        assert_eq!((angle.get_data(), y.get_grad(), x.get_grad()), (0.0, 0.0, 0.0));
     }
 
     #[test]
-    fn add_grad() {
+    fn sinh_and_cosh() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        // Value + Value
-        let a = vf.value(2.5);
-        let b = vf.value(3.2);
+        for x in [-2.5, -0.5, 0.0, 0.75, 3.0] {
+            let a = vf.value(x);
+            let (s, c) = (a.sinh(), a.cosh());
+            assert_eq!((s.get_data(), c.get_data()), (x.sinh(), x.cosh()));
+            // cosh^2 - sinh^2 is identically 1, so nothing flows back to x
+            let one = &c.powi(2) - &s.powi(2);
+            one.backward();
+            assert_value_close!(one, 1.0, 1e-12);
+            assert_grad_close!(a, 0.0, 1e-12);
+
+            let b = vf.value(x);
+            let sum = &b.sinh() + &(&b.cosh() * 2.0);
+            sum.backward();
+            assert_grad_close!(b, numeric_grad(|x| x.sinh() + 2.0 * x.cosh(), x), 1e-6);
+        }
+    }
+
+    #[test]
+    fn sqrt() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [1e-8, 0.25, 2.0, 1e6] {
+            let (a, b) = (vf.value(x), vf.value(x));
+            let (root, pow) = (a.sqrt(), b.powf(0.5));
+            root.backward();
+            pow.backward();
+            assert_eq!((root.get_data(), root.op()), (x.sqrt(), Some(OpKind::Sqrt)));
+            assert_grad_close!(a, b.get_grad(), 1e-12);
+        }
+
+        // at zero the parameter still gets the gradient of the other term instead of NaN
+        let w = vf.param(0.0);
+        let out = &w.sqrt() + &(&w * 3.0);
+        out.backward();
+        assert_eq!((out.get_data(), w.get_grad()), (0.0, 3.0));
+    }
+
+    #[test]
+    fn cbrt() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [-8.0, -1.0, 1.0, 8.0] {
+            let a = vf.value(x);
+            let root = a.cbrt();
+            root.backward();
+            assert_eq!(root.get_data(), f64::cbrt(x));
+            assert_grad_close!(a, numeric_grad(f64::cbrt, x), 1e-6);
+        }
+        assert!(vf.value(-8.0).powf(1.0 / 3.0).get_data().is_nan());
+
+        let w = vf.param(0.0);
+        let out = &w.cbrt() + &w;
+        out.backward();
+        assert_eq!((out.get_data(), w.get_grad()), (0.0, 1.0));
+    }
+
+    #[test]
+    fn abs() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for (x, grad) in [(2.5, 1.0), (-0.5, -1.0), (0.0, 0.0)] {
+            let a = vf.value(x);
+            let out = a.abs();
+            out.backward();
+            assert_eq!((out.get_data(), a.get_grad(), out.op()), (f64::abs(x), grad, Some(OpKind::Abs)));
+        }
+        assert_eq!(OpKind::Abs.to_string(), "abs");
+
+        // the L1 loss |w - 3| walks w to 3 in steps of 0.1 and then oscillates around it
+        let w = vf.param(0.0);
+        for _ in 0..40 {
+            w.set_grad(0.0);
+            let loss = (&w - 3.0).abs();
+            loss.backward();
+            w.set_data(w.get_data() - 0.1 * w.get_grad());
+        }
+        assert!((w.get_data() - 3.0).abs() <= 0.1, "{}", w.get_data());
+    }
+
+    #[test]
+    fn sign() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for (x, sign) in [(2.5, 1.0), (-0.5, -1.0), (0.0, 0.0)] {
+            let a = vf.value(x);
+            let s = a.sign();
+            assert_eq!((s.get_data(), s.op(), s.trace().1.len()), (sign, Some(OpKind::Sign), 1));
+            // x.sign() * x is |x| with only the direct path contributing
+            let out = &s * &a;
+            out.backward();
+            assert_eq!((out.get_data(), a.get_grad()), (f64::abs(x), sign));
+            assert_eq!(s.get_grad(), x);
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        type Round = fn(&Value) -> Value;
+        let ops: [(Round, &str, [f64; 4]); 4] = [
+            (Value::floor, "floor", [2.0, -3.0, 0.0, 2.0]),
+            (Value::ceil, "ceil", [3.0, -2.0, 1.0, 3.0]),
+            (Value::round, "round", [3.0, -3.0, 1.0, 2.0]),
+            (Value::round_ste, "round_ste", [3.0, -3.0, 1.0, 2.0])
+        ];
+        for (op, name, expected) in ops {
+            let data: Vec<f64> = [2.5, -2.5, 0.5, 2.25].into_iter().map(|x| op(&vf.value(x)).get_data()).collect();
+            assert_eq!((data.as_slice(), op(&vf.value(0.0)).op().unwrap().to_string()), (&expected[..], String::from(name)));
+        }
+
+        // fit round(w) to 3: the plain op leaves w where it is, the straight-through one moves it
+        let train = |op: Round| {
+            let w = vf.param(0.2);
+            for _ in 0..20 {
+                w.set_grad(0.0);
+                let loss = (&op(&w) - 3.0).powi(2);
+                loss.backward();
+                w.set_data(w.get_data() - 0.1 * w.get_grad());
+            }
+            w.get_data()
+        };
+        assert_eq!(train(Value::round), 0.2);
+        assert_eq!(train(Value::round_ste).round(), 3.0);
+    }
+
+    #[test]
+    fn max_and_min() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (a, b) = (vf.param(1.0), vf.param(2.0));
+        let grads = |out: &Value| {
+            a.set_grad(0.0);
+            b.set_grad(0.0);
+            out.backward();
+            (out.get_data(), a.get_grad(), b.get_grad())
+        };
+        assert_eq!(grads(&a.max(&b)), (2.0, 0.0, 1.0));
+        assert_eq!(grads(&a.min(&b)), (1.0, 1.0, 0.0));
+        // the winner follows the data at backward time
+        a.set_data(3.0);
+        assert_eq!(grads(&a.max(&b)), (3.0, 1.0, 0.0));
+        assert_eq!(grads(&a.min(&b)), (2.0, 0.0, 1.0));
+        // ties split the gradient, also between a node and itself
+        b.set_data(3.0);
+        assert_eq!(grads(&a.max(&b)), (3.0, 0.5, 0.5));
+        assert_eq!(grads(&a.max(&a)), (3.0, 1.0, 0.0));
+
+        assert_eq!(grads(&(&a.max_scalar(2.0) + &b.min_scalar(2.0))), (5.0, 1.0, 0.0));
+        assert_eq!(grads(&a.min_scalar(3.0)), (3.0, 1.0, 0.0));
+        assert_eq!(grads(&a.max_scalar(3.0)), (3.0, 1.0, 0.0));
+        assert_eq!(a.max_scalar(-1.5).op(), Some(OpKind::MaxScalar(-1.5)));
+        assert_eq!(max_by_data(&[a.clone(), b.clone(), vf.value(-1.0)]).1.get_data(), a.max(&b).max_scalar(-1.0).get_data());
+    }
+
+    #[test]
+    fn clamp() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for (x, data, grad) in [(-3.0, -1.0, 0.0), (0.25, 0.25, 1.0), (5.0, 2.0, 0.0), (-1.0, -1.0, 0.0), (2.0, 2.0, 0.0)] {
+            let w = vf.param(x);
+            let out = &w.clamp(-1.0, 2.0) * 3.0;
+            out.backward();
+            assert_eq!((out.get_data(), w.get_grad()), (data * 3.0, grad * 3.0), "{}", x);
+        }
+        assert_eq!(vf.value(0.5).clamp(-1.0, 2.0).op().unwrap().to_string(), "clamp[-1,2]");
+        assert_eq!(vf.value(7.0).clamp(1.5, 1.5).get_data(), 1.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "clamp bounds out of order")]
+    fn clamp_bounds() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        vf.value(0.0).clamp(1.0, -1.0);
+    }
+
+    #[test]
+    fn pow_value_exponent() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for (a, b) in [(2.0, 3.0), (0.5, -1.5), (3.0, 0.25), (1.5, 0.0)] {
+            let (base, exponent) = (vf.value(a), vf.value(b));
+            let out = base.pow(&exponent);
+            out.backward();
+            assert_eq!((out.get_data(), out.op()), (f64::powf(a, b), Some(OpKind::Pow)));
+            assert_grad_close!(base, numeric_grad(|a| a.powf(b), a), 1e-6);
+            assert_grad_close!(exponent, numeric_grad(|b| a.powf(b), b), 1e-6);
+
+            // a constant exponent agrees with powf
+            let x = vf.value(a);
+            let y = x.powf(b);
+            y.backward();
+            assert_eq!((out.get_data(), base.get_grad()), (y.get_data(), x.get_grad()));
+        }
+
+        // no exponent gradient where ln(base) is undefined
+        for a in [0.0, -2.0] {
+            let (base, exponent) = (vf.value(a), vf.value(2.0));
+            base.pow(&exponent).backward();
+            assert_eq!((base.get_grad(), exponent.get_grad()), (2.0 * a, 0.0));
+        }
+    }
+
+    #[test]
+    fn exp2_and_log2() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [-3.0, 0.0, 0.5, 10.0] {
+            let a = vf.value(x);
+            let out = a.exp2().log2();
+            out.backward();
+            assert_value_close!(out, x, 1e-12);
+            assert_grad_close!(a, 1.0, 1e-12);
+            assert_eq!(out.op(), Some(OpKind::Log2));
+        }
+        let a = vf.value(1.5);
+        let out = &a.exp2() + &a.log2();
+        out.backward();
+        assert_grad_close!(a, numeric_grad(|x| x.exp2() + x.log2(), 1.5), 1e-6);
+    }
+
+    #[test]
+    fn log10() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        for x in [0.01, 0.5, 1.0, 1000.0] {
+            let a = vf.value(x);
+            let db = &a.log10() * 10.0;
+            db.backward();
+            assert_eq!(a.log10().get_data(), x.log10());
+            assert_grad_close!(a, numeric_grad(|x| 10.0 * x.log10(), x), 1e-4);
+        }
+
+        // next to zero the data and gradient get large but stay finite
+        let a = vf.value(1e-300);
+        let out = a.log10();
+        out.backward();
+        assert_value_close!(out, -300.0, 1e-12);
+        assert!(a.get_grad() > 1e299 && a.get_grad().is_finite(), "{}", a.get_grad());
+    }
+
+    #[test]
+    fn reset() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.value(2.0);
+        let b = vf.value(-1.0);
+        let out = &(&w * 3.0) + &b;
+        out.backward();
+        assert_eq!(vf.arena().len(), 4);
+
+        assert_eq!(vf.arena().reset(&[w.clone(), b.clone()]), 2);
+        assert_eq!(vf.arena().len(), 2);
+        assert!(w.is_alive() && b.is_alive() && !out.is_alive());
+        assert_eq!((w.get_grad(), b.get_grad()), (3.0, 1.0));
+        let out = &w * &b;
+        out.backward();
+        assert_eq!(out.get_data(), -2.0);
+
+        // a survivor keeps the nodes it was computed from
+        assert_eq!(vf.arena().reset(&[out]), 0);
+        assert_eq!(vf.arena().reset(&[]), 3);
+        assert!(vf.arena().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "DAG properties of autograd graph violated")]
+    fn dangling_after_reset() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.value(2.0);
+        let out = &w * 3.0;
+        vf.arena().reset(&[w]);
+        out.get_data();
+    }
+
+    #[test]
+    fn reset_bounds_training_loop() {
+        use crate::{Module, Optimizer, Sgd, MLP};
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let model = MLP::new_with_seed(&vf, 2, &[4, 1], 3);
+        let params: Vec<Value> = model.parameters().cloned().collect();
+        let mut opt = Sgd::new(params.clone(), 0.05);
+        let xs = [[0.5, -1.0], [1.0, 2.0], [-1.5, 0.5]];
+        let ys = [1.0, -1.0, 0.5];
+        let mut losses = Vec::new();
+        for _ in 0..50 {
+            opt.zero_grad();
+            let loss = xs.iter().zip(ys).fold(vf.value(0.0), |acc, (x, y)| {
+                let pred = &model.call(&[vf.value(x[0]), vf.value(x[1])])[0];
+                &acc + &(pred - y).powi(2)
+            });
+            loss.backward();
+            losses.push(loss.get_data());
+            opt.step();
+            vf.arena().reset(&params);
+            assert_eq!(vf.arena().len(), params.len());
+        }
+        assert!(losses.last().unwrap() < &losses[0]);
+    }
+
+    #[test]
+    fn arena_counts() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(1.0);
+        let b = vf.value(2.0);
+        assert_eq!((vf.len(), vf.live_count()), (2, 2));
+        let mut bytes = vf.approx_bytes();
+        let mut expect = |len: usize| {
+            assert_eq!(vf.len(), len);
+            assert!(vf.approx_bytes() > bytes);
+            bytes = vf.approx_bytes();
+        };
         let c = &a + &b;
-        assert_eq!(c.get_data(), 5.7);
-        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+        expect(3);
+        let d = &c + 2.0;
+        expect(4);
+        // an abandoned result stays allocated, but nothing refers to it any more
+        drop(&d * &d);
+        expect(5);
+        assert_eq!(vf.live_count(), 4);
+
+        let mark = vf.arena().checkpoint();
+        let _e = d.tanh();
+        vf.arena().truncate_to(mark);
+        assert_eq!((vf.len(), vf.approx_bytes()), (5, bytes));
+        vf.arena().reset(std::slice::from_ref(&c));
+        assert_eq!((vf.len(), vf.live_count()), (3, 3));
+        assert!(vf.approx_bytes() < bytes);
+    }
 
-        assert_eq!(a.get_grad(), 0.0);
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 1.0);
-        assert_eq!(b.get_grad(), 1.0);
+    #[test]
+    fn compact() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        // Value + f64
-        let a = vf.value(2.5);
-        let b = 3.2;
-        let c = &a + b;
-        assert_eq!(c.get_data(), 5.7);
-        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+        let w = vf.value(0.5);
+        {
+            let x = vf.value(2.0);
+            let y = (&(&w * &x) + 1.0).tanh();
+            y.backward();
+        }
+        assert_eq!(vf.len(), 5);
+        // x, the product, the sum and the tanh go; w is still held
+        assert_eq!(vf.arena().compact(), 4);
+        assert_eq!(vf.len(), 1);
+
+        let loss = (&w - 3.0).powi(2);
+        loss.backward();
+        let len = vf.len();
+        assert_eq!(vf.arena().compact(), 0);
+        assert_eq!(vf.len(), len);
+        assert_eq!(loss.get_data(), 6.25);
+        drop(loss);
+        assert_eq!(vf.arena().compact(), len - 1);
+
+        for _ in 0..100 {
+            let y = &(&w * &w) + &w.exp();
+            y.backward();
+            // only the current graph is left
+            vf.arena().compact();
+            assert_eq!(vf.len(), 4);
+        }
+        assert_eq!(vf.arena().compact(), 3);
+        assert!(w.get_grad() > 0.0);
+    }
 
-        assert_eq!(a.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 1.0);
+    #[test]
+    fn inline_children() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        // f64 + Value
-        let a = 2.5;
-        let b = vf.value(3.2);
-        let c = a + &b;
-        assert_eq!(c.get_data(), 5.7);
-        assert_eq!(c.to_string(), "Value(data=5.7, grad=0)");
+        // up to two operands live inside the node itself
+        assert!(mem::size_of::<Children<f64>>() <= 2 * mem::size_of::<Value>() + mem::size_of::<usize>());
 
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(b.get_grad(), 1.0);
+        // a + a has a single distinct operand but both edges contribute
+        let a = vf.value(3.0);
+        let b = &a + &a;
+        b.backward();
+        assert_eq!(a.get_grad(), 2.0);
+        assert_eq!(b.op_and_children().1.len(), 1);
+
+        let vs: Vec<Value> = (0..5).map(|i| vf.value(i as f64)).collect();
+        let mut children = Children::None;
+        vs.iter().for_each(|v| children.push(v.internal()));
+        assert!(children.as_slice() == vs.as_slice());
+        assert!(children.heap_bytes() >= 5 * mem::size_of::<Value>());
+        assert!(children.contains(&vs[4]) && !children.contains(&a));
+    }
+
+    #[test]
+    fn interned_constants() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let x = vf.value(3.0);
+        let (a, b) = (&x * &vf.constant(2.0), &x * &vf.constant(2.0));
+        let two = |v: &Value| v.op_and_children().1[1].clone();
+        assert!(two(&a) == two(&b) && two(&a) == vf.constant(2.0));
+        assert_eq!(vf.len(), 4);
+
+        let y = &a + &b;
+        y.backward();
+        assert_eq!(x.get_grad(), 4.0);
+        assert!(!two(&a).requires_grad() && x.requires_grad());
+        assert_eq!(two(&a).get_grad(), 0.0);
+
+        assert!(vf.constant(f64::NAN) != vf.constant(f64::NAN));
+        let mark = vf.arena().checkpoint();
+        let seven = vf.constant(7.0);
+        vf.arena().truncate_to(mark);
+        let again = vf.constant(7.0);
+        assert!(!seven.is_alive() && again.is_alive() && again != seven);
+        vf.clear_constants();
+        assert!(vf.constant(7.0) != again);
+    }
+
+    #[test]
+    fn bulk_values() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let x = vf.value(1.0);
+        let data = [0.5, -2.0, 3.0];
+        let vs = vf.values(&data);
+        assert_eq!(vf.len(), 4);
+        assert!(vs.iter().zip(data).all(|(v, d)| v.get_data() == d && v.requires_grad() && v.label().is_none()));
+        let y = &(&vs[0] * &vs[1]) + &(&vs[2] * &x);
+        y.backward();
+        assert_eq!((vs[0].get_grad(), vs[1].get_grad(), x.get_grad()), (-2.0, 0.5, 3.0));
+        assert!(vs[0] != vs[1]);
+
+        let labeled = vf.values_labeled(&[(1.5, "w"), (2.5, "b")]);
+        assert_eq!((labeled[1].get_data(), labeled[1].label().as_deref()), (2.5, Some("b")));
+        assert_eq!(labeled[0].label().as_deref(), Some("w"));
+        assert!(vf.values(&[]).is_empty());
+
+        // counted like any other handle: dropping them lets compact free them
+        let len = vf.len();
+        let big = vf.values(&vec![0.25; 10_000]);
+        assert_eq!(vf.len(), len + 10_000);
+        assert_eq!(big[9_999].get_data(), 0.25);
+        drop(big);
+        assert_eq!(vf.arena().compact(), 10_000);
+    }
+
+    #[test]
+    fn arena_identity() {
+        let (arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (_other_life_time, other_ref) = Arena::build();
+        let other = ValueFactory::new(other_ref);
+
+        let (a, b) = (vf.value(1.0), other.value(1.0));
+        assert!(a.arena_id() != b.arena_id());
+        assert_eq!(a.arena_id(), vf.arena().id());
+        let freed = &a * 2.0;
+
+        let message = |f: &dyn Fn()| {
+            let err = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+            err.downcast_ref::<String>().unwrap().clone()
+        };
+        vf.arena().reset(std::slice::from_ref(&a));
+        assert!(freed.arena_alive() && !freed.is_alive());
+        let expected = format!("node {} of arena {} is gone (node was freed)", freed.node.index(), a.arena_id());
+        assert!(message(&|| {freed.get_data();}).contains(&expected));
+
+        drop(arena_life_time);
+        assert!(!a.arena_alive() && b.arena_alive());
+        let expected = format!("node 0 of arena {} is gone (arena was dropped)", a.arena_id());
+        assert!(message(&|| {a.get_data();}).contains(&expected));
+        assert!(message(&|| {vf.value(1.0);}).contains(&format!("(arena {})", a.arena_id())));
+    }
+
+    #[test]
+    fn release_reuses_slots() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.value(0.5);
+        let mut last = None;
+        for i in 0..100 {
+            let x = vf.value(i as f64);
+            let y = &w * &x;
+            w.set_grad(0.0);
+            y.backward();
+            assert_eq!(w.get_grad(), i as f64);
+            // y first, it uses x
+            vf.arena().release(&y);
+            vf.arena().release(&x);
+            assert_eq!(vf.len(), 1);
+            last = Some(y);
+        }
+        // the released slots are taken before the arena grows, the last one released first
+        let (bytes, stale) = (vf.arena().approx_bytes(), last.unwrap());
+        let _x = vf.value(1.0);
+        let reused = vf.value(2.0);
+        assert_eq!(vf.arena().approx_bytes(), bytes);
+        assert_eq!(reused.node.index(), stale.node.index());
+
+        // a stale handle to a reused slot is caught, not read
+        assert!(!stale.is_alive() && reused.get_data() == 2.0);
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| stale.get_data())).unwrap_err();
+        assert!(panic.downcast_ref::<String>().unwrap().contains("node was freed"));
+    }
+
+    #[test]
+    fn compact_reused_slots() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let scratch = vf.values(&[0.0, 0.0]);
+        let w = vf.value(2.0);
+        scratch.iter().for_each(|v| vf.arena().release(v));
+        // x takes slot 1 and the product slot 0, below both its operands
+        let x = vf.value(3.0);
+        let y = &w * &x;
+        drop(x);
+        assert!(y.node.index() < y.op_and_children().1[1].node.index());
+        assert_eq!((vf.arena().compact(), vf.live_count()), (0, 3));
+        y.backward();
+        assert_eq!(w.get_grad(), 3.0);
+    }
+
+    #[test]
+    fn truncate_reused_slots() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (w, scratch) = (vf.value(2.0), vf.value(0.0));
+        let mark = vf.arena().checkpoint();
+        vf.arena().release(&scratch);
+        // x takes the released slot, which predates the mark, but is allocated after it
+        let x = vf.value(3.0);
+        let y = &w * &x;
+        assert_eq!(x.node.index(), scratch.node.index());
+        vf.arena().truncate_to(mark);
+        assert!(!x.is_alive() && !y.is_alive() && w.is_alive());
+        assert_eq!(vf.len(), 1);
     }
 
     #[test]
-    fn sub() {
+    fn freed_holes_are_reused() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        // Value - Value
-        let a = vf.value(2.5);
-        let b = vf.value(5.0);
-        let c = &a - &b;
-        assert_eq!(c.get_data(), -2.5);
-        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+        // a released pre-mark slot, reused after the mark and then truncated
+        let (scratch, w) = (vf.value(0.0), vf.value(2.0));
+        let mark = vf.arena().checkpoint();
+        vf.arena().release(&scratch);
+        let x = vf.value(3.0);
+        vf.arena().truncate_to(mark);
+        assert!(!x.is_alive());
+        let bytes = vf.arena().approx_bytes();
+        let y = vf.value(4.0);
+        assert_eq!((vf.arena().approx_bytes(), y.node.index()), (bytes, scratch.node.index()));
+
+        // a hole left by compact below a live node
+        let (dropped, _kept) = (vf.value(5.0), vf.value(6.0));
+        let hole = dropped.node.index();
+        drop(dropped);
+        assert_eq!(vf.arena().compact(), 1);
+        let bytes = vf.arena().approx_bytes();
+        let z = &w * &y;
+        assert_eq!((vf.arena().approx_bytes(), z.node.index()), (bytes, hole));
+        assert_eq!(z.get_data(), 8.0);
+    }
 
-        assert_eq!(a.get_grad(), 0.0);
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 1.0);
-        assert_eq!(b.get_grad(), -1.0);
-        
-        // Value - f64
-        let a = vf.value(2.5);
-        let b = 5.0;
-        let c = &a - b;
-        assert_eq!(c.get_data(), -2.5);
-        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+    #[test]
+    #[should_panic(expected = "1 other handles still refer to")]
+    fn release_shared() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        assert_eq!(a.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 1.0);
+        let a = vf.value(1.0);
+        let _b = a.clone();
+        vf.arena().release(&a);
+    }
 
-        // f64 - Value
-        let a = 2.5;
-        let b = vf.value(5.0);
-        let c = a - &b;
-        assert_eq!(c.get_data(), -2.5);
-        assert_eq!(c.to_string(), "Value(data=-2.5, grad=0)");
+    #[test]
+    fn op_kinds() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(b.get_grad(), -1.0);
+        let a = vf.value(2.0);
+        let ops = [
+            (&a + &a, "+"), (&a * &a, "*"), (a.relu(), "ReLU"), (a.tanh(), "tanh"), (a.exp(), "exp"),
+            (a.powi(-1), "powi-1"), (a.powf(0.5), "powf0.5"), (&a + 1.0, "+1"), (&a * 2.5, "*2.5"), (-&a, "*-1"),
+            (&a - 1.0, "+-1"), (&a / 4.0, "*0.25")
+        ];
+        ops.iter().for_each(|(v, name)| assert_eq!(v.op().unwrap().to_string(), *name));
+        assert_eq!(a.op(), None);
+        assert_eq!(a.powi(3).op(), Some(OpKind::PowI(3)));
+        assert_eq!(OpKind::Custom("sigmoid").to_string(), "sigmoid");
+        // no heap allocation, and no bigger than the String it replaces
+        assert!(mem::size_of::<Option<OpKind>>() <= mem::size_of::<Option<String>>());
+        // data, grad, closure pointer, operands, op, label and kind
+        let fields = 2 * 8 + mem::size_of::<Option<Rc<dyn Fn()>>>() + mem::size_of::<Children<f64>>() + 2 * mem::size_of::<Option<String>>() + 1;
+        assert!(mem::size_of::<ValueData>() <= fields.next_multiple_of(8));
     }
 
     #[test]
-    fn mul() {
+    fn live_report() {
+        use crate::{Module, Optimizer, Sgd, MLP};
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
-        
-        // value mul with other values
-        let a = vf.value(2.5);
-        let b = vf.value(3.0);
-        let c = &a * &b;
-        let d = &(&a * &b) * &c;
-        assert_eq!(c.get_data(), 7.5);
-        assert_eq!(d.get_data(), 56.25);
-        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
-        assert_eq!(d.to_string(), "Value(data=56.25, grad=0)");
 
-        // float mul with value
-        let e = 2.0 * &d;
-        assert_eq!(e.get_data(), 112.5);
-        assert_eq!(e.to_string(), "Value(data=112.5, grad=0)");
+        let w = vf.value(0.5);
+        w.set_label("w");
+        let x = vf.value(2.0);
+        let y = &(&w * &x) + 1.0;
+        drop(x);
+        drop(&y * 2.0);
+        // w, x, w * x and y are held through w and y; y * 2 is not
+        let report = vf.arena().live_report();
+        assert_eq!(report, LiveReport { nodes: 5, live: 4, handled: 2, labels: vec![String::from("w")] });
+
+        let model = MLP::new_with_seed(&vf, 2, &[3, 1], 1);
+        let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.1);
+        let mut step = |keep: &mut Vec<Value>| {
+            opt.zero_grad();
+            let loss = (&model.call(&vf.values(&[0.5, -1.0]))[0] - 1.0).powi(2);
+            loss.backward();
+            opt.step();
+            keep.push(loss);
+        };
 
-        // value mul with float
-        let f = &e * 2.0;
-        assert_eq!(f.get_data(), 225.0);
-        assert_eq!(f.to_string(), "Value(data=225, grad=0)");
+        // the marked loop frees each step's graph
+        let mark = vf.arena().checkpoint();
+        let before = vf.arena().live_report();
+        let mut kept = Vec::new();
+        (0..5).for_each(|_| {
+            step(&mut Vec::new());
+            vf.arena().truncate_to(mark);
+        });
+        assert_no_growth(&before, &vf.arena().live_report());
+
+        // holding on to every loss keeps every graph alive
+        (0..5).for_each(|_| step(&mut kept));
+        let leaked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| assert_no_growth(&before, &vf.arena().live_report())));
+        assert!(leaked.is_err());
+        assert_eq!(vf.arena().live_report().handled, before.handled + 5);
     }
 
     #[test]
-    fn mul_grad() {
+    fn backward_upgrades_arena_once() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        // value * value
-        let a = vf.value(2.5);
-        let b = vf.value(3.0);
-        let c = &a * &b;
-        assert_eq!(c.get_data(), 7.5);
-        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
+        // a 100k node chain: sum of x * x
+        let xs = vf.values(&(0..33_334).map(|i| (i % 7) as f64 - 3.0).collect::<Vec<_>>());
+        let y = xs.iter().skip(1).fold(&xs[0] * &xs[0], |acc, x| &acc + &(x * x));
+        assert!(vf.len() >= 100_000);
 
-        assert_eq!(a.get_grad(), 0.0);
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 3.0);
-        assert_eq!(b.get_grad(), 2.5);
+        let before = crate::arena::UPGRADES.with(|n| n.get());
+        y.backward();
+        // one for topo_order, one for the sweep
+        assert_eq!(crate::arena::UPGRADES.with(|n| n.get()) - before, 2);
+        xs.iter().for_each(|x| assert_eq!(x.get_grad(), 2.0 * x.get_data()));
+    }
 
-        // value * float
-        let e = vf.value(2.5);
-        let f = &e * 3.0;
-        assert_eq!(f.get_data(), 7.5);
-        assert_eq!(f.to_string(), "Value(data=7.5, grad=0)");
+    #[test]
+    fn backward_buffers() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let mut buffers = BackwardBuffers::default();
+
+        let build = |x: &Value, w: &Value| (&(&(x * w).tanh() * x) + &(&w.relu() + &x.exp())).powi(2);
+        let (x, w, fx, fw) = (vf.value(0.5), vf.value(-1.5), vf.value(0.5), vf.value(-1.5));
+        let (y, fy) = (build(&x, &w), build(&fx, &fw));
+        y.backward_with(&mut buffers);
+        fy.backward();
+        assert_eq!((x.get_grad(), w.get_grad()), (fx.get_grad(), fw.get_grad()));
+
+        // repeated calls keep the capacity of the first
+        let capacity = buffers.capacity();
+        assert!(capacity >= y.topo_order().len());
+        (0..5).for_each(|_| {
+            y.backward_with(&mut buffers);
+            assert_eq!(buffers.capacity(), capacity);
+        });
 
-        assert_eq!(e.get_grad(), 0.0);
-        f.backward();
-        assert_eq!(e.get_grad(), 3.0);
+        // and a graph of another arena can use the same buffers
+        let (_other_life_time, other_ref) = Arena::build();
+        let z = ValueFactory::new(other_ref).value(3.0);
+        let u = &z * &z;
+        u.backward_with(&mut buffers);
+        assert_eq!(z.get_grad(), 6.0);
+        assert_eq!(buffers.capacity(), capacity);
+    }
 
-        // float * value
-        let g = vf.value(3.0);
-        let h = 2.5 * &g;
-        assert_eq!(h.get_data(), 7.5);
-        assert_eq!(h.to_string(), "Value(data=7.5, grad=0)");
+    #[test]
+    fn graph_memory_report() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        assert_eq!(g.get_grad(), 0.0);
-        h.backward();
-        assert_eq!(h.to_string(), "Value(data=7.5, grad=1)");
-        assert_eq!(g.get_grad(), 2.5);
-        assert_eq!(g.to_string(), "Value(data=3, grad=2.5)");
+        let (a, b) = (vf.value(1.0), vf.value(2.0));
+        a.set_label("a");
+        let y = (&(&a * &b) + 1.0).tanh();
+        let report = y.graph_memory_report();
+        assert_eq!(report.nodes, y.trace().0.len());
+        assert_eq!(report.node_bytes, report.nodes * mem::size_of::<ValueData>());
+        assert!(report.closure_bytes > 0);
+        assert_eq!(report.other_heap_bytes, 1);
+        assert_eq!(report.total_bytes(), report.node_bytes + report.closure_bytes + 1);
+        let text = report.to_string();
+        assert!(text.starts_with(&format!("graph memory: {} nodes, ", report.nodes)));
+        assert!(text.contains(&format!("  node data: {} B", report.node_bytes)));
+        assert!(text.contains("  operand lists and labels: 1 B"));
+
+        // the arena-wide report drops with the nodes a truncate frees
+        let mark = vf.arena().checkpoint();
+        let before = vf.memory_report();
+        let _z = &y * &y.exp();
+        assert_eq!(vf.memory_report().nodes, before.nodes + 2);
+        vf.arena().truncate_to(mark);
+        assert_eq!(vf.memory_report(), before);
+        assert_eq!(format_bytes(1536), "1.5 KiB");
     }
 
     #[test]
-    fn pow() {
+    fn debug_format() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
-        
-        // pow with int
+        let id = vf.arena().id();
+
         let a = vf.value(2.0);
-        let b = a.powi(2);
-        assert_eq!(b.get_data(), 4.0);
-        assert_eq!(b.to_string(), "Value(data=4, grad=0)");
+        a.set_label("a");
+        let b = vf.value(-1.5);
+        let c = &a * &b;
+        assert_eq!(format!("{:?}", a), format!("Value {{ data: 2.0, grad: 0.0, op: None, label: \"a\", children: 0, arena: {}, node: 0 }}", id));
+        assert_eq!(format!("{:?}", c), format!("Value {{ data: -3.0, grad: 0.0, op: Some(Mul), children: 2, arena: {}, node: 2 }}", id));
+        assert_eq!(format!("{:#?}", c), format!("{:?}\n  child 0: {:?}\n  child 1: {:?}", c, a, b));
+
+        let mark = vf.arena().checkpoint();
+        let d = c.tanh();
+        vf.arena().truncate_to(mark);
+        assert_eq!(format!("{:?}", d), format!("Value {{ gone, arena: {}, node: 3 }}", id));
+    }
 
-        // test grad as well
-        b.backward();
-        assert_eq!(b.get_grad(), 1.0);
-        assert_eq!(b.to_string(), "Value(data=4, grad=1)");
-        assert_eq!(a.get_grad(), 4.0);
-        assert_eq!(a.to_string(), "Value(data=2, grad=4)");
+    #[test]
+    fn item_and_try_from() {
+        let (arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let id = vf.arena().id();
 
-        // pow with float works the same as pow with int
-        let a = vf.value(2.0);
-        let b = a.powf(2.0);
-        assert_eq!(b.get_data(), 4.0);
-        assert_eq!(b.to_string(), "Value(data=4, grad=0)");
+        let a = vf.value(1.5);
+        let y = (&a * &a).exp();
+        assert_eq!(y.item(), y.get_data());
+        assert_eq!(f64::try_from(&y), Ok(2.25f64.exp()));
 
-        // test grad as well
-        b.backward();
-        assert_eq!(b.get_grad(), 1.0);
-        assert_eq!(b.to_string(), "Value(data=4, grad=1)");
-        assert_eq!(a.get_grad(), 4.0);
-        assert_eq!(a.to_string(), "Value(data=2, grad=4)");
+        let mark = vf.arena().checkpoint();
+        let z = y.tanh();
+        vf.arena().truncate_to(mark);
+        let freed = f64::try_from(&z).unwrap_err();
+        assert_eq!(freed, DanglingValueError { arena: id, node: 3, arena_dropped: false });
+        assert_eq!(freed.to_string(), format!("node 3 of arena {} is gone (node was freed)", id));
+
+        drop(arena_life_time);
+        assert!(f64::try_from(&y).unwrap_err().arena_dropped);
+
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let x = ValueFactoryG::<f32>::new(arena_ref).value(0.5);
+        assert_eq!(f32::try_from(&x.relu()), Ok(0.5));
     }
 
     #[test]
-    fn neg() {
+    fn factory_constructors() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
-        
-        let a = vf.value(2.0);
-        let b = -&a;
-        assert_eq!(b.get_data(), -2.0);
-        assert_eq!(b.to_string(), "Value(data=-2, grad=0)");
 
-        // test grad as well
-        b.backward();
-        assert_eq!(b.get_grad(), 1.0);
-        assert_eq!(b.to_string(), "Value(data=-2, grad=1)");
-        assert_eq!(a.get_grad(), -1.0);
-        assert_eq!(a.to_string(), "Value(data=2, grad=-1)");
+        let (zero, one, c) = (vf.zero(), vf.one(), vf.constant(2.5));
+        let w = vf.param(0.5);
+        let x = vf.value_labeled(-1.0, "x");
+        assert_eq!((zero.get_data(), one.get_data(), c.get_data(), w.get_data(), x.get_data()), (0.0, 1.0, 2.5, 0.5, -1.0));
+        assert_eq!((zero.kind(), one.kind(), c.kind(), w.kind(), x.kind()), (NodeKind::Plain, NodeKind::Plain, NodeKind::Constant, NodeKind::Param, NodeKind::Plain));
+        assert!(zero.requires_grad() && w.requires_grad() && !c.requires_grad());
+        assert_eq!((x.label(), w.label()), (Some(String::from("x")), None));
+        assert_ne!(vf.zero(), zero);
+        assert_eq!(vf.constant(2.5), c);
+
+        let y = &(&(&w * &x) * &c) + &one;
+        y.backward();
+        assert_eq!((w.get_grad(), c.get_grad()), (-2.5, 0.0));
+        let dot = y.draw_dot();
+        assert!(dot.contains("label = \"{x | data -1.0000 | grad 1.2500}\" ]"));
+        assert!(dot.contains("label = \"{data 0.5000 | grad -2.5000}\", style=filled, fillcolor=lightblue ]"));
+        assert!(dot.contains("label = \"{data 2.5000 | grad 0.0000}\", style=dashed ]"));
     }
 
     #[test]
-    fn div() {
+    fn random_values() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        // value / value
-        let a = vf.value(2.5);
-        let b = vf.value(1.0/3.0);
-        let c = &a / &b;
-        assert_eq!(c.get_data(), 7.5);
-        assert_eq!(c.to_string(), "Value(data=7.5, grad=0)");
-
-        assert_eq!(a.get_grad(), 0.0);
-        assert_eq!(b.get_grad(), 0.0);
-        c.backward();
-        assert_eq!(a.get_grad(), 3.0);
-        assert_eq!(b.get_grad(), -22.5);
+        let stats = |vs: &[Value]| {
+            let mean = vs.iter().map(Value::get_data).sum::<f64>() / vs.len() as f64;
+            let var = vs.iter().map(|v| (v.get_data() - mean).powi(2)).sum::<f64>() / vs.len() as f64;
+            (mean, var.sqrt())
+        };
+        let uniform = vf.rand_uniform_vec(20_000, -1.0, 3.0, &mut Rng::new(1));
+        assert!(uniform.iter().all(|v| (-1.0..3.0).contains(&v.get_data()) && v.kind() == NodeKind::Param));
+        let (mean, std) = stats(&uniform);
+        // 1 and 4 / sqrt(12)
+        assert!((mean - 1.0).abs() < 0.03 && (std - 4.0 / 12f64.sqrt()).abs() < 0.03);
+        let (mean, std) = stats(&vf.rand_normal_vec(20_000, 2.0, 0.5, &mut Rng::new(2)));
+        assert!((mean - 2.0).abs() < 0.02 && (std - 0.5).abs() < 0.02);
+
+        // the same seed gives the same values, one at a time or in bulk
+        let (mut r1, mut r2) = (Rng::new(7), Rng::new(7));
+        let one_by_one: Vec<f64> = (0..5).map(|_| vf.rand_normal(0.0, 1.0, &mut r1).get_data()).collect();
+        let bulk: Vec<f64> = vf.rand_normal_vec(5, 0.0, 1.0, &mut r2).iter().map(Value::get_data).collect();
+        assert_eq!(one_by_one, bulk);
+        assert_eq!(vf.rand_uniform(0.0, 1.0, &mut r1).get_data(), vf.rand_uniform(0.0, 1.0, &mut r2).get_data());
+    }
 
-        // value / float
-        let e = vf.value(2.5);
-        let f = &e / (1.0/3.0);
-        assert_eq!(f.get_data(), 7.5);
-        assert_eq!(f.to_string(), "Value(data=7.5, grad=0)");
+    #[test]
+    fn format_options() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        assert_eq!(e.get_grad(), 0.0);
-        f.backward();
-        assert_eq!(e.get_grad(), 3.0);
+        let third = &vf.value(1.0) / 3.0;
+        assert_eq!(third.to_string(), "Value(data=0.3333333333333333, grad=0)");
+        assert_eq!(third.format_with(&FormatOptions::default()), third.to_string());
+        assert_eq!(third.format_with(&FormatOptions { precision: Some(3), scientific: true }), "Value(data=3.333e-1, grad=0.000e0)");
+        assert_eq!(third.format_with(&FormatOptions { precision: Some(2), scientific: false }), "Value(data=0.33, grad=0.00)");
+
+        let cases: [(f64, &str, &str, &str); 4] = [
+            (1.5e-12, "0.0000", "1.50e-12", "1.5e-12"),
+            (-2.5e20, "-250000000000000000000.0000", "-2.50e20", "-2.5e20"),
+            (-0.0, "-0.0000", "-0.00e0", "-0e0"),
+            (-7.0, "-7.0000", "-7.00e0", "-7e0")
+        ];
+        for (x, fixed, sci, shortest) in cases {
+            assert_eq!(FormatOptions { precision: Some(4), scientific: false }.format(x), fixed);
+            assert_eq!(FormatOptions { precision: Some(2), scientific: true }.format(x), sci);
+            assert_eq!(FormatOptions { precision: None, scientific: true }.format(x), shortest);
+        }
+        assert_eq!(FormatOptions::default().format(2.5f32), "2.5");
+
+        let y = (&vf.value(1.0) / 3.0).tanh();
+        y.backward();
+        assert!(y.draw_dot().contains("data 0.3215 | grad 1.0000"));
+        let dot = y.draw_dot_with(&DotOptions { format: FormatOptions { precision: Some(1), scientific: true } });
+        assert!(dot.contains("data 3.2e-1 | grad 1.0e0"));
+        assert!(dot.contains("data 3.3e-1 | grad 9.0e-1"));
+    }
 
-        // float / value
-        let g = vf.value(1.0/3.0);
-        let h = 2.5 / &g;
-        assert_eq!(h.get_data(), 7.5);
-        assert_eq!(h.to_string(), "Value(data=7.5, grad=0)");
+    #[test]
+    fn checked_division() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
 
-        assert_eq!(g.get_grad(), 0.0);
-        h.backward();
-        assert_eq!(h.to_string(), "Value(data=7.5, grad=1)");
-        assert_eq!(g.get_grad(), -22.5);
-        assert_eq!(g.to_string(), "Value(data=0.3333333333333333, grad=-22.5)");
+        let a = vf.value_labeled(3.0, "a");
+        let zero = vf.value_labeled(0.0, "b");
+        let err = a.try_div(&zero).unwrap_err();
+        assert_eq!(err, MathError::DivisionByZero { numerator: Some(String::from("\"a\"")), divisor: String::from("\"b\""), data: 0.0, eps: 0.0 });
+        assert_eq!(err.to_string(), "cannot divide \"a\" by \"b\": its data 0e0 is within 0e0 of zero");
+        let tiny = vf.value(-1e-15);
+        assert!(a.try_div(&tiny).is_ok());
+        assert_eq!(
+            a.try_div_with_eps(&tiny, 1e-12).unwrap_err().to_string(),
+            format!("cannot divide \"a\" by node {}: its data -1e-15 is within 1e-12 of zero", tiny.node.index())
+        );
+        assert_eq!(tiny.try_reciprocal_with_eps(1e-12).unwrap_err().to_string(), format!("cannot take the reciprocal of node {}: its data -1e-15 is within 1e-12 of zero", tiny.node.index()));
+        assert!(zero.try_reciprocal().is_err());
+        assert!(a.try_div_with_eps(&vf.value(2e-12), 1e-12).is_ok());
+
+        // same data and gradients as the operator
+        let graph = |checked: bool| {
+            let (x, y) = (vf.value(1.5), vf.value(-0.4));
+            let q = if checked {x.try_div(&y).unwrap()} else {&x / &y};
+            let r = if checked {q.try_reciprocal().unwrap()} else {q.powi(-1)};
+            let out = &q * &r.tanh();
+            out.backward();
+            (out.get_data(), x.get_grad(), y.get_grad())
+        };
+        assert_eq!(graph(true), graph(false));
     }
 
     #[test]
-    fn relu() {
+    fn domain_checked_ops() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
-        
-        let a = vf.value(2.0);
-        let b = a.relu();
-        assert_eq!(b.get_data(), 2.0);
-        assert_eq!(b.to_string(), "Value(data=2, grad=0)");
 
-        // test grad as well
-        b.backward();
-        assert_eq!(b.get_grad(), 1.0);
-        assert_eq!(b.to_string(), "Value(data=2, grad=1)");
-        assert_eq!(a.get_grad(), 1.0);
-        assert_eq!(a.to_string(), "Value(data=2, grad=1)");
+        let neg = vf.value_labeled(-2.0, "x");
+        let zero = vf.value(0.0);
+        assert_eq!(neg.try_sqrt().unwrap_err(), MathError::Domain { op: String::from("sqrt"), operand: String::from("\"x\""), data: -2.0 });
+        assert_eq!(neg.try_powf(1.5).unwrap_err().to_string(), "powf1.5 of \"x\" is undefined: its data is -2e0");
+        assert_eq!(zero.try_powf(-0.5).unwrap_err().to_string(), format!("powf-0.5 of node {} is undefined: its data is 0e0", zero.node.index()));
+        assert!(zero.try_powf(-2.0).is_err());
+        // integer exponents of negative bases and zero are fine
+        assert_eq!(neg.try_powf(3.0).unwrap().get_data(), -8.0);
+        assert_eq!(neg.try_powf(-1.0).unwrap().get_data(), -0.5);
+        assert_eq!(zero.try_sqrt().unwrap().get_data(), 0.0);
+
+        // clamped inputs give finite data and grads
+        let w = vf.param(0.5);
+        for (x, n) in [(-2.0, 0.5), (-1e-15, -1.5), (0.0, -3.0), (-3.0, 2.5)] {
+            w.set_grad(0.0);
+            let y = (&w * x).safe_powf(n, DomainPolicy::Clamp).unwrap();
+            let z = (&w * x).safe_sqrt(DomainPolicy::Clamp).unwrap();
+            let out = &y + &z;
+            out.backward();
+            assert!(out.get_data().is_finite() && w.get_grad().is_finite(), "{} ** {}", x, n);
+        }
 
-        // less than 0 case
-        let c = vf.value(-2.0);
-        let d = c.relu();
-        assert_eq!(d.get_data(), 0.0);
-        assert_eq!(d.to_string(), "Value(data=0, grad=0)");
+        // valid inputs build exactly the unchecked graph
+        for policy in [DomainPolicy::Error, DomainPolicy::Clamp] {
+            let graph = |checked: bool| {
+                let x = vf.value(0.7);
+                let y = if checked {x.safe_powf(-1.5, policy).unwrap()} else {x.powf(-1.5)};
+                let z = if checked {y.safe_sqrt(policy).unwrap()} else {y.sqrt()};
+                z.backward();
+                (z.get_data().to_bits(), x.get_grad().to_bits())
+            };
+            assert_eq!(graph(true), graph(false));
+        }
 
-        // test grad as well
-        d.backward();
-        assert_eq!(d.get_grad(), 1.0);
-        assert_eq!(d.to_string(), "Value(data=0, grad=1)");
-        assert_eq!(c.get_grad(), 0.0);
-        assert_eq!(c.to_string(), "Value(data=-2, grad=0)");
+        // as are valid inputs closer to the edge than DOMAIN_EPS
+        type Checked = fn(&Value, DomainPolicy) -> Result<Value, MathError>;
+        type Unchecked = fn(&Value) -> Value;
+        let ops: [(Checked, Unchecked); 4] = [
+            (Value::safe_sqrt, Value::sqrt),
+            (Value::safe_ln, Value::ln),
+            (|v, policy| v.safe_powf(0.5, policy), |v| v.powf(0.5)),
+            (|v, policy| v.safe_powf(-2.0, policy), |v| v.powf(-2.0))
+        ];
+        for (checked, unchecked) in ops {
+            let run = |op: &dyn Fn(&Value) -> Value| {
+                let x = vf.value(DOMAIN_EPS / 4.0);
+                let y = op(&x);
+                y.backward();
+                (y.get_data().to_bits(), x.get_grad().to_bits())
+            };
+            assert_eq!(run(&|v| checked(v, DomainPolicy::Clamp).unwrap()), run(&unchecked));
+        }
     }
 
     #[test]
-    fn tanh() {
+    fn data_comparisons() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        let a = vf.value(2.0);
-        let b = a.tanh();
+        let vs = vf.values(&[0.5, -1.0, 0.5, f64::NAN, 2.0, -0.0, 0.0]);
+        assert!(vs[0].data_eq(&vs[2], 0.0) && vs[0] != vs[2]);
+        assert!(vs[0].data_eq(&vf.value(0.5 + 1e-10), 1e-9) && !vs[0].data_eq(&vs[4], 1.0));
+        assert!(!vs[3].data_eq(&vs[3], f64::INFINITY));
+        assert_eq!(vs[0].data_cmp(&vs[1]), Ordering::Greater);
+        assert_eq!(vs[0].data_cmp(&vs[2]), Ordering::Equal);
+        assert_eq!(vs[5].data_cmp(&vs[6]), Ordering::Equal);
+        assert_eq!(vs[3].data_cmp(&vs[4]), Ordering::Greater);
+        assert_eq!(vs[4].data_cmp(&vs[3]), Ordering::Less);
+        assert_eq!(vs[3].data_cmp(&vf.value(f64::NAN)), Ordering::Equal);
+
+        // stable: equal data keep their order
+        assert_eq!(sort_indices_by_data(&vs), vec![1, 5, 6, 0, 2, 4, 3]);
+        let (i, max) = max_by_data(&vs[..3]);
+        assert_eq!((i, max), (0, &vs[0]));
+        assert_eq!(max_by_data(&vs).0, 3);
+        let finite = &vs[4..];
+        let folded = finite.iter().map(Value::get_data).fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(max_by_data(finite).1.get_data(), folded);
+    }
 
-        let expected_data = 2.0_f64.tanh();
-        assert!((b.get_data() - expected_data).abs() < 1e-12);
-        assert_eq!(b.to_string(), format!("Value(data={}, grad=0)", b.get_data()));
+    #[test]
+    fn panic_messages_name_the_node() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref.clone());
+        let message = |f: &dyn Fn()| {
+            let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+            payload.downcast_ref::<String>().cloned().unwrap()
+        };
 
-        // test grad as well
-        b.backward();
+        // an operand freed before use in an op
+        let a = vf.value_labeled(2.0, "a");
+        let b = vf.value(3.0);
+        let gone = vf.value(1.0);
+        let gone_idx = gone.node.index();
+        arena_ref.free_slot(&gone.node);
+        let msg = message(&|| {let _ = &a * &gone;});
+        assert_eq!(msg, format!("DAG properties of autograd graph violated: node {} of arena {} is gone (node was freed), used as an operand of *", gone_idx, a.arena_id()));
+        assert!(message(&|| {gone.tanh();}).ends_with("used as an operand of tanh"));
+
+        // an operand freed after use: backward names the node that used it
+        let c = vf.value(4.0);
+        let y = &(&a * &c) + &b;
+        y.set_label("y");
+        let c_idx = c.node.index();
+        arena_ref.free_slot(&c.node);
+        let msg = message(&|| y.backward());
+        assert!(msg.contains(&format!("node {} of arena {} is gone (node was freed), operand of node #", c_idx, a.arena_id())), "{}", msg);
+        assert!(msg.ends_with("(op=*)"), "{}", msg);
+
+        // going back through a handle while the graph is being swept
+        let msg = message(&|| arena_ref.with_view(|_| {a.get_data();}));
+        assert_eq!(msg, format!("node {} of arena {} was accessed while the arena is borrowed (from inside a sweep over the graph)", a.node.index(), a.arena_id()));
+    }
 
-        let expected_grad = 1.0 - expected_data * expected_data;
-        assert!((a.get_grad() - expected_grad).abs() < 1e-12);
+    #[test]
+    fn max_nodes() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        vf.set_max_nodes(Some(3));
+        let (a, b) = (vf.value(2.0), vf.value(3.0));
+        let c = &a + &b;
+        assert_eq!(vf.node_count(), 3);
+        let full = ArenaLimitError { arena: vf.arena().id(), limit: 3, len: 3, requested: 1 };
+        assert_eq!(vf.try_value(1.0).unwrap_err(), full);
+        let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| &a * &c)).unwrap_err();
+        assert_eq!(panic.downcast_ref::<String>(), Some(&full.to_string()));
+        assert_eq!(vf.node_count(), 3);
+
+        // a batch that does not fit allocates nothing
+        vf.set_max_nodes(Some(4));
+        assert_eq!(vf.try_values(&[1.0, 2.0]).unwrap_err().requested, 2);
+        assert_eq!(vf.node_count(), 3);
+        assert_eq!(vf.try_values(&[1.0]).unwrap().len(), 1);
+        assert!(vf.try_value(1.0).is_err());
+
+        // freed nodes make room again
+        drop(c);
+        vf.arena().compact();
+        assert_eq!(vf.node_count(), 2);
+        let _kept = vf.values(&[1.0, 2.0]);
+        assert!(vf.try_value(1.0).is_err());
+        vf.set_max_nodes(None);
+        let _more = vf.values(&[0.0; 10]);
+        assert_eq!((vf.max_nodes(), vf.node_count()), (None, 14));
+    }
 
-        assert_eq!(b.get_grad(), 1.0);
+    #[test]
+    fn max_nodes_training() {
+        use crate::{Module, Optimizer, Sgd, MLP};
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let model = MLP::new_with_seed(&vf, 2, &[3, 1], 1);
+        let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.1);
+        let mut step = || {
+            opt.zero_grad();
+            let loss = (&model.call(&vf.values(&[0.5, -1.0]))[0] - 1.0).powi(2);
+            loss.backward();
+            opt.step();
+            loss.get_data()
+        };
+        let mark = vf.arena().checkpoint();
+        let first = step();
+        vf.set_max_nodes(Some(vf.node_count()));
+        vf.arena().truncate_to(mark);
+
+        // a truncated loop fits exactly
+        let losses: Vec<f64> = (0..5).map(|_| {
+            let loss = step();
+            vf.arena().truncate_to(mark);
+            loss
+        }).collect();
+        assert!(losses.iter().all(|l| *l < first));
+
+        // one that forgets to truncate does not
+        step();
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(&mut step)).is_err());
     }
 
     #[test]
-    fn exp() {
+    fn scalar_operands_inline() {
         let (_arena_life_time, arena_ref) = Arena::build();
         let vf = ValueFactory::new(arena_ref);
 
-        let a = vf.value(2.0);
-        let b = a.exp();
+        // one node per op, none for the literals
+        let x = vf.value(1.5);
+        let y = &(&(&(&(3.0 * &x) + 2.0) * &x) - 1.0) / 4.0;
+        let z = &(2.0 - &y) + &(1.0 / &x);
+        assert_eq!(vf.len(), 11);
+        assert!(z.op_and_children().1.iter().all(|c| c.op().is_some()));
+        z.backward();
+
+        // the same expression with the literals as leaves
+        let lit = |c: f64| vf.value(c);
+        let fx = vf.value(1.5);
+        let fy = &(&(&(&(&lit(3.0) * &fx) + &lit(2.0)) * &fx) - &lit(1.0)) / &lit(4.0);
+        let fz = &(&lit(2.0) - &fy) + &(&lit(1.0) / &fx);
+        fz.backward();
+        assert_eq!((z.get_data(), x.get_grad()), (fz.get_data(), fx.get_grad()));
+
+        let dot = y.draw_dot();
+        assert!(dot.contains("[ label = \"*3\" ]") && dot.contains("[ label = \"+-1\" ]") && dot.contains("[ label = \"*0.25\" ]"));
+        assert!(!dot.contains("style=dashed"));
+        assert_eq!(format!("{:?}", &x + 2.0).split(", children").next(), Some("Value { data: 3.5, grad: 0.0, op: Some(AddScalar(2.0))"));
+    }
 
-        let expected_data = 2.0_f64.exp();
-        assert!((b.get_data() - expected_data).abs() < 1e-12);
-        assert_eq!(b.to_string(), format!("Value(data={}, grad=0)", b.get_data()));
+    type UnaryOp<T> = fn(&ValueG<T>) -> ValueG<T>;
 
-        // test grad as well
-        b.backward();
+    // every op and its grad at x = 2.5, for any Float; tol is relative
+    fn op_suite<T: Float>(tol: f64) {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactoryG::<T>::new(arena_ref);
+
+        let (t, e) = (2.5_f64.tanh(), 2.5_f64.exp());
+        let sig = 1.0 / (1.0 + (-2.5_f64).exp());
+        let cases: [(UnaryOp<T>, f64, f64); 29] = [
+            (|x| x + T::from_f64(3.2), 5.7, 1.0),
+            (|x| x - T::from_f64(5.0), -2.5, 1.0),
+            (|x| x * x, 6.25, 5.0),
+            (|x| x * T::from_f64(3.0), 7.5, 3.0),
+            (|x| x / T::from_f64(0.5), 5.0, 2.0),
+            (|x| &(x + x) / x, 2.0, 0.0),
+            (|x| -x, -2.5, -1.0),
+            (|x| x.powi(3), 15.625, 18.75),
+            (|x| x.powi(-1), 0.4, -0.16),
+            (|x| x.powf(T::from_f64(0.5)), 2.5_f64.sqrt(), 0.5 / 2.5_f64.sqrt()),
+            (|x| x.relu(), 2.5, 1.0),
+            (|x| (-x).relu(), 0.0, 0.0),
+            (|x| x.tanh(), t, 1.0 - t * t),
+            (|x| x.exp(), e, e),
+            (|x| x.sigmoid(), sig, sig * (1.0 - sig)),
+            (|x| x.sin(), 2.5_f64.sin(), 2.5_f64.cos()),
+            (|x| x.cos(), 2.5_f64.cos(), -2.5_f64.sin()),
+            (|x| x.tan(), 2.5_f64.tan(), 1.0 / 2.5_f64.cos().powi(2)),
+            (|x| (x * T::from_f64(0.2)).asin(), 0.5_f64.asin(), 0.2 / 0.75_f64.sqrt()),
+            (|x| (x * T::from_f64(0.2)).acos(), 0.5_f64.acos(), -0.2 / 0.75_f64.sqrt()),
+            (|x| x.atan(), 2.5_f64.atan(), 1.0 / 7.25),
+            (|x| x.sinh(), 2.5_f64.sinh(), 2.5_f64.cosh()),
+            (|x| x.cosh(), 2.5_f64.cosh(), 2.5_f64.sinh()),
+            (|x| x.sqrt(), 2.5_f64.sqrt(), 0.5 / 2.5_f64.sqrt()),
+            (|x| x.cbrt(), 2.5_f64.cbrt(), 1.0 / (3.0 * 2.5_f64.cbrt().powi(2))),
+            (|x| (-x).abs(), 2.5, 1.0),
+            (|x| x.exp2(), 2.5_f64.exp2(), 2.5_f64.exp2() * std::f64::consts::LN_2),
+            (|x| x.log2(), 2.5_f64.log2(), 1.0 / (2.5 * std::f64::consts::LN_2)),
+            (|x| x.log10(), 2.5_f64.log10(), 1.0 / (2.5 * std::f64::consts::LN_10))
+        ];
+        for (i, (op, data, grad)) in cases.into_iter().enumerate() {
+            let x = vf.value(T::from_f64(2.5));
+            let y = op(&x);
+            y.backward();
+            let close = |v: T, expected: f64| (v.to_f64() - expected).abs() <= tol * expected.abs().max(1.0);
+            assert!(close(y.get_data(), data) && close(x.get_grad(), grad), "case {}: {} {}", i, y, x);
+        }
+    }
+
+    #[test]
+    fn generic_ops() {
+        op_suite::<f64>(1e-12);
+        op_suite::<f32>(1e-5);
+    }
 
-        let expected_grad = expected_data;
-        assert!((a.get_grad() - expected_grad).abs() < 1e-12);
+    #[test]
+    fn f32_values() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactoryG::<f32>::new(arena_ref);
 
-        assert_eq!(b.get_grad(), 1.0);
+        let a = vf.value(2.5);
+        let b = 3.0 / &(&(2.0 * &a) - 1.0);
+        b.backward();
+        assert_eq!(b.get_data(), 0.75);
+        assert_eq!(a.get_grad(), -0.375);
+        assert_eq!(a.to_string(), "Value(data=2.5, grad=-0.375)");
+        // constants are interned per arena, by their f32 bits
+        assert!(vf.constant(0.1) == vf.constant(0.1_f64 as f32));
+        assert!(mem::size_of::<ValueDataG<f32>>() < mem::size_of::<ValueData>());
     }
 }
-
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::{Value, ValueFactory};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    // byte offset into the source where the problem was found
+    pub offset: usize,
+    pub message: String
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Unary functions callable from expressions
+fn apply_function(name: &str, arg: &Value) -> Option<Value> {
+    match name {
+        "tanh" => Some(arg.tanh()),
+        "relu" => Some(arg.relu()),
+        "exp" => Some(arg.exp()),
+        "ln" => Some(arg.ln()),
+        "sigmoid" => Some(arg.sigmoid()),
+        "sin" => Some(arg.sin()),
+        "cos" => Some(arg.cos()),
+        "tan" => Some(arg.tan()),
+        "asin" => Some(arg.asin()),
+        "acos" => Some(arg.acos()),
+        "atan" => Some(arg.atan()),
+        "sinh" => Some(arg.sinh()),
+        "cosh" => Some(arg.cosh()),
+        "sqrt" => Some(arg.sqrt()),
+        "cbrt" => Some(arg.cbrt()),
+        "abs" => Some(arg.abs()),
+        "sign" => Some(arg.sign()),
+        "floor" => Some(arg.floor()),
+        "ceil" => Some(arg.ceil()),
+        "round" => Some(arg.round()),
+        "round_ste" => Some(arg.round_ste()),
+        "exp2" => Some(arg.exp2()),
+        "log2" => Some(arg.log2()),
+        "log10" => Some(arg.log10()),
+        _ => None
+    }
+}
+
+// Builds a Value graph from an expression such as "tanh(x1*w1 + x2*w2 + b)". Supports + - * /, unary
+// minus, parentheses, float literals, variables and the functions in apply_function. Every variable must
+// have an initial value in bindings; it becomes a leaf, shared by all of its uses, and is returned in
+// the map alongside the result.
+pub fn parse_expr(vf: &ValueFactory, src: &str, bindings: &HashMap<String, f64>) -> Result<(Value, HashMap<String, Value>), ParseError> {
+    let mut parser = Parser { vf, src, pos: 0, bindings, vars: HashMap::new() };
+    let value = parser.expr()?;
+    parser.skip_ws();
+    if parser.pos < src.len() {
+        return Err(parser.error(if parser.peek() == Some(')') {"unmatched ')'"} else {"unexpected input"}));
+    }
+    Ok((value, parser.vars))
+}
+
+struct Parser<'a> {
+    vf: &'a ValueFactory,
+    src: &'a str,
+    pos: usize,
+    bindings: &'a HashMap<String, f64>,
+    vars: HashMap<String, Value>
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { offset: self.pos, message: message.to_string() }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += self.peek().unwrap().len_utf8();
+        }
+    }
+
+    // Consumes c (after whitespace) if it is next
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_ws();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_while(&mut self, f: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&f) {
+            self.pos += self.peek().unwrap().len_utf8();
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Value, ParseError> {
+        let mut acc = self.term()?;
+        loop {
+            if self.eat('+') {
+                acc = &acc + &self.term()?;
+            } else if self.eat('-') {
+                acc = &acc - &self.term()?;
+            } else {
+                return Ok(acc);
+            }
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Value, ParseError> {
+        let mut acc = self.unary()?;
+        loop {
+            if self.eat('*') {
+                acc = &acc * &self.unary()?;
+            } else if self.eat('/') {
+                acc = &acc / &self.unary()?;
+            } else {
+                return Ok(acc);
+            }
+        }
+    }
+
+    // unary := '-' unary | primary
+    fn unary(&mut self) -> Result<Value, ParseError> {
+        if self.eat('-') {
+            Ok(-&self.unary()?)
+        } else {
+            self.primary()
+        }
+    }
+
+    // primary := number | name | name '(' expr ')' | '(' expr ')'
+    fn primary(&mut self) -> Result<Value, ParseError> {
+        self.skip_ws();
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected ')'"));
+                }
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                self.skip_while(|c| c.is_ascii_digit() || c == '.');
+                // exponent, e.g. 1e-3
+                if matches!(self.peek(), Some('e' | 'E')) {
+                    self.pos += 1;
+                    if matches!(self.peek(), Some('+' | '-')) {
+                        self.pos += 1;
+                    }
+                    self.skip_while(|c| c.is_ascii_digit());
+                }
+                let literal = &self.src[start..self.pos];
+                literal.parse::<f64>()
+                    .map(|v| self.vf.value(v))
+                    .map_err(|_| ParseError { offset: start, message: format!("invalid number {:?}", literal) })
+            }
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                self.skip_while(|c| c.is_alphanumeric() || c == '_');
+                let name = &self.src[start..self.pos];
+                if self.eat('(') {
+                    let arg = self.expr()?;
+                    if !self.eat(')') {
+                        return Err(self.error("expected ')'"));
+                    }
+                    apply_function(name, &arg)
+                        .ok_or_else(|| ParseError { offset: start, message: format!("unknown function {:?}", name) })
+                } else {
+                    self.variable(name).ok_or_else(|| ParseError { offset: start, message: format!("unbound variable {:?}", name) })
+                }
+            }
+            Some(_) => Err(self.error("expected a number, variable, function call or '('")),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+
+    fn variable(&mut self, name: &str) -> Option<Value> {
+        if let Some(v) = self.vars.get(name) {
+            return Some(v.clone());
+        }
+        let v = self.vf.value(*self.bindings.get(name)?);
+        self.vars.insert(name.to_string(), v.clone());
+        Some(v)
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Arena;
+
+    fn eval(vf: &ValueFactory, src: &str) -> f64 {
+        parse_expr(vf, src, &HashMap::new()).unwrap().0.get_data()
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        assert_eq!(eval(&vf, "1 + 2 * 3 - 4 / 2"), 5.0);
+        assert_eq!(eval(&vf, "(1 + 2) * 3"), 9.0);
+        assert_eq!(eval(&vf, "8 - 3 - 2"), 3.0);
+        assert_eq!(eval(&vf, "16 / 4 / 2"), 2.0);
+        assert_eq!(eval(&vf, "-2 * 3"), -6.0);
+        assert_eq!(eval(&vf, "2 * -3 - -1"), -5.0);
+        assert_eq!(eval(&vf, "1.5e1 + .5"), 15.5);
+        assert_eq!(eval(&vf, "relu(-2) + exp(0) + ln(1)"), 1.0);
+    }
+
+    #[test]
+    fn neuron_matches_hand_built() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let bindings: HashMap<String, f64> = [("x1", 2.0), ("x2", 0.0), ("w1", -3.0), ("w2", 1.0), ("b", 6.881_373_587_019_543)]
+            .iter()
+            .map(|(k, v)| (k.to_string(), *v))
+            .collect();
+        let (o, vars) = parse_expr(&vf, "tanh(x1*w1 + x2*w2 + b)", &bindings).unwrap();
+        o.backward();
+
+        let (x1, x2, w1, w2, b) = (vf.value(2.0), vf.value(0.0), vf.value(-3.0), vf.value(1.0), vf.value(6.881_373_587_019_543));
+        let expected = (&(&(&x1 * &w1) + &(&x2 * &w2)) + &b).tanh();
+        expected.backward();
+
+        assert_eq!(o.get_data(), expected.get_data());
+        assert_eq!(vars.len(), 5);
+        for (name, v) in [("x1", &x1), ("x2", &x2), ("w1", &w1), ("w2", &w2), ("b", &b)] {
+            assert_eq!(vars[name].get_grad(), v.get_grad(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn errors_and_whitespace() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let bindings: HashMap<String, f64> = [(String::from("x"), 0.5), (String::from("w"), 2.0)].into_iter().collect();
+        let err = |src: &str| parse_expr(&vf, src, &bindings).err().unwrap();
+
+        let e = err("x + foo(x)");
+        assert_eq!((e.offset, e.message.as_str()), (4, "unknown function \"foo\""));
+        assert_eq!(err("(x + 1").offset, 6);
+        assert_eq!(err("tanh(x").offset, 6);
+        let e = err("x + 1)");
+        assert_eq!((e.offset, e.message.as_str()), (5, "unmatched ')'"));
+        assert_eq!(err("x * y").offset, 4);
+        assert_eq!(err("x +").offset, 3);
+
+        let (v, vars) = parse_expr(&vf, "  tanh (\tx *\n w )  ", &bindings).unwrap();
+        assert_eq!(v.get_data(), 1.0_f64.tanh());
+        // x appears once as a leaf even when used twice
+        let (v, vars2) = parse_expr(&vf, "x*x", &bindings).unwrap();
+        v.backward();
+        assert_eq!((vars.len(), vars2["x"].get_grad()), (2, 1.0));
+    }
+}
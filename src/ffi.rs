@@ -0,0 +1,149 @@
+// C interface for graph-free inference with a model saved by MLP::save_json. See include/rust_micrograd.h
+// for the C declarations. No panic crosses the boundary: every entry point runs under catch_unwind and
+// reports failures through its return value.
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use crate::{Arena, ArenaLifeTime, ValueData, ValueFactory, MLP};
+
+pub const MLP_OK: i32 = 0;
+pub const MLP_ERR_NULL: i32 = -1;
+pub const MLP_ERR_LENGTH: i32 = -2;
+pub const MLP_ERR_PANIC: i32 = -3;
+
+// Opaque to C. Owns the arena its parameters live in.
+pub struct CMlp {
+    _life_time: ArenaLifeTime<ValueData>,
+    model: MLP
+}
+
+/// Returns NULL when the file cannot be read or parsed.
+///
+/// # Safety
+/// path must be NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mlp_load_json(path: *const c_char) -> *mut CMlp {
+    if path.is_null() {
+        return ptr::null_mut();
+    }
+    let path = unsafe { CStr::from_ptr(path) };
+    catch_unwind(|| {
+        let path = path.to_str().ok()?;
+        let (life_time, arena_ref) = Arena::build();
+        let model = MLP::load_json(&ValueFactory::new(arena_ref), path).ok()?;
+        Some(Box::into_raw(Box::new(CMlp { _life_time: life_time, model })))
+    }).ok().flatten().unwrap_or(ptr::null_mut())
+}
+
+/// Returns 0 for NULL or when the lookup panics.
+///
+/// # Safety
+/// handle must be NULL or a live handle from mlp_load_json.
+#[no_mangle]
+pub unsafe extern "C" fn mlp_input_len(handle: *const CMlp) -> usize {
+    let handle = unsafe { handle.as_ref() };
+    catch_unwind(AssertUnwindSafe(|| handle.map_or(0, |h| h.model.nin()))).unwrap_or(0)
+}
+
+/// Returns 0 for NULL or when the lookup panics.
+///
+/// # Safety
+/// handle must be NULL or a live handle from mlp_load_json.
+#[no_mangle]
+pub unsafe extern "C" fn mlp_output_len(handle: *const CMlp) -> usize {
+    let handle = unsafe { handle.as_ref() };
+    catch_unwind(AssertUnwindSafe(|| handle.map_or(0, |h| h.model.nout()))).unwrap_or(0)
+}
+
+/// Writes the model output for input into output. Returns MLP_OK, or MLP_ERR_NULL / MLP_ERR_LENGTH /
+/// MLP_ERR_PANIC without touching output.
+///
+/// # Safety
+/// handle must be NULL or a live handle; input and output must be NULL or valid for input_len and
+/// output_len doubles.
+#[no_mangle]
+pub unsafe extern "C" fn mlp_predict(handle: *const CMlp, input: *const f64, input_len: usize, output: *mut f64, output_len: usize) -> i32 {
+    if handle.is_null() || input.is_null() || output.is_null() {
+        return MLP_ERR_NULL;
+    }
+    let (model, input, output) = unsafe {
+        (&(*handle).model, slice::from_raw_parts(input, input_len), slice::from_raw_parts_mut(output, output_len))
+    };
+    if input_len != model.nin() || output_len != model.nout() {
+        return MLP_ERR_LENGTH;
+    }
+    match catch_unwind(AssertUnwindSafe(|| model.predict(input))) {
+        Ok(prediction) => {
+            output.copy_from_slice(&prediction);
+            MLP_OK
+        }
+        Err(_) => MLP_ERR_PANIC
+    }
+}
+
+/// NULL is a no-op. A panic while dropping the model is swallowed, leaking whatever was left.
+///
+/// # Safety
+/// handle must be NULL or a live handle from mlp_load_json. Like free(), each handle must be freed
+/// exactly once: freeing it again is undefined behavior, so callers should reset their pointer to NULL.
+#[no_mangle]
+pub unsafe extern "C" fn mlp_free(handle: *mut CMlp) {
+    if !handle.is_null() {
+        let model = unsafe { Box::from_raw(handle) };
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(model)));
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn load_and_predict() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let n = MLP::new_with_seed(&vf, 3, &[4, 2], 5);
+        let path = std::env::temp_dir().join(format!("rust_micrograd_ffi_{}.json", std::process::id()));
+        n.save_json(&path).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        unsafe {
+            let handle = mlp_load_json(c_path.as_ptr());
+            std::fs::remove_file(&path).unwrap();
+            assert!(!handle.is_null());
+            assert_eq!((mlp_input_len(handle), mlp_output_len(handle)), (3, 2));
+
+            let input = [0.5, -1.0, 2.0];
+            let mut output = [0.0; 2];
+            assert_eq!(mlp_predict(handle, input.as_ptr(), 3, output.as_mut_ptr(), 2), MLP_OK);
+            assert_eq!(output.to_vec(), n.predict(&input));
+
+            // bad arguments leave the output alone
+            let mut untouched = [7.0; 3];
+            assert_eq!(mlp_predict(handle, input.as_ptr(), 2, untouched.as_mut_ptr(), 2), MLP_ERR_LENGTH);
+            assert_eq!(mlp_predict(handle, input.as_ptr(), 3, untouched.as_mut_ptr(), 3), MLP_ERR_LENGTH);
+            assert_eq!(mlp_predict(handle, ptr::null(), 3, untouched.as_mut_ptr(), 2), MLP_ERR_NULL);
+            assert_eq!(mlp_predict(handle, input.as_ptr(), 3, ptr::null_mut(), 2), MLP_ERR_NULL);
+            assert_eq!(mlp_predict(ptr::null(), input.as_ptr(), 3, untouched.as_mut_ptr(), 2), MLP_ERR_NULL);
+            assert_eq!(untouched, [7.0; 3]);
+
+            mlp_free(handle);
+        }
+    }
+
+    #[test]
+    fn invalid_handles() {
+        unsafe {
+            assert!(mlp_load_json(ptr::null()).is_null());
+            let missing = CString::new("does/not/exist.json").unwrap();
+            assert!(mlp_load_json(missing.as_ptr()).is_null());
+            assert_eq!(mlp_input_len(ptr::null()), 0);
+            // freeing NULL is allowed, so resetting a freed handle to NULL makes a second free harmless
+            mlp_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt::{Debug, Display, LowerExp};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+
+// Scalar type of a graph: what ValueG, ValueFactoryG and MLPG are generic over. Implemented for f64 (the
+// default everywhere, see the Value / ValueFactory / MLP aliases) and f32.
+pub trait Float:
+    Copy + PartialOrd + Debug + Display + LowerExp + 'static
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self> + AddAssign
+{
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    // Rounds for f32. Literals and the f64 results of Rng go through it.
+    fn from_f64(x: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    fn exp(self) -> Self;
+
+    fn ln(self) -> Self;
+
+    fn exp2(self) -> Self;
+
+    fn log2(self) -> Self;
+
+    fn log10(self) -> Self;
+
+    fn sin(self) -> Self;
+
+    fn cos(self) -> Self;
+
+    fn tan(self) -> Self;
+
+    fn asin(self) -> Self;
+
+    fn acos(self) -> Self;
+
+    fn atan(self) -> Self;
+
+    fn abs(self) -> Self;
+
+    fn sqrt(self) -> Self;
+
+    fn floor(self) -> Self;
+
+    fn ceil(self) -> Self;
+
+    // halfway cases away from zero
+    fn round(self) -> Self;
+
+    fn cbrt(self) -> Self;
+
+    fn sinh(self) -> Self;
+
+    fn cosh(self) -> Self;
+
+    // angle of the point (other, self)
+    fn atan2(self, other: Self) -> Self;
+
+    // 1 / (1 + e^-x) computed from e^-|x|, which cannot overflow. Shared by Value::sigmoid and tape replays.
+    fn sigmoid(self) -> Self {
+        let negative = self < Self::zero();
+        let z = (if negative {self} else {-self}).exp();
+        if negative {z / (Self::one() + z)} else {Self::one() / (Self::one() + z)}
+    }
+
+    // -1, 0 or 1; unlike signum zero maps to itself. NaN stays NaN.
+    fn sign(self) -> Self {
+        if self > Self::zero() {Self::one()} else if self < Self::zero() {-Self::one()} else {self}
+    }
+
+    fn tanh(self) -> Self;
+
+    fn powi(self, n: i32) -> Self;
+
+    fn powf(self, n: Self) -> Self;
+
+    fn max(self, other: Self) -> Self;
+
+    fn min(self, other: Self) -> Self;
+
+    fn is_nan(self) -> bool;
+
+    // Bit pattern, widened to u64 for f32
+    fn to_bits_u64(self) -> u64;
+}
+
+impl Float for f64 {
+    fn zero() -> f64 { 0.0 }
+    fn one() -> f64 { 1.0 }
+    fn from_f64(x: f64) -> f64 { x }
+    fn to_f64(self) -> f64 { self }
+    fn exp(self) -> f64 { f64::exp(self) }
+    fn ln(self) -> f64 { f64::ln(self) }
+    fn exp2(self) -> f64 { f64::exp2(self) }
+    fn log2(self) -> f64 { f64::log2(self) }
+    fn log10(self) -> f64 { f64::log10(self) }
+    fn sin(self) -> f64 { f64::sin(self) }
+    fn cos(self) -> f64 { f64::cos(self) }
+    fn tan(self) -> f64 { f64::tan(self) }
+    fn asin(self) -> f64 { f64::asin(self) }
+    fn acos(self) -> f64 { f64::acos(self) }
+    fn atan(self) -> f64 { f64::atan(self) }
+    fn abs(self) -> f64 { f64::abs(self) }
+    fn sqrt(self) -> f64 { f64::sqrt(self) }
+    fn floor(self) -> f64 { f64::floor(self) }
+    fn ceil(self) -> f64 { f64::ceil(self) }
+    fn round(self) -> f64 { f64::round(self) }
+    fn cbrt(self) -> f64 { f64::cbrt(self) }
+    fn sinh(self) -> f64 { f64::sinh(self) }
+    fn cosh(self) -> f64 { f64::cosh(self) }
+    fn atan2(self, other: f64) -> f64 { f64::atan2(self, other) }
+    fn tanh(self) -> f64 { f64::tanh(self) }
+    fn powi(self, n: i32) -> f64 { f64::powi(self, n) }
+    fn powf(self, n: f64) -> f64 { f64::powf(self, n) }
+    fn max(self, other: f64) -> f64 { f64::max(self, other) }
+    fn min(self, other: f64) -> f64 { f64::min(self, other) }
+    fn is_nan(self) -> bool { f64::is_nan(self) }
+    fn to_bits_u64(self) -> u64 { self.to_bits() }
+}
+
+impl Float for f32 {
+    fn zero() -> f32 { 0.0 }
+    fn one() -> f32 { 1.0 }
+    fn from_f64(x: f64) -> f32 { x as f32 }
+    fn to_f64(self) -> f64 { self as f64 }
+    fn exp(self) -> f32 { f32::exp(self) }
+    fn ln(self) -> f32 { f32::ln(self) }
+    fn exp2(self) -> f32 { f32::exp2(self) }
+    fn log2(self) -> f32 { f32::log2(self) }
+    fn log10(self) -> f32 { f32::log10(self) }
+    fn sin(self) -> f32 { f32::sin(self) }
+    fn cos(self) -> f32 { f32::cos(self) }
+    fn tan(self) -> f32 { f32::tan(self) }
+    fn asin(self) -> f32 { f32::asin(self) }
+    fn acos(self) -> f32 { f32::acos(self) }
+    fn atan(self) -> f32 { f32::atan(self) }
+    fn abs(self) -> f32 { f32::abs(self) }
+    fn sqrt(self) -> f32 { f32::sqrt(self) }
+    fn floor(self) -> f32 { f32::floor(self) }
+    fn ceil(self) -> f32 { f32::ceil(self) }
+    fn round(self) -> f32 { f32::round(self) }
+    fn cbrt(self) -> f32 { f32::cbrt(self) }
+    fn sinh(self) -> f32 { f32::sinh(self) }
+    fn cosh(self) -> f32 { f32::cosh(self) }
+    fn atan2(self, other: f32) -> f32 { f32::atan2(self, other) }
+    fn tanh(self) -> f32 { f32::tanh(self) }
+    fn powi(self, n: i32) -> f32 { f32::powi(self, n) }
+    fn powf(self, n: f32) -> f32 { f32::powf(self, n) }
+    fn max(self, other: f32) -> f32 { f32::max(self, other) }
+    fn min(self, other: f32) -> f32 { f32::min(self, other) }
+    fn is_nan(self) -> bool { f32::is_nan(self) }
+    fn to_bits_u64(self) -> u64 { self.to_bits() as u64 }
+}
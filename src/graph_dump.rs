@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::{TapeOp, Value, ValueFactory};
+
+// One node of a dumped graph. op is the Value's op as displayed by OpKind (None for leaves).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DumpNode {
+    pub id: usize,
+    pub data: f64,
+    pub grad: f64,
+    pub op: Option<String>,
+    pub label: Option<String>
+}
+
+// Whole autograd state of a graph: every node reachable from the root with its data, grad, op and label.
+// Node ids are positions in nodes, operands come before the nodes using them and the root is last.
+// edges holds (operand, node) pairs, in operand order for each node.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GraphDump {
+    pub nodes: Vec<DumpNode>,
+    pub edges: Vec<(usize, usize)>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GraphDumpError {
+    Empty,
+    UnsupportedOp { node: usize, op: String },
+    // edge pointing outside the dump or to a node that is not an earlier one
+    InvalidEdge { from: usize, to: usize },
+    Arity { node: usize, expected: usize, got: usize }
+}
+
+impl fmt::Display for GraphDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphDumpError::Empty => write!(f, "graph dump has no nodes"),
+            GraphDumpError::UnsupportedOp { node, op } => write!(f, "node {} has unsupported op {:?}", node, op),
+            GraphDumpError::InvalidEdge { from, to } => write!(f, "invalid edge {} -> {}", from, to),
+            GraphDumpError::Arity { node, expected, got } => write!(f, "node {} has {} operands, expected {}", node, got, expected)
+        }
+    }
+}
+
+impl std::error::Error for GraphDumpError {}
+
+impl Value {
+    pub fn serialize_graph(&self) -> GraphDump {
+        // post order DFS, operands before the node that uses them
+        fn visit(v: &Value, ids: &mut HashMap<Value, usize>, dump: &mut GraphDump) -> usize {
+            if let Some(&id) = ids.get(v) {
+                return id;
+            }
+            let (op, children) = v.op_and_children();
+            let operands: Vec<usize> = children.iter().map(|c| visit(c, ids, dump)).collect();
+            let id = dump.nodes.len();
+            ids.insert(v.clone(), id);
+            dump.nodes.push(DumpNode { id, data: v.get_data(), grad: v.get_grad(), op: op.map(|o| o.to_string()), label: v.label() });
+            dump.edges.extend(operands.into_iter().map(|o| (o, id)));
+            id
+        }
+        let mut dump = GraphDump { nodes: Vec::new(), edges: Vec::new() };
+        visit(self, &mut HashMap::new(), &mut dump);
+        dump
+    }
+}
+
+impl GraphDump {
+    pub fn rebuild(&self, vf: &ValueFactory) -> Result<Value, GraphDumpError> {
+        self.rebuild_with_nodes(vf).map(|(root, _)| root)
+    }
+
+    // Also returns every rebuilt Value, indexed by node id. Ops are replayed through the regular Value
+    // operations, so data is recomputed from the leaves and backward works as on the original graph.
+    // Grads and labels are restored from the dump.
+    pub fn rebuild_with_nodes(&self, vf: &ValueFactory) -> Result<(Value, Vec<Value>), GraphDumpError> {
+        if self.nodes.is_empty() {
+            return Err(GraphDumpError::Empty);
+        }
+        let mut operands: Vec<Vec<usize>> = vec![Vec::new(); self.nodes.len()];
+        for &(from, to) in &self.edges {
+            if to >= self.nodes.len() || from >= to {
+                return Err(GraphDumpError::InvalidEdge { from, to });
+            }
+            operands[to].push(from);
+        }
+
+        let mut values: Vec<Value> = Vec::with_capacity(self.nodes.len());
+        for (id, node) in self.nodes.iter().enumerate() {
+            let args: Vec<&Value> = operands[id].iter().map(|&o| &values[o]).collect();
+            let value = match &node.op {
+                None if args.is_empty() => vf.value(node.data),
+                None => return Err(GraphDumpError::Arity { node: id, expected: 0, got: args.len() }),
+                Some(op_str) => {
+                    let op = TapeOp::parse(op_str).ok_or_else(|| GraphDumpError::UnsupportedOp { node: id, op: op_str.clone() })?;
+                    // operands are stored deduplicated, so x * x has a single edge
+                    let max_args = op.arity();
+                    if args.is_empty() || args.len() > max_args {
+                        return Err(GraphDumpError::Arity { node: id, expected: max_args, got: args.len() });
+                    }
+                    let (a, b) = (args[0], args.get(1).copied().unwrap_or(args[0]));
+                    match op {
+                        TapeOp::Add => a + b,
+                        TapeOp::Mul => a * b,
+                        TapeOp::Relu => a.relu(),
+                        TapeOp::Tanh => a.tanh(),
+                        TapeOp::Exp => a.exp(),
+                        TapeOp::Ln => a.ln(),
+                        TapeOp::Sigmoid => a.sigmoid(),
+                        TapeOp::Sin => a.sin(),
+                        TapeOp::Cos => a.cos(),
+                        TapeOp::Tan => a.tan(),
+                        TapeOp::Asin => a.asin(),
+                        TapeOp::Acos => a.acos(),
+                        TapeOp::Atan => a.atan(),
+                        TapeOp::Atan2 => a.atan2(b),
+                        TapeOp::Max => a.max(b),
+                        TapeOp::Min => a.min(b),
+                        TapeOp::Pow => a.pow(b),
+                        TapeOp::Sinh => a.sinh(),
+                        TapeOp::Cosh => a.cosh(),
+                        TapeOp::Sqrt => a.sqrt(),
+                        TapeOp::Cbrt => a.cbrt(),
+                        TapeOp::Abs => a.abs(),
+                        TapeOp::Sign => a.sign(),
+                        TapeOp::Floor => a.floor(),
+                        TapeOp::Ceil => a.ceil(),
+                        TapeOp::Round => a.round(),
+                        TapeOp::RoundSte => a.round_ste(),
+                        TapeOp::Exp2 => a.exp2(),
+                        TapeOp::Log2 => a.log2(),
+                        TapeOp::Log10 => a.log10(),
+                        TapeOp::Powi(n) => a.powi(n),
+                        TapeOp::Powf(x) => a.powf(x),
+                        TapeOp::AddScalar(x) => a + x,
+                        TapeOp::MulScalar(x) => a * x,
+                        TapeOp::MaxScalar(x) => a.max_scalar(x),
+                        TapeOp::MinScalar(x) => a.min_scalar(x),
+                        TapeOp::Clamp(lo, hi) => a.clamp(lo, hi)
+                    }
+                }
+            };
+            value.set_grad(node.grad);
+            if let Some(label) = &node.label {
+                value.set_label(label);
+            }
+            values.push(value);
+        }
+        Ok((values.last().unwrap().clone(), values))
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Arena;
+
+    // returns the root and the leaves a, b
+    fn expression(vf: &ValueFactory) -> (Value, Value, Value) {
+        let a = vf.value(-1.5);
+        let b = vf.value(0.75);
+        a.set_label("a");
+        b.set_label("b");
+        let c = &(&a * &b) + &a.powi(2);
+        let d = &(&c * &c).tanh() + &(&b + 2.0).relu();
+        let e = &d.exp() + &b.powf(0.5);
+        e.set_label("e");
+        (e, a, b)
+    }
+
+    #[test]
+    fn dump_and_rebuild() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (e, a, b) = expression(&vf);
+        let dump = e.serialize_graph();
+        assert_eq!(dump.nodes.last().unwrap().label.as_deref(), Some("e"));
+        assert!(dump.edges.iter().all(|(from, to)| from < to));
+
+        let (root, nodes) = dump.rebuild_with_nodes(&vf).unwrap();
+        assert_eq!(root.serialize_graph(), dump);
+        assert_eq!(root.get_data(), e.get_data());
+        assert_eq!(root.label().as_deref(), Some("e"));
+        let find = |label: &str| nodes.iter().find(|v| v.label().as_deref() == Some(label)).unwrap().clone();
+        let (ra, rb) = (find("a"), find("b"));
+
+        e.backward();
+        root.backward();
+        assert_eq!((ra.get_grad(), rb.get_grad()), (a.get_grad(), b.get_grad()));
+        // grads after backward survive the round trip too
+        assert_eq!(e.serialize_graph().rebuild(&vf).unwrap().serialize_graph(), e.serialize_graph());
+    }
+
+    #[test]
+    fn invalid_dumps() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (e, _, _) = expression(&vf);
+        let mut dump = e.serialize_graph();
+        let last = dump.nodes.len() - 1;
+        dump.nodes[last].op = Some(String::from("softplus"));
+        assert_eq!(dump.rebuild(&vf).err(), Some(GraphDumpError::UnsupportedOp { node: last, op: String::from("softplus") }));
+
+        let mut dump = e.serialize_graph();
+        dump.edges.push((last, 0));
+        assert_eq!(dump.rebuild(&vf).err(), Some(GraphDumpError::InvalidEdge { from: last, to: 0 }));
+        assert_eq!(GraphDump { nodes: Vec::new(), edges: Vec::new() }.rebuild(&vf).err(), Some(GraphDumpError::Empty));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (e, _, _) = expression(&vf);
+        e.backward();
+        let json = serde_json::to_string(&e.serialize_graph()).unwrap();
+        let dump: GraphDump = serde_json::from_str(&json).unwrap();
+        assert_eq!(dump, e.serialize_graph());
+        assert_eq!(dump.rebuild(&vf).unwrap().get_data(), e.get_data());
+    }
+}
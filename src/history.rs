@@ -0,0 +1,179 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EpochRecord {
+    pub epoch: usize,
+    pub train_loss: f64,
+    pub val_loss: Option<f64>,
+    pub lr: Option<f64>,
+    pub duration: Duration,
+    // extra named metrics, e.g. ("accuracy", 0.93)
+    pub metrics: Vec<(String, f64)>
+}
+
+impl EpochRecord {
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        self.metrics.iter().find(|(n, _)| n == name).map(|(_, v)| *v)
+    }
+}
+
+// Per-epoch record of a training run. Filled in by Trainer::fit, or by hand from a manual loop.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    records: Vec<EpochRecord>,
+    // epoch at which early stopping ended training
+    pub stopped_epoch: Option<usize>
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn record(&mut self, record: EpochRecord) {
+        self.records.push(record);
+    }
+
+    pub fn records(&self) -> &[EpochRecord] {
+        &self.records
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn losses(&self) -> Vec<f64> {
+        self.records.iter().map(|r| r.train_loss).collect()
+    }
+
+    // Only the epochs that were validated
+    pub fn val_losses(&self) -> Vec<f64> {
+        self.records.iter().filter_map(|r| r.val_loss).collect()
+    }
+
+    pub fn lrs(&self) -> Vec<Option<f64>> {
+        self.records.iter().map(|r| r.lr).collect()
+    }
+
+    pub fn metric(&self, name: &str) -> Vec<Option<f64>> {
+        self.records.iter().map(|r| r.metric(name)).collect()
+    }
+
+    // Epoch with the lowest validation loss, or the lowest training loss when nothing was validated
+    pub fn best_epoch(&self) -> Option<usize> {
+        let key = |r: &EpochRecord| if self.records.iter().any(|r| r.val_loss.is_some()) {
+            r.val_loss.unwrap_or(f64::INFINITY)
+        } else {
+            r.train_loss
+        };
+        self.records
+            .iter()
+            .min_by(|a, b| key(a).total_cmp(&key(b)))
+            .map(|r| r.epoch)
+    }
+
+    // Columns: epoch,train_loss,val_loss,lr,duration_secs followed by the metric names of the first record.
+    // Missing values are left empty.
+    pub fn to_csv_string(&self) -> String {
+        let metric_names: Vec<&str> = self.records
+            .first()
+            .map(|r| r.metrics.iter().map(|(n, _)| n.as_str()).collect())
+            .unwrap_or_default();
+        let opt = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+
+        let mut csv = String::from("epoch,train_loss,val_loss,lr,duration_secs");
+        metric_names.iter().for_each(|n| {csv.push(','); csv.push_str(n);});
+        csv.push('\n');
+        self.records.iter().for_each(|r| {
+            csv.push_str(&format!("{},{},{},{},{}", r.epoch, r.train_loss, opt(r.val_loss), opt(r.lr), r.duration.as_secs_f64()));
+            metric_names.iter().for_each(|n| {csv.push(','); csv.push_str(&opt(r.metric(n)));});
+            csv.push('\n');
+        });
+        csv
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv_string())
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manual_history() -> History {
+        let mut history = History::new();
+        [(0.9, 1.0), (0.5, 0.6), (0.3, 0.7)].iter().enumerate().for_each(|(epoch, (train, val))| {
+            history.record(EpochRecord {
+                epoch,
+                train_loss: *train,
+                val_loss: Some(*val),
+                lr: Some(0.1),
+                duration: Duration::from_millis(250),
+                metrics: vec![(String::from("accuracy"), 0.5 + epoch as f64 * 0.1)]
+            });
+        });
+        history
+    }
+
+    #[test]
+    fn accessors() {
+        let history = manual_history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.losses(), vec![0.9, 0.5, 0.3]);
+        assert_eq!(history.val_losses(), vec![1.0, 0.6, 0.7]);
+        assert_eq!(history.metric("accuracy"), vec![Some(0.5), Some(0.6), Some(0.7)]);
+        assert_eq!(history.metric("missing"), vec![None, None, None]);
+        // minimum validation loss, not training loss
+        assert_eq!(history.best_epoch(), Some(1));
+    }
+
+    #[test]
+    fn without_validation() {
+        let mut history = History::new();
+        [3.0, 1.0, 2.0].iter().enumerate().for_each(|(epoch, loss)| {
+            history.record(EpochRecord { epoch, train_loss: *loss, ..Default::default() });
+        });
+        assert!(history.val_losses().is_empty());
+        assert_eq!(history.best_epoch(), Some(1));
+        assert_eq!(History::new().best_epoch(), None);
+        assert_eq!(history.to_csv_string().lines().nth(2).unwrap(), "1,1,,,0");
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let history = manual_history();
+        let path = std::env::temp_dir().join(format!("rust_micrograd_history_{}.csv", std::process::id()));
+        history.to_csv(&path).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut lines = contents.lines();
+        assert_eq!(lines.next().unwrap(), "epoch,train_loss,val_loss,lr,duration_secs,accuracy");
+        let rows: Vec<Vec<f64>> = lines
+            .map(|l| l.split(',').map(|f| f.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), 3);
+        rows.iter().zip(history.records()).for_each(|(row, r)| {
+            assert_eq!(row[0], r.epoch as f64);
+            assert_eq!(row[1], r.train_loss);
+            assert_eq!(Some(row[2]), r.val_loss);
+            assert_eq!(Some(row[3]), r.lr);
+            assert_eq!(row[4], 0.25);
+            assert_eq!(row[5], r.metrics[0].1);
+        });
+    }
+}
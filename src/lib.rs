@@ -1,7 +1,66 @@
 mod arena;
+mod callback;
+mod codegen;
+mod compiled;
+mod data;
+mod default_arena;
 mod engine;
+mod expr;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod float;
+mod graph_dump;
+mod history;
+mod model_io;
+#[cfg(feature = "ndarray")]
+mod ndarray_interop;
 mod nn;
+#[cfg(feature = "onnx")]
+mod onnx;
+mod optim;
+mod parallel;
+#[cfg(feature = "plots")]
+mod plot;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "rayon")]
+mod rayon_batch;
+mod rng;
+mod tape;
+mod testing;
+#[cfg(feature = "trace")]
+mod trace;
+mod train;
+mod vec_value;
+mod viz;
 
 pub use arena::*;
+pub use callback::*;
+pub use compiled::*;
+pub use data::*;
+pub use default_arena::*;
 pub use engine::*;
+pub use expr::*;
+#[cfg(feature = "ffi")]
+pub use ffi::*;
+pub use float::*;
+pub use graph_dump::*;
+pub use history::*;
+pub use model_io::*;
+#[cfg(feature = "ndarray")]
+pub use ndarray_interop::*;
 pub use nn::*;
+pub use optim::*;
+pub use parallel::*;
+#[cfg(feature = "plots")]
+pub use plot::*;
+#[cfg(feature = "python")]
+pub use python::*;
+pub use rng::*;
+pub use tape::*;
+pub use testing::*;
+#[cfg(feature = "trace")]
+pub use trace::*;
+pub use train::*;
+pub use vec_value::*;
+pub use viz::*;
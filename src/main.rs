@@ -1,9 +1,13 @@
 use rust_micrograd::{Arena, Module};
 use rust_micrograd::{Value, ValueFactory, MLP};
+#[cfg(not(target_arch = "wasm32"))]
 use std::process::Command;
+#[cfg(not(target_arch = "wasm32"))]
 use std::fs;
 
+#[cfg(not(target_arch = "wasm32"))]
 const DOT_FILE: &str = "graph.dot";
+#[cfg(not(target_arch = "wasm32"))]
 const DOT_PNG: &str = "graph.png";
 
 fn main() {
@@ -37,7 +41,7 @@ fn example_usage(vf: &ValueFactory) {
 }
 
 fn example_training_loop(vf: &ValueFactory) {
-    let n = MLP::new(vf, 3, &vec![4 as usize, 4 as usize, 1 as usize]);
+    let n = MLP::new(vf, 3, &[4, 4, 1]);
 
     let xs = vec![
         vec![vf.value(2.0), vf.value(3.0), vf.value(-1.0)],
@@ -58,11 +62,14 @@ fn example_training_loop(vf: &ValueFactory) {
     let forward = |xs: &Vec<Vec<Value>>| -> Vec<Value> {
         xs.iter()
             .map(|x| {
-                n.call(x).get(0).unwrap().clone()
+                n.call(x).first().unwrap().clone()
             })
             .collect()
     };
 
+    // everything allocated after this is per-step scratch and freed at the end of each step
+    let mark = vf.arena().checkpoint();
+
     let epochs = 500;
     let lr = -0.1;
     println!("Beginning Training Loop");
@@ -78,6 +85,7 @@ fn example_training_loop(vf: &ValueFactory) {
         if i % 10 == 0 {
             println!("Loss at step {}: {}", i, loss.get_data());
         }
+        vf.arena().truncate_to(mark);
     }
     let ypred_final = forward(&xs);
     let final_loss = mse_loss(&ys, &ypred_final);
@@ -93,7 +101,7 @@ fn example_expression_with_dot(vf: &ValueFactory) {
     let w1 = vf.value(-3.0);
     let w2 = vf.value(1.0);
     // bias of the neuron
-    let b = vf.value(6.8813735870195432);
+    let b = vf.value(6.881_373_587_019_543);
     // x1*w1 + x2*w2 + b
     let x1w1 = &x1*&w1;
     let x2w2 = &x2*&w2;
@@ -104,14 +112,22 @@ fn example_expression_with_dot(vf: &ValueFactory) {
     o.backward();
     let dot = o.draw_dot();
 
-    write_dot(&dot, DOT_FILE).unwrap();
-    render_dot(DOT_FILE, DOT_PNG).unwrap();
+    // no filesystem or Graphviz to shell out to on wasm32, so just print the graph there
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        write_dot(&dot, DOT_FILE).unwrap();
+        render_dot(DOT_FILE, DOT_PNG).unwrap();
+    }
+    #[cfg(target_arch = "wasm32")]
+    println!("{}", dot);
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn write_dot(dot: &str, out_path: &str) -> std::io::Result<()> {
     fs::write(out_path, dot)
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 fn render_dot(dot_path: &str, png_path: &str) -> std::io::Result<()> {
     let status = Command::new("dot")
         .args(["-Tpng", dot_path, "-o", png_path])
@@ -0,0 +1,519 @@
+use std::fmt;
+use std::io;
+use std::iter::{once, zip, Peekable};
+use std::str::Chars;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+use std::{fs, path::Path};
+use crate::{Activation, ValueFactory, MLP};
+#[cfg(feature = "serde")]
+use crate::OptimizerState;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+use crate::{Module, Optimizer};
+
+#[derive(Debug)]
+pub enum ModelIoError {
+    Io(io::Error),
+    // the file could not be decoded
+    Parse(String),
+    // the file decoded but doesn't describe a valid model
+    Architecture(String),
+    // binary file written with a different format version
+    Version { expected: u8, found: u8 }
+}
+
+impl fmt::Display for ModelIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelIoError::Io(e) => write!(f, "io error: {}", e),
+            ModelIoError::Parse(msg) => write!(f, "could not parse model: {}", msg),
+            ModelIoError::Architecture(msg) => write!(f, "invalid model architecture: {}", msg),
+            ModelIoError::Version { expected, found } => write!(f, "model file has format version {}, expected {}", found, expected)
+        }
+    }
+}
+
+impl std::error::Error for ModelIoError {}
+
+impl From<io::Error> for ModelIoError {
+    fn from(e: io::Error) -> ModelIoError {
+        ModelIoError::Io(e)
+    }
+}
+
+// Serializable description of an MLP: architecture plus all weights. weights[j] holds the input weights of
+// neuron j and biases[j] its bias.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MlpSpec {
+    pub nin: usize,
+    pub layers: Vec<LayerSpec>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerSpec {
+    pub nout: usize,
+    pub activation: Activation,
+    pub bias: bool,
+    pub weights: Vec<Vec<f64>>,
+    pub biases: Vec<f64>
+}
+
+impl MlpSpec {
+    pub fn layer_sizes(&self) -> Vec<usize> {
+        self.layers.iter().map(|l| l.nout).collect()
+    }
+
+    // Weights in Module::parameters() order (each neuron's weights followed by its bias)
+    pub fn state_dict(&self) -> Vec<f64> {
+        self.layers.iter()
+            .flat_map(|l| zip(&l.weights, &l.biases))
+            .flat_map(|(w, b)| w.iter().chain(once(b)).copied())
+            .collect()
+    }
+
+    // Same layer sizes and activations, weights may differ
+    pub fn same_architecture(&self, other: &MlpSpec) -> bool {
+        self.nin == other.nin
+            && self.layers.len() == other.layers.len()
+            && zip(&self.layers, &other.layers).all(|(a, b)| a.nout == b.nout && a.activation == b.activation && a.bias == b.bias)
+    }
+
+    // Checks that the declared sizes agree with the number of weights in every layer
+    pub fn validate(&self) -> Result<(), ModelIoError> {
+        let err = |msg: String| Err(ModelIoError::Architecture(msg));
+        if self.nin == 0 || self.layers.is_empty() {
+            return err(format!("need at least one input and one layer, got nin {} and {} layers", self.nin, self.layers.len()));
+        }
+        let mut nin = self.nin;
+        for (i, l) in self.layers.iter().enumerate() {
+            if l.nout == 0 {
+                return err(format!("layer {} has no neurons", i));
+            }
+            if !l.bias {
+                return err(format!("layer {} has no bias, which MLP does not support", i));
+            }
+            if l.weights.len() != l.nout || l.biases.len() != l.nout {
+                return err(format!("layer {} declares {} neurons but has {} weight rows and {} biases", i, l.nout, l.weights.len(), l.biases.len()));
+            }
+            if let Some(row) = l.weights.iter().position(|w| w.len() != nin) {
+                return err(format!("layer {} neuron {} has {} weights, expected {}", i, row, l.weights[row].len(), nin));
+            }
+            nin = l.nout;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl MLP {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_json(&self, path: impl AsRef<Path>) -> Result<(), ModelIoError> {
+        let json = serde_json::to_string_pretty(&self.to_spec()).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_json(vf: &ValueFactory, path: impl AsRef<Path>) -> Result<MLP, ModelIoError> {
+        let json = fs::read_to_string(path)?;
+        let spec: MlpSpec = serde_json::from_str(&json).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+        MLP::from_spec(vf, &spec)
+    }
+}
+
+// Model weights plus optimizer state, for resuming an interrupted training run
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    pub model: MlpSpec,
+    pub optimizer: OptimizerState
+}
+
+#[cfg(feature = "serde")]
+impl Checkpoint {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(path: impl AsRef<Path>, model: &MLP, optimizer: &dyn Optimizer) -> Result<(), ModelIoError> {
+        let checkpoint = Checkpoint { model: model.to_spec(), optimizer: optimizer.state_dict() };
+        let json = serde_json::to_string(&checkpoint).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    // Restores into an existing model and the optimizer built over its parameters. Nothing is modified
+    // unless both the architecture and the optimizer state match.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<Path>, model: &MLP, optimizer: &mut dyn Optimizer) -> Result<(), ModelIoError> {
+        let json = fs::read_to_string(path)?;
+        let checkpoint: Checkpoint = serde_json::from_str(&json).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+        checkpoint.model.validate()?;
+        if !checkpoint.model.same_architecture(&model.to_spec()) {
+            return Err(ModelIoError::Architecture(format!(
+                "checkpoint is for an MLP {} -> {:?}, not {} -> {:?}",
+                checkpoint.model.nin, checkpoint.model.layer_sizes(), model.to_spec().nin, model.to_spec().layer_sizes()
+            )));
+        }
+        let previous = optimizer.state_dict();
+        optimizer.load_state_dict(&checkpoint.optimizer).map_err(|e| ModelIoError::Architecture(e.to_string()))?;
+        if let Err(e) = model.load_state_dict(&checkpoint.model.state_dict()) {
+            optimizer.load_state_dict(&previous).unwrap();
+            return Err(ModelIoError::Architecture(e.to_string()));
+        }
+        Ok(())
+    }
+}
+
+// First byte of every binary model file. Bump it whenever the MlpSpec layout changes.
+#[cfg(feature = "bincode")]
+pub const BIN_FORMAT_VERSION: u8 = 1;
+
+// Same MlpSpec as the JSON files, bincode encoded after the version byte
+#[cfg(feature = "bincode")]
+impl MLP {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_bin(&self, path: impl AsRef<Path>) -> Result<(), ModelIoError> {
+        let mut bytes = vec![BIN_FORMAT_VERSION];
+        bincode::serialize_into(&mut bytes, &self.to_spec()).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_bin(vf: &ValueFactory, path: impl AsRef<Path>) -> Result<MLP, ModelIoError> {
+        let bytes = fs::read(path)?;
+        match bytes.first() {
+            None => Err(ModelIoError::Parse(String::from("empty file"))),
+            Some(&found) if found != BIN_FORMAT_VERSION => Err(ModelIoError::Version { expected: BIN_FORMAT_VERSION, found }),
+            Some(_) => {
+                let spec: MlpSpec = bincode::deserialize(&bytes[1..]).map_err(|e| ModelIoError::Parse(e.to_string()))?;
+                MLP::from_spec(vf, &spec)
+            }
+        }
+    }
+}
+
+// Weight exchange format shared with Python micrograd: nested JSON lists, layers -> neurons -> [w..., b].
+// It carries no activations; imported models use micrograd's layout of ReLU hidden layers and a linear
+// output layer. tests/fixtures/gen_micrograd_fixture.py shows how to produce it from a micrograd MLP:
+//   [[[w.data for w in n.w] + [n.b.data] for n in layer.neurons] for layer in model.layers]
+impl MLP {
+    // Fails on NaN or infinite weights (e.g. of a diverged model), which JSON has no numbers for
+    pub fn export_py_json(&self) -> Result<String, ModelIoError> {
+        let layers = self.to_spec().layers.iter().enumerate().map(|(i, l)| {
+            let neurons = zip(&l.weights, &l.biases).enumerate().map(|(j, (w, b))| {
+                let values = w.iter().chain(once(b)).map(|v| if v.is_finite() {
+                    Ok(format!("{:?}", v))
+                } else {
+                    Err(ModelIoError::Parse(format!("layer {} neuron {} has non-finite weight {}", i, j, v)))
+                }).collect::<Result<Vec<String>, ModelIoError>>()?;
+                Ok(format!("[{}]", values.join(", ")))
+            }).collect::<Result<Vec<String>, ModelIoError>>()?;
+            Ok(format!("[{}]", neurons.join(", ")))
+        }).collect::<Result<Vec<String>, ModelIoError>>()?;
+        Ok(format!("[{}]\n", layers.join(",\n ")))
+    }
+
+    pub fn import_py_json(vf: &ValueFactory, json: &str) -> Result<MLP, ModelIoError> {
+        let parse_err = |msg: String| ModelIoError::Parse(msg);
+        let layers = match parse_nested(json)? {
+            NestedList::List(layers) => layers,
+            NestedList::Num(_) => return Err(parse_err(String::from("expected a list of layers")))
+        };
+        let n_layers = layers.len();
+        let mut nin = None;
+        let layers = layers.into_iter().enumerate().map(|(i, layer)| {
+            let NestedList::List(neurons) = layer else {
+                return Err(parse_err(format!("layer {} is not a list of neurons", i)));
+            };
+            let rows = neurons.into_iter().enumerate().map(|(j, neuron)| match neuron {
+                NestedList::List(values) if !values.is_empty() => values.into_iter().map(|v| match v {
+                    NestedList::Num(v) => Ok(v),
+                    NestedList::List(_) => Err(parse_err(format!("layer {} neuron {} contains a nested list", i, j)))
+                }).collect::<Result<Vec<f64>, ModelIoError>>(),
+                _ => Err(parse_err(format!("layer {} neuron {} is not a non-empty list of numbers", i, j)))
+            }).collect::<Result<Vec<Vec<f64>>, ModelIoError>>()?;
+            nin = nin.or(rows.first().map(|r| r.len() - 1));
+            Ok(LayerSpec {
+                nout: rows.len(),
+                activation: if i + 1 == n_layers {Activation::Linear} else {Activation::Relu},
+                bias: true,
+                weights: rows.iter().map(|r| r[..r.len() - 1].to_vec()).collect(),
+                biases: rows.iter().map(|r| r[r.len() - 1]).collect()
+            })
+        }).collect::<Result<Vec<LayerSpec>, ModelIoError>>()?;
+        MLP::from_spec(vf, &MlpSpec { nin: nin.unwrap_or(0), layers })
+    }
+}
+
+// Nested JSON arrays of numbers, which is all the py-json format needs
+enum NestedList {
+    Num(f64),
+    List(Vec<NestedList>)
+}
+
+// Deepest nesting parse_nested accepts. The py-json format has three levels (layers, neurons, values); the
+// cap keeps malformed input from recursing without bound.
+const MAX_NESTING: usize = 3;
+
+fn parse_nested(json: &str) -> Result<NestedList, ModelIoError> {
+    fn skip_ws(s: &mut Peekable<Chars>) {
+        while s.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn parse(s: &mut Peekable<Chars>, depth: usize) -> Result<NestedList, ModelIoError> {
+        skip_ws(s);
+        if s.next_if_eq(&'[').is_some() {
+            if depth == MAX_NESTING {
+                return Err(ModelIoError::Parse(format!("lists nested deeper than {} levels", MAX_NESTING)));
+            }
+            let mut items = Vec::new();
+            skip_ws(s);
+            if s.next_if_eq(&']').is_some() {
+                return Ok(NestedList::List(items));
+            }
+            loop {
+                items.push(parse(s, depth + 1)?);
+                skip_ws(s);
+                match s.next() {
+                    Some(',') => (),
+                    Some(']') => return Ok(NestedList::List(items)),
+                    other => return Err(ModelIoError::Parse(format!("expected ',' or ']', found {:?}", other)))
+                }
+            }
+        }
+        let mut token = String::new();
+        while let Some(c) = s.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+            token.push(c);
+        }
+        token.parse()
+            .map(NestedList::Num)
+            .map_err(|_| ModelIoError::Parse(format!("invalid number {:?} before {:?}", token, s.peek())))
+    }
+
+    let mut chars = json.chars().peekable();
+    let nested = parse(&mut chars, 0)?;
+    skip_ws(&mut chars);
+    match chars.next() {
+        None => Ok(nested),
+        Some(c) => Err(ModelIoError::Parse(format!("unexpected trailing {:?}", c)))
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, Module, Rng};
+
+    #[test]
+    fn spec_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let activations = [Activation::Relu, Activation::Tanh, Activation::Linear];
+        let n = MLP::new_with_activations(&vf, 3, &[4, 4, 2], &activations, &mut Rng::new(1));
+        let spec = n.to_spec();
+        assert_eq!(spec.layer_sizes(), vec![4, 4, 2]);
+        let m = MLP::from_spec(&vf, &spec).unwrap();
+        assert_eq!(m.to_spec(), spec);
+        assert_eq!(m.predict(&[0.5, -1.0, 2.0]), n.predict(&[0.5, -1.0, 2.0]));
+    }
+
+    #[test]
+    fn inconsistent_specs_rejected() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let spec = MLP::new_with_seed(&vf, 2, &[3, 1], 4).to_spec();
+        let mut bad = spec.clone();
+        bad.layers[0].nout = 4;
+        assert!(matches!(MLP::from_spec(&vf, &bad), Err(ModelIoError::Architecture(_))));
+        let mut bad = spec.clone();
+        bad.layers[1].weights[0].pop();
+        assert!(matches!(bad.validate(), Err(ModelIoError::Architecture(_))));
+        let mut bad = spec.clone();
+        bad.nin = 3;
+        assert!(bad.validate().is_err());
+        let mut bad = spec;
+        bad.layers[0].biases.clear();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn py_json_fixture() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::import_py_json(&vf, include_str!("../tests/fixtures/micrograd_mlp.json")).unwrap();
+        assert_eq!(n.to_spec().layer_sizes(), vec![4, 4, 2]);
+        let NestedList::List(cases) = parse_nested(include_str!("../tests/fixtures/micrograd_forward.json")).unwrap() else {
+            panic!("forward fixture is not a list");
+        };
+        let numbers = |l: &NestedList| match l {
+            NestedList::List(values) => values.iter().map(|v| match v {NestedList::Num(v) => *v, _ => panic!()}).collect::<Vec<f64>>(),
+            NestedList::Num(_) => panic!()
+        };
+        assert_eq!(cases.len(), 8);
+        cases.iter().for_each(|case| {
+            let NestedList::List(pair) = case else { panic!() };
+            let (x, expected) = (numbers(&pair[0]), numbers(&pair[1]));
+            let out: Vec<f64> = n.call(&x.iter().map(|v| vf.value(*v)).collect::<Vec<_>>()).iter().map(|v| v.get_data()).collect();
+            out.iter().zip(&expected).for_each(|(o, e)| assert!((o - e).abs() < 1e-10, "{} vs {}", o, e));
+        });
+
+        // export then import is the identity
+        let exported = n.export_py_json().unwrap();
+        let m = MLP::import_py_json(&vf, &exported).unwrap();
+        assert_eq!(m.to_spec(), n.to_spec());
+        assert_eq!(m.export_py_json().unwrap(), exported);
+
+        // a diverged model is refused rather than written as something no JSON reader accepts
+        m.parameters().nth(5).unwrap().set_data(f64::NAN);
+        let err = m.export_py_json().unwrap_err().to_string();
+        assert!(err.contains("layer 0 neuron 1") && err.contains("NaN"), "{}", err);
+    }
+
+    #[test]
+    fn py_json_shape_errors() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // second layer expects 2 weights + bias per neuron, neuron 1 has only one weight
+        let json = "[[[1, 2, 0.5], [3, 4, -0.5]], [[1, 1, 0], [1, 0]]]";
+        let err = MLP::import_py_json(&vf, json).err().unwrap().to_string();
+        assert!(err.contains("layer 1 neuron 1"), "{}", err);
+
+        let err = MLP::import_py_json(&vf, "[[[1, 2, 0.5], []]]").err().unwrap().to_string();
+        assert!(err.contains("layer 0 neuron 1"), "{}", err);
+        assert!(matches!(MLP::import_py_json(&vf, "[[[1, 2, 0.5]]"), Err(ModelIoError::Parse(_))));
+        assert!(matches!(MLP::import_py_json(&vf, "[[[1, x]]]"), Err(ModelIoError::Parse(_))));
+        let err = MLP::import_py_json(&vf, &format!("{}1{}", "[".repeat(100_000), "]".repeat(100_000))).err().unwrap().to_string();
+        assert!(err.contains("nested deeper than 3"), "{}", err);
+    }
+
+    #[cfg(feature = "serde")]
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rust_micrograd_{}_{}", name, std::process::id()))
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let path = temp_path("model.json");
+
+        let n = MLP::new_with_seed(&vf, 3, &[8, 8, 1], 2);
+        n.save_json(&path).unwrap();
+        let m = MLP::load_json(&vf, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut rng = Rng::new(3);
+        for _ in 0..20 {
+            let x: Vec<f64> = (0..3).map(|_| rng.uniform(-5.0, 5.0)).collect();
+            assert_eq!(n.predict(&x).iter().map(|v| v.to_bits()).collect::<Vec<_>>(), m.predict(&x).iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_errors() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let path = temp_path("bad_model.json");
+
+        let n = MLP::new_with_seed(&vf, 2, &[3, 1], 2);
+        n.save_json(&path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+
+        fs::write(&path, &json[..json.len() / 2]).unwrap();
+        assert!(matches!(MLP::load_json(&vf, &path), Err(ModelIoError::Parse(_))));
+
+        // declared size no longer matches the weights
+        let mut spec: MlpSpec = serde_json::from_str(&json).unwrap();
+        spec.layers[0].nout = 5;
+        fs::write(&path, serde_json::to_string(&spec).unwrap()).unwrap();
+        assert!(matches!(MLP::load_json(&vf, &path), Err(ModelIoError::Architecture(_))));
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(MLP::load_json(&vf, &path), Err(ModelIoError::Io(_))));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn checkpoint_resume() {
+        use crate::{Adam, Loss, Trainer, TrainerOptions};
+
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, labels) = crate::make_moons(12, 0.1, 4);
+        let ys: Vec<Vec<f64>> = labels.iter().map(|&l| vec![l as f64]).collect();
+        let train = |n: &MLP, opt: Adam, epochs: usize| {
+            let options = TrainerOptions { epochs, batch_size: 4, ..Default::default() };
+            let mut trainer = Trainer::new_mlp(n, opt, Loss::Mse, &vf, options);
+            trainer.fit(&xs, &ys);
+            trainer.optimizer().state_dict()
+        };
+        let adam_with_state = |n: &MLP, state: &OptimizerState| {
+            let mut opt = Adam::new(n.parameters().cloned().collect(), 0.05);
+            opt.load_state_dict(state).unwrap();
+            opt
+        };
+
+        let straight = MLP::new_with_seed(&vf, 2, &[6, 1], 3);
+        train(&straight, Adam::new(straight.parameters().cloned().collect(), 0.05), 50);
+
+        let path = temp_path("checkpoint.json");
+        let first = MLP::new_with_seed(&vf, 2, &[6, 1], 3);
+        let state = train(&first, Adam::new(first.parameters().cloned().collect(), 0.05), 25);
+        Checkpoint::save(&path, &first, &adam_with_state(&first, &state)).unwrap();
+
+        let resumed = MLP::new_with_seed(&vf, 2, &[6, 1], 99);
+        let mut opt = Adam::new(resumed.parameters().cloned().collect(), 0.05);
+        Checkpoint::load(&path, &resumed, &mut opt).unwrap();
+        train(&resumed, opt, 25);
+        assert_eq!(resumed.state_dict(), straight.state_dict());
+
+        // wrong architecture is rejected without touching the model
+        let other = MLP::new_with_seed(&vf, 2, &[5, 1], 3);
+        let before = other.state_dict();
+        let mut opt = Adam::new(other.parameters().cloned().collect(), 0.05);
+        assert!(matches!(Checkpoint::load(&path, &other, &mut opt), Err(ModelIoError::Architecture(_))));
+        assert_eq!(other.state_dict(), before);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bin_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (bin_path, json_path) = (temp_path("model.bin"), temp_path("model_bin.json"));
+
+        let n = MLP::new_with_seed(&vf, 3, &[16, 16, 1], 5);
+        n.save_bin(&bin_path).unwrap();
+        n.save_json(&json_path).unwrap();
+        let from_bin = MLP::load_bin(&vf, &bin_path).unwrap();
+        let from_json = MLP::load_json(&vf, &json_path).unwrap();
+        assert_eq!(from_bin.to_spec(), from_json.to_spec());
+        assert_eq!(from_bin.to_spec(), n.to_spec());
+
+        let (bin_size, json_size) = (fs::metadata(&bin_path).unwrap().len(), fs::metadata(&json_path).unwrap().len());
+        assert!(bin_size * 2 < json_size, "bin {} bytes, json {} bytes", bin_size, json_size);
+        fs::remove_file(&json_path).unwrap();
+
+        let mut bytes = fs::read(&bin_path).unwrap();
+        bytes[0] = BIN_FORMAT_VERSION + 1;
+        fs::write(&bin_path, &bytes).unwrap();
+        assert!(matches!(
+            MLP::load_bin(&vf, &bin_path),
+            Err(ModelIoError::Version { expected: BIN_FORMAT_VERSION, found }) if found == BIN_FORMAT_VERSION + 1
+        ));
+
+        bytes[0] = BIN_FORMAT_VERSION;
+        fs::write(&bin_path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(MLP::load_bin(&vf, &bin_path), Err(ModelIoError::Parse(_))));
+        fs::remove_file(&bin_path).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+use ndarray::{Array1, Array2, Axis};
+use crate::{Value, ValueFactory, MLP};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DimensionError {
+    pub expected: usize,
+    pub got: usize
+}
+
+impl fmt::Display for DimensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} input features, got {}", self.expected, self.got)
+    }
+}
+
+impl std::error::Error for DimensionError {}
+
+impl ValueFactory {
+    pub fn values_from_array(&self, array: &Array1<f64>) -> Vec<Value> {
+        array.iter().map(|v| self.value(*v)).collect()
+    }
+}
+
+pub fn values_to_array(values: &[Value]) -> Array1<f64> {
+    values.iter().map(|v| v.get_data()).collect()
+}
+
+// Gradients in iteration order, e.g. grads_to_array(model.parameters())
+pub fn grads_to_array<'a>(params: impl IntoIterator<Item = &'a Value>) -> Array1<f64> {
+    params.into_iter().map(|p| p.get_grad()).collect()
+}
+
+impl MLP {
+    // Graph-free inference on one sample, see MLP::predict
+    pub fn call_array(&self, x: &Array1<f64>) -> Result<Array1<f64>, DimensionError> {
+        if x.len() != self.nin() {
+            return Err(DimensionError { expected: self.nin(), got: x.len() });
+        }
+        Ok(Array1::from(self.predict(&x.to_vec())))
+    }
+
+    // One sample per row
+    pub fn call_array2(&self, xs: &Array2<f64>) -> Result<Array2<f64>, DimensionError> {
+        if xs.ncols() != self.nin() {
+            return Err(DimensionError { expected: self.nin(), got: xs.ncols() });
+        }
+        let mut out = Array2::zeros((xs.nrows(), self.nout()));
+        out.axis_iter_mut(Axis(0)).zip(xs.axis_iter(Axis(0))).for_each(|(mut row, x)| {
+            row.assign(&Array1::from(self.predict(&x.to_vec())));
+        });
+        Ok(out)
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    use crate::{Arena, Module};
+
+    #[test]
+    fn array_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = array![1.5, -2.0, 0.25];
+        let values = vf.values_from_array(&a);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values_to_array(&values), a);
+    }
+
+    #[test]
+    fn batched_call() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 2], 3);
+        let xs = array![[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0]];
+        let out = n.call_array2(&xs).unwrap();
+        assert_eq!(out.dim(), (3, 2));
+        xs.axis_iter(Axis(0)).zip(out.axis_iter(Axis(0))).for_each(|(x, row)| {
+            assert_eq!(n.call_array(&x.to_owned()).unwrap(), row);
+        });
+
+        assert_eq!(n.call_array(&array![1.0, 2.0]), Err(DimensionError { expected: 3, got: 2 }));
+        assert_eq!(n.call_array2(&Array2::zeros((2, 4))), Err(DimensionError { expected: 3, got: 4 }));
+    }
+
+    #[test]
+    fn grads_follow_named_parameters() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 2, &[3, 1], 3);
+        let out = &n.call(&vf.values_from_array(&array![0.5, -1.0]))[0];
+        out.backward();
+        let grads = grads_to_array(n.parameters());
+        let named: Vec<f64> = n.named_parameters().iter().flat_map(|(_, ps)| ps.iter().map(|p| p.get_grad())).collect();
+        assert_eq!(grads.to_vec(), named);
+        assert!(grads.iter().any(|g| *g != 0.0));
+    }
+}
@@ -1,112 +1,916 @@
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
-use crate::{Value, ValueFactory};
+use crate::{format_bytes, Float, LayerSpec, MlpSpec, ModelIoError, ParamGroup, Rng, Value, ValueDataG, ValueFactory, ValueFactoryG, ValueG};
+use std::cell::RefCell;
+use std::{fmt, mem};
 use std::iter::{zip, once};
 
-pub trait Module {
+pub trait Module<T: Float = f64> {
     fn zero_grad(&self) {
         self.parameters()
-            .for_each(|v| v.set_grad(0.0));
+            .for_each(|v| v.set_grad(T::zero()));
     }
 
-    fn parameters(&self) -> impl Iterator<Item = &Value>;
+    fn parameters(&self) -> impl Iterator<Item = &ValueG<T>>;
+
+    // Redraws the data of every trainable parameter, in parameters() order. Grads are left alone.
+    // Init::Uniform { lo: -1.0, hi: 1.0 } with a fresh Rng::new(seed) gives back the weights of
+    // MLP::new_with_seed(.., seed).
+    fn reset_parameters(&self, init: &Init, rng: &mut Rng) {
+        self.trainable_parameters().for_each(|p| p.set_data(T::from_f64(init.sample(rng))));
+    }
+
+    // Parameter data in parameters() order
+    fn state_dict(&self) -> Vec<T> {
+        self.parameters().map(|p| p.get_data()).collect()
+    }
+
+    fn load_state_dict(&self, state: &[T]) -> Result<(), StateDictError> {
+        let expected = self.parameters().count();
+        if state.len() != expected {
+            return Err(StateDictError { expected, got: state.len() });
+        }
+        zip(self.parameters(), state).for_each(|(p, v)| p.set_data(*v));
+        Ok(())
+    }
+
+    fn num_parameters(&self) -> usize {
+        self.parameters().count()
+    }
+
+    // parameters() without constants, for modules that list fixed values among their parameters
+    fn trainable_parameters(&self) -> impl Iterator<Item = &ValueG<T>> {
+        self.parameters().filter(|p| p.requires_grad())
+    }
+
+    // Parameters grouped into named tensors, in parameters() order. Modules without a structure of their
+    // own report a single tensor.
+    fn named_parameters(&self) -> Vec<(String, Vec<ValueG<T>>)> {
+        vec![(String::from("params"), self.parameters().cloned().collect())]
+    }
+
+    // Names of the individual parameters, in parameters() order: the tensor name for one-element tensors
+    // and "{tensor}.{i}" otherwise, e.g. "layers.1.neurons.0.w.2" and "layers.1.neurons.0.b" for an MLP
+    fn parameter_names(&self) -> Vec<String> {
+        self.named_parameters().into_iter().flat_map(|(name, ps)| {
+            let n = ps.len();
+            (0..n).map(move |i| if n == 1 {name.clone()} else {format!("{}.{}", name, i)})
+        }).collect()
+    }
+
+    fn get_parameter(&self, name: &str) -> Option<&ValueG<T>> {
+        let idx = self.parameter_names().iter().position(|n| n == name)?;
+        self.parameters().nth(idx)
+    }
+
+    fn set_parameter(&self, name: &str, value: T) -> Result<(), UnknownParameterError> {
+        match self.get_parameter(name) {
+            Some(p) => {
+                p.set_data(value);
+                Ok(())
+            }
+            None => Err(UnknownParameterError::new(name, &self.parameter_names()))
+        }
+    }
+
+    // One flat vector of all parameter data plus the layout needed to map it back
+    fn flatten(&self) -> (Vec<T>, LayoutSpec) {
+        let named = self.named_parameters();
+        let data = named.iter().flat_map(|(_, ps)| ps.iter().map(|p| p.get_data())).collect();
+        let entries = named.into_iter().map(|(name, ps)| LayoutEntry { name, len: ps.len() }).collect();
+        (data, LayoutSpec { entries })
+    }
+
+    // Inverse of flatten. spec must describe this module's layout exactly.
+    fn unflatten(&self, data: &[T], spec: &LayoutSpec) -> Result<(), LayoutError> {
+        let named = self.named_parameters();
+        if named.len() != spec.entries.len() {
+            return Err(LayoutError::EntryCount { expected: named.len(), got: spec.entries.len() });
+        }
+        if let Some((idx, ((name, ps), entry))) = zip(&named, &spec.entries).enumerate().find(|(_, ((name, ps), e))| *name != e.name || ps.len() != e.len) {
+            return Err(LayoutError::EntryMismatch { idx, expected: LayoutEntry { name: name.clone(), len: ps.len() }, got: entry.clone() });
+        }
+        if data.len() != spec.total_len() {
+            return Err(LayoutError::DataLength { expected: spec.total_len(), got: data.len() });
+        }
+        zip(named.iter().flat_map(|(_, ps)| ps), data).for_each(|(p, v)| p.set_data(*v));
+        Ok(())
+    }
 }
 
-struct Neuron {
-    w: Vec<Value>,
-    b: Value
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayoutEntry {
+    pub name: String,
+    pub len: usize
 }
 
-impl Neuron {
-    fn new(vf: &ValueFactory, nin: usize) -> Neuron {
-        Neuron {
-            w: (0..nin).map(|_| vf.value(rand::random::<f64>() * 2.0 - 1.0)).collect(),
-            b: vf.value(rand::random::<f64>() * 2.0 - 1.0)
+// Names and lengths of the tensors in a flattened parameter vector, in order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayoutSpec {
+    pub entries: Vec<LayoutEntry>
+}
+
+impl LayoutSpec {
+    pub fn total_len(&self) -> usize {
+        self.entries.iter().map(|e| e.len).sum()
+    }
+
+    // Range of the named tensor within the flat vector
+    pub fn range_of(&self, name: &str) -> Option<std::ops::Range<usize>> {
+        let mut start = 0;
+        for e in &self.entries {
+            if e.name == name {
+                return Some(start..start + e.len);
+            }
+            start += e.len;
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutError {
+    EntryCount { expected: usize, got: usize },
+    EntryMismatch { idx: usize, expected: LayoutEntry, got: LayoutEntry },
+    DataLength { expected: usize, got: usize }
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::EntryCount { expected, got } => write!(f, "layout has {} tensors but the module has {}", got, expected),
+            LayoutError::EntryMismatch { idx, expected, got } => write!(
+                f, "layout entry {} is {} ({} values) but the module has {} ({} values)", idx, got.name, got.len, expected.name, expected.len
+            ),
+            LayoutError::DataLength { expected, got } => write!(f, "data has {} values but the layout needs {}", got, expected)
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDictError {
+    pub expected: usize,
+    pub got: usize
+}
+
+impl fmt::Display for StateDictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "state dict has {} entries but the module has {} parameters", self.got, self.expected)
+    }
+}
+
+impl std::error::Error for StateDictError {}
+
+// Up to 3 parameter names within edit distance 3 of the requested one, closest first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownParameterError {
+    pub name: String,
+    pub close_matches: Vec<String>
+}
+
+impl UnknownParameterError {
+    fn new(name: &str, names: &[String]) -> UnknownParameterError {
+        let mut scored: Vec<(usize, &String)> = names.iter()
+            .map(|n| (edit_distance(name, n), n))
+            .filter(|(d, _)| *d <= 3)
+            .collect();
+        scored.sort_by_key(|(d, _)| *d);
+        UnknownParameterError { name: name.to_string(), close_matches: scored.into_iter().take(3).map(|(_, n)| n.clone()).collect() }
+    }
+}
+
+impl fmt::Display for UnknownParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no parameter named {:?}", self.name)?;
+        if !self.close_matches.is_empty() {
+            write!(f, "; close matches: {}", self.close_matches.join(", "))?;
         }
+        Ok(())
     }
+}
 
-    fn new_with_seed(vf: &ValueFactory, nin: usize, seed: u64) -> Neuron {
-        let mut rng = StdRng::seed_from_u64(seed);
+impl std::error::Error for UnknownParameterError {}
+
+// Levenshtein distance over chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != *cb)).min(row[j] + 1).min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// Distribution Module::reset_parameters draws from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Init {
+    Uniform { lo: f64, hi: f64 },
+    Normal { mean: f64, std: f64 },
+    // every parameter gets the same value, which draws nothing from the rng
+    Constant(f64)
+}
+
+impl Init {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        match *self {
+            Init::Uniform { lo, hi } => rng.uniform(lo, hi),
+            Init::Normal { mean, std } => mean + std * rng.normal(),
+            Init::Constant(x) => x
+        }
+    }
+}
+
+// Nonlinearity applied to each neuron's output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "lowercase"))]
+pub enum Activation {
+    Tanh,
+    Relu,
+    Linear
+}
+
+impl Activation {
+    pub fn apply<T: Float>(&self, x: &ValueG<T>) -> ValueG<T> {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.relu(),
+            Activation::Linear => x.clone()
+        }
+    }
+
+    pub fn apply_f64(&self, x: f64) -> f64 {
+        self.apply_float(x)
+    }
+
+    pub fn apply_float<T: Float>(&self, x: T) -> T {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.max(T::zero()),
+            Activation::Linear => x
+        }
+    }
+}
+
+struct Neuron<T: Float> {
+    w: Vec<ValueG<T>>,
+    b: ValueG<T>,
+    activation: Activation
+}
+
+impl<T: Float> Neuron<T> {
+    fn new_with_rng(vf: &ValueFactoryG<T>, nin: usize, activation: Activation, rng: &mut Rng) -> Neuron<T> {
         Neuron {
-            w: (0..nin).map(|_| vf.value(rng.random_range(-1.0..1.0))).collect(),
-            b: vf.value(rand::random::<f64>() * 2.0 - 1.0)
+            w: vf.rand_uniform_vec(nin, -1.0, 1.0, rng),
+            b: vf.rand_uniform(-1.0, 1.0, rng),
+            activation
         }
     }
 
-    fn call(&self, x: &Vec<Value>) -> Value {
+    fn call(&self, x: &[ValueG<T>]) -> ValueG<T> {
         let out = zip(&self.w, x)
             .map(|(wi, xi)| wi * xi)
             .fold(self.b.clone(), |acc, v| &acc + &v);
-        out.tanh()
+        self.activation.apply(&out)
+    }
+
+    // nin products, nin sums and the activation
+    fn nodes_per_call(&self) -> usize {
+        2 * self.w.len() + if self.activation == Activation::Linear {0} else {1}
+    }
+
+    // Same as call but on plain floats, without building a graph
+    fn predict(&self, x: &[T]) -> T {
+        let out = zip(&self.w, x)
+            .fold(self.b.get_data(), |acc, (wi, xi)| acc + wi.get_data() * *xi);
+        self.activation.apply_float(out)
     }
 }
 
-impl Module for Neuron {
-    fn parameters(&self) -> impl Iterator<Item = &Value> {
+impl<T: Float> Module<T> for Neuron<T> {
+    fn parameters(&self) -> impl Iterator<Item = &ValueG<T>> {
         self.w.iter().chain(once(&self.b))
     }
+
+    fn named_parameters(&self) -> Vec<(String, Vec<ValueG<T>>)> {
+        vec![(String::from("w"), self.w.clone()), (String::from("b"), vec![self.b.clone()])]
+    }
 }
 
-struct Layer {
-    neurons: Vec<Neuron>
+// Prefixes the tensor names of each child with "{prefix}.{index}."
+fn prefixed<'a, T: Float, M: Module<T> + 'a>(prefix: &str, children: impl Iterator<Item = &'a M>) -> Vec<(String, Vec<ValueG<T>>)> {
+    children.enumerate()
+        .flat_map(|(i, c)| c.named_parameters().into_iter().map(move |(name, ps)| (format!("{}.{}.{}", prefix, i, name), ps)))
+        .collect()
 }
 
-impl Layer {
-    fn new(vf: &ValueFactory, nin: usize, nout: usize) -> Layer {
-        Layer {
-            neurons: (0..nout).map(|_| Neuron::new(vf, nin)).collect()
-        }
-    }
+struct Layer<T: Float> {
+    neurons: Vec<Neuron<T>>
+}
 
-    fn new_with_seed(vf: &ValueFactory, nin: usize, nout: usize, seed: u64) -> Layer {
+impl<T: Float> Layer<T> {
+    fn new_with_rng(vf: &ValueFactoryG<T>, nin: usize, nout: usize, activation: Activation, rng: &mut Rng) -> Layer<T> {
         Layer {
-            neurons: (0..nout).map(|_| Neuron::new_with_seed(vf, nin, seed)).collect()
+            neurons: (0..nout).map(|_| Neuron::new_with_rng(vf, nin, activation, rng)).collect()
         }
     }
 
-    fn call(&self, x: &Vec<Value>) -> Vec<Value> {
+    fn call(&self, x: &[ValueG<T>]) -> Vec<ValueG<T>> {
         self.neurons.iter().map(|n| n.call(x)).collect()
     }
+
+    fn predict(&self, x: &[T]) -> Vec<T> {
+        self.neurons.iter().map(|n| n.predict(x)).collect()
+    }
 }
 
-impl Module for Layer {
-    fn parameters(&self) -> impl Iterator<Item = &Value> {
+impl<T: Float> Module<T> for Layer<T> {
+    fn parameters(&self) -> impl Iterator<Item = &ValueG<T>> {
         self.neurons.iter().flat_map(|n| n.parameters())
     }
+
+    fn named_parameters(&self) -> Vec<(String, Vec<ValueG<T>>)> {
+        prefixed("neurons", self.neurons.iter())
+    }
 }
 
-pub struct MLP {
-    layers: Vec<Layer>
+// See MLPG::memory_report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelMemoryReport {
+    pub parameters: usize,
+    pub parameter_bytes: usize,
+    pub nodes_per_call: usize,
+    pub node_bytes_per_call: usize,
+    // upper bound
+    pub closure_bytes_per_call: usize
 }
 
-impl MLP {
-    pub fn new(vf: &ValueFactory, nin: usize, nout: &Vec<usize>) -> MLP {
+impl fmt::Display for ModelMemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model memory: {} parameters, {}", self.parameters, format_bytes(self.parameter_bytes))?;
+        write!(
+            f, "  graph per call: {} nodes, {} (node data {}, closures up to {})",
+            self.nodes_per_call, format_bytes(self.node_bytes_per_call + self.closure_bytes_per_call),
+            format_bytes(self.node_bytes_per_call), format_bytes(self.closure_bytes_per_call)
+        )
+    }
+}
+
+// Spread of one layer's outputs for a single input, see MLPG::activation_stats. saturated is the fraction of
+// tanh outputs beyond +-SATURATION_THRESHOLD, or of relu outputs stuck at 0; always 0 for linear layers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerStats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+    pub saturated: f64
+}
+
+pub const SATURATION_THRESHOLD: f64 = 0.99;
+
+impl LayerStats {
+    fn new(outputs: &[f64], activation: Activation) -> LayerStats {
+        let saturated = outputs.iter().filter(|a| match activation {
+            Activation::Tanh => a.abs() > SATURATION_THRESHOLD,
+            Activation::Relu => **a == 0.0,
+            Activation::Linear => false
+        }).count();
+        LayerStats {
+            min: outputs.iter().copied().fold(f64::INFINITY, f64::min),
+            mean: outputs.iter().sum::<f64>() / outputs.len() as f64,
+            max: outputs.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            saturated: saturated as f64 / outputs.len() as f64
+        }
+    }
+}
+
+pub type MLP = MLPG<f64>;
+
+pub struct MLPG<T: Float> {
+    layers: Vec<Layer<T>>
+}
+
+impl<T: Float> MLPG<T> {
+    // Unseeded: draws its init from OS entropy. Use new_with_seed / new_with_rng for reproducible runs.
+    pub fn new(vf: &ValueFactoryG<T>, nin: usize, nout: &[usize]) -> MLPG<T> {
+        MLPG::new_with_rng(vf, nin, nout, &mut Rng::new_from_entropy())
+    }
+
+    pub fn new_with_seed(vf: &ValueFactoryG<T>, nin: usize, nout: &[usize], seed: u64) -> MLPG<T> {
+        MLPG::new_with_rng(vf, nin, nout, &mut Rng::new(seed))
+    }
+
+    pub fn new_with_rng(vf: &ValueFactoryG<T>, nin: usize, nout: &[usize], rng: &mut Rng) -> MLPG<T> {
+        MLPG::new_with_activations(vf, nin, nout, &vec![Activation::Tanh; nout.len()], rng)
+    }
+
+    // One activation per layer, e.g. [Relu, Relu, Linear] for the original micrograd MLP. The init is drawn
+    // in f64 and rounded, so an f32 model starts from the same weights as the f64 one with that rng.
+    pub fn new_with_activations(vf: &ValueFactoryG<T>, nin: usize, nout: &[usize], activations: &[Activation], rng: &mut Rng) -> MLPG<T> {
+        assert_eq!(nout.len(), activations.len(), "MLP needs one activation per layer");
         let sz: Vec<usize> = once(nin)
             .chain(nout.iter().copied())
             .collect();
-        MLP {
-            layers: (0..nout.len()).map(|i| Layer::new(vf, sz[i], sz[i + 1])).collect()
+        MLPG {
+            layers: (0..nout.len()).map(|i| Layer::new_with_rng(vf, sz[i], sz[i + 1], activations[i], rng)).collect()
         }
     }
 
-    pub fn new_with_seed(vf: &ValueFactory, nin: usize, nout: &Vec<usize>, seed: u64) -> MLP {
-        let sz: Vec<usize> = std::iter::once(nin)
-            .chain(nout.iter().copied())
-            .collect();
-        MLP {
-            layers: (0..nout.len()).map(|i| Layer::new_with_seed(vf, sz[i], sz[i + 1], seed)).collect()
+    // The first layer reads x in place and each later one the previous layer's output, so the only Vecs
+    // allocated are the layer outputs
+    pub fn call(&self, x: &[ValueG<T>]) -> Vec<ValueG<T>> {
+        let (first, rest) = self.layers.split_first().unwrap();
+        rest.iter().fold(first.call(x), |acc, layer| layer.call(&acc))
+    }
+
+    // call, also returning every layer's output (the last one is the output itself). Builds the same nodes
+    // as call.
+    pub fn call_with_activations(&self, x: &[ValueG<T>]) -> (Vec<ValueG<T>>, Vec<Vec<ValueG<T>>>) {
+        let (first, rest) = self.layers.split_first().unwrap();
+        let mut activations = vec![first.call(x)];
+        rest.iter().for_each(|layer| activations.push(layer.call(activations.last().unwrap())));
+        (activations.last().unwrap().clone(), activations)
+    }
+
+    // Per layer stats of the activations for input x, e.g. to spot tanh layers stuck at +-1. Computed
+    // like predict, without building a graph.
+    pub fn activation_stats(&self, x: &[T]) -> Vec<LayerStats> {
+        let mut acc = x.to_vec();
+        self.layers.iter().map(|layer| {
+            acc = layer.predict(&acc);
+            LayerStats::new(&acc.iter().map(|a| a.to_f64()).collect::<Vec<f64>>(), layer.neurons[0].activation)
+        }).collect()
+    }
+
+    // Exact number of nodes one call allocates. The inputs are not included.
+    pub fn nodes_per_call(&self) -> usize {
+        self.layers.iter().flat_map(|l| &l.neurons).map(Neuron::nodes_per_call).sum()
+    }
+
+    // What the parameters take and what one call adds on top. Every op of a call captures at most three
+    // handles in its backward closure, which is what the closure estimate assumes.
+    pub fn memory_report(&self) -> ModelMemoryReport {
+        let (parameters, nodes_per_call) = (self.parameters().count(), self.nodes_per_call());
+        ModelMemoryReport {
+            parameters,
+            parameter_bytes: parameters * mem::size_of::<ValueDataG<T>>(),
+            nodes_per_call,
+            node_bytes_per_call: nodes_per_call * mem::size_of::<ValueDataG<T>>(),
+            closure_bytes_per_call: nodes_per_call * 3 * mem::size_of::<ValueG<T>>()
         }
     }
 
-    pub fn call(&self, x: &Vec<Value>) -> Vec<Value> {
+    // Reserves arena room for n calls, so that running them doesn't grow the arena
+    pub fn reserve_for_calls(&self, vf: &ValueFactoryG<T>, n: usize) {
+        vf.arena().reserve(n * self.nodes_per_call());
+    }
+
+    pub fn nin(&self) -> usize {
+        self.layers[0].neurons[0].w.len()
+    }
+
+    pub fn nout(&self) -> usize {
+        self.layers[self.layers.len() - 1].neurons.len()
+    }
+
+    // Graph-free inference: reads the current parameter data and allocates no Values
+    pub fn predict(&self, x: &[T]) -> Vec<T> {
         self.layers
             .iter()
-            .fold(x.to_vec(), |acc, layer: &Layer| layer.call(&acc))
+            .fold(x.to_vec(), |acc, layer: &Layer<T>| layer.predict(&acc))
     }
 }
 
-impl Module for MLP {
-    fn parameters(&self) -> impl Iterator<Item = &Value> {
+impl MLP {
+
+    pub fn to_spec(&self) -> MlpSpec {
+        MlpSpec {
+            nin: self.nin(),
+            layers: self.layers.iter().map(|l| LayerSpec {
+                nout: l.neurons.len(),
+                activation: l.neurons[0].activation,
+                bias: true,
+                weights: l.neurons.iter().map(|n| n.w.iter().map(|w| w.get_data()).collect()).collect(),
+                biases: l.neurons.iter().map(|n| n.b.get_data()).collect()
+            }).collect()
+        }
+    }
+
+    // Rebuilds a model from a spec, after checking that the spec is consistent
+    pub fn from_spec(vf: &ValueFactory, spec: &MlpSpec) -> Result<MLP, ModelIoError> {
+        spec.validate()?;
+        Ok(MLP {
+            layers: spec.layers.iter().map(|l| Layer {
+                neurons: zip(&l.weights, &l.biases).map(|(w, b)| Neuron {
+                    w: w.iter().map(|wi| vf.param(*wi)).collect(),
+                    b: vf.param(*b),
+                    activation: l.activation
+                }).collect()
+            }).collect()
+        })
+    }
+
+    // One ParamGroup per layer, all using the optimizer defaults until configured otherwise
+    pub fn param_groups(&self) -> Vec<ParamGroup> {
+        self.layers
+            .iter()
+            .map(|l| ParamGroup::new(l.parameters().cloned().collect()))
+            .collect()
+    }
+}
+
+impl<T: Float> Module<T> for MLPG<T> {
+    fn parameters(&self) -> impl Iterator<Item = &ValueG<T>> {
         self.layers.iter().flat_map(|l| l.parameters())
     }
+
+    fn named_parameters(&self) -> Vec<(String, Vec<ValueG<T>>)> {
+        prefixed("layers", self.layers.iter())
+    }
+}
+
+// Inverted dropout: while training, each input is zeroed with probability p and the survivors are scaled by
+// 1 / (1 - p), so nothing needs rescaling at inference. Outside training the inputs pass through unchanged.
+pub struct Dropout {
+    p: f64,
+    training: bool,
+    rng: RefCell<Rng>
+}
+
+impl Dropout {
+    pub fn new(p: f64, rng: Rng) -> Dropout {
+        assert!((0.0..1.0).contains(&p), "Dropout probability must be in [0, 1), got {}", p);
+        Dropout { p, training: true, rng: RefCell::new(rng) }
+    }
+
+    pub fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    pub fn call(&self, x: &[Value]) -> Vec<Value> {
+        if !self.training || self.p == 0.0 {
+            return x.to_vec();
+        }
+        let mut rng = self.rng.borrow_mut();
+        let scale = 1.0 / (1.0 - self.p);
+        x.iter()
+            .map(|xi| xi * if rng.bernoulli(self.p) {0.0} else {scale})
+            .collect()
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Arena;
+
+    #[test]
+    fn nodes_per_call() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let architectures: [(usize, &[usize], &[Activation]); 4] = [
+            (3, &[4, 2], &[Activation::Tanh, Activation::Tanh]),
+            (2, &[5, 5, 1], &[Activation::Relu, Activation::Relu, Activation::Linear]),
+            (1, &[1], &[Activation::Linear]),
+            (4, &[3, 2], &[Activation::Linear, Activation::Relu])
+        ];
+
+        for (nin, nout, activations) in architectures {
+            let n = MLP::new_with_activations(&vf, nin, nout, activations, &mut Rng::new(3));
+            let x = vf.values(&vec![0.5; nin]);
+            let len = vf.len();
+            n.call(&x);
+            assert_eq!(vf.len() - len, n.nodes_per_call());
+
+            n.reserve_for_calls(&vf, 3);
+            let capacity = vf.arena().capacity();
+            (0..3).for_each(|_| {n.call(&x);});
+            assert_eq!(vf.arena().capacity(), capacity);
+        }
+    }
+
+    #[test]
+    fn memory_report() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let n = MLP::new_with_seed(&vf, 3, &[4, 2], 1);
+
+        let report = n.memory_report();
+        // (3 + 1) * 4 + (4 + 1) * 2 parameters
+        assert_eq!(report.parameters, 26);
+        assert_eq!(report.parameter_bytes, 26 * mem::size_of::<crate::ValueData>());
+        assert_eq!(report.nodes_per_call, n.nodes_per_call());
+        let x = vf.values(&[0.5, -1.0, 2.0]);
+        let y = n.call(&x);
+        assert!(y[0].graph_memory_report().closure_bytes <= report.closure_bytes_per_call);
+
+        let text = report.to_string();
+        assert!(text.starts_with(&format!("model memory: 26 parameters, {}", format_bytes(report.parameter_bytes))));
+        assert!(text.contains(&format!("  graph per call: {} nodes", report.nodes_per_call)));
+    }
+
+    #[test]
+    fn trainable_parameters() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // y = scale * w * x + b with a fixed scale
+        struct Scaled {
+            w: Value,
+            b: Value,
+            scale: Value
+        }
+        impl Module for Scaled {
+            fn parameters(&self) -> impl Iterator<Item = &Value> {
+                [&self.w, &self.b, &self.scale].into_iter()
+            }
+        }
+
+        let m = Scaled { w: vf.param(0.5), b: vf.param(0.0), scale: vf.constant(3.0) };
+        assert_eq!(m.num_parameters(), 3);
+        assert_eq!(m.trainable_parameters().cloned().collect::<Vec<_>>(), vec![m.w.clone(), m.b.clone()]);
+        let n = MLP::new_with_seed(&vf, 2, &[2, 1], 1);
+        assert!(n.parameters().all(|p| p.kind() == crate::NodeKind::Param));
+        assert_eq!(n.trainable_parameters().count(), n.num_parameters());
+    }
+
+    #[test]
+    fn init_follows_rng() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // per neuron: its weights, then its bias
+        let n = MLP::new_with_seed(&vf, 3, &[2, 1], 5);
+        let mut rng = Rng::new(5);
+        let expected: Vec<f64> = (0..n.num_parameters()).map(|_| rng.uniform(-1.0, 1.0)).collect();
+        assert_eq!(n.state_dict(), expected);
+    }
+
+    #[test]
+    fn zero_grad_keeps_data() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        fn check<M: Module>(m: &M, out: &Value) {
+            out.backward();
+            let data = m.state_dict();
+            assert!(m.parameters().any(|p| p.get_grad() != 0.0) && data.iter().all(|w| *w != 0.0));
+            m.zero_grad();
+            assert_eq!(m.state_dict(), data);
+            assert!(m.parameters().all(|p| p.get_grad() == 0.0));
+        }
+        let mut rng = Rng::new(2);
+        let x = vf.values(&[0.5, -1.5, 2.0]);
+        let neuron = Neuron::new_with_rng(&vf, 3, Activation::Tanh, &mut rng);
+        check(&neuron, &neuron.call(&x));
+        let layer = Layer::new_with_rng(&vf, 3, 2, Activation::Tanh, &mut rng);
+        check(&layer, &layer.call(&x).iter().fold(vf.zero(), |acc, y| &acc + y));
+        let mlp = MLP::new_with_rng(&vf, 3, &[4, 1], &mut rng);
+        check(&mlp, &mlp.call(&x)[0]);
+    }
+
+    #[test]
+    fn reset_parameters() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 1], 8);
+        let init = n.state_dict();
+        n.reset_parameters(&Init::Normal { mean: 0.0, std: 0.1 }, &mut Rng::new(1));
+        assert!(n.state_dict().iter().all(|w| w.abs() < 1.0) && n.state_dict() != init);
+        n.reset_parameters(&Init::Uniform { lo: -1.0, hi: 1.0 }, &mut Rng::new(8));
+        assert_eq!(n.state_dict(), init);
+
+        // the old zero_grad bug on purpose: wiping the weights every step instead of the grads stalls the
+        // demo training loop, while clearing the grads trains it
+        let xs = [[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0], [1.0, 1.0, -1.0]];
+        let ys = [1.0, -1.0, -1.0, 1.0];
+        let train = |wipe_weights: bool| {
+            let n = MLP::new_with_seed(&vf, 3, &[4, 4, 1], 1);
+            let mut loss = 0.0;
+            for _ in 0..30 {
+                let total = zip(&xs, ys)
+                    .map(|(x, y)| (&n.call(&vf.values(x))[0] - y).powi(2))
+                    .reduce(|acc, v| &acc + &v)
+                    .unwrap();
+                if wipe_weights {
+                    n.reset_parameters(&Init::Constant(0.0), &mut Rng::new(0));
+                } else {
+                    n.zero_grad();
+                }
+                total.backward();
+                n.parameters().for_each(|p| p.set_data(p.get_data() - 0.05 * p.get_grad()));
+                loss = total.get_data();
+            }
+            loss
+        };
+        let (fixed, wiped) = (train(false), train(true));
+        assert!(fixed < 0.1 && wiped > 1.0, "{} {}", fixed, wiped);
+    }
+
+    #[test]
+    fn call_matches_layers() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 4, 2], 6);
+        let x = vf.values(&[0.5, -1.0, 2.0]);
+        let y = n.call(&x);
+        (&y[0] + &y[1]).backward();
+        let grads: Vec<f64> = n.parameters().map(Value::get_grad).collect();
+
+        // every neuron by hand on copies of the inputs
+        n.zero_grad();
+        let by_hand = n.layers.iter().fold(x.to_vec(), |acc, layer| layer.neurons.iter().map(|neuron| neuron.call(&acc)).collect());
+        (&by_hand[0] + &by_hand[1]).backward();
+        assert_eq!(y.iter().map(Value::get_data).collect::<Vec<_>>(), by_hand.iter().map(Value::get_data).collect::<Vec<_>>());
+        assert_eq!(n.parameters().map(Value::get_grad).collect::<Vec<f64>>(), grads);
+        assert_eq!(y.iter().map(Value::get_data).collect::<Vec<_>>(), n.predict(&[0.5, -1.0, 2.0]));
+    }
+
+    #[test]
+    fn parameters_by_name() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 2, &[3, 1], 5);
+        let names = n.parameter_names();
+        assert_eq!(names.len(), n.num_parameters());
+        assert_eq!(&names[..4], &["layers.0.neurons.0.w.0", "layers.0.neurons.0.w.1", "layers.0.neurons.0.b", "layers.0.neurons.1.w.0"]);
+        assert_eq!(names.last().unwrap(), "layers.1.neurons.0.b");
+        // every name resolves to the parameter in the same position, and get then set is the identity
+        for (name, p) in zip(&names, n.parameters()) {
+            assert_eq!(n.get_parameter(name), Some(p));
+            n.set_parameter(name, n.get_parameter(name).unwrap().get_data()).unwrap();
+        }
+        assert_eq!(n.state_dict(), MLP::new_with_seed(&vf, 2, &[3, 1], 5).state_dict());
+
+        // zeroing the output layer's weights leaves only its bias
+        (0..3).for_each(|i| n.set_parameter(&format!("layers.1.neurons.0.w.{}", i), 0.0).unwrap());
+        n.set_parameter("layers.1.neurons.0.b", 0.25).unwrap();
+        assert_eq!(n.predict(&[1.0, -2.0]), vec![0.25f64.tanh()]);
+
+        let err = n.set_parameter("layers.1.neuron.0.b", 1.0).unwrap_err();
+        assert_eq!(err.close_matches, vec!["layers.1.neurons.0.b", "layers.0.neurons.0.b", "layers.0.neurons.1.b"]);
+        assert_eq!(err.to_string(), "no parameter named \"layers.1.neuron.0.b\"; close matches: layers.1.neurons.0.b, layers.0.neurons.0.b, layers.0.neurons.1.b");
+        assert_eq!(n.set_parameter("bias", 1.0).unwrap_err().to_string(), "no parameter named \"bias\"");
+        assert_eq!(n.get_parameter("layers.2.neurons.0.b"), None);
+    }
+
+    #[test]
+    fn call_with_activations() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 4, 2], 6);
+        let x = vf.values(&[0.5, -1.0, 2.0]);
+        let len = vf.len();
+        let (y, activations) = n.call_with_activations(&x);
+        assert_eq!(vf.len() - len, n.nodes_per_call());
+        assert_eq!(activations.iter().map(Vec::len).collect::<Vec<_>>(), vec![4, 4, 2]);
+        assert_eq!(y, activations[2]);
+
+        let data = |vs: &[Value]| vs.iter().map(Value::get_data).collect::<Vec<f64>>();
+        let mut input = x.clone();
+        zip(&n.layers, &activations).for_each(|(layer, out)| {
+            input = layer.call(&input);
+            assert_eq!(data(&input), data(out));
+        });
+        assert_eq!(data(&y), data(&n.call(&x)));
+
+        let len = vf.len();
+        let stats = n.activation_stats(&[0.5, -1.0, 2.0]);
+        assert_eq!(vf.len(), len);
+        zip(&stats, &activations).for_each(|(s, out)| assert_eq!(*s, LayerStats::new(&data(out), Activation::Tanh)));
+    }
+
+    #[test]
+    fn activation_stats() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let layer = |activation, weights: Vec<Vec<f64>>| LayerSpec { nout: weights.len(), activation, bias: true, biases: vec![0.0; weights.len()], weights };
+        let spec = MlpSpec { nin: 2, layers: vec![
+            layer(Activation::Tanh, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![20.0, 0.0]]),
+            layer(Activation::Relu, vec![vec![1.0; 3], vec![-1.0; 3]])
+        ]};
+        let n = MLP::from_spec(&vf, &spec).unwrap();
+        let stats = n.activation_stats(&[0.5, -0.25]);
+
+        let (a, b, c) = (0.5f64.tanh(), (-0.25f64).tanh(), 10.0f64.tanh());
+        let close = |s: LayerStats, expected: [f64; 4]| zip([s.min, s.mean, s.max, s.saturated], expected).all(|(x, e)| (x - e).abs() < 1e-12);
+        assert!(close(stats[0], [b, (a + b + c) / 3.0, c, 1.0 / 3.0]), "{:?}", stats[0]);
+        assert!(close(stats[1], [0.0, (a + b + c) / 2.0, a + b + c, 0.5]), "{:?}", stats[1]);
+    }
+
+    #[test]
+    fn dropout() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let x: Vec<Value> = (0..200).map(|_| vf.value(1.0)).collect();
+        let mut d = Dropout::new(0.25, Rng::new(1));
+        let out: Vec<f64> = d.call(&x).iter().map(|v| v.get_data()).collect();
+        assert!(out.iter().all(|&v| v == 0.0 || v == 1.0 / 0.75));
+        let dropped = out.iter().filter(|&&v| v == 0.0).count();
+        assert!(dropped > 30 && dropped < 70, "dropped {}", dropped);
+
+        // gradient flows only through the kept inputs
+        let sum = d.call(&x[..10]).into_iter().reduce(|acc, v| &acc + &v).unwrap();
+        sum.backward();
+        assert!(x[..10].iter().all(|v| v.get_grad() == 0.0 || v.get_grad() == 1.0 / 0.75));
+
+        d.set_training(false);
+        assert!(d.call(&x).iter().zip(&x).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn injected_rng_init() {
+        use rand::{Rng as _, SeedableRng};
+        use rand::rngs::StdRng;
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // every init path draws w then b per neuron, uniform in [-1, 1) from one StdRng stream
+        let mut raw = StdRng::seed_from_u64(3);
+        let expected: Vec<f64> = (0..3 * 4 + 4 + 4 + 1).map(|_| raw.random_range(-1.0..1.0)).collect();
+        let seeded = MLP::new_with_seed(&vf, 3, &[4, 1], 3);
+        let injected = MLP::new_with_rng(&vf, 3, &[4, 1], &mut Rng::from(StdRng::seed_from_u64(3)));
+        assert_eq!(seeded.state_dict(), expected);
+        assert_eq!(injected.state_dict(), expected);
+    }
+
+    #[test]
+    fn flatten_unflatten() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 2], 1);
+        let (mut data, spec) = n.flatten();
+        assert_eq!(data.len(), n.num_parameters());
+        assert_eq!(spec.total_len(), 3 * 4 + 4 + 4 * 2 + 2);
+        assert_eq!(spec.entries[0], LayoutEntry { name: String::from("layers.0.neurons.0.w"), len: 3 });
+        assert_eq!(spec.entries[1], LayoutEntry { name: String::from("layers.0.neurons.0.b"), len: 1 });
+        assert_eq!(spec.entries.last().unwrap().name, "layers.1.neurons.1.b");
+        // stable across seeded builds
+        assert_eq!(MLP::new_with_seed(&vf, 3, &[4, 2], 1).flatten(), (data.clone(), spec.clone()));
+
+        // only the targeted weights change
+        let before = n.state_dict();
+        let range = spec.range_of("layers.1.neurons.0.w").unwrap();
+        data[range.clone()].iter_mut().for_each(|v| *v = 7.0);
+        n.unflatten(&data, &spec).unwrap();
+        n.state_dict().iter().zip(&before).enumerate().for_each(|(i, (after, before))| {
+            if range.contains(&i) { assert_eq!(*after, 7.0) } else { assert_eq!(after, before) }
+        });
+
+        let mut renamed = spec.clone();
+        renamed.entries[2].name = String::from("layers.0.neurons.9.w");
+        assert!(matches!(n.unflatten(&data, &renamed), Err(LayoutError::EntryMismatch { idx: 2, .. })));
+        let mut resized = spec.clone();
+        resized.entries[0].len = 2;
+        assert!(matches!(n.unflatten(&data, &resized), Err(LayoutError::EntryMismatch { idx: 0, .. })));
+        assert!(matches!(n.unflatten(&data[1..], &spec), Err(LayoutError::DataLength { .. })));
+        assert_eq!(n.state_dict()[range.start], 7.0);
+    }
+
+    #[test]
+    fn f32_mlp_trains() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactoryG::<f32>::new(arena_ref);
+        let (_arena_life_time_64, arena_ref_64) = Arena::build();
+        let vf64 = ValueFactory::new(arena_ref_64);
+
+        let n = MLPG::<f32>::new_with_seed(&vf, 3, &[4, 4, 1], 42);
+        // the same init as an f64 model, rounded
+        let n64 = MLP::new_with_seed(&vf64, 3, &[4, 4, 1], 42);
+        assert_eq!(n.state_dict(), n64.state_dict().iter().map(|w| *w as f32).collect::<Vec<f32>>());
+
+        let xs = [[2.0, 3.0, -1.0], [3.0, -1.0, 0.5], [0.5, 1.0, 1.0], [1.0, 1.0, -1.0]];
+        let ys = [1.0, -1.0, -1.0, 1.0];
+        let mark = vf.arena().checkpoint();
+        let mut losses = Vec::new();
+        for _ in 0..100 {
+            let loss = zip(&xs, ys)
+                .map(|(x, y)| (&n.call(&x.map(|xi| vf.value(xi)))[0] - y).powi(2))
+                .reduce(|acc, v| &acc + &v)
+                .unwrap();
+            n.zero_grad();
+            loss.backward();
+            n.parameters().for_each(|p| p.set_data(p.get_data() - 0.05 * p.get_grad()));
+            losses.push(loss.get_data());
+            vf.arena().truncate_to(mark);
+        }
+        assert!(losses[99] < 0.05 && losses[99] < losses[0], "{:?}", losses);
+        assert!(zip(&xs, ys).all(|(x, y)| n.predict(x)[0] * y > 0.0));
+    }
 }
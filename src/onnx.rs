@@ -0,0 +1,228 @@
+// Minimal ONNX export of an MLP: per layer one Gemm node (Y = X * W^T + B) followed by a Tanh, Relu or
+// Identity node, with weights stored as double initializers. The protobuf encoding is written by hand
+// so the feature pulls in no dependencies.
+//
+// Manual check against onnxruntime (python):
+//   import numpy as np, onnxruntime as ort
+//   sess = ort.InferenceSession("model.onnx")
+//   sess.run(None, {"input": np.array([[0.5, -1.0, 2.0]])})
+// and compare with MLP::predict(&[0.5, -1.0, 2.0]).
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use crate::{Activation, MLP};
+
+const IR_VERSION: u64 = 8;
+const OPSET_VERSION: u64 = 13;
+// TensorProto.DataType.DOUBLE
+const DOUBLE: u64 = 11;
+// AttributeProto.AttributeType.INT
+const ATTRIBUTE_INT: u64 = 2;
+
+// Protobuf message builder, only the wire types ONNX needs (varint and length delimited)
+#[derive(Default)]
+struct ProtoWriter {
+    buf: Vec<u8>
+}
+
+impl ProtoWriter {
+    fn raw_varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push((v as u8 & 0x7f) | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+
+    fn varint(&mut self, field: u32, v: u64) -> &mut ProtoWriter {
+        self.raw_varint((field as u64) << 3);
+        self.raw_varint(v);
+        self
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) -> &mut ProtoWriter {
+        self.raw_varint(((field as u64) << 3) | 2);
+        self.raw_varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    fn string(&mut self, field: u32, s: &str) -> &mut ProtoWriter {
+        self.bytes(field, s.as_bytes())
+    }
+
+    fn message(&mut self, field: u32, build: impl FnOnce(&mut ProtoWriter)) -> &mut ProtoWriter {
+        let mut inner = ProtoWriter::default();
+        build(&mut inner);
+        self.bytes(field, &inner.buf)
+    }
+}
+
+// TensorProto with the values as little endian raw_data
+fn tensor(w: &mut ProtoWriter, name: &str, dims: &[usize], values: &[f64]) {
+    dims.iter().for_each(|d| {w.varint(1, *d as u64);});
+    w.varint(2, DOUBLE).string(8, name);
+    w.bytes(9, &values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>());
+}
+
+// ValueInfoProto for a [N, width] double tensor with a symbolic batch dimension
+fn value_info(w: &mut ProtoWriter, name: &str, width: usize) {
+    w.string(1, name).message(2, |t| {
+        t.message(1, |tensor_type| {
+            tensor_type.varint(1, DOUBLE).message(2, |shape| {
+                shape.message(1, |dim| {dim.string(2, "N");});
+                shape.message(1, |dim| {dim.varint(1, width as u64);});
+            });
+        });
+    });
+}
+
+fn node(w: &mut ProtoWriter, op_type: &str, name: &str, inputs: &[&str], output: &str) {
+    inputs.iter().for_each(|i| {w.string(1, i);});
+    w.string(2, output).string(3, name).string(4, op_type);
+}
+
+impl MLP {
+    pub fn to_onnx_bytes(&self) -> Vec<u8> {
+        let spec = self.to_spec();
+        let n_layers = spec.layers.len();
+        let mut model = ProtoWriter::default();
+        model.varint(1, IR_VERSION).string(2, "rust_micrograd");
+        model.message(7, |graph| {
+            let mut input = String::from("input");
+            for (i, l) in spec.layers.iter().enumerate() {
+                let (weight, bias, gemm) = (format!("W{}", i), format!("B{}", i), format!("gemm{}", i));
+                let output = if i + 1 == n_layers { String::from("output") } else { format!("h{}", i) };
+                graph.message(1, |n| {
+                    node(n, "Gemm", &gemm, &[&input, &weight, &bias], &gemm);
+                    n.message(5, |attr| {attr.string(1, "transB").varint(3, 1).varint(20, ATTRIBUTE_INT);});
+                });
+                let op_type = match l.activation {
+                    Activation::Tanh => "Tanh",
+                    Activation::Relu => "Relu",
+                    Activation::Linear => "Identity"
+                };
+                graph.message(1, |n| node(n, op_type, &format!("act{}", i), &[&gemm], &output));
+                input = output;
+            }
+            graph.string(2, "mlp");
+            let mut nin = spec.nin;
+            for (i, l) in spec.layers.iter().enumerate() {
+                graph.message(5, |t| tensor(t, &format!("W{}", i), &[l.nout, nin], &l.weights.concat()));
+                graph.message(5, |t| tensor(t, &format!("B{}", i), &[l.nout], &l.biases));
+                nin = l.nout;
+            }
+            graph.message(11, |v| value_info(v, "input", spec.nin));
+            graph.message(12, |v| value_info(v, "output", spec.layers[n_layers - 1].nout));
+        });
+        model.message(8, |opset| {opset.string(1, "").varint(2, OPSET_VERSION);});
+        model.buf
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_onnx(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_onnx_bytes())
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, Module, Rng, ValueFactory};
+
+    #[derive(Debug)]
+    enum Field {
+        Varint(u64),
+        Bytes(Vec<u8>)
+    }
+
+    // Just enough of a protobuf reader to walk the exported messages
+    fn read_fields(mut bytes: &[u8]) -> Vec<(u32, Field)> {
+        fn varint(bytes: &mut &[u8]) -> u64 {
+            let mut v = 0;
+            for shift in (0..64).step_by(7) {
+                let b = bytes[0];
+                *bytes = &bytes[1..];
+                v |= ((b & 0x7f) as u64) << shift;
+                if b & 0x80 == 0 {
+                    break;
+                }
+            }
+            v
+        }
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = varint(&mut bytes);
+            let field = match key & 7 {
+                0 => Field::Varint(varint(&mut bytes)),
+                2 => {
+                    let len = varint(&mut bytes) as usize;
+                    let (data, rest) = bytes.split_at(len);
+                    bytes = rest;
+                    Field::Bytes(data.to_vec())
+                }
+                wire => panic!("unexpected wire type {}", wire)
+            };
+            fields.push(((key >> 3) as u32, field));
+        }
+        fields
+    }
+
+    fn all(fields: &[(u32, Field)], number: u32) -> Vec<&Field> {
+        fields.iter().filter(|(f, _)| *f == number).map(|(_, v)| v).collect()
+    }
+
+    fn bytes(field: &Field) -> &[u8] {
+        match field {
+            Field::Bytes(b) => b,
+            Field::Varint(_) => panic!("expected bytes")
+        }
+    }
+
+    fn string(fields: &[(u32, Field)], number: u32) -> String {
+        String::from_utf8(bytes(all(fields, number)[0]).to_vec()).unwrap()
+    }
+
+    #[test]
+    fn onnx_structure() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let activations = [Activation::Tanh, Activation::Relu, Activation::Linear];
+        let n = MLP::new_with_activations(&vf, 3, &[4, 4, 2], &activations, &mut Rng::new(6));
+
+        let model = read_fields(&n.to_onnx_bytes());
+        assert!(matches!(all(&model, 1)[..], [Field::Varint(IR_VERSION)]));
+        let opset = read_fields(bytes(all(&model, 8)[0]));
+        assert!(matches!(all(&opset, 2)[..], [Field::Varint(OPSET_VERSION)]));
+
+        let graph = read_fields(bytes(all(&model, 7)[0]));
+        let nodes: Vec<Vec<(u32, Field)>> = all(&graph, 1).into_iter().map(|f| read_fields(bytes(f))).collect();
+        assert_eq!(nodes.len(), 2 * 3);
+        let op_types: Vec<String> = nodes.iter().map(|n| string(n, 4)).collect();
+        assert_eq!(op_types, vec!["Gemm", "Tanh", "Gemm", "Relu", "Gemm", "Identity"]);
+        assert_eq!(all(&nodes[0], 1).len(), 3);
+        assert_eq!(string(&nodes[5], 2), "output");
+
+        // initializers hold the parameters row major, weights then bias for each layer
+        let initializers: Vec<Vec<(u32, Field)>> = all(&graph, 5).into_iter().map(|f| read_fields(bytes(f))).collect();
+        assert_eq!(initializers.len(), 6);
+        assert_eq!(string(&initializers[0], 8), "W0");
+        let dims: Vec<u64> = all(&initializers[0], 1).iter().map(|d| match d {Field::Varint(v) => *v, _ => panic!()}).collect();
+        assert_eq!(dims, vec![4, 3]);
+        let values: Vec<f64> = initializers.iter()
+            .flat_map(|t| bytes(all(t, 9)[0]).chunks(8).map(|c| f64::from_le_bytes(c.try_into().unwrap())).collect::<Vec<f64>>())
+            .collect();
+        let spec = n.to_spec();
+        let expected: Vec<f64> = spec.layers.iter().flat_map(|l| l.weights.concat().into_iter().chain(l.biases.clone())).collect();
+        assert_eq!(values, expected);
+        assert_eq!(values.len(), n.parameters().count());
+
+        assert_eq!(string(&read_fields(bytes(all(&graph, 11)[0])), 1), "input");
+        assert_eq!(string(&read_fields(bytes(all(&graph, 12)[0])), 1), "output");
+    }
+}
@@ -0,0 +1,1427 @@
+use std::fmt;
+use std::iter::zip;
+use crate::{DanglingValueError, Dataset, Loss, Module, Value, ValueFactory, MLP};
+
+pub trait Optimizer {
+    fn step(&mut self);
+
+    fn zero_grad(&self);
+
+    fn get_lr(&self) -> f64;
+
+    // Sets the default lr. Groups with their own lr are rescaled proportionally.
+    fn set_lr(&mut self, lr: f64);
+
+    fn get_lr_for_group(&self, idx: usize) -> f64;
+
+    fn set_lr_for_group(&mut self, idx: usize, lr: f64);
+
+    // Everything needed to resume training where it left off (lrs, step count, moment buffers)
+    fn state_dict(&self) -> OptimizerState;
+
+    fn load_state_dict(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError>;
+}
+
+// Snapshot of an optimizer. buffers holds one entry per parameter for each buffer the optimizer keeps
+// (Sgd: velocity, Adam: first and second moments).
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptimizerState {
+    pub kind: String,
+    pub lr: f64,
+    pub group_lrs: Vec<Option<f64>>,
+    pub step: u64,
+    pub buffers: Vec<Vec<f64>>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptimizerStateError {
+    KindMismatch { expected: String, got: String },
+    // number of groups, buffers or buffer entries differs from the optimizer
+    LengthMismatch { what: &'static str, expected: usize, got: usize }
+}
+
+impl fmt::Display for OptimizerStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimizerStateError::KindMismatch { expected, got } => write!(f, "state is for a {} optimizer, not {}", got, expected),
+            OptimizerStateError::LengthMismatch { what, expected, got } => write!(f, "state has {} {} but the optimizer has {}", got, what, expected)
+        }
+    }
+}
+
+impl std::error::Error for OptimizerStateError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipMode {
+    // Rescale all grads together so their global L2 norm is at most the given value
+    Norm(f64),
+    // Clamp every grad into [-max_abs, max_abs]
+    Value(f64)
+}
+
+impl ClipMode {
+    fn apply<'a>(&self, params: impl IntoIterator<Item = &'a Value>) {
+        match *self {
+            ClipMode::Norm(max_norm) => {clip_grad_norm(params, max_norm);},
+            ClipMode::Value(max_abs) => clip_grad_value(params, max_abs)
+        }
+    }
+}
+
+// Rescales the grads in place so that their global L2 norm is at most max_norm. Returns the norm before clipping.
+pub fn clip_grad_norm<'a>(params: impl IntoIterator<Item = &'a Value>, max_norm: f64) -> f64 {
+    let params: Vec<&Value> = params.into_iter().collect();
+    let norm = params.iter().map(|p| p.get_grad().powi(2)).sum::<f64>().sqrt();
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        params.iter().for_each(|p| p.set_grad(p.get_grad() * scale));
+    }
+    norm
+}
+
+pub fn clip_grad_value<'a>(params: impl IntoIterator<Item = &'a Value>, max_abs: f64) {
+    params.into_iter().for_each(|p| p.set_grad(p.get_grad().clamp(-max_abs, max_abs)));
+}
+
+// A set of parameters sharing hyperparameters. None falls back to the optimizer's default.
+pub struct ParamGroup {
+    pub params: Vec<Value>,
+    pub lr: Option<f64>,
+    pub weight_decay: Option<f64>
+}
+
+impl ParamGroup {
+    pub fn new(params: Vec<Value>) -> ParamGroup {
+        ParamGroup { params, lr: None, weight_decay: None }
+    }
+
+    pub fn new_with_lr(params: Vec<Value>, lr: f64) -> ParamGroup {
+        ParamGroup { params, lr: Some(lr), weight_decay: None }
+    }
+}
+
+// Parameter groups plus the defaults they fall back to. Shared by all the optimizers.
+struct ParamGroups {
+    groups: Vec<ParamGroup>,
+    lr: f64,
+    weight_decay: f64,
+    // the default and group lrs set_lr scales from, as of construction, the last set_group_lr or load_state
+    base_lr: f64,
+    base_group_lrs: Vec<Option<f64>>
+}
+
+impl ParamGroups {
+    fn new(groups: Vec<ParamGroup>, lr: f64) -> ParamGroups {
+        let base_group_lrs = groups.iter().map(|g| g.lr).collect();
+        ParamGroups { groups, lr, weight_decay: 0.0, base_lr: lr, base_group_lrs }
+    }
+
+    fn rebase(&mut self) {
+        self.base_lr = self.lr;
+        self.base_group_lrs = self.groups.iter().map(|g| g.lr).collect();
+    }
+
+    fn len(&self) -> usize {
+        self.groups.iter().map(|g| g.params.len()).sum()
+    }
+
+    fn params(&self) -> impl Iterator<Item = &Value> {
+        self.groups.iter().flat_map(|g| g.params.iter())
+    }
+
+    // (lr, weight_decay, param) for every parameter, in group order
+    fn iter(&self) -> impl Iterator<Item = (f64, f64, &Value)> {
+        self.groups.iter().flat_map(|g| {
+            let (lr, wd) = (g.lr.unwrap_or(self.lr), g.weight_decay.unwrap_or(self.weight_decay));
+            g.params.iter().map(move |p| (lr, wd, p))
+        })
+    }
+
+    // Groups with their own lr keep their ratio to the default as of the last rebase. Scaled from the base
+    // lrs rather than the current ones, which schedulers may drive to zero. With a zero base lr there is no
+    // ratio and the groups keep their base lr.
+    fn set_lr(&mut self, lr: f64) {
+        let base_lr = self.base_lr;
+        zip(&mut self.groups, &self.base_group_lrs).for_each(|(g, base)| {
+            g.lr = base.map(|group_lr| if base_lr == 0.0 {group_lr} else {group_lr * lr / base_lr});
+        });
+        self.lr = lr;
+    }
+
+    fn group_lr(&self, idx: usize) -> f64 {
+        self.groups[idx].lr.unwrap_or(self.lr)
+    }
+
+    fn set_group_lr(&mut self, idx: usize, lr: f64) {
+        self.groups[idx].lr = Some(lr);
+        self.rebase();
+    }
+
+    fn state(&self, kind: &str, step: u64, buffers: &[&Vec<f64>]) -> OptimizerState {
+        OptimizerState {
+            kind: kind.to_string(),
+            lr: self.lr,
+            group_lrs: self.groups.iter().map(|g| g.lr).collect(),
+            step,
+            buffers: buffers.iter().map(|b| b.to_vec()).collect()
+        }
+    }
+
+    // Checks state against this optimizer and restores the lrs. The caller copies the buffers.
+    fn load_state(&mut self, kind: &str, n_buffers: usize, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        if state.kind != kind {
+            return Err(OptimizerStateError::KindMismatch { expected: kind.to_string(), got: state.kind.clone() });
+        }
+        let check = |what, expected, got| if expected == got {
+            Ok(())
+        } else {
+            Err(OptimizerStateError::LengthMismatch { what, expected, got })
+        };
+        check("groups", self.groups.len(), state.group_lrs.len())?;
+        check("buffers", n_buffers, state.buffers.len())?;
+        state.buffers.iter().try_for_each(|b| check("buffer entries", self.len(), b.len()))?;
+        self.lr = state.lr;
+        zip(&mut self.groups, &state.group_lrs).for_each(|(g, lr)| g.lr = *lr);
+        self.rebase();
+        Ok(())
+    }
+}
+
+// Plain gradient descent with optional (heavy ball) momentum
+pub struct Sgd {
+    groups: ParamGroups,
+    momentum: f64,
+    velocity: Vec<f64>,
+    grad_clip: Option<ClipMode>
+}
+
+impl Sgd {
+    pub fn new(params: Vec<Value>, lr: f64) -> Sgd {
+        Sgd::new_with_momentum(params, lr, 0.0)
+    }
+
+    pub fn new_with_momentum(params: Vec<Value>, lr: f64, momentum: f64) -> Sgd {
+        Sgd::new_with_groups(vec![ParamGroup::new(params)], lr, momentum)
+    }
+
+    pub fn new_with_groups(groups: Vec<ParamGroup>, lr: f64, momentum: f64) -> Sgd {
+        let groups = ParamGroups::new(groups, lr);
+        let velocity = vec![0.0; groups.len()];
+        Sgd { groups, momentum, velocity, grad_clip: None }
+    }
+
+    pub fn set_grad_clip(&mut self, grad_clip: Option<ClipMode>) {
+        self.grad_clip = grad_clip;
+    }
+
+    // Default L2 penalty for groups that don't set their own
+    pub fn set_weight_decay(&mut self, weight_decay: f64) {
+        self.groups.weight_decay = weight_decay;
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self) {
+        if let Some(clip) = &self.grad_clip {
+            clip.apply(self.groups.params());
+        }
+        self.groups.iter().zip(self.velocity.iter_mut()).for_each(|((lr, wd, p), v)| {
+            let data = p.get_data();
+            *v = self.momentum * *v + p.get_grad() + wd * data;
+            p.set_data(data - lr * *v);
+        });
+    }
+
+    fn zero_grad(&self) {
+        self.groups.params().for_each(|p| p.set_grad(0.0));
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.groups.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.groups.set_lr(lr);
+    }
+
+    fn get_lr_for_group(&self, idx: usize) -> f64 {
+        self.groups.group_lr(idx)
+    }
+
+    fn set_lr_for_group(&mut self, idx: usize, lr: f64) {
+        self.groups.set_group_lr(idx, lr);
+    }
+
+    fn state_dict(&self) -> OptimizerState {
+        self.groups.state("sgd", 0, &[&self.velocity])
+    }
+
+    fn load_state_dict(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        self.groups.load_state("sgd", 1, state)?;
+        self.velocity.clone_from(&state.buffers[0]);
+        Ok(())
+    }
+}
+
+pub struct Adam {
+    groups: ParamGroups,
+    beta1: f64,
+    beta2: f64,
+    eps: f64,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+    grad_clip: Option<ClipMode>
+}
+
+impl Adam {
+    pub fn new(params: Vec<Value>, lr: f64) -> Adam {
+        Adam::new_with_betas(params, lr, 0.9, 0.999, 1e-8)
+    }
+
+    pub fn new_with_betas(params: Vec<Value>, lr: f64, beta1: f64, beta2: f64, eps: f64) -> Adam {
+        Adam::new_with_groups(vec![ParamGroup::new(params)], lr, beta1, beta2, eps)
+    }
+
+    pub fn new_with_groups(groups: Vec<ParamGroup>, lr: f64, beta1: f64, beta2: f64, eps: f64) -> Adam {
+        let groups = ParamGroups::new(groups, lr);
+        let n = groups.len();
+        Adam { groups, beta1, beta2, eps, m: vec![0.0; n], v: vec![0.0; n], t: 0, grad_clip: None }
+    }
+
+    pub fn set_grad_clip(&mut self, grad_clip: Option<ClipMode>) {
+        self.grad_clip = grad_clip;
+    }
+
+    // Default L2 penalty for groups that don't set their own
+    pub fn set_weight_decay(&mut self, weight_decay: f64) {
+        self.groups.weight_decay = weight_decay;
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self) {
+        if let Some(clip) = &self.grad_clip {
+            clip.apply(self.groups.params());
+        }
+        self.t += 1;
+        let bias1 = 1.0 - self.beta1.powi(self.t);
+        let bias2 = 1.0 - self.beta2.powi(self.t);
+        for (i, (lr, wd, p)) in self.groups.iter().enumerate() {
+            let data = p.get_data();
+            let g = p.get_grad() + wd * data;
+            self.m[i] = self.beta1 * self.m[i] + (1.0 - self.beta1) * g;
+            self.v[i] = self.beta2 * self.v[i] + (1.0 - self.beta2) * g * g;
+            let m_hat = self.m[i] / bias1;
+            let v_hat = self.v[i] / bias2;
+            p.set_data(data - lr * m_hat / (v_hat.sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&self) {
+        self.groups.params().for_each(|p| p.set_grad(0.0));
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.groups.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.groups.set_lr(lr);
+    }
+
+    fn get_lr_for_group(&self, idx: usize) -> f64 {
+        self.groups.group_lr(idx)
+    }
+
+    fn set_lr_for_group(&mut self, idx: usize, lr: f64) {
+        self.groups.set_group_lr(idx, lr);
+    }
+
+    fn state_dict(&self) -> OptimizerState {
+        self.groups.state("adam", self.t as u64, &[&self.m, &self.v])
+    }
+
+    fn load_state_dict(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        self.groups.load_state("adam", 2, state)?;
+        self.m.clone_from(&state.buffers[0]);
+        self.v.clone_from(&state.buffers[1]);
+        self.t = state.step as i32;
+        Ok(())
+    }
+}
+
+// A Scheduler owns its optimizer and rewrites the optimizer's lr every time step() is called.
+// Call step() once per epoch, after the optimizer step(s) for that epoch.
+pub trait Scheduler {
+    fn step(&mut self);
+
+    fn get_lr(&self) -> f64;
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer;
+}
+
+// lr = base_lr * gamma^(floor(epoch / step_size))
+pub struct StepLR<O: Optimizer> {
+    opt: O,
+    base_lr: f64,
+    step_size: usize,
+    gamma: f64,
+    epoch: usize
+}
+
+impl<O: Optimizer> StepLR<O> {
+    pub fn new(opt: O, step_size: usize, gamma: f64) -> StepLR<O> {
+        assert!(step_size > 0, "StepLR step_size must be positive");
+        let base_lr = opt.get_lr();
+        StepLR { opt, base_lr, step_size, gamma, epoch: 0 }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+impl<O: Optimizer> Scheduler for StepLR<O> {
+    fn step(&mut self) {
+        self.epoch += 1;
+        let lr = self.base_lr * self.gamma.powi((self.epoch / self.step_size) as i32);
+        self.opt.set_lr(lr);
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+}
+
+// lr = base_lr * gamma^epoch
+pub struct ExponentialLR<O: Optimizer> {
+    opt: O,
+    base_lr: f64,
+    gamma: f64,
+    epoch: usize
+}
+
+impl<O: Optimizer> ExponentialLR<O> {
+    pub fn new(opt: O, gamma: f64) -> ExponentialLR<O> {
+        let base_lr = opt.get_lr();
+        ExponentialLR { opt, base_lr, gamma, epoch: 0 }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+impl<O: Optimizer> Scheduler for ExponentialLR<O> {
+    fn step(&mut self) {
+        self.epoch += 1;
+        self.opt.set_lr(self.base_lr * self.gamma.powi(self.epoch as i32));
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+}
+
+// Half-cosine decay from the initial lr down to eta_min over t_max epochs, held at eta_min afterwards
+pub struct CosineAnnealingLR<O: Optimizer> {
+    opt: O,
+    base_lr: f64,
+    t_max: usize,
+    eta_min: f64,
+    epoch: usize
+}
+
+impl<O: Optimizer> CosineAnnealingLR<O> {
+    pub fn new(opt: O, t_max: usize, eta_min: f64) -> CosineAnnealingLR<O> {
+        assert!(t_max > 0, "CosineAnnealingLR t_max must be positive");
+        let base_lr = opt.get_lr();
+        CosineAnnealingLR { opt, base_lr, t_max, eta_min, epoch: 0 }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+fn cosine_lr(base_lr: f64, eta_min: f64, t: usize, period: usize) -> f64 {
+    let progress = t.min(period) as f64 / period as f64;
+    eta_min + (base_lr - eta_min) * (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0
+}
+
+impl<O: Optimizer> Scheduler for CosineAnnealingLR<O> {
+    fn step(&mut self) {
+        self.epoch += 1;
+        self.opt.set_lr(cosine_lr(self.base_lr, self.eta_min, self.epoch, self.t_max));
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+}
+
+// Cosine annealing with warm restarts (SGDR). The first cycle lasts t_0 epochs and every following
+// cycle is t_mult times longer than the previous one. At each restart the lr jumps back to the initial lr.
+pub struct CosineWithRestarts<O: Optimizer> {
+    opt: O,
+    base_lr: f64,
+    t_mult: usize,
+    eta_min: f64,
+    t_cur: usize,
+    t_i: usize
+}
+
+impl<O: Optimizer> CosineWithRestarts<O> {
+    pub fn new(opt: O, t_0: usize, t_mult: usize, eta_min: f64) -> CosineWithRestarts<O> {
+        assert!(t_0 > 0, "CosineWithRestarts t_0 must be positive");
+        assert!(t_mult > 0, "CosineWithRestarts t_mult must be positive");
+        let base_lr = opt.get_lr();
+        CosineWithRestarts { opt, base_lr, t_mult, eta_min, t_cur: 0, t_i: t_0 }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+impl<O: Optimizer> Scheduler for CosineWithRestarts<O> {
+    fn step(&mut self) {
+        self.t_cur += 1;
+        if self.t_cur >= self.t_i {
+            self.t_cur = 0;
+            self.t_i *= self.t_mult;
+        }
+        self.opt.set_lr(cosine_lr(self.base_lr, self.eta_min, self.t_cur, self.t_i));
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+}
+
+// Keeps the optimizer's lr fixed. Lets a bare optimizer be used wherever a Scheduler is expected (e.g. Warmup).
+pub struct ConstantLR<O: Optimizer> {
+    opt: O
+}
+
+impl<O: Optimizer> ConstantLR<O> {
+    pub fn new(opt: O) -> ConstantLR<O> {
+        ConstantLR { opt }
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+impl<O: Optimizer> Scheduler for ConstantLR<O> {
+    fn step(&mut self) {}
+
+    fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+}
+
+// Linearly ramps the lr from start_factor * lr to lr over the first warmup_steps steps, then hands
+// every following step to the inner schedule (which starts from its own step 0 at that point).
+pub struct Warmup<S: Scheduler> {
+    inner: S,
+    base_lr: f64,
+    warmup_steps: usize,
+    start_factor: f64,
+    t: usize
+}
+
+impl<S: Scheduler> Warmup<S> {
+    pub fn new(mut inner: S, warmup_steps: usize, start_factor: f64) -> Warmup<S> {
+        let base_lr = inner.get_lr();
+        if warmup_steps > 0 {
+            inner.optimizer().set_lr(base_lr * start_factor);
+        }
+        Warmup { inner, base_lr, warmup_steps, start_factor, t: 0 }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Scheduler> Scheduler for Warmup<S> {
+    fn step(&mut self) {
+        if self.t < self.warmup_steps {
+            self.t += 1;
+            let progress = self.t as f64 / self.warmup_steps as f64;
+            let factor = self.start_factor + (1.0 - self.start_factor) * progress;
+            self.inner.optimizer().set_lr(self.base_lr * factor);
+        } else {
+            self.inner.step();
+        }
+    }
+
+    fn get_lr(&self) -> f64 {
+        self.inner.get_lr()
+    }
+
+    fn optimizer(&mut self) -> &mut dyn Optimizer {
+        self.inner.optimizer()
+    }
+}
+
+// Watches a metric that should decrease (e.g. validation loss). When the metric has failed to improve on
+// the best value seen by more than threshold for more than patience consecutive steps, the lr is multiplied
+// by factor (never going below min_lr) and the patience counter starts over.
+// Not a Scheduler since step() needs the metric.
+pub struct ReduceLROnPlateau<O: Optimizer> {
+    opt: O,
+    factor: f64,
+    patience: usize,
+    min_lr: f64,
+    threshold: f64,
+    best: f64,
+    bad_steps: usize
+}
+
+impl<O: Optimizer> ReduceLROnPlateau<O> {
+    pub fn new(opt: O, factor: f64, patience: usize, min_lr: f64, threshold: f64) -> ReduceLROnPlateau<O> {
+        assert!(factor > 0.0 && factor < 1.0, "ReduceLROnPlateau factor must be in (0, 1)");
+        ReduceLROnPlateau { opt, factor, patience, min_lr, threshold, best: f64::INFINITY, bad_steps: 0 }
+    }
+
+    // Returns true if the lr was reduced by this step
+    pub fn step(&mut self, metric: f64) -> bool {
+        if metric < self.best - self.threshold {
+            self.best = metric;
+            self.bad_steps = 0;
+            return false;
+        }
+        self.bad_steps += 1;
+        if self.bad_steps <= self.patience {
+            return false;
+        }
+        self.bad_steps = 0;
+        let lr = self.opt.get_lr();
+        let new_lr = (lr * self.factor).max(self.min_lr);
+        self.opt.set_lr(new_lr);
+        new_lr < lr
+    }
+
+    pub fn get_lr(&self) -> f64 {
+        self.opt.get_lr()
+    }
+
+    pub fn best(&self) -> f64 {
+        self.best
+    }
+
+    pub fn optimizer(&mut self) -> &mut dyn Optimizer {
+        &mut self.opt
+    }
+
+    pub fn into_inner(self) -> O {
+        self.opt
+    }
+}
+
+// Exponential moving average of parameters. Calling update() after every optimizer step moves a shadow copy
+// towards the current values, ema = decay * ema + (1 - decay) * w, starting from the values at new().
+// apply_to_model swaps the averages into the parameters (e.g. for evaluation) and restore swaps the trained
+// values back.
+pub struct Ema {
+    params: Vec<Value>,
+    decay: f64,
+    averages: Vec<f64>,
+    // trained values while the averages are applied
+    stashed: Option<Vec<f64>>
+}
+
+impl Ema {
+    pub fn new(params: Vec<Value>, decay: f64) -> Ema {
+        assert!((0.0..1.0).contains(&decay), "Ema decay must be in [0, 1), got {}", decay);
+        let averages = params.iter().map(|p| p.get_data()).collect();
+        Ema { params, decay, averages, stashed: None }
+    }
+
+    pub fn update(&mut self) {
+        assert!(self.stashed.is_none(), "Ema::update while the averages are applied; call restore first");
+        zip(&mut self.averages, &self.params).for_each(|(avg, p)| *avg = self.decay * *avg + (1.0 - self.decay) * p.get_data());
+    }
+
+    // Writes the averages into the parameters. Applying twice keeps the values stashed by the first call.
+    pub fn apply_to_model(&mut self) {
+        if self.stashed.is_none() {
+            self.stashed = Some(self.params.iter().map(|p| p.get_data()).collect());
+        }
+        zip(&self.params, &self.averages).for_each(|(p, avg)| p.set_data(*avg));
+    }
+
+    // Puts back the values apply_to_model replaced. Does nothing if the averages are not applied.
+    pub fn restore(&mut self) {
+        if let Some(stashed) = self.stashed.take() {
+            zip(&self.params, stashed).for_each(|(p, w)| p.set_data(w));
+        }
+    }
+
+    pub fn is_applied(&self) -> bool {
+        self.stashed.is_some()
+    }
+
+    pub fn averages(&self) -> &[f64] {
+        &self.averages
+    }
+
+    pub fn decay(&self) -> f64 {
+        self.decay
+    }
+}
+
+// Copy of the parameters' data to roll back to, e.g. after a step with a huge gradient wrecked them. restore
+// writes every value back into the parameter it was taken from; restore_to writes them into another list of
+// parameters in the same order, e.g. those of a model that was rebuilt.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    params: Vec<Value>,
+    data: Vec<f64>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+    LengthMismatch { expected: usize, got: usize },
+    Dangling(DanglingValueError)
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::LengthMismatch { expected, got } => write!(f, "snapshot has {} parameters but {} were given", expected, got),
+            SnapshotError::Dangling(e) => write!(f, "cannot restore a parameter: {}", e)
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn take<'a>(params: impl IntoIterator<Item = &'a Value>) -> Snapshot {
+        let params: Vec<Value> = params.into_iter().cloned().collect();
+        let data = params.iter().map(|p| p.get_data()).collect();
+        Snapshot { params, data }
+    }
+
+    // Fails without writing anything if one of the parameters was freed
+    pub fn restore(&self) -> Result<(), SnapshotError> {
+        self.restore_to(&self.params)
+    }
+
+    pub fn restore_to<'a>(&self, params: impl IntoIterator<Item = &'a Value>) -> Result<(), SnapshotError> {
+        let params: Vec<&Value> = params.into_iter().collect();
+        if params.len() != self.data.len() {
+            return Err(SnapshotError::LengthMismatch { expected: self.data.len(), got: params.len() });
+        }
+        params.iter().try_for_each(|p| f64::try_from(*p).map(|_| ())).map_err(SnapshotError::Dangling)?;
+        zip(params, &self.data).for_each(|(p, x)| p.set_data(*x));
+        Ok(())
+    }
+
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LrFindOptions {
+    pub start_lr: f64,
+    pub end_lr: f64,
+    pub num_steps: usize,
+    // exponential moving average factor applied to the raw losses
+    pub smoothing: f64,
+    // stop once the smoothed loss exceeds diverge_factor * the best smoothed loss so far
+    pub diverge_factor: f64
+}
+
+impl Default for LrFindOptions {
+    fn default() -> LrFindOptions {
+        LrFindOptions { start_lr: 1e-5, end_lr: 10.0, num_steps: 100, smoothing: 0.9, diverge_factor: 4.0 }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LrFindResult {
+    pub lrs: Vec<f64>,
+    // smoothed losses, one per entry of lrs
+    pub losses: Vec<f64>,
+    // lr at the steepest descent of the smoothed loss (against log lr), None with fewer than two points
+    pub suggested_lr: Option<f64>
+}
+
+// lr used at step i of the sweep: start_lr * (end_lr / start_lr)^(i / (num_steps - 1))
+fn lr_find_schedule(options: &LrFindOptions, i: usize) -> f64 {
+    if options.num_steps < 2 {
+        return options.start_lr;
+    }
+    options.start_lr * (options.end_lr / options.start_lr).powf(i as f64 / (options.num_steps - 1) as f64)
+}
+
+// Sweeps the lr exponentially from start_lr to end_lr, calling train_step(lr) once per step. train_step
+// runs one optimization step at the given lr and returns the loss. The sweep ends early when the loss
+// explodes or stops being finite.
+pub fn lr_find_with(mut train_step: impl FnMut(f64) -> f64, options: &LrFindOptions) -> LrFindResult {
+    let mut result = LrFindResult::default();
+    let mut avg = 0.0;
+    let mut best = f64::INFINITY;
+    for i in 0..options.num_steps {
+        let lr = lr_find_schedule(options, i);
+        let loss = train_step(lr);
+        if !loss.is_finite() {
+            break;
+        }
+        avg = options.smoothing * avg + (1.0 - options.smoothing) * loss;
+        // bias correction for the zero initialised average
+        let smoothed = avg / (1.0 - options.smoothing.powi(i as i32 + 1));
+        if smoothed > options.diverge_factor * best {
+            break;
+        }
+        best = best.min(smoothed);
+        result.lrs.push(lr);
+        result.losses.push(smoothed);
+    }
+
+    result.suggested_lr = (0..result.lrs.len().saturating_sub(1))
+        .map(|i| {
+            let slope = (result.losses[i + 1] - result.losses[i]) / (result.lrs[i + 1].ln() - result.lrs[i].ln());
+            (i, slope)
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| result.lrs[i]);
+    result
+}
+
+// Learning rate range test: trains a fresh model with full batch Sgd on the Mse loss while sweeping the lr
+pub fn lr_find(vf: &ValueFactory, build_model: impl FnOnce(&ValueFactory) -> MLP, data: &Dataset, options: &LrFindOptions) -> LrFindResult {
+    let model = build_model(vf);
+    let mut opt = Sgd::new(model.parameters().cloned().collect(), options.start_lr);
+    let wrap = |x: &[f64]| vf.values(x);
+    lr_find_with(|lr| {
+        opt.set_lr(lr);
+        let loss = data.xs.iter()
+            .zip(&data.ys)
+            .map(|(x, y)| Loss::Mse.value(&model.call(&wrap(x)), &wrap(y)))
+            .reduce(|acc, v| &acc + &v)
+            .expect("lr_find on an empty dataset");
+        let loss = &loss / data.len() as f64;
+        opt.zero_grad();
+        loss.backward();
+        opt.step();
+        loss.get_data()
+    }, options)
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, ValueFactory};
+
+    #[test]
+    fn sgd_step() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.value(1.0);
+        let loss = &w * 3.0;
+        loss.backward();
+
+        let mut opt = Sgd::new(vec![w.clone()], 0.1);
+        opt.step();
+        assert!((w.get_data() - 0.7).abs() < 1e-12);
+        opt.zero_grad();
+        assert_eq!(w.get_grad(), 0.0);
+    }
+
+    #[test]
+    fn step_lr() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let mut sched = StepLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 3, 0.5);
+        assert_eq!(sched.get_lr(), 0.1);
+        for k in 1..=10 {
+            sched.step();
+            let expected = 0.1 * 0.5_f64.powi(k / 3);
+            assert!((sched.get_lr() - expected).abs() < 1e-15);
+            assert_eq!(sched.optimizer().get_lr(), sched.get_lr());
+        }
+    }
+
+    #[test]
+    fn exponential_lr() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // schedulers work with any optimizer implementing set_lr
+        let mut sched = ExponentialLR::new(Adam::new(vec![vf.value(1.0)], 0.01), 0.9);
+        for k in 1..=10 {
+            sched.step();
+            assert!((sched.get_lr() - 0.01 * 0.9_f64.powi(k)).abs() < 1e-15);
+        }
+        assert_eq!(sched.into_inner().get_lr(), 0.01 * 0.9_f64.powi(10));
+    }
+
+    #[test]
+    fn schedule_changes_update_magnitude() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // d(loss)/dw is always 2.0, so every update is exactly lr * 2.0
+        let w = vf.value(0.0);
+        let mut sched = StepLR::new(Sgd::new(vec![w.clone()], 0.1), 1, 0.5);
+        let mut deltas = Vec::new();
+        for _ in 0..4 {
+            let loss = &w * 2.0;
+            sched.optimizer().zero_grad();
+            loss.backward();
+            let before = w.get_data();
+            sched.optimizer().step();
+            deltas.push(before - w.get_data());
+            sched.step();
+        }
+        let expected = [0.2, 0.1, 0.05, 0.025];
+        deltas.iter().zip(expected).for_each(|(d, e)| assert!((d - e).abs() < 1e-12));
+    }
+
+    #[test]
+    fn cosine_annealing() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let mut sched = CosineAnnealingLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 10, 0.001);
+        assert!((sched.get_lr() - 0.1).abs() < 1e-12);
+        let mut lrs = Vec::new();
+        for _ in 0..12 {
+            sched.step();
+            lrs.push(sched.get_lr());
+        }
+        // t_max / 2 sits halfway between the initial lr and eta_min
+        assert!((lrs[4] - (0.001 + 0.099 / 2.0)).abs() < 1e-12);
+        assert!((lrs[9] - 0.001).abs() < 1e-12);
+        // held at eta_min after t_max
+        assert!((lrs[11] - 0.001).abs() < 1e-12);
+        lrs.windows(2).take(9).for_each(|w| assert!(w[1] < w[0]));
+    }
+
+    #[test]
+    fn cosine_with_restarts() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // cycles of length 2, then 4
+        let mut sched = CosineWithRestarts::new(Sgd::new(vec![vf.value(1.0)], 0.1), 2, 2, 0.0);
+        let mut lrs = Vec::new();
+        for _ in 0..7 {
+            sched.step();
+            lrs.push(sched.get_lr());
+        }
+        assert!((lrs[0] - 0.05).abs() < 1e-12);
+        assert!((lrs[1] - 0.1).abs() < 1e-12);
+        assert!((lrs[2] - cosine_lr(0.1, 0.0, 1, 4)).abs() < 1e-12);
+        assert!((lrs[3] - 0.05).abs() < 1e-12);
+        assert!((lrs[5] - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cosine_training_loop() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // minimize (w - 3)^2
+        let w = vf.value(0.0);
+        let mut sched = CosineAnnealingLR::new(Sgd::new(vec![w.clone()], 0.3), 50, 0.0);
+        for _ in 0..50 {
+            let loss = (&w - 3.0).powi(2);
+            sched.optimizer().zero_grad();
+            loss.backward();
+            sched.optimizer().step();
+            sched.step();
+        }
+        assert!((w.get_data() - 3.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn warmup_is_linear() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let mut sched = Warmup::new(ConstantLR::new(Sgd::new(vec![vf.value(1.0)], 0.1)), 4, 0.2);
+        let mut lrs = vec![sched.get_lr()];
+        for _ in 0..6 {
+            sched.step();
+            lrs.push(sched.get_lr());
+        }
+        let expected = [0.02, 0.04, 0.06, 0.08, 0.1, 0.1, 0.1];
+        lrs.iter().zip(expected).for_each(|(lr, e)| assert!((lr - e).abs() < 1e-12));
+    }
+
+    #[test]
+    fn warmup_hands_off_to_inner() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // StepLR
+        let mut sched = Warmup::new(StepLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 2, 0.5), 3, 0.0);
+        let mut reference = StepLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 2, 0.5);
+        assert_eq!(sched.get_lr(), 0.0);
+        (0..3).for_each(|_| sched.step());
+        // the handoff step matches the inner scheduler's initial value
+        assert_eq!(sched.get_lr(), reference.get_lr());
+        for _ in 0..5 {
+            sched.step();
+            reference.step();
+            assert_eq!(sched.get_lr(), reference.get_lr());
+        }
+
+        // cosine
+        let mut sched = Warmup::new(CosineAnnealingLR::new(Adam::new(vec![vf.value(1.0)], 0.01), 5, 0.0), 2, 0.5);
+        let mut reference = CosineAnnealingLR::new(Adam::new(vec![vf.value(1.0)], 0.01), 5, 0.0);
+        assert!((sched.get_lr() - 0.005).abs() < 1e-15);
+        (0..2).for_each(|_| sched.step());
+        assert_eq!(sched.get_lr(), reference.get_lr());
+        for _ in 0..5 {
+            sched.step();
+            reference.step();
+            assert_eq!(sched.get_lr(), reference.get_lr());
+        }
+    }
+
+    #[test]
+    fn zero_warmup_is_noop() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let mut sched = Warmup::new(ExponentialLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 0.5), 0, 0.1);
+        let mut reference = ExponentialLR::new(Sgd::new(vec![vf.value(1.0)], 0.1), 0.5);
+        assert_eq!(sched.get_lr(), 0.1);
+        for _ in 0..4 {
+            sched.step();
+            reference.step();
+            assert_eq!(sched.get_lr(), reference.get_lr());
+        }
+    }
+
+    #[test]
+    fn reduce_on_plateau() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let mut sched = ReduceLROnPlateau::new(Sgd::new(vec![vf.value(1.0)], 1.0), 0.5, 2, 0.1, 0.0);
+        let metrics = [5.0, 4.0, 4.0, 4.5, 4.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0];
+        let reduced: Vec<usize> = metrics.iter()
+            .enumerate()
+            .filter(|(_, m)| sched.step(**m))
+            .map(|(i, _)| i)
+            .collect();
+        // three non-improving steps after 4.0 at index 1, the improvement at index 5 resets patience,
+        // and the reduction at index 14 is capped at min_lr
+        assert_eq!(reduced, vec![4, 8, 11, 14]);
+        assert_eq!(sched.get_lr(), 0.1);
+        assert_eq!(sched.best(), 3.0);
+
+        // once at min_lr no further reductions are reported
+        assert!((0..6).all(|_| !sched.step(3.0)));
+        assert_eq!(sched.get_lr(), 0.1);
+    }
+
+    #[test]
+    fn reduce_on_plateau_threshold() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // improvements smaller than the threshold don't count
+        let mut sched = ReduceLROnPlateau::new(Sgd::new(vec![vf.value(1.0)], 1.0), 0.1, 1, 0.0, 0.5);
+        assert!(!sched.step(10.0));
+        assert!(!sched.step(9.8));
+        assert!(sched.step(9.6));
+        assert!((sched.get_lr() - 0.1).abs() < 1e-12);
+        assert!(!sched.step(9.0));
+        assert_eq!(sched.best(), 9.0);
+    }
+
+    #[test]
+    fn clip_norm() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let params = vec![vf.value(0.0), vf.value(0.0)];
+        params[0].set_grad(3.0);
+        params[1].set_grad(4.0);
+        let norm = clip_grad_norm(&params, 1.0);
+        assert_eq!(norm, 5.0);
+        assert!((params[0].get_grad() - 0.6).abs() < 1e-12);
+        assert!((params[1].get_grad() - 0.8).abs() < 1e-12);
+
+        // below max_norm nothing changes
+        let norm = clip_grad_norm(&params, 2.0);
+        assert!((norm - 1.0).abs() < 1e-12);
+        assert!((params[0].get_grad() - 0.6).abs() < 1e-12);
+        assert!((params[1].get_grad() - 0.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn clip_value() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let params = vec![vf.value(0.0), vf.value(0.0), vf.value(0.0)];
+        [-7.0, 0.25, 3.0].iter().zip(&params).for_each(|(g, p)| p.set_grad(*g));
+        clip_grad_value(&params, 0.5);
+        let grads: Vec<f64> = params.iter().map(|p| p.get_grad()).collect();
+        assert_eq!(grads, vec![-0.5, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn clipping_keeps_exploding_run_finite() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // lr is far too large for this curvature, so unclipped descent oscillates with growing amplitude
+        let run = |clip: Option<ClipMode>| {
+            let w = vf.value(1.0);
+            let mut opt = Sgd::new(vec![w.clone()], 1.0);
+            opt.set_grad_clip(clip);
+            for _ in 0..200 {
+                let loss = (&w * 100.0).powi(2);
+                opt.zero_grad();
+                loss.backward();
+                opt.step();
+            }
+            w.get_data()
+        };
+        assert!(!run(None).is_finite());
+        assert!(run(Some(ClipMode::Norm(1.0))).is_finite());
+        assert!(run(Some(ClipMode::Value(1.0))).is_finite());
+
+        let w = vf.value(1.0);
+        let mut opt = Adam::new(vec![w.clone()], 0.1);
+        opt.set_grad_clip(Some(ClipMode::Norm(1.0)));
+        let loss = (&w * 100.0).powi(2);
+        loss.backward();
+        opt.step();
+        // clipped in place before the update
+        assert_eq!(w.get_grad(), 1.0);
+    }
+
+    #[test]
+    fn param_groups() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (a, b) = (vf.value(1.0), vf.value(1.0));
+        let groups = vec![ParamGroup::new_with_lr(vec![a.clone()], 0.01), ParamGroup::new(vec![b.clone()])];
+        let mut opt = Sgd::new_with_groups(groups, 0.1, 0.0);
+        assert_eq!(opt.get_lr_for_group(0), 0.01);
+        // None falls through to the default
+        assert_eq!(opt.get_lr_for_group(1), 0.1);
+
+        // identical gradients, 10x different updates
+        a.set_grad(1.0);
+        b.set_grad(1.0);
+        opt.step();
+        assert!((1.0 - a.get_data() - 0.01).abs() < 1e-12);
+        assert!((1.0 - b.get_data() - 0.1).abs() < 1e-12);
+
+        opt.set_lr_for_group(1, 0.5);
+        assert_eq!(opt.get_lr_for_group(1), 0.5);
+        assert_eq!(opt.get_lr(), 0.1);
+    }
+
+    #[test]
+    fn param_group_weight_decay() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (a, b) = (vf.value(2.0), vf.value(2.0));
+        let mut decayed = ParamGroup::new(vec![a.clone()]);
+        decayed.weight_decay = Some(0.5);
+        let mut opt = Sgd::new_with_groups(vec![decayed, ParamGroup::new(vec![b.clone()])], 0.1, 0.0);
+        opt.step();
+        assert!((a.get_data() - 1.9).abs() < 1e-12);
+        assert_eq!(b.get_data(), 2.0);
+    }
+
+    #[test]
+    fn schedulers_scale_all_groups() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let groups = vec![ParamGroup::new_with_lr(vec![vf.value(1.0)], 0.01), ParamGroup::new(vec![vf.value(1.0)])];
+        let mut sched = StepLR::new(Adam::new_with_groups(groups, 0.1, 0.9, 0.999, 1e-8), 1, 0.5);
+        sched.step();
+        sched.step();
+        let opt = sched.optimizer();
+        assert!((opt.get_lr_for_group(0) - 0.0025).abs() < 1e-15);
+        assert!((opt.get_lr_for_group(1) - 0.025).abs() < 1e-15);
+    }
+
+    #[test]
+    fn schedulers_through_zero_lr() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (a, b) = (vf.value(1.0), vf.value(1.0));
+        let groups = || vec![ParamGroup::new_with_lr(vec![a.clone()], 0.01), ParamGroup::new(vec![b.clone()])];
+        // cosine down to eta_min = 0 at epoch 2, where it stays
+        let mut sched = CosineAnnealingLR::new(Sgd::new_with_groups(groups(), 0.1, 0.0), 2, 0.0);
+        for _ in 0..4 {
+            sched.step();
+            a.set_grad(1.0);
+            sched.optimizer().step();
+        }
+        assert_eq!((sched.optimizer().get_lr_for_group(0), sched.optimizer().get_lr_for_group(1)), (0.0, 0.0));
+        assert!((a.get_data() - 0.995).abs() < 1e-12, "{}", a.get_data());
+        // and back up from zero, with the ratio between the groups intact
+        sched.optimizer().set_lr(0.2);
+        assert_eq!(sched.optimizer().get_lr_for_group(0), 0.02);
+
+        // a warmup starting from 0 leaves the group at its lr once done
+        let mut sched = Warmup::new(StepLR::new(Sgd::new_with_groups(groups(), 0.1, 0.0), 10, 0.5), 2, 0.0);
+        assert_eq!(sched.optimizer().get_lr_for_group(0), 0.0);
+        sched.step();
+        sched.step();
+        assert!((sched.optimizer().get_lr_for_group(0) - 0.01).abs() < 1e-15);
+        assert_eq!(sched.optimizer().get_lr_for_group(1), 0.1);
+    }
+
+    #[test]
+    fn mlp_param_groups() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = crate::MLP::new(&vf, 3, &[4, 2]);
+        let mut groups = n.param_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].params.len(), 16);
+        assert_eq!(groups[1].params.len(), 10);
+
+        // 10x smaller lr for the first layer
+        groups[0].lr = Some(0.01);
+        let opt = Sgd::new_with_groups(groups, 0.1, 0.0);
+        assert_eq!(opt.get_lr_for_group(0), 0.01);
+        assert_eq!(opt.get_lr_for_group(1), 0.1);
+    }
+
+    #[test]
+    fn lr_find_schedule_is_exponential() {
+        let options = LrFindOptions { start_lr: 1e-4, end_lr: 1.0, num_steps: 5, ..Default::default() };
+        let result = lr_find_with(|_| 1.0, &options);
+        assert_eq!(result.lrs.len(), 5);
+        [1e-4, 1e-3, 1e-2, 1e-1, 1.0].iter().zip(&result.lrs).for_each(|(expected, lr)| {
+            assert!((lr / expected - 1.0).abs() < 1e-12);
+        });
+        assert!(result.losses.iter().all(|l| (l - 1.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn lr_find_stops_on_divergence() {
+        let options = LrFindOptions { start_lr: 1e-3, end_lr: 1.0, num_steps: 50, smoothing: 0.0, diverge_factor: 4.0 };
+        // decreases for 10 steps, then doubles every step
+        let mut step = 0;
+        let result = lr_find_with(|_| {
+            step += 1;
+            if step <= 10 { 1.0 / step as f64 } else { 0.1 * 2.0_f64.powi(step - 10) }
+        }, &options);
+        // 0.1 * 2^2 = 0.4 is the last one within 4x of the best (0.1)
+        assert_eq!(result.lrs.len(), 12);
+        assert_eq!(step, 13);
+        let suggested = result.suggested_lr.unwrap();
+        assert!(suggested >= options.start_lr && suggested <= options.end_lr);
+        assert!(result.lrs.contains(&suggested));
+        // steepest drop is from 1.0 to 0.5 at the first step
+        assert_eq!(suggested, result.lrs[0]);
+    }
+
+    #[test]
+    fn lr_find_smoke() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let data = Dataset {
+            xs: vec![vec![2.0, 3.0, -1.0], vec![3.0, -1.0, 0.5], vec![0.5, 1.0, 1.0], vec![1.0, 1.0, -1.0]],
+            ys: vec![vec![1.0], vec![-1.0], vec![-1.0], vec![1.0]]
+        };
+        let options = LrFindOptions { start_lr: 1e-4, end_lr: 10.0, num_steps: 60, ..Default::default() };
+        let result = lr_find(&vf, |vf| MLP::new_with_seed(vf, 3, &[4, 4, 1], 7), &data, &options);
+        assert!(!result.lrs.is_empty());
+        let suggested = result.suggested_lr.unwrap();
+        assert!(suggested.is_finite());
+        assert!(suggested >= options.start_lr && suggested <= options.end_lr);
+    }
+
+    // Runs 10 steps on loss = sum (w_i - target_i)^2, snapshotting the optimizer after 5 into a fresh one
+    fn resume_after_5<O: Optimizer>(vf: &ValueFactory, build: impl Fn(Vec<Value>) -> O) -> (Vec<f64>, Vec<f64>) {
+        let run = |ws: &[Value], opt: &mut dyn Optimizer, steps: usize| {
+            for _ in 0..steps {
+                let loss = ws.iter()
+                    .enumerate()
+                    .map(|(i, w)| (w - i as f64).powi(2))
+                    .reduce(|acc, v| &acc + &v)
+                    .unwrap();
+                opt.zero_grad();
+                loss.backward();
+                opt.step();
+            }
+        };
+        let (a, b): (Vec<Value>, Vec<Value>) = ((0..3).map(|_| vf.value(0.5)).collect(), (0..3).map(|_| vf.value(0.5)).collect());
+        let mut straight = build(a.clone());
+        run(&a, &mut straight, 10);
+
+        let mut first = build(b.clone());
+        run(&b, &mut first, 5);
+        let mut resumed = build(b.clone());
+        resumed.load_state_dict(&first.state_dict()).unwrap();
+        run(&b, &mut resumed, 5);
+        (a.iter().map(|v| v.get_data()).collect(), b.iter().map(|v| v.get_data()).collect())
+    }
+
+    #[test]
+    fn optimizer_state_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let (straight, resumed) = resume_after_5(&vf, |ps| Sgd::new_with_momentum(ps, 0.05, 0.9));
+        assert_eq!(straight, resumed);
+        let (straight, resumed) = resume_after_5(&vf, |ps| Adam::new(ps, 0.05));
+        assert_eq!(straight, resumed);
+
+        let ws: Vec<Value> = (0..3).map(|_| vf.value(0.5)).collect();
+        let mut adam = Adam::new(ws.clone(), 0.1);
+        adam.set_lr_for_group(0, 0.2);
+        let state = adam.state_dict();
+        assert_eq!((state.kind.as_str(), state.group_lrs.clone(), state.buffers.len()), ("adam", vec![Some(0.2)], 2));
+
+        let mut short = Adam::new(ws[..2].to_vec(), 0.1);
+        assert_eq!(
+            short.load_state_dict(&state),
+            Err(OptimizerStateError::LengthMismatch { what: "buffer entries", expected: 2, got: 3 })
+        );
+        let mut sgd = Sgd::new(ws, 0.1);
+        assert!(matches!(sgd.load_state_dict(&state), Err(OptimizerStateError::KindMismatch { .. })));
+    }
+
+    #[test]
+    fn ema_is_geometric_average() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let w = vf.value(1.0);
+        let mut ema = Ema::new(vec![w.clone()], 0.8);
+        let ws = [2.0, -1.0, 0.5, 4.0];
+        ws.iter().for_each(|wi| {
+            w.set_data(*wi);
+            ema.update();
+        });
+        // 0.8^n w0 + 0.2 * sum of 0.8^(n - i) w_i
+        let n = ws.len() as i32;
+        let expected = 0.8f64.powi(n) + ws.iter().enumerate().map(|(i, wi)| 0.2 * 0.8f64.powi(n - 1 - i as i32) * wi).sum::<f64>();
+        assert!((ema.averages()[0] - expected).abs() < 1e-12);
+
+        ema.apply_to_model();
+        ema.apply_to_model();
+        assert_eq!(w.get_data(), ema.averages()[0]);
+        ema.restore();
+        assert_eq!(w.get_data(), 4.0);
+        assert!(!ema.is_applied());
+
+        assert!(std::panic::catch_unwind(|| Ema::new(Vec::new(), 1.0)).is_err());
+        assert!(std::panic::catch_unwind(|| Ema::new(Vec::new(), -0.1)).is_err());
+    }
+
+    #[test]
+    fn ema_evaluation_leaves_training_alone() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = ([[0.5, -1.0], [1.0, 2.0], [-0.5, 0.25]], [1.0, -1.0, 0.5]);
+
+        let run = |with_ema: bool| {
+            let model = MLP::new_with_seed(&vf, 2, &[4, 1], 9);
+            let mut opt = Sgd::new(model.parameters().cloned().collect(), 0.05);
+            let mut ema = Ema::new(model.parameters().cloned().collect(), 0.9);
+            let mse = |model: &MLP| zip(&xs, &ys).map(|(x, y)| (model.predict(x)[0] - y).powi(2)).sum::<f64>() / 3.0;
+            let mut ema_losses = Vec::new();
+            for _ in 0..20 {
+                let loss = zip(&xs, &ys)
+                    .map(|(x, y)| (&model.call(&vf.values(x))[0] - *y).powi(2))
+                    .reduce(|acc, v| &acc + &v)
+                    .unwrap();
+                opt.zero_grad();
+                loss.backward();
+                opt.step();
+                if with_ema {
+                    ema.update();
+                    ema.apply_to_model();
+                    ema_losses.push(mse(&model));
+                    ema.restore();
+                }
+            }
+            (model.state_dict(), mse(&model), ema_losses)
+        };
+
+        let (plain, plain_loss, _) = run(false);
+        let (trained, loss, ema_losses) = run(true);
+        assert_eq!(plain, trained);
+        // the average lags behind, but training still improves it
+        assert!(ema_losses[19] < ema_losses[0]);
+        assert!(ema_losses[19] > loss);
+        assert_eq!(loss, plain_loss);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let model = MLP::new_with_seed(&vf, 2, &[3, 1], 4);
+        let before = model.state_dict();
+        let snapshot = Snapshot::take(model.parameters());
+        assert_eq!((snapshot.len(), snapshot.data()), (13, before.as_slice()));
+
+        model.parameters().for_each(|p| p.set_data(p.get_data() * 1e6 + 0.1));
+        snapshot.restore().unwrap();
+        assert_eq!(model.state_dict(), before);
+
+        // into a model of the same shape, but not one of another size
+        let same = MLP::new_with_seed(&vf, 2, &[3, 1], 5);
+        snapshot.restore_to(same.parameters()).unwrap();
+        assert_eq!(same.state_dict(), before);
+        let wider = MLP::new_with_seed(&vf, 2, &[4, 1], 5);
+        let widened = wider.state_dict();
+        assert_eq!(snapshot.restore_to(wider.parameters()), Err(SnapshotError::LengthMismatch { expected: 13, got: 17 }));
+        assert_eq!(wider.state_dict(), widened);
+    }
+}
@@ -0,0 +1,488 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::iter::{zip, once};
+use std::ops;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+use crate::{Activation, LayerSpec, MlpSpec, ModelIoError, Rng};
+
+// Thread-safe counterparts of Arena, Value, ValueFactory and MLP. The graph works the same way, but the
+// arena and every node sit behind Arc<RwLock> so handles can be sent to and shared between threads. The
+// Rc/RefCell types stay the default; these pay for atomic counts and a lock on every access.
+
+// A panic while holding a lock leaves plain numbers behind, so poisoning is ignored
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct SyncArena {
+    arena: Vec<Arc<RwLock<SyncValueData>>>
+}
+
+impl SyncArena {
+    pub fn build() -> (SyncArenaLifeTime, SyncArenaRef) {
+        let arena = Arc::new(RwLock::new(SyncArena { arena: Vec::new() }));
+        (SyncArenaLifeTime(arena.clone()), SyncArenaRef(Arc::downgrade(&arena)))
+    }
+}
+
+// Arena deallocated when SyncArenaLifeTime goes out of scope, as with ArenaLifeTime
+#[must_use]
+pub struct SyncArenaLifeTime(#[allow(dead_code)]Arc<RwLock<SyncArena>>);
+
+#[derive(Clone)]
+pub struct SyncArenaRef(Weak<RwLock<SyncArena>>);
+
+impl SyncArenaRef {
+    // Always panic if Arena deallocated
+    fn arena(&self) -> Arc<RwLock<SyncArena>> {
+        self.0.upgrade().expect("Arena lifetime has ended")
+    }
+
+    fn alloc(&self, value: SyncValueData) -> Weak<RwLock<SyncValueData>> {
+        let shared = Arc::new(RwLock::new(value));
+        write(&self.arena()).arena.push(shared.clone());
+        Arc::downgrade(&shared)
+    }
+
+    pub fn len(&self) -> usize {
+        read(&self.arena()).arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Clone)]
+pub struct SyncValueFactory {
+    arena: SyncArenaRef
+}
+
+impl SyncValueFactory {
+    pub fn new(arena: SyncArenaRef) -> SyncValueFactory {
+        SyncValueFactory { arena }
+    }
+
+    pub fn value(&self, data: f64) -> SyncValue {
+        SyncValue::build(self.arena.clone(), data)
+    }
+}
+
+pub struct SyncValueData {
+    data: f64,
+    grad: f64,
+    // Arc so backward can call it without holding the node's lock
+    backward: Arc<dyn Fn() + Send + Sync>,
+    prev: Vec<SyncValue>
+}
+
+#[derive(Clone)]
+pub struct SyncValue {
+    value: Weak<RwLock<SyncValueData>>,
+    arena: SyncArenaRef
+}
+
+impl SyncValue {
+    pub fn build(arena: SyncArenaRef, data: f64) -> SyncValue {
+        SyncValue::new(arena, data, &[])
+    }
+
+    fn new(arena: SyncArenaRef, data: f64, children: &[SyncValue]) -> SyncValue {
+        let mut prev: Vec<SyncValue> = Vec::with_capacity(children.len());
+        children.iter().for_each(|c| if !prev.contains(c) {prev.push(c.clone())});
+        let node = SyncValueData { data, grad: 0.0, backward: Arc::new(|| {}), prev };
+        SyncValue { value: arena.alloc(node), arena }
+    }
+
+    // Locks are only held for the duration of f, never across two nodes, so concurrent use cannot deadlock
+    fn with_read<R>(&self, f: impl FnOnce(&SyncValueData) -> R) -> R {
+        let value_ptr = self.value.upgrade().expect("DAG properties of autograd graph violated");
+        let guard = read(&value_ptr);
+        f(&guard)
+    }
+
+    fn with_write<R>(&self, f: impl FnOnce(&mut SyncValueData) -> R) -> R {
+        let value_ptr = self.value.upgrade().expect("DAG properties of autograd graph violated");
+        let mut guard = write(&value_ptr);
+        f(&mut guard)
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.value.strong_count() > 0
+    }
+
+    pub fn get_data(&self) -> f64 {
+        self.with_read(|v| v.data)
+    }
+
+    pub fn get_grad(&self) -> f64 {
+        self.with_read(|v| v.grad)
+    }
+
+    pub fn set_data(&self, data: f64) {
+        self.with_write(|v| v.data = data);
+    }
+
+    pub fn set_grad(&self, grad: f64) {
+        self.with_write(|v| v.grad = grad);
+    }
+
+    fn add_grad(&self, delta: f64) {
+        self.with_write(|v| v.grad += delta);
+    }
+
+    fn set_backward(&self, backward_fn: impl Fn() + Send + Sync + 'static) {
+        self.with_write(|v| v.backward = Arc::new(backward_fn));
+    }
+
+    // Builds a unary op node whose backward adds local_grad(x, out) * out.grad to the operand
+    fn unary(&self, data: f64, local_grad: impl Fn(f64, f64) -> f64 + Send + Sync + 'static) -> SyncValue {
+        let out = SyncValue::new(self.arena.clone(), data, std::slice::from_ref(self));
+        let (out_ref, self_ref) = (out.clone(), self.clone());
+        out.set_backward(move || {
+            let (out_grad, out_data) = (out_ref.get_grad(), out_ref.get_data());
+            self_ref.add_grad(local_grad(self_ref.get_data(), out_data) * out_grad);
+        });
+        out
+    }
+
+    pub fn backward(&self) {
+        let mut topo: Vec<SyncValue> = Vec::new();
+        let mut visited: HashSet<SyncValue> = HashSet::new();
+        fn build_topo(v: &SyncValue, visited: &mut HashSet<SyncValue>, topo: &mut Vec<SyncValue>) {
+            if visited.insert(v.clone()) {
+                let prev = v.with_read(|node| node.prev.clone());
+                prev.iter().for_each(|child| build_topo(child, visited, topo));
+                topo.push(v.clone());
+            }
+        }
+        build_topo(self, &mut visited, &mut topo);
+
+        self.set_grad(1.0);
+        topo.iter().rev().for_each(|node| {
+            let backward = node.with_read(|v| v.backward.clone());
+            backward();
+        });
+    }
+
+    pub fn relu(&self) -> SyncValue {
+        let x = self.get_data();
+        self.unary(if x < 0.0 {0.0} else {x}, |_, out| if out > 0.0 {1.0} else {0.0})
+    }
+
+    pub fn tanh(&self) -> SyncValue {
+        let x = self.get_data();
+        let t = ((2.0*x).exp() - 1.0) / ((2.0*x).exp() + 1.0);
+        self.unary(t, |_, out| 1.0 - out.powi(2))
+    }
+
+    pub fn exp(&self) -> SyncValue {
+        self.unary(self.get_data().exp(), |_, out| out)
+    }
+
+    pub fn powi(&self, n: i32) -> SyncValue {
+        self.unary(self.get_data().powi(n), move |x, _| n as f64 * x.powi(n - 1))
+    }
+
+    pub fn powf(&self, n: f64) -> SyncValue {
+        self.unary(self.get_data().powf(n), move |x, _| n * x.powf(n - 1.0))
+    }
+}
+
+impl PartialEq for SyncValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.ptr_eq(&other.value)
+    }
+}
+
+impl Eq for SyncValue {}
+
+impl Hash for SyncValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.as_ptr().hash(state);
+    }
+}
+
+impl<'b> ops::Add<&'b SyncValue> for &SyncValue {
+    type Output = SyncValue;
+
+    fn add(self, rhs: &'b SyncValue) -> SyncValue {
+        let out = SyncValue::new(self.arena.clone(), self.get_data() + rhs.get_data(), &[self.clone(), rhs.clone()]);
+        let (out_ref, self_ref, rhs_ref) = (out.clone(), self.clone(), rhs.clone());
+        out.set_backward(move || {
+            let out_grad = out_ref.get_grad();
+            self_ref.add_grad(out_grad);
+            rhs_ref.add_grad(out_grad);
+        });
+        out
+    }
+}
+
+impl<'b> ops::Mul<&'b SyncValue> for &SyncValue {
+    type Output = SyncValue;
+
+    fn mul(self, rhs: &'b SyncValue) -> SyncValue {
+        let out = SyncValue::new(self.arena.clone(), self.get_data() * rhs.get_data(), &[self.clone(), rhs.clone()]);
+        let (out_ref, self_ref, rhs_ref) = (out.clone(), self.clone(), rhs.clone());
+        out.set_backward(move || {
+            let out_grad = out_ref.get_grad();
+            self_ref.add_grad(rhs_ref.get_data() * out_grad);
+            rhs_ref.add_grad(self_ref.get_data() * out_grad);
+        });
+        out
+    }
+}
+
+impl ops::Neg for &SyncValue {
+    type Output = SyncValue;
+
+    fn neg(self) -> SyncValue {
+        self * -1.0
+    }
+}
+
+impl<'b> ops::Sub<&'b SyncValue> for &SyncValue {
+    type Output = SyncValue;
+
+    fn sub(self, rhs: &'b SyncValue) -> SyncValue {
+        self + &(-rhs)
+    }
+}
+
+impl<'b> ops::Div<&'b SyncValue> for &SyncValue {
+    type Output = SyncValue;
+
+    fn div(self, rhs: &'b SyncValue) -> SyncValue {
+        self * &rhs.powi(-1)
+    }
+}
+
+// Scalar operands on either side become leaves in the same arena, as with Value
+macro_rules! scalar_ops {
+    ($($trait:ident $method:ident),*) => {$(
+        impl ops::$trait<f64> for &SyncValue {
+            type Output = SyncValue;
+
+            fn $method(self, rhs: f64) -> SyncValue {
+                ops::$trait::$method(self, &SyncValue::build(self.arena.clone(), rhs))
+            }
+        }
+
+        impl<'a> ops::$trait<&'a SyncValue> for f64 {
+            type Output = SyncValue;
+
+            fn $method(self, rhs: &'a SyncValue) -> SyncValue {
+                ops::$trait::$method(&SyncValue::build(rhs.arena.clone(), self), rhs)
+            }
+        }
+    )*};
+}
+
+scalar_ops!(Add add, Sub sub, Mul mul, Div div);
+
+fn apply(activation: Activation, x: &SyncValue) -> SyncValue {
+    match activation {
+        Activation::Tanh => x.tanh(),
+        Activation::Relu => x.relu(),
+        Activation::Linear => x.clone()
+    }
+}
+
+struct SyncNeuron {
+    w: Vec<SyncValue>,
+    b: SyncValue,
+    activation: Activation
+}
+
+// MLP on SyncValues. Initialization draws from the rng in the same order as MLP, so equal seeds give equal
+// models; from_spec / to_spec move a trained MLP across.
+pub struct SyncMLP {
+    layers: Vec<Vec<SyncNeuron>>
+}
+
+impl SyncMLP {
+    pub fn new_with_seed(vf: &SyncValueFactory, nin: usize, nout: &[usize], seed: u64) -> SyncMLP {
+        let mut rng = Rng::new(seed);
+        let sz: Vec<usize> = once(nin).chain(nout.iter().copied()).collect();
+        SyncMLP {
+            layers: sz.windows(2).map(|w| (0..w[1]).map(|_| SyncNeuron {
+                w: (0..w[0]).map(|_| vf.value(rng.uniform(-1.0, 1.0))).collect(),
+                b: vf.value(rng.uniform(-1.0, 1.0)),
+                activation: Activation::Tanh
+            }).collect()).collect()
+        }
+    }
+
+    pub fn from_spec(vf: &SyncValueFactory, spec: &MlpSpec) -> Result<SyncMLP, ModelIoError> {
+        spec.validate()?;
+        Ok(SyncMLP {
+            layers: spec.layers.iter().map(|l| zip(&l.weights, &l.biases).map(|(w, b)| SyncNeuron {
+                w: w.iter().map(|wi| vf.value(*wi)).collect(),
+                b: vf.value(*b),
+                activation: l.activation
+            }).collect()).collect()
+        })
+    }
+
+    pub fn to_spec(&self) -> MlpSpec {
+        MlpSpec {
+            nin: self.nin(),
+            layers: self.layers.iter().map(|l| LayerSpec {
+                nout: l.len(),
+                activation: l[0].activation,
+                bias: true,
+                weights: l.iter().map(|n| n.w.iter().map(|w| w.get_data()).collect()).collect(),
+                biases: l.iter().map(|n| n.b.get_data()).collect()
+            }).collect()
+        }
+    }
+
+    pub fn call(&self, x: &[SyncValue]) -> Vec<SyncValue> {
+        self.layers.iter().fold(x.to_vec(), |acc, layer| layer.iter().map(|n| {
+            let out = zip(&n.w, &acc).map(|(wi, xi)| wi * xi).fold(n.b.clone(), |sum, v| &sum + &v);
+            apply(n.activation, &out)
+        }).collect())
+    }
+
+    // Graph-free inference, see MLP::predict
+    pub fn predict(&self, x: &[f64]) -> Vec<f64> {
+        self.layers.iter().fold(x.to_vec(), |acc, layer| layer.iter().map(|n| {
+            let out = zip(&n.w, &acc).fold(n.b.get_data(), |sum, (wi, xi)| sum + wi.get_data() * xi);
+            n.activation.apply_f64(out)
+        }).collect())
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = &SyncValue> {
+        self.layers.iter().flatten().flat_map(|n| n.w.iter().chain(once(&n.b)))
+    }
+
+    pub fn zero_grad(&self) {
+        self.parameters().for_each(|p| p.set_grad(0.0));
+    }
+
+    pub fn nin(&self) -> usize {
+        self.layers[0][0].w.len()
+    }
+
+    pub fn nout(&self) -> usize {
+        self.layers[self.layers.len() - 1].len()
+    }
+}
+
+// Compile time check that everything here can cross threads
+#[allow(dead_code)]
+fn assert_send_sync<T: Send + Sync>() {}
+
+const _: fn() = || {
+    assert_send_sync::<SyncArenaLifeTime>();
+    assert_send_sync::<SyncArenaRef>();
+    assert_send_sync::<SyncValueFactory>();
+    assert_send_sync::<SyncValue>();
+    assert_send_sync::<SyncMLP>();
+};
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, ValueFactory, MLP};
+    use std::thread;
+
+    #[test]
+    fn matches_engine() {
+        let (_arena_life_time, arena_ref) = SyncArena::build();
+        let vf = SyncValueFactory::new(arena_ref);
+
+        // the micrograd example
+        let a = vf.value(-4.0);
+        let b = vf.value(2.0);
+        let mut c = &a + &b;
+        let mut d = &(&a * &b) + &b.powi(3);
+        c = &(&c + &c) + 1.0;
+        c = &(&(&c + 1.0) + &c) + &-&a;
+        d = &(&d + &(&d * 2.0)) + &(&b + &a).relu();
+        d = &d + &(&(3.0 * &d) + &(&b - &a).relu());
+        let e = &c - &d;
+        let f = e.powi(2);
+        let g = &(&f / 2.0) + &(10.0 / &f);
+        g.backward();
+        assert!((g.get_data() - 24.7041).abs() < 1e-4);
+        assert!((a.get_grad() - 138.8338).abs() < 1e-4);
+        assert!((b.get_grad() - 645.5773).abs() < 1e-4);
+
+        let (_life_time, arena) = Arena::build();
+        let engine_vf = ValueFactory::new(arena);
+        let model = MLP::new_with_seed(&engine_vf, 3, &[4, 2], 9);
+        let sync_model = SyncMLP::from_spec(&vf, &model.to_spec()).unwrap();
+        assert_eq!(sync_model.to_spec(), model.to_spec());
+        assert_eq!(SyncMLP::new_with_seed(&vf, 3, &[4, 2], 9).to_spec(), model.to_spec());
+        let x = [0.5, -1.0, 2.0];
+        let out: Vec<f64> = sync_model.call(&x.map(|xi| vf.value(xi))).iter().map(|v| v.get_data()).collect();
+        let expected: Vec<f64> = model.call(&x.map(|xi| engine_vf.value(xi))).iter().map(|v| v.get_data()).collect();
+        assert_eq!(out, expected);
+        assert_eq!(sync_model.predict(&x), model.predict(&x));
+    }
+
+    #[test]
+    fn concurrent_evaluation() {
+        // built on another thread and moved here with its arena
+        let (_arena_life_time, vf, model) = thread::spawn(|| {
+            let (life_time, arena_ref) = SyncArena::build();
+            let vf = SyncValueFactory::new(arena_ref);
+            let model = SyncMLP::new_with_seed(&vf, 2, &[8, 8, 1], 4);
+            (life_time, vf, model)
+        }).join().unwrap();
+
+        let inputs: Vec<[f64; 2]> = (0..4).map(|i| [i as f64 * 0.5, 1.0 - i as f64]).collect();
+        let outputs: Vec<Vec<f64>> = thread::scope(|s| {
+            let handles: Vec<_> = inputs.iter().map(|x| {
+                let (vf, model) = (&vf, &model);
+                s.spawn(move || (0..20).map(|_| model.call(&x.map(|xi| vf.value(xi)))[0].get_data()).collect::<Vec<f64>>())
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        for (x, out) in zip(&inputs, &outputs) {
+            let expected = model.call(&x.map(|xi| vf.value(xi)))[0].get_data();
+            assert!(out.iter().all(|&y| y == expected));
+        }
+    }
+
+    #[test]
+    fn concurrent_backward() {
+        let (_arena_life_time, arena_ref) = SyncArena::build();
+        let vf = SyncValueFactory::new(arena_ref);
+
+        let grads: Vec<f64> = thread::scope(|s| {
+            let handles: Vec<_> = [1.5, -0.5].into_iter().map(|x| {
+                let vf = &vf;
+                s.spawn(move || {
+                    let x = vf.value(x);
+                    let mut y = x.clone();
+                    for _ in 0..200 {
+                        y = &(&y * 0.5) + &x.tanh();
+                    }
+                    y.backward();
+                    x.get_grad()
+                })
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // the same graphs built single threaded
+        let expected: Vec<f64> = [1.5, -0.5].into_iter().map(|x| {
+            let x = vf.value(x);
+            let mut y = x.clone();
+            for _ in 0..200 {
+                y = &(&y * 0.5) + &x.tanh();
+            }
+            y.backward();
+            x.get_grad()
+        }).collect();
+        assert_eq!(grads, expected);
+    }
+}
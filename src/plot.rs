@@ -0,0 +1,196 @@
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use plotters::coord::ranged1d::{AsRangedCoord, ValueFormatter};
+use plotters::prelude::*;
+use crate::History;
+
+// Loss curve rendering for History, behind the `plots` feature. The image format follows the file
+// extension (png, bmp, ...).
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotOptions {
+    pub log_y: bool,
+    pub title: String,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Default for PlotOptions {
+    fn default() -> PlotOptions {
+        PlotOptions { log_y: false, title: String::from("Loss"), width: 800, height: 600 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlotError {
+    // no finite loss to draw
+    Empty,
+    // log_y needs every plotted loss to be positive
+    NonPositiveLoss { epoch: usize, loss: f64 },
+    Backend(String)
+}
+
+impl fmt::Display for PlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlotError::Empty => write!(f, "history has no finite losses to plot"),
+            PlotError::NonPositiveLoss { epoch, loss } => write!(f, "log scale needs positive losses, epoch {} has {}", epoch, loss),
+            PlotError::Backend(msg) => write!(f, "drawing failed: {}", msg)
+        }
+    }
+}
+
+impl std::error::Error for PlotError {}
+
+// Axis range covering the finite values, padded by 5% of the span. A constant series gets a range around
+// its value (a factor of 2 either way on a log axis). None when nothing is finite.
+fn axis_range(values: &[f64], log: bool) -> Option<Range<f64>> {
+    let finite = values.iter().copied().filter(|v| v.is_finite());
+    let (lo, hi) = finite.fold(None, |acc: Option<(f64, f64)>, v| match acc {
+        None => Some((v, v)),
+        Some((lo, hi)) => Some((lo.min(v), hi.max(v)))
+    })?;
+    Some(match (log, lo == hi) {
+        (true, true) => lo / 2.0..hi * 2.0,
+        (true, false) => {
+            let pad = (hi / lo).powf(0.05);
+            lo / pad..hi * pad
+        }
+        (false, true) => {
+            let pad = if lo == 0.0 { 1.0 } else { lo.abs() * 0.1 };
+            lo - pad..hi + pad
+        }
+        (false, false) => {
+            let pad = (hi - lo) * 0.05;
+            lo - pad..hi + pad
+        }
+    })
+}
+
+// Train and validation loss per epoch. NaN and infinite losses are skipped rather than breaking the axes.
+pub fn plot_history(history: &History, path: impl AsRef<Path>, options: PlotOptions) -> Result<(), PlotError> {
+    let finite = |epoch: usize, loss: Option<f64>| loss.filter(|l| l.is_finite()).map(|l| (epoch as f64, l));
+    let train: Vec<(f64, f64)> = history.records().iter().filter_map(|r| finite(r.epoch, Some(r.train_loss))).collect();
+    let val: Vec<(f64, f64)> = history.records().iter().filter_map(|r| finite(r.epoch, r.val_loss)).collect();
+
+    if options.log_y {
+        if let Some((epoch, loss)) = train.iter().chain(&val).find(|(_, l)| *l <= 0.0) {
+            return Err(PlotError::NonPositiveLoss { epoch: *epoch as usize, loss: *loss });
+        }
+    }
+    let losses: Vec<f64> = train.iter().chain(&val).map(|(_, l)| *l).collect();
+    let y_range = axis_range(&losses, options.log_y).ok_or(PlotError::Empty)?;
+    // half an epoch of room on either side
+    let first = train.iter().chain(&val).map(|(e, _)| *e).fold(f64::INFINITY, f64::min);
+    let last = train.iter().chain(&val).map(|(e, _)| *e).fold(f64::NEG_INFINITY, f64::max);
+    let x_range = first - 0.5..last + 0.5;
+
+    if options.log_y {
+        draw(path.as_ref(), &options, x_range, y_range.log_scale(), &train, &val)
+    } else {
+        draw(path.as_ref(), &options, x_range, y_range, &train, &val)
+    }
+}
+
+fn draw<Y>(path: &Path, options: &PlotOptions, x_range: Range<f64>, y_coord: Y, train: &[(f64, f64)], val: &[(f64, f64)]) -> Result<(), PlotError>
+where
+    Y: AsRangedCoord<Value = f64>,
+    Y::CoordDescType: ValueFormatter<f64>
+{
+    let backend = |e: &dyn fmt::Display| PlotError::Backend(e.to_string());
+    let root = BitMapBackend::new(path, (options.width, options.height)).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| backend(&e))?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(&options.title, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x_range, y_coord)
+        .map_err(|e| backend(&e))?;
+    chart.configure_mesh()
+        .x_desc("epoch")
+        .y_desc("loss")
+        .x_label_formatter(&|x| format!("{}", x.round()))
+        .draw()
+        .map_err(|e| backend(&e))?;
+
+    for (name, points, color) in [("train", train, BLUE), ("validation", val, RED)] {
+        if points.is_empty() {
+            continue;
+        }
+        chart.draw_series(LineSeries::new(points.iter().copied(), color.stroke_width(2)))
+            .map_err(|e| backend(&e))?
+            .label(name)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        // markers keep a single-epoch history visible
+        chart.draw_series(points.iter().map(|p| Circle::new(*p, 3, color.filled())))
+            .map_err(|e| backend(&e))?;
+    }
+    chart.configure_series_labels()
+        .border_style(BLACK)
+        .background_style(WHITE.mix(0.8))
+        .draw()
+        .map_err(|e| backend(&e))?;
+    root.present().map_err(|e| backend(&e))
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EpochRecord;
+    use std::fs;
+
+    fn history(losses: &[(f64, Option<f64>)]) -> History {
+        let mut history = History::new();
+        losses.iter().enumerate().for_each(|(epoch, (train_loss, val_loss))| {
+            history.record(EpochRecord { epoch, train_loss: *train_loss, val_loss: *val_loss, ..Default::default() });
+        });
+        history
+    }
+
+    fn temp_png(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rust_micrograd_{}_{}.png", name, std::process::id()))
+    }
+
+    #[test]
+    fn ranges() {
+        let r = axis_range(&[1.0, 3.0, f64::NAN, 2.0], false).unwrap();
+        assert!((r.start - 0.9).abs() < 1e-12 && (r.end - 3.1).abs() < 1e-12);
+        assert_eq!(axis_range(&[2.0, 2.0], false), Some(1.8..2.2));
+        assert_eq!(axis_range(&[0.0], false), Some(-1.0..1.0));
+        assert_eq!(axis_range(&[0.5, 0.5], true), Some(0.25..1.0));
+        let r = axis_range(&[0.01, 1.0], true).unwrap();
+        assert!(r.start < 0.01 && r.start > 0.0 && r.end > 1.0);
+        assert_eq!(axis_range(&[f64::NAN, f64::INFINITY], false), None);
+        assert_eq!(axis_range(&[], false), None);
+    }
+
+    #[test]
+    fn writes_png() {
+        let path = temp_png("loss_curve");
+        let h = history(&[(1.0, Some(1.2)), (0.5, None), (f64::NAN, Some(0.8)), (0.2, Some(0.4))]);
+        plot_history(&h, &path, PlotOptions { log_y: true, ..Default::default() }).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+
+        // a single epoch still plots
+        let path = temp_png("single_epoch");
+        plot_history(&history(&[(0.3, None)]), &path, PlotOptions::default()).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_histories() {
+        let path = temp_png("invalid");
+        let err = plot_history(&history(&[(1.0, Some(0.0))]), &path, PlotOptions { log_y: true, ..Default::default() });
+        assert_eq!(err, Err(PlotError::NonPositiveLoss { epoch: 0, loss: 0.0 }));
+        assert_eq!(plot_history(&History::new(), &path, PlotOptions::default()), Err(PlotError::Empty));
+        assert_eq!(plot_history(&history(&[(f64::NAN, None)]), &path, PlotOptions::default()), Err(PlotError::Empty));
+        assert!(!path.exists());
+    }
+}
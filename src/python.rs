@@ -0,0 +1,458 @@
+use crate::{Arena, ArenaLifeTime, MLP, Module, Value, ValueData, ValueFactory};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyList;
+
+// Python bindings. The factory owns the arena; once it is closed (or garbage collected) every Value
+// created through it raises RuntimeError instead of tripping the engine's dead-value panic.
+
+fn dead_value() -> PyErr {
+    PyRuntimeError::new_err("Value used after its ValueFactory was closed")
+}
+
+fn check_alive(value: &Value) -> PyResult<()> {
+    if value.is_alive() { Ok(()) } else { Err(dead_value()) }
+}
+
+#[pyclass(unsendable, name = "ValueFactory")]
+pub struct PyValueFactory {
+    life_time: Option<ArenaLifeTime<ValueData>>,
+    vf: ValueFactory
+}
+
+#[pymethods]
+impl PyValueFactory {
+    #[new]
+    pub fn new() -> PyValueFactory {
+        let (life_time, arena_ref) = Arena::build();
+        PyValueFactory { life_time: Some(life_time), vf: ValueFactory::new(arena_ref) }
+    }
+
+    pub fn value(&self, data: f64) -> PyResult<PyValue> {
+        self.check_open()?;
+        Ok(PyValue { value: self.vf.value(data) })
+    }
+
+    #[pyo3(signature = (nin, nouts, seed=None))]
+    pub fn mlp(&self, nin: usize, nouts: Vec<usize>, seed: Option<u64>) -> PyResult<PyMLP> {
+        PyMLP::new(self, nin, nouts, seed)
+    }
+
+    // Frees the whole graph. Values and models created by this factory are unusable afterwards.
+    pub fn close(&mut self) {
+        self.life_time = None;
+    }
+
+    #[getter]
+    pub fn closed(&self) -> bool {
+        self.life_time.is_none()
+    }
+}
+
+impl Default for PyValueFactory {
+    fn default() -> Self {
+        PyValueFactory::new()
+    }
+}
+
+impl PyValueFactory {
+    fn check_open(&self) -> PyResult<()> {
+        if self.life_time.is_some() { Ok(()) } else { Err(PyRuntimeError::new_err("ValueFactory is closed")) }
+    }
+}
+
+// Right hand side of an arithmetic dunder: another Value or a plain number
+#[derive(FromPyObject)]
+enum Operand<'py> {
+    Value(PyRef<'py, PyValue>),
+    Scalar(f64)
+}
+
+#[pyclass(unsendable, name = "Value")]
+pub struct PyValue {
+    value: Value
+}
+
+impl PyValue {
+    pub fn inner(&self) -> &Value {
+        &self.value
+    }
+
+    fn live(&self) -> PyResult<&Value> {
+        check_alive(&self.value)?;
+        Ok(&self.value)
+    }
+
+    fn binary(&self, other: Operand, op: impl Fn(&Value, &Value) -> Value, op_f64: impl Fn(&Value, f64) -> Value) -> PyResult<PyValue> {
+        let lhs = self.live()?;
+        let value = match other {
+            Operand::Value(rhs) => op(lhs, rhs.live()?),
+            Operand::Scalar(rhs) => op_f64(lhs, rhs)
+        };
+        Ok(PyValue { value })
+    }
+}
+
+#[pymethods]
+impl PyValue {
+    #[getter]
+    pub fn data(&self) -> PyResult<f64> {
+        Ok(self.live()?.get_data())
+    }
+
+    #[setter]
+    pub fn set_data(&self, data: f64) -> PyResult<()> {
+        self.live()?.set_data(data);
+        Ok(())
+    }
+
+    #[getter]
+    pub fn grad(&self) -> PyResult<f64> {
+        Ok(self.live()?.get_grad())
+    }
+
+    #[setter]
+    pub fn set_grad(&self, grad: f64) -> PyResult<()> {
+        self.live()?.set_grad(grad);
+        Ok(())
+    }
+
+    pub fn backward(&self) -> PyResult<()> {
+        self.live()?.backward();
+        Ok(())
+    }
+
+    pub fn tanh(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.tanh() })
+    }
+
+    pub fn relu(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.relu() })
+    }
+
+    pub fn exp(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.exp() })
+    }
+
+    pub fn ln(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.ln() })
+    }
+
+    pub fn sigmoid(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.sigmoid() })
+    }
+
+    pub fn sin(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.sin() })
+    }
+
+    pub fn cos(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.cos() })
+    }
+
+    pub fn tan(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.tan() })
+    }
+
+    pub fn asin(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.asin() })
+    }
+
+    pub fn acos(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.acos() })
+    }
+
+    pub fn atan(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.atan() })
+    }
+
+    pub fn sinh(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.sinh() })
+    }
+
+    pub fn cosh(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.cosh() })
+    }
+
+    pub fn sqrt(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.sqrt() })
+    }
+
+    pub fn cbrt(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.cbrt() })
+    }
+
+    pub fn abs(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.abs() })
+    }
+
+    pub fn sign(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.sign() })
+    }
+
+    pub fn floor(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.floor() })
+    }
+
+    pub fn ceil(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.ceil() })
+    }
+
+    pub fn round(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.round() })
+    }
+
+    pub fn round_ste(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.round_ste() })
+    }
+
+    fn max(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a.max(b), |a, b| a.max_scalar(b))
+    }
+
+    fn min(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a.min(b), |a, b| a.min_scalar(b))
+    }
+
+    pub fn clamp(&self, lo: f64, hi: f64) -> PyResult<PyValue> {
+        if lo > hi {
+            return Err(PyValueError::new_err(format!("clamp bounds out of order: lo {} > hi {}", lo, hi)));
+        }
+        Ok(PyValue { value: self.live()?.clamp(lo, hi) })
+    }
+
+    pub fn exp2(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.exp2() })
+    }
+
+    pub fn log2(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.log2() })
+    }
+
+    pub fn log10(&self) -> PyResult<PyValue> {
+        Ok(PyValue { value: self.live()?.log10() })
+    }
+
+    fn __add__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a + b, |a, b| a + b)
+    }
+
+    fn __radd__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| b + a, |a, b| b + a)
+    }
+
+    fn __sub__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a - b, |a, b| a - b)
+    }
+
+    fn __rsub__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| b - a, |a, b| b - a)
+    }
+
+    fn __mul__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a * b, |a, b| a * b)
+    }
+
+    fn __rmul__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| b * a, |a, b| b * a)
+    }
+
+    fn __truediv__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| a / b, |a, b| a / b)
+    }
+
+    fn __rtruediv__(&self, other: Operand) -> PyResult<PyValue> {
+        self.binary(other, |a, b| b / a, |a, b| b / a)
+    }
+
+    fn __neg__(&self) -> PyResult<PyValue> {
+        let value = self.live()?;
+        Ok(PyValue { value: -value })
+    }
+
+    // Integer exponents go through powi so the graph matches the Rust side; Value exponents use pow
+    fn __pow__(&self, exponent: Operand, modulo: Option<PyObject>) -> PyResult<PyValue> {
+        if modulo.is_some() {
+            return Err(PyValueError::new_err("pow() with a modulus is not supported"));
+        }
+        let value = self.live()?;
+        let value = match exponent {
+            Operand::Value(exponent) => value.pow(exponent.live()?),
+            Operand::Scalar(exponent) if exponent.fract() == 0.0 && exponent.abs() <= i32::MAX as f64 => value.powi(exponent as i32),
+            Operand::Scalar(exponent) => value.powf(exponent)
+        };
+        Ok(PyValue { value })
+    }
+
+    fn __repr__(&self) -> String {
+        if self.value.is_alive() {
+            format!("Value(data={}, grad={})", self.value.get_data(), self.value.get_grad())
+        } else {
+            String::from("Value(<closed>)")
+        }
+    }
+}
+
+#[pyclass(unsendable, name = "MLP")]
+pub struct PyMLP {
+    model: MLP,
+    vf: ValueFactory
+}
+
+#[pymethods]
+impl PyMLP {
+    #[new]
+    #[pyo3(signature = (factory, nin, nouts, seed=None))]
+    pub fn new(factory: &PyValueFactory, nin: usize, nouts: Vec<usize>, seed: Option<u64>) -> PyResult<PyMLP> {
+        factory.check_open()?;
+        if nouts.is_empty() {
+            return Err(PyValueError::new_err("an MLP needs at least one layer"));
+        }
+        let model = match seed {
+            Some(seed) => MLP::new_with_seed(&factory.vf, nin, &nouts, seed),
+            None => MLP::new(&factory.vf, nin, &nouts)
+        };
+        Ok(PyMLP { model, vf: factory.vf.clone() })
+    }
+
+    // Like micrograd, a single output comes back as a Value rather than a one element list
+    fn __call__<'py>(&self, py: Python<'py>, x: Vec<Operand>) -> PyResult<Bound<'py, PyAny>> {
+        self.check_alive()?;
+        if x.len() != self.model.nin() {
+            return Err(PyValueError::new_err(format!("expected {} inputs, got {}", self.model.nin(), x.len())));
+        }
+        let x = x.into_iter().map(|xi| match xi {
+            Operand::Value(v) => v.live().cloned(),
+            Operand::Scalar(v) => Ok(self.vf.value(v))
+        }).collect::<PyResult<Vec<Value>>>()?;
+        let mut out = self.model.call(&x);
+        if out.len() == 1 {
+            return Ok(Bound::new(py, PyValue { value: out.remove(0) })?.into_any());
+        }
+        let out = out.into_iter().map(|value| Bound::new(py, PyValue { value })).collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new(py, out)?.into_any())
+    }
+
+    pub fn parameters(&self) -> PyResult<Vec<PyValue>> {
+        self.check_alive()?;
+        Ok(self.model.parameters().map(|p| PyValue { value: p.clone() }).collect())
+    }
+
+    pub fn zero_grad(&self) -> PyResult<()> {
+        self.check_alive()?;
+        self.model.zero_grad();
+        Ok(())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("MLP(nin={}, nout={}, parameters={})", self.model.nin(), self.model.nout(), self.model.num_parameters())
+    }
+}
+
+impl PyMLP {
+    pub fn inner(&self) -> &MLP {
+        &self.model
+    }
+
+    fn check_alive(&self) -> PyResult<()> {
+        self.model.parameters().next().map_or(Ok(()), check_alive)
+    }
+}
+
+#[pymodule]
+pub fn rust_micrograd(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyValueFactory>()?;
+    m.add_class::<PyValue>()?;
+    m.add_class::<PyMLP>()?;
+    Ok(())
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+    use std::ffi::CString;
+
+    // Runs src with the module's classes in scope and returns the resulting globals
+    fn run<'py>(py: Python<'py>, src: &str) -> PyResult<Bound<'py, PyDict>> {
+        let module = PyModule::new(py, "rust_micrograd")?;
+        rust_micrograd(&module)?;
+        let globals = module.dict().copy()?;
+        py.run(&CString::new(src).unwrap(), Some(&globals), None)?;
+        Ok(globals)
+    }
+
+    fn get(globals: &Bound<'_, PyDict>, expr: &str) -> f64 {
+        globals.py().eval(&CString::new(expr).unwrap(), Some(globals), None).unwrap().extract().unwrap()
+    }
+
+    #[test]
+    fn micrograd_example() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = run(py, r#"
+vf = ValueFactory()
+a = vf.value(-4.0)
+b = vf.value(2.0)
+c = a + b
+d = a * b + b**3
+c += c + 1
+c += 1 + c + (-a)
+d += d * 2 + (b + a).relu()
+d += 3 * d + (b - a).relu()
+e = c - d
+f = e**2
+g = f / 2.0
+g += 10.0 / f
+g.backward()
+"#).unwrap();
+            assert!((get(&globals, "g.data") - 24.7041).abs() < 1e-4);
+            assert!((get(&globals, "a.grad") - 138.8338).abs() < 1e-4);
+            assert!((get(&globals, "b.grad") - 645.5773).abs() < 1e-4);
+        });
+    }
+
+    #[test]
+    fn mlp_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = run(py, r#"
+vf = ValueFactory()
+model = MLP(vf, 2, [4, 1], seed=7)
+out = model([1.0, vf.value(-2.0)])
+out.backward()
+n_params = len(model.parameters())
+grads_before = sum(abs(p.grad) for p in model.parameters())
+model.zero_grad()
+grads_after = sum(abs(p.grad) for p in model.parameters())
+"#).unwrap();
+            assert_eq!(get(&globals, "n_params"), 17.0);
+            assert!(get(&globals, "grads_before") > 0.0);
+            assert_eq!(get(&globals, "grads_after"), 0.0);
+
+            let model: PyRef<PyMLP> = globals.get_item("model").unwrap().unwrap().extract().unwrap();
+            let expected = model.inner().predict(&[1.0, -2.0])[0];
+            assert!((get(&globals, "out.data") - expected).abs() < 1e-12);
+        });
+    }
+
+    #[test]
+    fn closed_factory_raises() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = run(py, r#"
+vf = ValueFactory()
+a = vf.value(3.0)
+model = vf.mlp(2, [1])
+vf.close()
+"#).unwrap();
+            ["a.data", "a + 1", "a.backward()", "model([1.0, 2.0])", "vf.value(1.0)"].iter().for_each(|expr| {
+                let err = py.eval(&CString::new(*expr).unwrap(), Some(&globals), None).unwrap_err();
+                assert!(err.is_instance_of::<PyRuntimeError>(py), "{}: {}", expr, err);
+            });
+            assert_eq!(get(&globals, "1.0 if vf.closed else 0.0"), 1.0);
+        });
+    }
+}
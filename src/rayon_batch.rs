@@ -0,0 +1,60 @@
+use std::iter::zip;
+use rayon::prelude::*;
+use crate::{MlpSpec, MLP};
+
+impl MLP {
+    // Graph-free inference over a batch on the current rayon pool. The Values cannot leave this thread, so
+    // the parameter data is copied once into an MlpSpec that the workers share. Outputs are in input order.
+    pub fn call_batch_parallel(&self, xs: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let spec = self.to_spec();
+        xs.par_iter().map(|x| predict_spec(&spec, x)).collect()
+    }
+}
+
+// MLP::predict on a spec, in the same operation order so the results match it exactly
+fn predict_spec(spec: &MlpSpec, x: &[f64]) -> Vec<f64> {
+    spec.layers.iter().fold(x.to_vec(), |acc, l| {
+        zip(&l.weights, &l.biases)
+            .map(|(w, b)| l.activation.apply_f64(zip(w, &acc).fold(*b, |sum, (wi, xi)| sum + wi * xi)))
+            .collect()
+    })
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Activation, Arena, Rng, ValueFactory};
+
+    #[test]
+    fn matches_predict() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let activations = [Activation::Relu, Activation::Tanh, Activation::Linear];
+        let n = MLP::new_with_activations(&vf, 3, &[8, 8, 2], &activations, &mut Rng::new(4));
+        let mut rng = Rng::new(9);
+        let xs: Vec<Vec<f64>> = (0..1000).map(|_| (0..3).map(|_| rng.uniform(-2.0, 2.0)).collect()).collect();
+        let len = vf.len();
+        let out = n.call_batch_parallel(&xs);
+        assert_eq!(vf.len(), len);
+        // exact and in input order
+        assert_eq!(out, xs.iter().map(|x| n.predict(x)).collect::<Vec<Vec<f64>>>());
+        assert!(n.call_batch_parallel(&[]).is_empty());
+    }
+
+    #[test]
+    fn more_threads_than_samples() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        // the model can't cross threads, so it is built on the pool too
+        let (out, expected) = pool.install(|| {
+            let (_arena_life_time, arena_ref) = Arena::build();
+            let vf = ValueFactory::new(arena_ref);
+            let n = MLP::new_with_seed(&vf, 2, &[4, 1], 1);
+            let xs = vec![vec![0.5, -1.0], vec![1.0, 2.0]];
+            (n.call_batch_parallel(&xs), vec![n.predict(&xs[0]), n.predict(&xs[1])])
+        });
+        assert_eq!(out, expected);
+    }
+}
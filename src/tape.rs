@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::{Float, OpKind, Value};
+
+// Operations a tape can replay. Mirrors the ops of Value, with scalar payloads inline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TapeOp {
+    Add,
+    Mul,
+    Relu,
+    Tanh,
+    Exp,
+    Ln,
+    Sigmoid,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Max,
+    Min,
+    Pow,
+    Sinh,
+    Cosh,
+    Sqrt,
+    Cbrt,
+    Abs,
+    Sign,
+    Floor,
+    Ceil,
+    Round,
+    RoundSte,
+    Exp2,
+    Log2,
+    Log10,
+    Powi(i32),
+    Powf(f64),
+    AddScalar(f64),
+    MulScalar(f64),
+    MaxScalar(f64),
+    MinScalar(f64),
+    Clamp(f64, f64)
+}
+
+impl TapeOp {
+    // None for custom ops
+    pub(crate) fn from_op(op: OpKind) -> Option<TapeOp> {
+        match op {
+            OpKind::Add => Some(TapeOp::Add),
+            OpKind::Mul => Some(TapeOp::Mul),
+            OpKind::Relu => Some(TapeOp::Relu),
+            OpKind::Tanh => Some(TapeOp::Tanh),
+            OpKind::Exp => Some(TapeOp::Exp),
+            OpKind::Ln => Some(TapeOp::Ln),
+            OpKind::Sigmoid => Some(TapeOp::Sigmoid),
+            OpKind::Sin => Some(TapeOp::Sin),
+            OpKind::Cos => Some(TapeOp::Cos),
+            OpKind::Tan => Some(TapeOp::Tan),
+            OpKind::Asin => Some(TapeOp::Asin),
+            OpKind::Acos => Some(TapeOp::Acos),
+            OpKind::Atan => Some(TapeOp::Atan),
+            OpKind::Atan2 => Some(TapeOp::Atan2),
+            OpKind::Max => Some(TapeOp::Max),
+            OpKind::Min => Some(TapeOp::Min),
+            OpKind::Pow => Some(TapeOp::Pow),
+            OpKind::Sinh => Some(TapeOp::Sinh),
+            OpKind::Cosh => Some(TapeOp::Cosh),
+            OpKind::Sqrt => Some(TapeOp::Sqrt),
+            OpKind::Cbrt => Some(TapeOp::Cbrt),
+            OpKind::Abs => Some(TapeOp::Abs),
+            OpKind::Sign => Some(TapeOp::Sign),
+            OpKind::Floor => Some(TapeOp::Floor),
+            OpKind::Ceil => Some(TapeOp::Ceil),
+            OpKind::Round => Some(TapeOp::Round),
+            OpKind::RoundSte => Some(TapeOp::RoundSte),
+            OpKind::Exp2 => Some(TapeOp::Exp2),
+            OpKind::Log2 => Some(TapeOp::Log2),
+            OpKind::Log10 => Some(TapeOp::Log10),
+            OpKind::PowI(n) => Some(TapeOp::Powi(n)),
+            OpKind::PowF(x) => Some(TapeOp::Powf(x)),
+            OpKind::AddScalar(x) => Some(TapeOp::AddScalar(x)),
+            OpKind::MulScalar(x) => Some(TapeOp::MulScalar(x)),
+            OpKind::MaxScalar(x) => Some(TapeOp::MaxScalar(x)),
+            OpKind::MinScalar(x) => Some(TapeOp::MinScalar(x)),
+            OpKind::Clamp(lo, hi) => Some(TapeOp::Clamp(lo, hi)),
+            OpKind::Custom(_) => None
+        }
+    }
+
+    // From the op label of a graph dump
+    pub(crate) fn parse(op: &str) -> Option<TapeOp> {
+        match op {
+            "+" => Some(TapeOp::Add),
+            "*" => Some(TapeOp::Mul),
+            "ReLU" => Some(TapeOp::Relu),
+            "tanh" => Some(TapeOp::Tanh),
+            "exp" => Some(TapeOp::Exp),
+            "ln" => Some(TapeOp::Ln),
+            "sigmoid" => Some(TapeOp::Sigmoid),
+            "sin" => Some(TapeOp::Sin),
+            "cos" => Some(TapeOp::Cos),
+            "tan" => Some(TapeOp::Tan),
+            "asin" => Some(TapeOp::Asin),
+            "acos" => Some(TapeOp::Acos),
+            "atan" => Some(TapeOp::Atan),
+            "atan2" => Some(TapeOp::Atan2),
+            "max" => Some(TapeOp::Max),
+            "min" => Some(TapeOp::Min),
+            "pow" => Some(TapeOp::Pow),
+            "sinh" => Some(TapeOp::Sinh),
+            "cosh" => Some(TapeOp::Cosh),
+            "sqrt" => Some(TapeOp::Sqrt),
+            "cbrt" => Some(TapeOp::Cbrt),
+            "abs" => Some(TapeOp::Abs),
+            "sign" => Some(TapeOp::Sign),
+            "floor" => Some(TapeOp::Floor),
+            "ceil" => Some(TapeOp::Ceil),
+            "round" => Some(TapeOp::Round),
+            "round_ste" => Some(TapeOp::RoundSte),
+            "exp2" => Some(TapeOp::Exp2),
+            "log2" => Some(TapeOp::Log2),
+            "log10" => Some(TapeOp::Log10),
+            _ => op.strip_prefix("powi").and_then(|n| n.parse().ok()).map(TapeOp::Powi)
+                .or_else(|| op.strip_prefix("powf").and_then(|x| x.parse().ok()).map(TapeOp::Powf))
+                .or_else(|| op.strip_prefix('+').and_then(|x| x.parse().ok()).map(TapeOp::AddScalar))
+                .or_else(|| op.strip_prefix('*').and_then(|x| x.parse().ok()).map(TapeOp::MulScalar))
+                .or_else(|| op.strip_prefix("max").and_then(|x| x.parse().ok()).map(TapeOp::MaxScalar))
+                .or_else(|| op.strip_prefix("min").and_then(|x| x.parse().ok()).map(TapeOp::MinScalar))
+                .or_else(|| {
+                    let (lo, hi) = op.strip_prefix("clamp[")?.strip_suffix(']')?.split_once(',')?;
+                    Some(TapeOp::Clamp(lo.parse().ok()?, hi.parse().ok()?))
+                })
+        }
+    }
+
+    pub(crate) fn arity(&self) -> usize {
+        match self {
+            TapeOp::Add | TapeOp::Mul | TapeOp::Atan2 | TapeOp::Max | TapeOp::Min | TapeOp::Pow => 2,
+            _ => 1
+        }
+    }
+
+    // Same arithmetic as the corresponding Value op so replays agree bit for bit
+    pub(crate) fn apply(&self, a: f64, b: f64) -> f64 {
+        match self {
+            TapeOp::Add => a + b,
+            TapeOp::Mul => a * b,
+            TapeOp::Relu => if a < 0.0 {0.0} else {a},
+            TapeOp::Tanh => ((2.0*a).exp() - 1.0) / ((2.0*a).exp() + 1.0),
+            TapeOp::Exp => a.exp(),
+            TapeOp::Ln => a.ln(),
+            TapeOp::Sigmoid => a.sigmoid(),
+            TapeOp::Sin => a.sin(),
+            TapeOp::Cos => a.cos(),
+            TapeOp::Tan => a.tan(),
+            TapeOp::Asin => a.asin(),
+            TapeOp::Acos => a.acos(),
+            TapeOp::Atan => a.atan(),
+            TapeOp::Atan2 => a.atan2(b),
+            TapeOp::Max => a.max(b),
+            TapeOp::Min => a.min(b),
+            TapeOp::Pow => a.powf(b),
+            TapeOp::Sinh => a.sinh(),
+            TapeOp::Cosh => a.cosh(),
+            TapeOp::Sqrt => a.sqrt(),
+            TapeOp::Cbrt => a.cbrt(),
+            TapeOp::Abs => a.abs(),
+            TapeOp::Sign => a.sign(),
+            TapeOp::Floor => a.floor(),
+            TapeOp::Ceil => a.ceil(),
+            TapeOp::Round => a.round(),
+            TapeOp::RoundSte => a.round(),
+            TapeOp::Exp2 => a.exp2(),
+            TapeOp::Log2 => a.log2(),
+            TapeOp::Log10 => a.log10(),
+            TapeOp::Powi(n) => a.powi(*n),
+            TapeOp::Powf(x) => a.powf(*x),
+            TapeOp::AddScalar(x) => a + x,
+            TapeOp::MulScalar(x) => a * x,
+            TapeOp::MaxScalar(x) => a.max(*x),
+            TapeOp::MinScalar(x) => a.min(*x),
+            TapeOp::Clamp(lo, hi) => a.max(*lo).min(*hi)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Instruction {
+    pub op: TapeOp,
+    // slots read by the op, one per operand
+    pub inputs: Vec<usize>
+}
+
+// Forward pass of a graph as plain data. Slots 0..leaves.len() hold the leaves, instruction i writes slot
+// leaves.len() + i, and the result is the last slot.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tape {
+    // leaf values at export time
+    pub leaves: Vec<f64>,
+    pub instructions: Vec<Instruction>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapeError(pub String);
+
+impl fmt::Display for TapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot record tape: {}", self.0)
+    }
+}
+
+impl std::error::Error for TapeError {}
+
+impl Tape {
+    // Replays the tape with the given leaf values (in the order of Tape::leaves)
+    pub fn execute(&self, inputs: &[f64]) -> f64 {
+        assert_eq!(inputs.len(), self.leaves.len(), "Tape has {} leaves but got {} inputs", self.leaves.len(), inputs.len());
+        let mut slots = inputs.to_vec();
+        slots.reserve(self.instructions.len());
+        self.instructions.iter().for_each(|ins| {
+            let a = slots[ins.inputs[0]];
+            let b = ins.inputs.get(1).map_or(0.0, |&i| slots[i]);
+            slots.push(ins.op.apply(a, b));
+        });
+        *slots.last().expect("Empty tape")
+    }
+}
+
+impl Value {
+    pub fn export_tape(&self) -> Result<Tape, TapeError> {
+        self.export_tape_with_leaves().map(|(tape, _)| tape)
+    }
+
+    // Also returns the leaf Values, in slot order, so callers can tell which input is which
+    pub fn export_tape_with_leaves(&self) -> Result<(Tape, Vec<Value>), TapeError> {
+        // post order DFS, operands before the node that uses them
+        fn visit(v: &Value, seen: &mut HashMap<Value, usize>, order: &mut Vec<(Value, Option<OpKind>, Vec<Value>)>) {
+            if seen.contains_key(v) {
+                return;
+            }
+            let (op, children) = v.op_and_children();
+            children.iter().for_each(|c| visit(c, seen, order));
+            seen.insert(v.clone(), order.len());
+            order.push((v.clone(), op, children));
+        }
+        let mut order = Vec::new();
+        visit(self, &mut HashMap::new(), &mut order);
+
+        let mut slot: HashMap<Value, usize> = HashMap::new();
+        let leaves: Vec<Value> = order.iter().filter(|(_, op, _)| op.is_none()).map(|(v, _, _)| v.clone()).collect();
+        leaves.iter().enumerate().for_each(|(i, v)| {slot.insert(v.clone(), i);});
+
+        let mut instructions = Vec::new();
+        for (v, op, children) in order.iter().filter_map(|(v, op, children)| Some((v, (*op)?, children))) {
+            let op = TapeOp::from_op(op).ok_or_else(|| TapeError(format!("unsupported op {:?}", op.to_string())))?;
+            // operands are stored deduplicated, so x * x has a single child
+            let inputs: Vec<usize> = (0..op.arity()).map(|i| slot[children.get(i).unwrap_or(&children[0])]).collect();
+            slot.insert(v.clone(), leaves.len() + instructions.len());
+            instructions.push(Instruction { op, inputs });
+        }
+        let tape = Tape { leaves: leaves.iter().map(|v| v.get_data()).collect(), instructions };
+        Ok((tape, leaves))
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arena, ValueFactory};
+
+    #[test]
+    fn tape_matches_graph() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(-4.0);
+        let b = vf.value(2.0);
+        let exprs = [
+            &(&a * &b) + &b.powi(3),
+            (&a * &a).tanh(),
+            &(&a + 2.0).relu() / &b,
+            &(&b.powf(0.5) - &a).exp() * 0.25,
+            &(&b.exp() + 1.0).ln() * &a.sigmoid(),
+            &a.sin() + &(&a * &b).cos(),
+            (&b * 0.5).tan(),
+            &(&b * 0.25).asin() + &(&(&b * 0.25).acos() * &a.atan()),
+            b.atan2(&(&a - 3.0)),
+            &a.sinh() * &b.cosh(),
+            (&b + 0.25).sqrt(),
+            (&a - 9.0).cbrt(),
+            &(&a - &b).abs() * 2.0,
+            &(&a - 3.0).sign() * &b,
+            &(&a * 2.7).floor() + &(&(&b * 0.3).ceil() * &(&a - 0.5).round()),
+            (&b * 1.6).round_ste(),
+            &a.max(&b).min_scalar(1.5) + &b.min(&a).max_scalar(-0.5),
+            &(&a * 3.0).clamp(-0.5, 2.5) - &b.clamp(0.0, 1.0),
+            b.pow(&(&a - 0.5)),
+            &a.exp2() * &b.log2(),
+            (&b * 50.0).log10(),
+            a.clone()
+        ];
+        exprs.iter().for_each(|e| {
+            let tape: Tape = e.export_tape().unwrap();
+            assert_eq!(tape.execute(&tape.leaves), e.get_data());
+        });
+    }
+
+    #[test]
+    fn tape_with_new_inputs() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let build = |x: f64, y: f64| {
+            let (x, y) = (vf.value(x), vf.value(y));
+            let out = &(&(&x * &y) + &x.tanh()) / &(&y.powi(2) + 1.0);
+            (out, x, y)
+        };
+
+        let (out, x, y) = build(0.5, -1.5);
+        let (tape, leaves) = out.export_tape_with_leaves().unwrap();
+        let (x_slot, y_slot) = (leaves.iter().position(|l| *l == x).unwrap(), leaves.iter().position(|l| *l == y).unwrap());
+        for (nx, ny) in [(2.0, 3.0), (-0.25, 0.0), (1e-3, -7.0)] {
+            let mut inputs = tape.leaves.clone();
+            inputs[x_slot] = nx;
+            inputs[y_slot] = ny;
+            assert_eq!(tape.execute(&inputs), build(nx, ny).0.get_data());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn tape_json_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(0.3);
+        let tape = (&a.powf(1.5) * &a.exp()).relu().export_tape().unwrap();
+        let json = serde_json::to_string(&tape).unwrap();
+        let back: Tape = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, tape);
+        assert_eq!(back.execute(&[0.7]), tape.execute(&[0.7]));
+    }
+}
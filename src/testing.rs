@@ -0,0 +1,81 @@
+// Assertions for tests comparing Values against expected floats within a tolerance, e.g.
+// assert_value_close!(y, 0.7616, 1e-4) or assert_grads_close!(model.parameters(), &grads, 1e-9). A failure
+// reports what was compared, the difference, and the op and label of the node.
+use std::iter::zip;
+use crate::{Float, ValueG};
+
+#[macro_export]
+macro_rules! assert_value_close {
+    ($value:expr, $expected:expr, $tol:expr) => {{
+        let value = &$value;
+        $crate::check_close("data", value, value.get_data(), $expected, $tol)
+    }};
+}
+
+#[macro_export]
+macro_rules! assert_grad_close {
+    ($value:expr, $expected:expr, $tol:expr) => {{
+        let value = &$value;
+        $crate::check_close("grad", value, value.get_grad(), $expected, $tol)
+    }};
+}
+
+// params yields &Value, e.g. model.parameters() or &vec
+#[macro_export]
+macro_rules! assert_grads_close {
+    ($params:expr, $expected:expr, $tol:expr) => {
+        $crate::check_grads_close($params, $expected, $tol)
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check_close<T: Float>(what: &str, value: &ValueG<T>, got: T, expected: f64, tol: f64) {
+    let got = got.to_f64();
+    let diff = (got - expected).abs();
+    if diff > tol || diff.is_nan() {
+        let label = value.label().map_or(String::new(), |l| format!(", label {:?}", l));
+        let op = value.op().map_or(String::from("leaf"), |op| format!("op {}", op));
+        panic!("{} not within {:e}: got {}, expected {}, diff {:e} ({}{})", what, tol, got, expected, diff, op, label);
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn check_grads_close<'a, T: Float>(params: impl IntoIterator<Item = &'a ValueG<T>>, expected: &[f64], tol: f64) {
+    let params: Vec<&ValueG<T>> = params.into_iter().collect();
+    assert_eq!(params.len(), expected.len(), "{} parameters but {} expected grads", params.len(), expected.len());
+    zip(params, expected).enumerate().for_each(|(i, (p, e))| check_close(&format!("grad of parameter {}", i), p, p.get_grad(), *e, tol));
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use crate::{Arena, Value, ValueFactory};
+
+    #[test]
+    fn close_values_pass() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let ws: Vec<Value> = vf.values(&[0.5, -2.0]);
+        let y = (&(&ws[0] * &ws[1]) + 1.0).tanh();
+        y.backward();
+        let t = 0.0f64.tanh();
+        assert_value_close!(y, t, 1e-12);
+        assert_grad_close!(y, 1.0, 0.0);
+        assert_grads_close!(&ws, &[-2.0 * (1.0 - t * t), 0.5 * (1.0 - t * t)], 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "data not within 1e-6: got 0.5, expected 0.501, diff 1.0000000000000009e-3 (op *2, label \"y\")")]
+    fn failure_reports_diff() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let y = &vf.value(0.25) * 2.0;
+        y.set_label("y");
+        assert_value_close!(y, 0.501, 1e-6);
+    }
+}
@@ -0,0 +1,170 @@
+// Debug logging of the engine through the log crate, behind the trace feature. Op nodes being created,
+// labels being set and backward passes are logged at debug level; non-finite data or grads at warn level,
+// whatever the filter. Any log implementation (env_logger, ...) shows them.
+use std::cell::RefCell;
+use log::{debug, log_enabled, warn, Level};
+use crate::{Float, OpKind};
+
+// Which nodes get debug events. labeled_only looks at the label a node has when the event happens, so
+// op nodes, which are created unlabeled, only show up through set_label.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    pub labeled_only: bool,
+    // op names as draw_dot shows them ("+", "tanh", "powi2", ...); None for every op
+    pub ops: Option<Vec<String>>
+}
+
+impl TraceFilter {
+    fn accepts(&self, op: Option<OpKind>, label: Option<&str>) -> bool {
+        let op_ok = match (&self.ops, op) {
+            (None, _) => true,
+            (Some(ops), Some(op)) => ops.contains(&op.to_string()),
+            (Some(_), None) => false
+        };
+        op_ok && (label.is_some() || !self.labeled_only)
+    }
+}
+
+thread_local! {
+    static FILTER: RefCell<TraceFilter> = RefCell::new(TraceFilter::default());
+}
+
+// Applies to the graphs of the calling thread, like the arenas themselves
+pub fn set_trace_filter(filter: TraceFilter) {
+    FILTER.with(|f| *f.borrow_mut() = filter);
+}
+
+fn accepted(op: Option<OpKind>, label: Option<&str>) -> bool {
+    log_enabled!(Level::Debug) && FILTER.with(|f| f.borrow().accepts(op, label))
+}
+
+pub(crate) fn node_created<T: Float>(index: usize, op: OpKind, children: impl Iterator<Item = usize>, data: T) {
+    if !data.to_f64().is_finite() {
+        warn!("node #{} (op={}) has non-finite data {}", index, op, data);
+    }
+    if accepted(Some(op), None) {
+        let children: Vec<String> = children.map(|c| format!("#{}", c)).collect();
+        debug!("node #{} created: op={}, children [{}], data {}", index, op, children.join(", "), data);
+    }
+}
+
+pub(crate) fn labeled<T: Float>(index: usize, op: Option<OpKind>, label: &str, data: T) {
+    if accepted(op, Some(label)) {
+        let op = op.map_or(String::from("leaf"), |op| format!("op={}", op));
+        debug!("node #{} ({}) labeled {:?}, data {}", index, op, label, data);
+    }
+}
+
+pub(crate) fn backward_started(index: usize, nodes: usize) {
+    debug!("backward from node #{}: {} nodes, seed grad 1", index, nodes);
+}
+
+pub(crate) fn backward_finished(index: usize) {
+    debug!("backward from node #{} done", index);
+}
+
+// description as in the engine's panic messages
+pub(crate) fn non_finite_grad<T: Float>(description: &str, grad: T) {
+    warn!("{} has non-finite grad {}", description, grad);
+}
+
+pub(crate) fn warn_enabled() -> bool {
+    log_enabled!(Level::Warn)
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+    use log::{Log, Metadata, Record};
+    use crate::{Arena, ValueFactory};
+
+    // Keeps the records of each thread apart, since tests run in parallel
+    struct TestLogger;
+
+    thread_local! {
+        static RECORDS: RefCell<Vec<(Level, String)>> = const { RefCell::new(Vec::new()) };
+    }
+
+    impl Log for TestLogger {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let _ = RECORDS.try_with(|r| r.borrow_mut().push((record.level(), record.args().to_string())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: TestLogger = TestLogger;
+    static INIT: Once = Once::new();
+
+    fn records<R>(f: impl FnOnce() -> R) -> (R, Vec<(Level, String)>) {
+        INIT.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Debug);
+        });
+        RECORDS.with(|r| r.borrow_mut().clear());
+        let out = f();
+        (out, RECORDS.with(|r| r.take()))
+    }
+
+    #[test]
+    fn records_forward_and_backward() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        set_trace_filter(TraceFilter::default());
+        let (a, b) = (vf.value(2.0), vf.value(-3.0));
+        let (y, log) = records(|| {
+            let y = (&a * &b).tanh();
+            y.set_label("y");
+            y.backward();
+            y
+        });
+        // a fresh arena numbers the nodes from 0: a, b, a * b, y
+        assert_eq!(y.get_data(), (-6.0f64).tanh());
+        let debug = |s: &str| (Level::Debug, s.replace("{t}", &(-6.0f64).tanh().to_string()));
+        assert_eq!(log, vec![
+            debug("node #2 created: op=*, children [#0, #1], data -6"),
+            debug("node #3 created: op=tanh, children [#2], data {t}"),
+            debug("node #3 (op=tanh) labeled \"y\", data {t}"),
+            debug("backward from node #3: 4 nodes, seed grad 1"),
+            debug("backward from node #3 done")
+        ]);
+
+        // non-finite values are warned about
+        let (_, log) = records(|| {
+            let z = vf.value(0.0).powi(-1);
+            z.backward();
+        });
+        assert!(log.iter().any(|(level, msg)| *level == Level::Warn && msg.ends_with("has non-finite data inf")));
+        assert!(log.iter().any(|(level, msg)| *level == Level::Warn && msg.contains("has non-finite grad")));
+    }
+
+    #[test]
+    fn filter() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let a = vf.value(0.5);
+        set_trace_filter(TraceFilter { labeled_only: true, ops: None });
+        let (_, log) = records(|| {
+            let h = (&a * 2.0).tanh();
+            h.set_label("h1");
+            &h + 1.0
+        });
+        assert_eq!(log.len(), 1);
+        assert!(log[0].1.ends_with("(op=tanh) labeled \"h1\", data 0.7615941559557649"));
+
+        set_trace_filter(TraceFilter { labeled_only: false, ops: Some(vec![String::from("tanh")]) });
+        let (_, log) = records(|| (&a * 2.0).tanh());
+        assert_eq!(log.len(), 1);
+        assert!(log[0].1.contains("created: op=tanh"));
+        set_trace_filter(TraceFilter::default());
+    }
+}
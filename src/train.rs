@@ -0,0 +1,758 @@
+use std::time::Instant;
+use crate::{BackwardBuffers, BatchIter, Callback, CallbackAction, CompiledGraph, ConsoleLogger, Dataset, EpochRecord, Fold, History, Module, Optimizer, Snapshot, Value, ValueFactory, MLP};
+
+// Per-sample loss between a prediction and its target. The Trainer averages it over each batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Loss {
+    // mean over outputs of (pred - target)^2
+    Mse
+}
+
+impl Loss {
+    pub fn value(&self, pred: &[Value], target: &[Value]) -> Value {
+        match self {
+            Loss::Mse => {
+                let sum = pred.iter()
+                    .zip(target)
+                    .map(|(p, t)| (p - t).powi(2))
+                    .reduce(|acc, v| &acc + &v)
+                    .expect("Loss of an empty prediction");
+                &sum / pred.len() as f64
+            }
+        }
+    }
+
+    pub fn eval(&self, pred: &[f64], target: &[f64]) -> f64 {
+        match self {
+            Loss::Mse => {
+                pred.iter().zip(target).map(|(p, t)| (p - t).powi(2)).sum::<f64>() / pred.len() as f64
+            }
+        }
+    }
+}
+
+// Stop when the validation loss hasn't improved by more than min_delta for patience epochs.
+// With restore_best the parameters from the best epoch are loaded back when training ends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EarlyStopping {
+    pub patience: usize,
+    pub min_delta: f64,
+    pub restore_best: bool
+}
+
+#[derive(Clone, Debug)]
+pub struct TrainerOptions {
+    pub epochs: usize,
+    // 0 or anything larger than the dataset means full batch
+    pub batch_size: usize,
+    // None keeps the dataset order, Some(seed) reshuffles every epoch
+    pub shuffle_seed: Option<u64>,
+    // print the training loss every log_every epochs, 0 disables logging
+    pub log_every: usize,
+    // only active when there is something to validate on
+    pub early_stopping: Option<EarlyStopping>,
+    // Snapshot the parameters and optimizer state at the start of every epoch. A batch with a NaN or
+    // infinite loss puts both back, halves the lr and ends the epoch there.
+    pub rollback_on_nonfinite_loss: bool
+}
+
+impl Default for TrainerOptions {
+    fn default() -> TrainerOptions {
+        TrainerOptions { epochs: 100, batch_size: 0, shuffle_seed: None, log_every: 0, early_stopping: None, rollback_on_nonfinite_loss: false }
+    }
+}
+
+type ForwardFn<'a> = Box<dyn Fn(&[Value]) -> Vec<Value> + 'a>;
+type PredictFn<'a> = Box<dyn Fn(&[f64]) -> Vec<f64> + 'a>;
+type ValMetricFn<'a> = Box<dyn FnMut(usize) -> f64 + 'a>;
+
+pub struct Trainer<'a, M: Module> {
+    model: &'a M,
+    forward: ForwardFn<'a>,
+    predict: Option<PredictFn<'a>>,
+    val_metric: Option<ValMetricFn<'a>>,
+    optimizer: Box<dyn Optimizer + 'a>,
+    loss: Loss,
+    vf: &'a ValueFactory,
+    options: TrainerOptions,
+    // reused by every batch
+    buffers: BackwardBuffers
+}
+
+impl<'a, M: Module> Trainer<'a, M> {
+    pub fn new(
+        model: &'a M,
+        forward: impl Fn(&[Value]) -> Vec<Value> + 'a,
+        optimizer: impl Optimizer + 'a,
+        loss: Loss,
+        vf: &'a ValueFactory,
+        options: TrainerOptions
+    ) -> Trainer<'a, M> {
+        Trainer { model, forward: Box::new(forward), predict: None, val_metric: None, optimizer: Box::new(optimizer), loss, vf, options, buffers: BackwardBuffers::default() }
+    }
+
+    // Graph-free forward used for validation. Without it validation goes through the Value forward.
+    pub fn set_predict(&mut self, predict: impl Fn(&[f64]) -> Vec<f64> + 'a) {
+        self.predict = Some(Box::new(predict));
+    }
+
+    // Replaces the validation loss with metric(epoch), e.g. for a custom metric or a scripted sequence
+    pub fn set_val_metric(&mut self, metric: impl FnMut(usize) -> f64 + 'a) {
+        self.val_metric = Some(Box::new(metric));
+    }
+
+    pub fn optimizer(&mut self) -> &mut dyn Optimizer {
+        self.optimizer.as_mut()
+    }
+
+    pub fn model(&self) -> &M {
+        self.model
+    }
+
+    pub fn fit(&mut self, xs: &[Vec<f64>], ys: &[Vec<f64>]) -> History {
+        self.fit_with_validation(xs, ys, &[], &[])
+    }
+
+    pub fn fit_with_validation(
+        &mut self,
+        xs: &[Vec<f64>],
+        ys: &[Vec<f64>],
+        val_xs: &[Vec<f64>],
+        val_ys: &[Vec<f64>]
+    ) -> History {
+        self.fit_with_callbacks(xs, ys, val_xs, val_ys, Vec::new())
+    }
+
+    pub fn fit_with_callbacks(
+        &mut self,
+        xs: &[Vec<f64>],
+        ys: &[Vec<f64>],
+        val_xs: &[Vec<f64>],
+        val_ys: &[Vec<f64>],
+        mut callbacks: Vec<Box<dyn Callback + '_>>
+    ) -> History {
+        assert_eq!(xs.len(), ys.len(), "xs and ys must have the same length");
+        assert_eq!(val_xs.len(), val_ys.len(), "val_xs and val_ys must have the same length");
+        assert!(!xs.is_empty(), "Cannot fit on an empty dataset");
+
+        let batch_size = match self.options.batch_size {
+            0 => xs.len(),
+            b => b.min(xs.len())
+        };
+        let shuffle_seed = self.options.shuffle_seed;
+        let mut batches = BatchIter::new(xs, ys, batch_size, shuffle_seed.is_some(), shuffle_seed.unwrap_or(0))
+            .expect("Invalid training data");
+        let mut history = History::default();
+        let mut stopper = self.options.early_stopping.map(EarlyStopper::new);
+        if self.options.log_every > 0 {
+            callbacks.push(Box::new(ConsoleLogger::new(self.options.log_every)));
+        }
+
+        for epoch in 0..self.options.epochs {
+            let start = Instant::now();
+            callbacks.iter_mut().for_each(|c| c.on_epoch_start(epoch));
+            if epoch > 0 {
+                batches.next_epoch();
+            }
+            let snapshot = self.options.rollback_on_nonfinite_loss.then(|| (Snapshot::take(self.model.parameters()), self.optimizer.state_dict()));
+            let mut loss_sum = 0.0;
+            for (batch, (bx, by)) in batches.by_ref().enumerate() {
+                let loss = self.train_batch(&bx, &by);
+                callbacks.iter_mut().for_each(|c| c.on_batch_end(epoch, batch, loss));
+                loss_sum += loss * bx.len() as f64;
+                if let (false, Some((params, state))) = (loss.is_finite(), &snapshot) {
+                    let lr = self.optimizer.get_lr();
+                    params.restore().expect("Model parameters changed during training");
+                    self.optimizer.load_state_dict(state).expect("Optimizer changed during training");
+                    self.optimizer.set_lr(lr / 2.0);
+                    break;
+                }
+            }
+            let epoch_loss = loss_sum / xs.len() as f64;
+
+            let val_loss = match self.val_metric.as_mut() {
+                Some(metric) => Some(metric(epoch)),
+                None if !val_xs.is_empty() => Some(self.evaluate(val_xs, val_ys)),
+                None => None
+            };
+
+            history.record(EpochRecord {
+                epoch,
+                train_loss: epoch_loss,
+                val_loss,
+                lr: Some(self.optimizer.get_lr()),
+                duration: start.elapsed(),
+                metrics: Vec::new()
+            });
+
+            // every callback sees the epoch end, even after one of them asked to stop
+            let actions: Vec<CallbackAction> = callbacks.iter_mut().map(|c| c.on_epoch_end(epoch, &history)).collect();
+            let mut stop = actions.contains(&CallbackAction::Stop);
+            if let (Some(val_loss), Some(stopper)) = (val_loss, stopper.as_mut()) {
+                stop |= stopper.should_stop(val_loss, self.model);
+            }
+            if stop {
+                history.stopped_epoch = Some(epoch);
+                break;
+            }
+        }
+
+        if let Some(stopper) = stopper {
+            stopper.restore(self.model);
+        }
+        callbacks.iter_mut().for_each(|c| c.on_train_end(&history));
+        history
+    }
+
+    fn train_batch(&mut self, bx: &[&[f64]], by: &[&[f64]]) -> f64 {
+        let loss = bx.iter()
+            .zip(by)
+            .map(|(x, y)| {
+                let pred = (self.forward)(&self.wrap(x));
+                self.loss.value(&pred, &self.wrap(y))
+            })
+            .reduce(|acc, v| &acc + &v)
+            .unwrap();
+        let loss = &loss / bx.len() as f64;
+
+        self.optimizer.zero_grad();
+        loss.backward_with(&mut self.buffers);
+        self.optimizer.step();
+        loss.get_data()
+    }
+
+    // Mean loss over a dataset, without touching the parameters
+    pub fn evaluate(&self, xs: &[Vec<f64>], ys: &[Vec<f64>]) -> f64 {
+        let total: f64 = xs.iter()
+            .zip(ys)
+            .map(|(x, y)| match &self.predict {
+                Some(predict) => self.loss.eval(&predict(x), y),
+                None => self.loss.value(&(self.forward)(&self.wrap(x)), &self.wrap(y)).get_data()
+            })
+            .sum();
+        total / xs.len() as f64
+    }
+
+    fn wrap(&self, x: &[f64]) -> Vec<Value> {
+        self.vf.values(x)
+    }
+}
+
+struct EarlyStopper {
+    config: EarlyStopping,
+    best: f64,
+    best_state: Option<Vec<f64>>,
+    wait: usize
+}
+
+impl EarlyStopper {
+    fn new(config: EarlyStopping) -> EarlyStopper {
+        EarlyStopper { config, best: f64::INFINITY, best_state: None, wait: 0 }
+    }
+
+    fn should_stop(&mut self, val_loss: f64, model: &impl Module) -> bool {
+        if val_loss < self.best - self.config.min_delta {
+            self.best = val_loss;
+            self.wait = 0;
+            if self.config.restore_best {
+                self.best_state = Some(model.state_dict());
+            }
+            return false;
+        }
+        self.wait += 1;
+        self.wait >= self.config.patience
+    }
+
+    fn restore(&self, model: &impl Module) {
+        if let Some(state) = &self.best_state {
+            model.load_state_dict(state).expect("Model parameters changed during training");
+        }
+    }
+}
+
+impl<'a> Trainer<'a, MLP> {
+    // Trainer wired to MLP::call for training and MLP::predict for validation
+    pub fn new_mlp(
+        model: &'a MLP,
+        optimizer: impl Optimizer + 'a,
+        loss: Loss,
+        vf: &'a ValueFactory,
+        options: TrainerOptions
+    ) -> Trainer<'a, MLP> {
+        let mut trainer = Trainer::new(model, |x| model.call(x), optimizer, loss, vf, options);
+        trainer.set_predict(|x| model.predict(x));
+        trainer
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossValidation {
+    // final validation loss of every fold's model
+    pub fold_metrics: Vec<f64>,
+    pub mean: f64
+}
+
+// Trains one fresh model per fold (see kfold) and evaluates it on that fold's validation indices
+pub fn cross_validate<O: Optimizer>(
+    vf: &ValueFactory,
+    data: &Dataset,
+    folds: &[Fold],
+    build_model: impl Fn(&ValueFactory) -> MLP,
+    build_optimizer: impl Fn(&MLP) -> O,
+    loss: Loss,
+    options: &TrainerOptions
+) -> CrossValidation {
+    let fold_metrics: Vec<f64> = folds
+        .iter()
+        .map(|fold| {
+            let train = Dataset::from_indices(&data.xs, &data.ys, &fold.train);
+            let val = Dataset::from_indices(&data.xs, &data.ys, &fold.val);
+            let model = build_model(vf);
+            let mut trainer = Trainer::new_mlp(&model, build_optimizer(&model), loss, vf, options.clone());
+            trainer.fit(&train.xs, &train.ys);
+            trainer.evaluate(&val.xs, &val.ys)
+        })
+        .collect();
+    let mean = fold_metrics.iter().sum::<f64>() / fold_metrics.len() as f64;
+    CrossValidation { fold_metrics, mean }
+}
+
+// Full batch training on a fixed dataset with a loss graph built only once. Inputs and targets are leaves
+// of that graph, so new data can be written into them; every step refreshes the graph with the current
+// parameters instead of rebuilding it, and the arena stops growing after construction.
+pub struct StaticTrainer<O: Optimizer> {
+    graph: CompiledGraph,
+    inputs: Vec<Vec<Value>>,
+    targets: Vec<Vec<Value>>,
+    optimizer: O
+}
+
+impl<O: Optimizer> StaticTrainer<O> {
+    pub fn new(
+        vf: &ValueFactory,
+        forward: impl Fn(&[Value]) -> Vec<Value>,
+        loss: Loss,
+        xs: &[Vec<f64>],
+        ys: &[Vec<f64>],
+        optimizer: O
+    ) -> StaticTrainer<O> {
+        assert_eq!(xs.len(), ys.len(), "xs and ys must have the same length");
+        assert!(!xs.is_empty(), "Cannot fit on an empty dataset");
+        let wrap = |rows: &[Vec<f64>]| -> Vec<Vec<Value>> {
+            rows.iter().map(|r| vf.values(r)).collect()
+        };
+        let (inputs, targets) = (wrap(xs), wrap(ys));
+        let total = inputs.iter()
+            .zip(&targets)
+            .map(|(x, y)| loss.value(&forward(x), y))
+            .reduce(|acc, v| &acc + &v)
+            .unwrap();
+        let mean = &total / xs.len() as f64;
+        let graph = mean.compile().expect("forward uses an op CompiledGraph cannot refresh");
+        StaticTrainer { graph, inputs, targets, optimizer }
+    }
+
+    // Takes effect at the next step. The shape must match the dataset the trainer was built with.
+    pub fn set_input_data(&mut self, xs: &[Vec<f64>]) {
+        Self::write(&self.inputs, xs);
+    }
+
+    pub fn set_target_data(&mut self, ys: &[Vec<f64>]) {
+        Self::write(&self.targets, ys);
+    }
+
+    fn write(leaves: &[Vec<Value>], rows: &[Vec<f64>]) {
+        assert_eq!(leaves.len(), rows.len(), "Dataset size changed");
+        for (leaf_row, row) in leaves.iter().zip(rows) {
+            assert_eq!(leaf_row.len(), row.len(), "Row length changed");
+            leaf_row.iter().zip(row).for_each(|(leaf, v)| leaf.set_data(*v));
+        }
+    }
+
+    // One optimizer step, returns the loss before the update
+    pub fn step(&mut self) -> f64 {
+        self.optimizer.zero_grad();
+        self.graph.refresh();
+        self.graph.backward();
+        self.optimizer.step();
+        self.loss()
+    }
+
+    // Loss as of the last refresh
+    pub fn loss(&self) -> f64 {
+        self.graph.root().get_data()
+    }
+
+    pub fn graph(&self) -> &CompiledGraph {
+        &self.graph
+    }
+
+    pub fn optimizer(&mut self) -> &mut O {
+        &mut self.optimizer
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{make_moons, Adam, Arena, Dropout, Rng, Sgd};
+
+    fn dataset() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let xs = vec![
+            vec![2.0, 3.0, -1.0],
+            vec![3.0, -1.0, 0.5],
+            vec![0.5, 1.0, 1.0],
+            vec![1.0, 1.0, -1.0]
+        ];
+        let ys = vec![vec![1.0], vec![-1.0], vec![-1.0], vec![1.0]];
+        (xs, ys)
+    }
+
+    // deterministic weights so two models can start from the same point
+    fn init(n: &MLP) {
+        n.parameters().enumerate().for_each(|(i, p)| p.set_data(((i * 7919) % 200) as f64 / 100.0 - 1.0));
+    }
+
+    #[test]
+    fn fit_matches_manual_loop() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 4, 1]);
+        init(&n);
+        let mut opt = Sgd::new(n.parameters().cloned().collect(), 0.1);
+        let mut manual_loss = 0.0;
+        for _ in 0..50 {
+            let loss = xs.iter()
+                .zip(&ys)
+                .map(|(x, y)| {
+                    let x: Vec<Value> = x.iter().map(|v| vf.value(*v)).collect();
+                    (&n.call(&x)[0] - y[0]).powi(2)
+                })
+                .reduce(|acc, v| &acc + &v)
+                .unwrap();
+            let loss = &loss / 4.0;
+            opt.zero_grad();
+            loss.backward();
+            opt.step();
+            manual_loss = loss.get_data();
+        }
+
+        let m = MLP::new(&vf, 3, &[4, 4, 1]);
+        init(&m);
+        let options = TrainerOptions { epochs: 50, ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&m, Sgd::new(m.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        let history = trainer.fit(&xs, &ys);
+
+        assert_eq!(history.len(), 50);
+        assert!(history.val_losses().is_empty());
+        assert!((history.losses()[49] - manual_loss).abs() < 1e-12);
+        assert!(history.lrs().iter().all(|lr| *lr == Some(0.1)));
+        n.parameters().zip(m.parameters()).for_each(|(a, b)| assert!((a.get_data() - b.get_data()).abs() < 1e-12));
+    }
+
+    #[test]
+    fn static_trainer_matches_fit() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new_with_seed(&vf, 3, &[4, 4, 1], 7);
+        let options = TrainerOptions { epochs: 100, ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Adam::new(n.parameters().cloned().collect(), 0.01), Loss::Mse, &vf, options);
+        let len = vf.len();
+        let history = trainer.fit(&xs, &ys);
+        let rebuilt_nodes = vf.len() - len;
+
+        let m = MLP::new_with_seed(&vf, 3, &[4, 4, 1], 7);
+        let opt = Adam::new(m.parameters().cloned().collect(), 0.01);
+        let len = vf.len();
+        let mut static_trainer = StaticTrainer::new(&vf, |x| m.call(x), Loss::Mse, &xs, &ys, opt);
+        let graph_nodes = vf.len() - len;
+        for expected in history.losses() {
+            assert!((static_trainer.step() - expected).abs() < 1e-9);
+        }
+        n.parameters().zip(m.parameters()).for_each(|(a, b)| assert!((a.get_data() - b.get_data()).abs() < 1e-9));
+        // fit allocated a whole graph per epoch, the static trainer one in total
+        assert_eq!(vf.len(), len + graph_nodes);
+        assert!(rebuilt_nodes >= 99 * graph_nodes);
+
+        // new targets go through the same graph
+        let flipped: Vec<Vec<f64>> = ys.iter().map(|y| vec![-y[0]]).collect();
+        let loss = static_trainer.step();
+        static_trainer.set_target_data(&flipped);
+        assert!(static_trainer.step() > loss);
+        assert_eq!(vf.len(), len + graph_nodes);
+    }
+
+    #[test]
+    fn fit_with_batches_and_validation() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 4, 1]);
+        init(&n);
+        // batch size larger than the dataset falls back to full batch
+        let options = TrainerOptions { epochs: 30, batch_size: 10, shuffle_seed: Some(3), ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        let history = trainer.fit_with_validation(&xs, &ys, &xs, &ys);
+        assert_eq!(history.val_losses().len(), 30);
+        assert!(history.losses()[29] < history.losses()[0]);
+        // validation on the training set after the last step is the loss the next step would see
+        assert!((history.val_losses()[29] - trainer.evaluate(&xs, &ys)).abs() < 1e-12);
+
+        // mini-batches
+        let options = TrainerOptions { epochs: 5, batch_size: 3, shuffle_seed: Some(3), ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        let history = trainer.fit(&xs, &ys);
+        assert_eq!(history.len(), 5);
+        assert!(history.losses().iter().all(|l| l.is_finite()));
+    }
+
+    #[test]
+    fn predict_matches_call() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new(&vf, 3, &[4, 2]);
+        let x = [0.5, -1.0, 2.0];
+        let values: Vec<Value> = x.iter().map(|v| vf.value(*v)).collect();
+        let called: Vec<f64> = n.call(&values).iter().map(|v| v.get_data()).collect();
+        called.iter().zip(n.predict(&x)).for_each(|(c, p)| assert!((c - p).abs() < 1e-12));
+    }
+
+    #[test]
+    fn early_stopping() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 4, 1]);
+        init(&n);
+        let early_stopping = EarlyStopping { patience: 3, min_delta: 0.01, restore_best: true };
+        let options = TrainerOptions { epochs: 20, early_stopping: Some(early_stopping), ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+
+        // best at epoch 2, epoch 3 doesn't beat it by min_delta, stop after 3 epochs without improvement
+        let scripted = [1.0, 0.8, 0.5, 0.495, 0.6, 0.7, 0.1, 0.1];
+        let snapshot = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let (snapshot_ref, model_ref) = (snapshot.clone(), &n);
+        trainer.set_val_metric(move |epoch| {
+            if epoch == 2 {
+                *snapshot_ref.borrow_mut() = model_ref.state_dict();
+            }
+            scripted[epoch]
+        });
+        let history = trainer.fit(&xs, &ys);
+        assert_eq!(history.stopped_epoch, Some(5));
+        assert_eq!(history.len(), 6);
+        assert_eq!(history.val_losses(), scripted[..6].to_vec());
+
+        // best weights put back
+        assert_eq!(n.state_dict(), *snapshot.borrow());
+    }
+
+    #[test]
+    fn early_stopping_disabled() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 1]);
+        let options = TrainerOptions { epochs: 8, ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        trainer.set_val_metric(|_| 1.0);
+        let history = trainer.fit(&xs, &ys);
+        assert_eq!(history.stopped_epoch, None);
+        assert_eq!(history.val_losses().len(), 8);
+    }
+
+    #[test]
+    fn state_dict_round_trip() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        let n = MLP::new(&vf, 2, &[3, 1]);
+        let state = n.state_dict();
+        assert_eq!(state.len(), 13);
+        n.parameters().for_each(|p| p.set_data(0.0));
+        n.load_state_dict(&state).unwrap();
+        assert_eq!(n.state_dict(), state);
+
+        let err = n.load_state_dict(&state[1..]).unwrap_err();
+        assert_eq!(err, crate::StateDictError { expected: 13, got: 12 });
+    }
+
+    #[test]
+    fn cross_validation_smoke() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+
+        // toy regression y = 0.5 * x0 - 0.25 * x1
+        let xs: Vec<Vec<f64>> = (0..12).map(|i| vec![(i as f64 / 6.0) - 1.0, ((i * 5) % 12) as f64 / 12.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![0.5 * x[0] - 0.25 * x[1]]).collect();
+        let data = Dataset { xs, ys };
+        let folds = crate::kfold(data.len(), 4, 1).unwrap();
+        let options = TrainerOptions { epochs: 20, ..Default::default() };
+        let cv = cross_validate(
+            &vf,
+            &data,
+            &folds,
+            |vf| MLP::new_with_seed(vf, 2, &[4, 1], 9),
+            |m| Sgd::new(m.parameters().cloned().collect(), 0.1),
+            Loss::Mse,
+            &options
+        );
+        assert_eq!(cv.fold_metrics.len(), 4);
+        assert!(cv.fold_metrics.iter().all(|m| m.is_finite()));
+        assert!((cv.mean - cv.fold_metrics.iter().sum::<f64>() / 4.0).abs() < 1e-12);
+    }
+
+    #[derive(Default)]
+    struct Counts {
+        epoch_starts: usize,
+        batch_ends: usize,
+        epochs_seen: Vec<usize>,
+        losses_seen: Vec<f64>,
+        train_ends: usize
+    }
+
+    struct CountingCallback(std::rc::Rc<std::cell::RefCell<Counts>>);
+
+    impl Callback for CountingCallback {
+        fn on_epoch_start(&mut self, _epoch: usize) {
+            self.0.borrow_mut().epoch_starts += 1;
+        }
+
+        fn on_batch_end(&mut self, _epoch: usize, _batch: usize, _loss: f64) {
+            self.0.borrow_mut().batch_ends += 1;
+        }
+
+        fn on_epoch_end(&mut self, epoch: usize, history: &History) -> CallbackAction {
+            let mut counts = self.0.borrow_mut();
+            counts.epochs_seen.push(epoch);
+            counts.losses_seen.push(*history.losses().last().unwrap());
+            CallbackAction::Continue
+        }
+
+        fn on_train_end(&mut self, _history: &History) {
+            self.0.borrow_mut().train_ends += 1;
+        }
+    }
+
+    #[test]
+    fn callback_invocations() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 1]);
+        let options = TrainerOptions { epochs: 6, batch_size: 3, ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        let counts = std::rc::Rc::new(std::cell::RefCell::new(Counts::default()));
+        let history = trainer.fit_with_callbacks(&xs, &ys, &[], &[], vec![Box::new(CountingCallback(counts.clone()))]);
+
+        let counts = counts.borrow();
+        assert_eq!(counts.epoch_starts, 6);
+        // 4 samples in batches of 3
+        assert_eq!(counts.batch_ends, 12);
+        assert_eq!(counts.epochs_seen, (0..6).collect::<Vec<_>>());
+        assert_eq!(counts.losses_seen, history.losses());
+        assert_eq!(counts.train_ends, 1);
+    }
+
+    #[test]
+    fn callback_stops_training() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        let n = MLP::new(&vf, 3, &[4, 1]);
+        let options = TrainerOptions { epochs: 50, log_every: 2, ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        let stop_at_3 = crate::CallbackFn::new(|epoch, _history: &History| {
+            if epoch == 3 {CallbackAction::Stop} else {CallbackAction::Continue}
+        });
+        let history = trainer.fit_with_callbacks(&xs, &ys, &[], &[], vec![Box::new(stop_at_3)]);
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.stopped_epoch, Some(3));
+    }
+
+    #[test]
+    fn moons_classifier() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, labels) = make_moons(100, 0.1, 1);
+        let ys: Vec<Vec<f64>> = labels.iter().map(|&l| if l == 0 {vec![1.0, -1.0]} else {vec![-1.0, 1.0]}).collect();
+
+        let n = MLP::new(&vf, 2, &[16, 16, 2]);
+        let mut rng = Rng::new(5);
+        n.parameters().for_each(|p| p.set_data(rng.uniform(-1.0, 1.0) * 0.5));
+        let options = TrainerOptions { epochs: 15, batch_size: 10, shuffle_seed: Some(2), ..Default::default() };
+        let mut trainer = Trainer::new_mlp(&n, Adam::new(n.parameters().cloned().collect(), 0.02), Loss::Mse, &vf, options);
+        trainer.fit(&xs, &ys);
+
+        let correct = xs.iter().zip(&labels).filter(|(x, &l)| {
+            let out = n.predict(x);
+            (if out[0] > out[1] {0} else {1}) == l
+        }).count();
+        assert!(correct > 90, "accuracy {}%", correct);
+    }
+
+    // init, shuffling, dropout and data all derived from one seed
+    fn seeded_run(seed: u64) -> Vec<f64> {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let mut rng = Rng::new(seed);
+        let (xs, labels) = make_moons(20, 0.1, rng.next_seed());
+        let ys: Vec<Vec<f64>> = labels.iter().map(|&l| vec![l as f64 * 2.0 - 1.0]).collect();
+
+        let n = MLP::new_with_rng(&vf, 2, &[8, 1], &mut rng);
+        let dropout = Dropout::new(0.2, rng.fork());
+        let forward = |x: &[Value]| n.call(&dropout.call(x));
+        let options = TrainerOptions { epochs: 5, batch_size: 4, shuffle_seed: Some(rng.next_seed()), ..Default::default() };
+        let mut trainer = Trainer::new(&n, forward, Sgd::new(n.parameters().cloned().collect(), 0.1), Loss::Mse, &vf, options);
+        trainer.fit(&xs, &ys).losses()
+    }
+
+    #[test]
+    fn seeded_runs_are_reproducible() {
+        let losses = seeded_run(11);
+        assert_eq!(losses.len(), 5);
+        assert_eq!(losses, seeded_run(11));
+        assert_ne!(losses, seeded_run(12));
+    }
+
+    #[test]
+    fn rollback_on_nonfinite_loss() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (xs, ys) = dataset();
+
+        // the first batch of epoch 3 blows up once, so its second batch is skipped
+        let n = MLP::new(&vf, 3, &[4, 1]);
+        init(&n);
+        let calls = std::cell::Cell::new(0);
+        let forward = |x: &[Value]| {
+            calls.set(calls.get() + 1);
+            let out = n.call(x);
+            if calls.get() == 3 * 4 + 1 {vec![&out[0] * f64::INFINITY]} else {out}
+        };
+        let options = TrainerOptions { epochs: 6, batch_size: 2, rollback_on_nonfinite_loss: true, ..Default::default() };
+        let mut trainer = Trainer::new(&n, forward, Sgd::new_with_momentum(n.parameters().cloned().collect(), 0.1, 0.9), Loss::Mse, &vf, options);
+        let start = n.state_dict();
+        let history = trainer.fit(&xs, &ys);
+
+        let losses = history.losses();
+        assert!(losses[3].is_nan() || losses[3].is_infinite());
+        assert!(losses.iter().enumerate().all(|(i, l)| i == 3 || l.is_finite()));
+        assert_eq!(history.lrs(), vec![Some(0.1), Some(0.1), Some(0.1), Some(0.05), Some(0.05), Some(0.05)]);
+        assert_eq!(calls.get(), 6 * 4 - 2);
+        assert!(n.parameters().all(|p| p.get_data().is_finite()));
+        assert_ne!(n.state_dict(), start);
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::iter::zip;
+use std::{fmt, ops};
+use crate::{ArenaHandle, ArenaRef, ArenaView, OpKind, Value, ValueData, ValueFactory};
+
+// Experimental: nodes holding a whole vector, e.g. one input feature across a batch, so that a batch goes
+// through a neuron in a fixed number of nodes rather than one graph per sample. Vectors live in an arena of
+// their own. Ops are elementwise, plus mul_scalar / add_scalar with scalar Values such as the parameters,
+// and sum bridges back to a scalar Value so that the usual losses and optimizers apply. The vector part of
+// the graph is differentiated when backward on that Value reaches the bridge.
+#[derive(Clone)]
+pub struct VecValueFactory {
+    arena: ArenaRef<VecData>
+}
+
+impl VecValueFactory {
+    pub fn new(arena: ArenaRef<VecData>) -> VecValueFactory {
+        VecValueFactory { arena }
+    }
+
+    pub fn value(&self, data: Vec<f64>) -> VecValue {
+        VecValue::build(&self.arena, data, VecOp::Leaf)
+    }
+
+    // One vector per column of rows, i.e. per feature of a batch of samples. Panics on ragged rows.
+    pub fn columns(&self, rows: &[Vec<f64>]) -> Vec<VecValue> {
+        let width = rows.first().map_or(0, Vec::len);
+        assert!(rows.iter().all(|r| r.len() == width), "VecValueFactory::columns needs rows of equal length");
+        (0..width).map(|j| self.value(rows.iter().map(|r| r[j]).collect())).collect()
+    }
+
+    // Number of vector nodes
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+}
+
+pub struct VecData {
+    data: Vec<f64>,
+    grad: Vec<f64>,
+    op: VecOp
+}
+
+// Operands are held through handles that don't count, as for scalar nodes
+#[derive(Clone)]
+enum VecOp {
+    Leaf,
+    Add(VecValue, VecValue),
+    Mul(VecValue, VecValue),
+    Tanh(VecValue),
+    Relu(VecValue),
+    MulScalar(VecValue, Value),
+    AddScalar(VecValue, Value)
+}
+
+impl VecOp {
+    fn vec_operands(&self) -> Vec<&VecValue> {
+        match self {
+            VecOp::Leaf => Vec::new(),
+            VecOp::Add(a, b) | VecOp::Mul(a, b) => vec![a, b],
+            VecOp::Tanh(a) | VecOp::Relu(a) | VecOp::MulScalar(a, _) | VecOp::AddScalar(a, _) => vec![a]
+        }
+    }
+
+    fn scalar_operand(&self) -> Option<&Value> {
+        match self {
+            VecOp::MulScalar(_, s) | VecOp::AddScalar(_, s) => Some(s),
+            _ => None
+        }
+    }
+}
+
+// Elementwise ops need operands of the same length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VecLengthError {
+    pub left: usize,
+    pub right: usize
+}
+
+impl fmt::Display for VecLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VecValue lengths differ: {} and {}", self.left, self.right)
+    }
+}
+
+impl std::error::Error for VecLengthError {}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct VecValue {
+    node: ArenaHandle<VecData>
+}
+
+impl VecValue {
+    fn build(arena: &ArenaRef<VecData>, data: Vec<f64>, op: VecOp) -> VecValue {
+        let grad = vec![0.0; data.len()];
+        VecValue { node: arena.alloc_with_mut_borrow(VecData { data, grad, op }) }
+    }
+
+    fn internal(&self) -> VecValue {
+        VecValue { node: self.node.uncounted() }
+    }
+
+    fn with_borrow<R>(&self, f: impl FnOnce(&VecData) -> R) -> R {
+        self.node.with(f).unwrap_or_else(|| panic!("VecValue node {} of arena {} is gone", self.node.index(), self.node.arena().id()))
+    }
+
+    pub fn get_data(&self) -> Vec<f64> {
+        self.with_borrow(|v| v.data.clone())
+    }
+
+    pub fn get_grad(&self) -> Vec<f64> {
+        self.with_borrow(|v| v.grad.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.with_borrow(|v| v.data.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn unary(&self, f: impl Fn(f64) -> f64, op: VecOp) -> VecValue {
+        let data = self.with_borrow(|v| v.data.iter().map(|x| f(*x)).collect());
+        VecValue::build(self.node.arena(), data, op)
+    }
+
+    fn binary(&self, rhs: &VecValue, f: impl Fn(f64, f64) -> f64, op: VecOp) -> Result<VecValue, VecLengthError> {
+        let data = self.with_borrow(|a| rhs.with_borrow(|b| {
+            match a.data.len() == b.data.len() {
+                true => Ok(zip(&a.data, &b.data).map(|(x, y)| f(*x, *y)).collect()),
+                false => Err(VecLengthError { left: a.data.len(), right: b.data.len() })
+            }
+        }))?;
+        Ok(VecValue::build(self.node.arena(), data, op))
+    }
+
+    pub fn try_add(&self, rhs: &VecValue) -> Result<VecValue, VecLengthError> {
+        self.binary(rhs, |x, y| x + y, VecOp::Add(self.internal(), rhs.internal()))
+    }
+
+    pub fn try_mul(&self, rhs: &VecValue) -> Result<VecValue, VecLengthError> {
+        self.binary(rhs, |x, y| x * y, VecOp::Mul(self.internal(), rhs.internal()))
+    }
+
+    pub fn tanh(&self) -> VecValue {
+        self.unary(f64::tanh, VecOp::Tanh(self.internal()))
+    }
+
+    pub fn relu(&self) -> VecValue {
+        self.unary(|x| x.max(0.0), VecOp::Relu(self.internal()))
+    }
+
+    // Every element times s, e.g. a feature column times its weight
+    pub fn mul_scalar(&self, s: &Value) -> VecValue {
+        let k = s.get_data();
+        self.unary(|x| x * k, VecOp::MulScalar(self.internal(), s.internal()))
+    }
+
+    pub fn add_scalar(&self, s: &Value) -> VecValue {
+        let k = s.get_data();
+        self.unary(|x| x + k, VecOp::AddScalar(self.internal(), s.internal()))
+    }
+
+    // Sum of the elements as a scalar node of vf. Its operands are the scalar Values the vector graph reads,
+    // so the scalar backward reaches them only after this node has passed their grads on.
+    pub fn sum(&self, vf: &ValueFactory) -> Value {
+        let (data, scalars) = (self.with_borrow(|v| v.data.iter().sum()), self.scalar_inputs());
+        let out = Value::new(vf.arena().clone(), data, &scalars, OpKind::Custom("vsum"));
+        let (out_ref, root) = (out.internal(), self.internal());
+        out.set_backward(move |view| {
+            let out_grad = out_ref.grad_in(view);
+            root.backward(out_grad, view);
+        });
+        out
+    }
+
+    // Every node of this vector's graph, operands first
+    fn topo_order(&self) -> Vec<VecValue> {
+        let (mut topo, mut visited, mut stack) = (Vec::new(), HashSet::new(), vec![(self.internal(), false)]);
+        self.node.arena().with_view(|view| {
+            while let Some((v, done)) = stack.pop() {
+                if done {
+                    topo.push(v);
+                } else if visited.insert(v.internal()) {
+                    let node = view.get(&v.node).expect("VecValue graph refers to a freed node");
+                    let operands: Vec<(VecValue, bool)> = node.op.vec_operands().into_iter().rev().map(|a| (a.internal(), false)).collect();
+                    stack.push((v, true));
+                    stack.extend(operands);
+                }
+            }
+        });
+        topo
+    }
+
+    fn scalar_inputs(&self) -> Vec<Value> {
+        let mut scalars: Vec<Value> = Vec::new();
+        self.topo_order().iter().for_each(|v| v.with_borrow(|node| {
+            if let Some(s) = node.op.scalar_operand().filter(|s| !scalars.contains(s)) {
+                scalars.push(s.internal());
+            }
+        }));
+        scalars
+    }
+
+    // Adds grad to each element's grad and passes it down the vector graph, into scalars for the scalar
+    // operands. Only this delta travels, so several sums over shared vectors each count once.
+    fn backward(&self, grad: f64, scalars: &mut ArenaView<'_, ValueData>) {
+        let topo = self.topo_order();
+        let mut deltas: HashMap<VecValue, Vec<f64>> = HashMap::new();
+        deltas.insert(self.internal(), vec![grad; self.len()]);
+        fn pass(deltas: &mut HashMap<VecValue, Vec<f64>>, v: &VecValue, delta: Vec<f64>) {
+            match deltas.get_mut(v) {
+                Some(d) => zip(d, delta).for_each(|(d, x)| *d += x),
+                None => {deltas.insert(v.internal(), delta);}
+            }
+        }
+        self.node.arena().with_view(|view| {
+            for v in topo.iter().rev() {
+                let Some(delta) = deltas.remove(v) else { continue };
+                let node = view.get_mut(&v.node).expect("VecValue graph refers to a freed node");
+                zip(&mut node.grad, &delta).for_each(|(g, d)| *g += d);
+                let (op, out) = (node.op.clone(), node.data.clone());
+                let data = |a: &VecValue| &view.get(&a.node).expect("VecValue graph refers to a freed node").data;
+                let scaled = |by: &[f64]| zip(&delta, by).map(|(d, x)| d * x).collect::<Vec<f64>>();
+                match &op {
+                    VecOp::Leaf => {}
+                    VecOp::Add(a, b) => {
+                        pass(&mut deltas, a, delta.clone());
+                        pass(&mut deltas, b, delta);
+                    }
+                    VecOp::Mul(a, b) => {
+                        let (da, db) = (scaled(data(b)), scaled(data(a)));
+                        pass(&mut deltas, a, da);
+                        pass(&mut deltas, b, db);
+                    }
+                    VecOp::Tanh(a) => {
+                        let local: Vec<f64> = out.iter().map(|t| 1.0 - t * t).collect();
+                        pass(&mut deltas, a, scaled(&local));
+                    }
+                    VecOp::Relu(a) => {
+                        let local: Vec<f64> = out.iter().map(|y| if *y > 0.0 {1.0} else {0.0}).collect();
+                        pass(&mut deltas, a, scaled(&local));
+                    }
+                    VecOp::MulScalar(a, s) => {
+                        s.add_grad_in(scalars, scaled(data(a)).iter().sum());
+                        let k = s.data_in(scalars);
+                        pass(&mut deltas, a, delta.iter().map(|d| d * k).collect());
+                    }
+                    VecOp::AddScalar(a, s) => {
+                        s.add_grad_in(scalars, delta.iter().sum());
+                        pass(&mut deltas, a, delta);
+                    }
+                }
+            }
+        });
+    }
+}
+
+// Operators panic on a length mismatch; try_add / try_mul return it instead
+impl<'b> ops::Add<&'b VecValue> for &VecValue {
+    type Output = VecValue;
+
+    fn add(self, rhs: &'b VecValue) -> VecValue {
+        self.try_add(rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl<'b> ops::Mul<&'b VecValue> for &VecValue {
+    type Output = VecValue;
+
+    fn mul(self, rhs: &'b VecValue) -> VecValue {
+        self.try_mul(rhs).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_grad_close, assert_grads_close, assert_value_close, Arena};
+
+    #[test]
+    fn elementwise_grads_match_scalar_graphs() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (_vec_life_time, vec_ref) = Arena::build();
+        let vvf = VecValueFactory::new(vec_ref);
+        let (xs, ys) = ([0.5, -1.0, 2.0, 0.0], [1.5, 0.25, -0.75, 3.0]);
+
+        let (w, b) = (vf.value(0.8), vf.value(-0.3));
+        let (x, y) = (vvf.value(xs.to_vec()), vvf.value(ys.to_vec()));
+        let xy = &x * &y;
+        let out = (&(&xy + &x).tanh() + &xy.relu()).mul_scalar(&w).add_scalar(&b);
+        let loss = out.sum(&vf).powi(2);
+        loss.backward();
+
+        // the same computation one element at a time
+        let (sw, sb) = (vf.value(0.8), vf.value(-0.3));
+        let (sx, sy): (Vec<Value>, Vec<Value>) = (vf.values(&xs), vf.values(&ys));
+        let sum = zip(&sx, &sy)
+            .map(|(xi, yi)| {
+                let xy = xi * yi;
+                &(&(&(&xy + xi).tanh() + &xy.relu()) * &sw) + &sb
+            })
+            .reduce(|acc, v| &acc + &v)
+            .unwrap();
+        let s_loss = sum.powi(2);
+        s_loss.backward();
+
+        assert_value_close!(loss, s_loss.get_data(), 1e-12);
+        assert_grads_close!(&sx, &x.get_grad(), 1e-12);
+        assert_grads_close!(&sy, &y.get_grad(), 1e-12);
+        assert_grads_close!([&sw, &sb], &[w.get_grad(), b.get_grad()], 1e-12);
+    }
+
+    #[test]
+    fn sum_bridge() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (_vec_life_time, vec_ref) = Arena::build();
+        let vvf = VecValueFactory::new(vec_ref);
+
+        let x = vvf.value(vec![1.0, 2.0, 3.0]);
+        let s = x.sum(&vf);
+        assert_eq!(s.get_data(), 6.0);
+        (&s * 3.0).backward();
+        assert_eq!(x.get_grad(), vec![3.0; 3]);
+
+        // two sums sharing x each pass their own grad on
+        let x = vvf.value(vec![1.0, 2.0, 3.0]);
+        (&x.sum(&vf) + &(&x * &x).sum(&vf)).backward();
+        assert_eq!(x.get_grad(), vec![3.0, 5.0, 7.0]);
+
+        let y = vvf.value(vec![1.0, 2.0]);
+        assert_eq!(x.try_add(&y).err(), Some(VecLengthError { left: 3, right: 2 }));
+        assert_eq!(y.try_mul(&x).err().unwrap().to_string(), "VecValue lengths differ: 2 and 3");
+    }
+
+    #[test]
+    fn batched_neuron() {
+        let (_arena_life_time, arena_ref) = Arena::build();
+        let vf = ValueFactory::new(arena_ref);
+        let (_vec_life_time, vec_ref) = Arena::build();
+        let vvf = VecValueFactory::new(vec_ref);
+        let rows: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64 * 0.3 - 1.0, 0.5 - i as f64 * 0.1, (i % 3) as f64]).collect();
+        let (w, b) = (vf.values(&[0.4, -0.7, 0.2]), vf.value(0.1));
+
+        // tanh(w . x + b) for the whole batch in a fixed number of vector nodes
+        let columns = vvf.columns(&rows);
+        let weighted = zip(&columns, &w).map(|(c, wi)| c.mul_scalar(wi)).reduce(|acc, v| &acc + &v).unwrap();
+        let out = weighted.add_scalar(&b).tanh();
+        assert_eq!(vvf.len(), 3 + 3 + 2 + 2);
+        out.sum(&vf).backward();
+
+        let (sw, sb) = (vf.values(&[0.4, -0.7, 0.2]), vf.value(0.1));
+        let looped: Vec<Value> = rows.iter()
+            .map(|r| zip(&sw, r).fold(sb.clone(), |acc, (wi, xi)| &acc + &(wi * *xi)).tanh())
+            .collect();
+        looped.iter().cloned().reduce(|acc, v| &acc + &v).unwrap().backward();
+
+        zip(&looped, out.get_data()).for_each(|(l, o)| assert_value_close!(l, o, 1e-12));
+        assert_grads_close!(&sw, &w.iter().map(Value::get_grad).collect::<Vec<_>>(), 1e-12);
+        assert_grad_close!(sb, b.get_grad(), 1e-12);
+    }
+}
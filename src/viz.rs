@@ -0,0 +1,191 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use crate::DataError;
+
+// Fill colors per class, cycled when there are more classes than colors. Training points are drawn in a
+// darker shade of their class color.
+const PALETTE: [[u8; 3]; 6] = [
+    [230, 120, 110],
+    [110, 150, 230],
+    [120, 200, 120],
+    [230, 200, 100],
+    [180, 120, 210],
+    [100, 200, 200]
+];
+
+fn class_color(class: usize, dark: bool) -> [u8; 3] {
+    let c = PALETTE[class % PALETTE.len()];
+    if dark { c.map(|v| v / 3) } else { c }
+}
+
+// Predicted class for every cell of a resolution x resolution grid. Rows go from the top (largest y) down
+// so row/col map directly to image pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid {
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+    pub resolution: usize,
+    cells: Vec<usize>
+}
+
+impl Grid {
+    pub fn get(&self, row: usize, col: usize) -> usize {
+        self.cells[row * self.resolution + col]
+    }
+
+    pub fn cells(&self) -> &[usize] {
+        &self.cells
+    }
+
+    // Center of the cell in input space
+    pub fn cell_center(&self, row: usize, col: usize) -> (f64, f64) {
+        let (dx, dy) = self.cell_size();
+        (self.x_range.0 + (col as f64 + 0.5) * dx, self.y_range.1 - (row as f64 + 0.5) * dy)
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        let n = self.resolution as f64;
+        ((self.x_range.1 - self.x_range.0) / n, (self.y_range.1 - self.y_range.0) / n)
+    }
+
+    // Cell containing (x, y), None outside the grid
+    fn locate(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let (dx, dy) = self.cell_size();
+        let col = ((x - self.x_range.0) / dx).floor();
+        let row = ((self.y_range.1 - y) / dy).floor();
+        let n = self.resolution as f64;
+        if (0.0..n).contains(&col) && (0.0..n).contains(&row) {
+            Some((row as usize, col as usize))
+        } else {
+            None
+        }
+    }
+
+    // One line per cell: x,y,class with x and y the cell center
+    pub fn to_csv_string(&self) -> String {
+        let mut csv = String::from("x,y,class\n");
+        for row in 0..self.resolution {
+            for col in 0..self.resolution {
+                let (x, y) = self.cell_center(row, col);
+                csv.push_str(&format!("{},{},{}\n", x, y, self.get(row, col)));
+            }
+        }
+        csv
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv_string())
+    }
+
+    // Binary PPM (P6) image with one pixel per cell. points/labels are overlaid as 3x3 squares.
+    pub fn to_ppm(&self, points: &[Vec<f64>], labels: &[usize]) -> Vec<u8> {
+        let n = self.resolution;
+        let mut pixels: Vec<[u8; 3]> = self.cells.iter().map(|&c| class_color(c, false)).collect();
+        points.iter().zip(labels).for_each(|(p, &label)| {
+            if let Some((row, col)) = self.locate(p[0], p[1]) {
+                for r in row.saturating_sub(1)..(row + 2).min(n) {
+                    for c in col.saturating_sub(1)..(col + 2).min(n) {
+                        pixels[r * n + c] = class_color(label, true);
+                    }
+                }
+            }
+        });
+        let mut ppm = format!("P6\n{} {}\n255\n", n, n).into_bytes();
+        ppm.extend(pixels.iter().flatten());
+        ppm
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_ppm(&self, path: impl AsRef<Path>, points: &[Vec<f64>], labels: &[usize]) -> io::Result<()> {
+        fs::write(path, self.to_ppm(points, labels))
+    }
+}
+
+// Evaluates a 2D classifier over a resolution x resolution grid covering x_range and y_range
+pub fn decision_grid(
+    predict: impl Fn(&[f64]) -> usize,
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    resolution: usize
+) -> Result<Grid, DataError> {
+    // written so that NaN bounds are rejected too
+    let increasing = |r: (f64, f64)| r.0 < r.1;
+    if !increasing(x_range) || !increasing(y_range) {
+        return Err(DataError::InvalidArgument(format!("ranges must be increasing, got x {:?} and y {:?}", x_range, y_range)));
+    }
+    if resolution == 0 {
+        return Err(DataError::InvalidArgument(String::from("resolution must be positive")));
+    }
+    let mut grid = Grid { x_range, y_range, resolution, cells: Vec::with_capacity(resolution * resolution) };
+    for row in 0..resolution {
+        for col in 0..resolution {
+            let (x, y) = grid.cell_center(row, col);
+            grid.cells.push(predict(&[x, y]));
+        }
+    }
+    Ok(grid)
+}
+
+/******************************** unit tests ********************************/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // class 1 above the line y = x
+    fn above_diagonal(p: &[f64]) -> usize {
+        if p[1] > p[0] {1} else {0}
+    }
+
+    #[test]
+    fn grid_dimensions() {
+        let grid = decision_grid(above_diagonal, (-1.0, 1.0), (-1.0, 1.0), 8).unwrap();
+        assert_eq!(grid.cells().len(), 64);
+        assert_eq!(grid.to_csv_string().lines().count(), 65);
+        assert_eq!(grid.cell_center(0, 0), (-0.875, 0.875));
+        assert_eq!(grid.cell_center(7, 7), (0.875, -0.875));
+    }
+
+    #[test]
+    fn linear_classifier_half_planes() {
+        let grid = decision_grid(above_diagonal, (0.0, 4.0), (0.0, 4.0), 4).unwrap();
+        // rows run top to bottom, so class 1 is the upper left triangle; cells centered on the diagonal are class 0
+        let expected = [
+            [1, 1, 1, 0],
+            [1, 1, 0, 0],
+            [1, 0, 0, 0],
+            [0, 0, 0, 0]
+        ];
+        for (row, cols) in expected.iter().enumerate() {
+            for (col, class) in cols.iter().enumerate() {
+                assert_eq!(grid.get(row, col), *class, "cell ({}, {})", row, col);
+            }
+        }
+    }
+
+    #[test]
+    fn ppm_output() {
+        let grid = decision_grid(above_diagonal, (-1.0, 1.0), (-1.0, 1.0), 10).unwrap();
+        let ppm = grid.to_ppm(&[vec![0.5, -0.5], vec![5.0, 5.0]], &[0, 1]);
+        let header = b"P6\n10 10\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 10 * 10 * 3);
+        // pixel under the in-range point is dark, the out-of-range point is ignored
+        let pixel = |row: usize, col: usize| &ppm[header.len() + (row * 10 + col) * 3..][..3];
+        assert_eq!(pixel(7, 7), class_color(0, true));
+        assert_eq!(pixel(0, 0), class_color(1, false));
+        assert_eq!(pixel(9, 9), class_color(0, false));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert!(decision_grid(above_diagonal, (1.0, -1.0), (-1.0, 1.0), 4).is_err());
+        assert!(decision_grid(above_diagonal, (-1.0, 1.0), (2.0, 2.0), 4).is_err());
+        assert!(decision_grid(above_diagonal, (-1.0, 1.0), (-1.0, 1.0), 0).is_err());
+    }
+}
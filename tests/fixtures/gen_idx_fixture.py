@@ -0,0 +1,18 @@
+# Writes a tiny MNIST-style pair of IDX files: 3 images of 4x4 pixels and their labels.
+# Pixel (r, c) of image i is (i * 16 + r * 4 + c) * 5, so every value is easy to check by hand.
+#
+#   python3 tests/fixtures/gen_idx_fixture.py
+import os
+import struct
+
+here = os.path.dirname(os.path.abspath(__file__))
+n, rows, cols = 3, 4, 4
+labels = [7, 2, 1]
+
+with open(os.path.join(here, "tiny-images-idx3-ubyte"), "wb") as f:
+    f.write(struct.pack(">IIII", 0x00000803, n, rows, cols))
+    f.write(bytes((i * rows * cols + p) * 5 for i in range(n) for p in range(rows * cols)))
+
+with open(os.path.join(here, "tiny-labels-idx1-ubyte"), "wb") as f:
+    f.write(struct.pack(">II", 0x00000801, n))
+    f.write(bytes(labels))
@@ -0,0 +1,91 @@
+# Regenerates micrograd_mlp.json and micrograd_forward.json with Karpathy's micrograd
+# (engine.Value and nn.Neuron/Layer/MLP, reproduced below so the script has no dependencies).
+#
+#   python3 tests/fixtures/gen_micrograd_fixture.py
+import json
+import os
+import random
+
+
+class Value:
+    def __init__(self, data, _children=(), _op=''):
+        self.data = data
+        self.grad = 0
+        self._prev = set(_children)
+        self._op = _op
+
+    def __add__(self, other):
+        other = other if isinstance(other, Value) else Value(other)
+        return Value(self.data + other.data, (self, other), '+')
+
+    def __mul__(self, other):
+        other = other if isinstance(other, Value) else Value(other)
+        return Value(self.data * other.data, (self, other), '*')
+
+    def relu(self):
+        return Value(0 if self.data < 0 else self.data, (self,), 'ReLU')
+
+    def __radd__(self, other):
+        return self + other
+
+
+class Module:
+    def parameters(self):
+        return []
+
+
+class Neuron(Module):
+    def __init__(self, nin, nonlin=True):
+        self.w = [Value(random.uniform(-1, 1)) for _ in range(nin)]
+        self.b = Value(0)
+        self.nonlin = nonlin
+
+    def __call__(self, x):
+        act = sum((wi * xi for wi, xi in zip(self.w, x)), self.b)
+        return act.relu() if self.nonlin else act
+
+    def parameters(self):
+        return self.w + [self.b]
+
+
+class Layer(Module):
+    def __init__(self, nin, nout, **kwargs):
+        self.neurons = [Neuron(nin, **kwargs) for _ in range(nout)]
+
+    def __call__(self, x):
+        out = [n(x) for n in self.neurons]
+        return out[0] if len(out) == 1 else out
+
+    def parameters(self):
+        return [p for n in self.neurons for p in n.parameters()]
+
+
+class MLP(Module):
+    def __init__(self, nin, nouts):
+        sz = [nin] + nouts
+        self.layers = [Layer(sz[i], sz[i + 1], nonlin=i != len(nouts) - 1) for i in range(len(nouts))]
+
+    def __call__(self, x):
+        for layer in self.layers:
+            x = layer(x)
+        return x
+
+
+random.seed(1337)
+model = MLP(3, [4, 4, 2])
+# micrograd initialises biases to 0, give them values so the fixture exercises them
+for layer in model.layers:
+    for n in layer.neurons:
+        n.b.data = random.uniform(-1, 1)
+
+weights = [[[w.data for w in n.w] + [n.b.data] for n in layer.neurons] for layer in model.layers]
+inputs = [[random.uniform(-2, 2) for _ in range(3)] for _ in range(8)]
+forward = [[x, [v.data for v in model(x)]] for x in inputs]
+
+here = os.path.dirname(os.path.abspath(__file__))
+with open(os.path.join(here, 'micrograd_mlp.json'), 'w') as f:
+    json.dump(weights, f, indent=1)
+    f.write('\n')
+with open(os.path.join(here, 'micrograd_forward.json'), 'w') as f:
+    json.dump(forward, f, indent=1)
+    f.write('\n')
@@ -0,0 +1,50 @@
+// Generated by rust_micrograd from an MLP 3 -> [5, 4, 2]
+const RELU_MLP_W0: [[f64; 3]; 5] = [
+    [-0.8857200420708313, -0.005234118098452534, -0.8136605311564207],
+    [-0.6289938802585984, -0.37105527404563743, 0.8832796214344154],
+    [-0.9631899863068569, -0.9196414630920504, -0.4829092862916742],
+    [0.25334080849292917, 0.44099748709769004, -0.6913643350653711],
+    [0.13921174351448729, 0.8768297547375736, 0.6299925628772134]
+];
+const RELU_MLP_B0: [f64; 5] = [0.24945464239928095, 0.5240251277298382, 0.29983140496076643, -0.09880799258216122, -0.5084206581870663];
+const RELU_MLP_W1: [[f64; 5]; 4] = [
+    [0.08980152886367687, 0.9720388701832876, 0.7925027484781642, 0.43845394024064843, -0.9283145623551001],
+    [0.18373412015291546, 0.8271432062795059, 0.05116595041708516, 0.008500135451931179, 0.9840346179292339],
+    [0.2337198270202303, -0.7910570853050776, 0.09782708040104371, 0.21510796064718773, -0.12361500495779865],
+    [-0.5650393849799413, -0.29562829108145694, 0.418204232958034, 0.7873182816332043, 0.12505133155160975]
+];
+const RELU_MLP_B1: [f64; 4] = [-0.8221745778967637, 0.8893133380825695, 0.6019578723173602, -0.7788573341529537];
+const RELU_MLP_W2: [[f64; 4]; 2] = [
+    [-0.837643259887789, 0.29788543723767, 0.8707410375439961, 0.4737835868253284],
+    [0.19642638815332703, -0.29166116149130117, 0.3483801201991139, -0.8727765066738895]
+];
+const RELU_MLP_B2: [f64; 2] = [-0.6443734322168124, -0.7495638720900906];
+
+pub fn relu_mlp(x: &[f64; 3]) -> [f64; 2] {
+    let h0 = *x;
+    let mut h1 = [0.0; 5];
+    for (j, out) in h1.iter_mut().enumerate() {
+        let mut acc = RELU_MLP_B0[j];
+        for (w, xi) in RELU_MLP_W0[j].iter().zip(&h0) {
+            acc += w * xi;
+        }
+        *out = acc.max(0.0);
+    }
+    let mut h2 = [0.0; 4];
+    for (j, out) in h2.iter_mut().enumerate() {
+        let mut acc = RELU_MLP_B1[j];
+        for (w, xi) in RELU_MLP_W1[j].iter().zip(&h1) {
+            acc += w * xi;
+        }
+        *out = acc.max(0.0);
+    }
+    let mut h3 = [0.0; 2];
+    for (j, out) in h3.iter_mut().enumerate() {
+        let mut acc = RELU_MLP_B2[j];
+        for (w, xi) in RELU_MLP_W2[j].iter().zip(&h2) {
+            acc += w * xi;
+        }
+        *out = acc;
+    }
+    h3
+}
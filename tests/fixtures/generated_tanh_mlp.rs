@@ -0,0 +1,50 @@
+// Generated by rust_micrograd from an MLP 3 -> [5, 4, 2]
+const TANH_MLP_W0: [[f64; 3]; 5] = [
+    [-0.8514568154543416, -0.4921746368554132, 0.8391025015469133],
+    [0.7066208493962867, -0.12888952488587035, 0.6975730499829673],
+    [-0.33446724981112297, 0.6118532642067196, -0.6943593211362469],
+    [0.9131434507767344, 0.2615081563427455, -0.5145192443773481],
+    [-0.5345660343936838, -0.4100656711086046, 0.3924643021928511]
+];
+const TANH_MLP_B0: [f64; 5] = [-0.2881443757949369, 0.300858094774354, -0.01823565249667336, -0.6787677092987665, -0.2918023994720791];
+const TANH_MLP_W1: [[f64; 5]; 4] = [
+    [0.8343796301573319, 0.5496885492771684, -0.6214770001952736, 0.7282845667695579, -0.5648968000508656],
+    [0.12362892212663157, -0.6109450038236037, 0.4655243473885857, -0.42090732876669135, 0.6230949274038],
+    [0.8388321811725801, 0.5498861991843742, -0.18542818458758692, 0.4357676914530191, -0.42653261407431353],
+    [-0.9829789812750378, 0.7382244584259969, 0.5140591009970423, -0.7270019974243236, 0.8640257711458963]
+];
+const TANH_MLP_B1: [f64; 4] = [-0.47625410272944624, -0.1423924493732338, -0.4137365181465422, 0.31397388156360506];
+const TANH_MLP_W2: [[f64; 4]; 2] = [
+    [0.9317263521823733, 0.4434834961102352, 0.4629647750457804, -0.6077408692281656],
+    [-0.20929219371099173, -0.7245923532293759, -0.025688075487916517, 0.3382703194822949]
+];
+const TANH_MLP_B2: [f64; 2] = [0.0684090490352105, 0.720014924558507];
+
+pub fn tanh_mlp(x: &[f64; 3]) -> [f64; 2] {
+    let h0 = *x;
+    let mut h1 = [0.0; 5];
+    for (j, out) in h1.iter_mut().enumerate() {
+        let mut acc = TANH_MLP_B0[j];
+        for (w, xi) in TANH_MLP_W0[j].iter().zip(&h0) {
+            acc += w * xi;
+        }
+        *out = acc.tanh();
+    }
+    let mut h2 = [0.0; 4];
+    for (j, out) in h2.iter_mut().enumerate() {
+        let mut acc = TANH_MLP_B1[j];
+        for (w, xi) in TANH_MLP_W1[j].iter().zip(&h1) {
+            acc += w * xi;
+        }
+        *out = acc.tanh();
+    }
+    let mut h3 = [0.0; 2];
+    for (j, out) in h3.iter_mut().enumerate() {
+        let mut acc = TANH_MLP_B2[j];
+        for (w, xi) in TANH_MLP_W2[j].iter().zip(&h2) {
+            acc += w * xi;
+        }
+        *out = acc.tanh();
+    }
+    h3
+}